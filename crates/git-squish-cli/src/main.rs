@@ -0,0 +1,1782 @@
+use clap::{Args, ColorChoice, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use git_squish_core::{
+    BumpLevel, CleanupMode, MessageNormalization, SignatureCheck, SquashRecord, SquashResult,
+    SquashStats, SquishError, SquishOptions, SquishPlan, StrategyOption, VersionBump,
+};
+use git2::{BranchType, Oid, Repository};
+use serde_json::{Value, json};
+use std::fmt::Display;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+/// Exit code used when the branch is already squashed and was left untouched.
+const EXIT_ALREADY_SQUASHED: i32 = 2;
+/// Exit code used when the branch has no commits which are not on the upstream.
+const EXIT_NOTHING_TO_SQUASH: i32 = 3;
+
+/// Squash the commits of a branch into a single commit on top of an upstream.
+///
+/// Without a subcommand, squashes BRANCH-REFNAME (or the current branch) onto UPSTREAM-SPEC.
+/// If both are omitted, the current branch is squashed onto its tracked upstream or
+/// origin/HEAD, or on a terminal the branch and upstream are picked interactively.
+#[derive(Parser)]
+#[command(
+    name = "git squish",
+    bin_name = "git squish",
+    version,
+    after_help = "Examples:\n  git squish topic main\n  git squish main  # uses the current branch\n  git squish -X theirs main"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    squish: SquishArgs,
+
+    /// Run as if git-squish was started in PATH instead of the current directory
+    #[arg(short = 'C', long = "repo", value_name = "PATH", global = true)]
+    repo: Option<PathBuf>,
+
+    /// Print nothing on success, only errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print each squashed commit, the generated message and the ref updates
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// When to color the output: auto colors it on a terminal unless NO_COLOR is set
+    #[arg(long, value_name = "WHEN", global = true, default_value = "auto")]
+    color: ColorChoice,
+
+    /// Print libgit2's own trace output to stderr, up to LEVEL (error, warn, info, debug
+    /// or trace, the default)
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        global = true,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "trace",
+        value_parser = ["error", "warn", "info", "debug", "trace"]
+    )]
+    trace_libgit2: Option<String>,
+
+    /// Remove a squish lock left behind (e.g. by a squish on another machine sharing the
+    /// checkout) before running; make sure no other squish is running first
+    #[arg(long, global = true)]
+    force_unlock: bool,
+}
+
+impl Cli {
+    /// Whether the command should print JSON instead of human readable text.
+    fn json(&self) -> bool {
+        match &self.command {
+            None => self.squish.json,
+            Some(Command::Continue { json })
+            | Some(Command::Plan { json, .. })
+            | Some(Command::Batch { json, .. })
+            | Some(Command::Log { json, .. })
+            | Some(Command::Stats { json, .. }) => *json,
+            Some(_) => false,
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            verbosity: if self.quiet {
+                Verbosity::Quiet
+            } else if self.verbose {
+                Verbosity::Verbose
+            } else {
+                Verbosity::Normal
+            },
+            color: use_color(self.color, std::io::stdout().is_terminal()),
+            error_color: use_color(self.color, std::io::stderr().is_terminal()),
+        }
+    }
+}
+
+/// Whether to color a stream, honoring --color, NO_COLOR (https://no-color.org) and dumb
+/// terminals like git does.
+fn use_color(choice: ColorChoice, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            is_terminal
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+        }
+    }
+}
+
+/// ANSI styles, matching the colors git uses for the same things.
+mod style {
+    pub const COMMIT: &str = "33";
+    pub const BRANCH: &str = "32";
+    pub const WARNING: &str = "33";
+    pub const ERROR: &str = "1;31";
+}
+
+/// How much the CLI prints about what it did. Errors are always printed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// Prints progress and results, filtered by the requested verbosity and colored if enabled.
+struct Output {
+    verbosity: Verbosity,
+    color: bool,
+    error_color: bool,
+}
+
+impl Output {
+    /// `text` in the given ANSI style when stdout is colored.
+    fn paint(&self, text: impl Display, style: &str) -> String {
+        paint(self.color, text, style)
+    }
+
+    /// Print a message unless --quiet was given.
+    fn info(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("{message}");
+        }
+    }
+
+    /// Print a message only with --verbose.
+    fn verbose(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Verbose {
+            println!("{message}");
+        }
+    }
+
+    /// Print an error, whatever the verbosity.
+    fn error(&self, message: impl Display) {
+        eprintln!(
+            "💀 {} {message}",
+            paint(self.error_color, "Error:", style::ERROR)
+        );
+    }
+
+    /// Report a finished squish: with --verbose, the commits which were squashed, the
+    /// message they were squashed into and how the branch moved, then the result.
+    fn squashed(&self, repo_path: &Path, result: &SquashResult) -> Result<(), SquishError> {
+        if self.verbosity >= Verbosity::Verbose {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            for id in &result.commits {
+                let commit = repo.find_commit(*id)?;
+                self.verbose(format!(
+                    "Squashed {} {}",
+                    self.paint(format!("{id:.7}"), style::COMMIT),
+                    commit.summary().unwrap_or_default()
+                ));
+            }
+            let commit = repo.find_commit(result.new_id)?;
+            self.verbose("Message:");
+            for line in commit.message().unwrap_or_default().lines() {
+                self.verbose(format!("    {line}"));
+            }
+            self.verbose(format!(
+                "Updated {}: {} -> {}",
+                self.paint(&result.branch, style::BRANCH),
+                self.paint(format!("{:.7}", result.old_id), style::COMMIT),
+                self.paint(format!("{:.7}", result.new_id), style::COMMIT)
+            ));
+        }
+        self.info(&result.message);
+        for warning in &result.warnings {
+            self.info(format!("⚠️ {}", self.paint(warning, style::WARNING)));
+        }
+        Ok(())
+    }
+}
+
+/// `text` wrapped in the ANSI escapes for `style`, or as is when `enabled` is false.
+fn paint(enabled: bool, text: impl Display, style: &str) -> String {
+    if enabled {
+        format!("\x1b[{style}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Arguments of the default squish action.
+#[derive(Args)]
+struct SquishArgs {
+    #[command(flatten)]
+    options: OptionArgs,
+
+    /// Rewrite the branch without asking for confirmation first (only asked on a terminal)
+    #[arg(short = 'y', long)]
+    yes: bool,
+
+    /// Print the id of the squashed commit without moving the branch (for merge queues)
+    #[arg(long)]
+    queue: bool,
+
+    /// Print the squashed commit with a diffstat afterwards (or the full patch with =patch)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "stat",
+        value_parser = ["stat", "patch"],
+        conflicts_with = "json"
+    )]
+    show: Option<String>,
+
+    /// Print the outcome as a JSON document instead of text
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    refs: RefArgs,
+}
+
+/// The branch to squash and the upstream to squash it onto.
+#[derive(Args)]
+struct RefArgs {
+    /// The branch to squash (e.g. feature, refs/heads/feature or HEAD), defaults to the
+    /// current branch. When only one argument is given, it is the upstream
+    #[arg(value_name = "BRANCH-REFNAME")]
+    first: Option<String>,
+
+    /// The upstream to squash onto (e.g. main or origin/main), defaults to the branch's
+    /// tracked upstream or origin/HEAD
+    #[arg(value_name = "UPSTREAM-SPEC")]
+    second: Option<String>,
+}
+
+/// Options controlling how a branch is squished, shared by the commands which squish.
+#[derive(Args)]
+struct OptionArgs {
+    /// How to clean up the squash message, overriding commit.cleanup
+    #[arg(long, value_name = "default|strip|whitespace|verbatim|scissors")]
+    cleanup: Option<CleanupMode>,
+
+    /// End the squash message with exactly one newline
+    #[arg(long)]
+    trailing_newline: bool,
+
+    /// Collapse runs of more than two blank lines in the squash message down to two
+    #[arg(long)]
+    collapse_blank_lines: bool,
+
+    /// Convert CRLF line endings in the squash message to LF
+    #[arg(long)]
+    crlf_to_lf: bool,
+
+    /// Hard-wrap the squash message body at COLUMNS (72 when no value is given)
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "72"
+    )]
+    wrap_body: Option<usize>,
+
+    /// Automatically resolve conflicting hunks in favor of one side
+    #[arg(short = 'X', long, value_name = "ours|theirs|union")]
+    strategy_option: Option<StrategyOption>,
+
+    /// Bump the version in Cargo.toml, package.json and VERSION (or the squish.versionFile
+    /// config) as part of the squash commit
+    #[arg(
+        long,
+        value_name = "patch|minor|major",
+        conflicts_with = "pause_on_conflict"
+    )]
+    bump: Option<BumpLevel>,
+
+    /// Create an empty commit when the branch has nothing to squash
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Create the squash commit even if its message is empty
+    #[arg(long)]
+    allow_empty_message: bool,
+
+    /// Read the squash message from stdin instead of taking the first commit's
+    #[arg(long, conflicts_with_all = ["message_url", "split_auto"])]
+    message_stdin: bool,
+
+    /// Download the squash message from a URL instead of taking the first commit's
+    #[arg(long, value_name = "URL", conflicts_with = "split_auto")]
+    message_url: Option<String>,
+
+    /// Fold commits added since the branch was last squished into that squash commit,
+    /// keeping its message and author
+    #[arg(long, conflicts_with_all = ["split_auto", "split_markers", "split"])]
+    amend: bool,
+
+    /// Squish a protected branch (main, master or a squish.protected pattern)
+    #[arg(long)]
+    allow_protected: bool,
+
+    /// Stash local changes before squishing and re-apply them afterwards (defaults to
+    /// rebase.autoStash)
+    #[arg(long, overrides_with = "no_autostash")]
+    autostash: bool,
+
+    /// Don't stash local changes, even if rebase.autoStash is set
+    #[arg(long, overrides_with = "autostash")]
+    no_autostash: bool,
+
+    /// Fetch the upstream from its remote before squishing
+    #[arg(long)]
+    fetch: bool,
+
+    /// Squish even with uncommitted changes, leaving local modifications in place
+    #[arg(short, long)]
+    force: bool,
+
+    /// Stop on conflicts so they can be resolved and finished with `git squish continue`
+    #[arg(long)]
+    pause_on_conflict: bool,
+
+    /// Force-push the squashed branch with a lease on its remote-tracking branch
+    #[arg(long)]
+    push: bool,
+
+    /// With --push, make a branch without tracking configuration track the pushed branch
+    #[arg(short = 'u', long)]
+    set_upstream: bool,
+
+    /// Squash each group of commits recommended by `git squish plan` separately
+    #[arg(long, conflicts_with = "pause_on_conflict")]
+    split_auto: bool,
+
+    /// Squash each group of commits started by a commit whose subject begins with "!group:"
+    /// separately, taking the marker commit's message without the marker
+    #[arg(long, conflicts_with_all = [
+        "split_auto", "keep_first", "only_author", "exclude_author", "skip",
+        "skip_subject_pattern", "since_date", "until_date", "message_stdin", "message_url",
+        "pause_on_conflict",
+    ])]
+    split_markers: bool,
+
+    /// Squash the changes to paths matching PATHSPEC (e.g. 'docs/**') into a commit of their
+    /// own, with the remaining changes in a last commit (may be repeated)
+    #[arg(long, value_name = "PATHSPEC", conflicts_with_all = [
+        "split_auto", "split_markers", "keep_first", "only_author", "exclude_author", "skip",
+        "skip_subject_pattern", "since_date", "until_date", "message_stdin", "message_url",
+    ])]
+    split: Vec<String>,
+
+    /// Keep the branch's first commit (e.g. a scaffold or vendored import) as it is and
+    /// squash the rest into a second commit
+    #[arg(long, conflicts_with = "split_auto")]
+    keep_first: bool,
+
+    /// Treat paths matching PATTERN (e.g. *.lock or dist/) as generated, moving their
+    /// changes into a trailing "Regenerate artifacts" commit (may be repeated)
+    #[arg(long, value_name = "PATTERN")]
+    generated: Vec<String>,
+
+    /// End the squash message with a References: trailer listing the email Message-Ids of
+    /// the squashed commits, for patchwork/lore threading
+    #[arg(long)]
+    references: bool,
+
+    /// End the squash message with a Squashed-from: trailer giving the abbreviated id of
+    /// each original commit
+    #[arg(long)]
+    annotate_sources: bool,
+
+    /// Record the original tip and the squashed commits' ids and subjects in the squash
+    /// commit's refs/notes/squish note
+    #[arg(long)]
+    record_provenance: bool,
+
+    /// Add a custom header to the squash commit object (may be repeated)
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_commit_header)]
+    commit_header: Vec<(String, String)>,
+
+    /// Read template variables from the JSON object in FILE, whose keys fill in {key} in the
+    /// squash message and --trailer values (may be repeated, later files win)
+    #[arg(long, value_name = "FILE", value_parser = parse_context_file)]
+    context: Vec<Vec<(String, String)>>,
+
+    /// End the squash message with TRAILER (e.g. "Ticket: {ticket}"), filling in template
+    /// variables (may be repeated)
+    #[arg(long, value_name = "TRAILER", value_parser = parse_trailer)]
+    trailer: Vec<String>,
+
+    /// Fail on anything which would otherwise only be a warning (e.g. dropped empty commits)
+    #[arg(long)]
+    strict: bool,
+
+    /// Check the squashed tree matches the branch rebased onto the upstream before moving
+    /// the branch, failing otherwise
+    #[arg(long, conflicts_with_all = ["skip", "skip_subject_pattern"])]
+    verify: bool,
+
+    /// Run the pre-commit hook against the squashed tree before moving the branch (defaults
+    /// to squish.verifyHooks)
+    #[arg(long, overrides_with = "no_verify")]
+    verify_hooks: bool,
+
+    /// Don't run the pre-commit hook, even if squish.verifyHooks is set
+    #[arg(long, overrides_with = "verify_hooks")]
+    no_verify: bool,
+
+    /// Run COMMAND with the shell against the squashed tree before moving the branch,
+    /// leaving the branch alone if it fails (may be repeated)
+    #[arg(long, value_name = "COMMAND")]
+    exec: Vec<String>,
+
+    /// Pipe the squash message through COMMAND with the shell, e.g. to translate it, and
+    /// commit what it prints (overrides squish.messageCommand; empty to turn it off)
+    #[arg(long, value_name = "COMMAND")]
+    message_command: Option<String>,
+
+    /// Only squash if the upstream still points at OID, e.g. the commit CI tested against,
+    /// failing without changing anything otherwise
+    #[arg(long, value_name = "OID")]
+    expect_upstream: Option<Oid>,
+
+    /// Sign the squash commit, even if commit.gpgsign is not set, failing if the signing
+    /// key cannot be loaded
+    #[arg(short = 'S', long, overrides_with = "no_sign")]
+    sign: bool,
+
+    /// Don't sign the squash commit (nor the tag of a release), even if commit.gpgsign or
+    /// squish.signingBackend is set
+    #[arg(long, overrides_with = "sign")]
+    no_sign: bool,
+
+    /// Check the signatures of the upstream tip and the commits being squashed first,
+    /// failing on any which does not verify, or only warning with =warn
+    #[arg(
+        long,
+        value_name = "fail|warn",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "fail"
+    )]
+    verify_signatures: Option<SignatureCheck>,
+
+    /// Only squash commits whose author ("Name <email>") contains PATTERN, keeping the
+    /// others around the squash commit (may be repeated)
+    #[arg(long, value_name = "PATTERN", conflicts_with = "split_auto")]
+    only_author: Vec<String>,
+
+    /// Keep commits whose author ("Name <email>") contains PATTERN out of the squash,
+    /// replaying them around it (may be repeated)
+    #[arg(long, value_name = "PATTERN", conflicts_with = "split_auto")]
+    exclude_author: Vec<String>,
+
+    /// Drop the commit SHA from the branch instead of squashing it, like `drop` in an
+    /// interactive rebase (may be repeated)
+    #[arg(long, value_name = "SHA", conflicts_with = "split_auto")]
+    skip: Vec<String>,
+
+    /// Drop commits whose subject matches the regular expression REGEX (e.g. "^wip") from
+    /// the branch (may be repeated)
+    #[arg(long, value_name = "REGEX", value_parser = parse_regex_arg, conflicts_with = "split_auto")]
+    skip_subject_pattern: Vec<String>,
+
+    /// Only squash commits authored at or after DATE (e.g. 2024-05-01, "1 week ago"),
+    /// keeping older ones below the squash commit
+    #[arg(long, value_name = "DATE", value_parser = parse_date_arg, conflicts_with = "split_auto")]
+    since_date: Option<i64>,
+
+    /// Only squash commits authored at or before DATE, keeping newer ones on top of the
+    /// squash commit
+    #[arg(long, value_name = "DATE", value_parser = parse_date_arg, conflicts_with = "split_auto")]
+    until_date: Option<i64>,
+}
+
+/// Add the squash message given with `--message-stdin` or `--message-url` to `options`.
+fn with_message(options: SquishOptions, args: &OptionArgs) -> Result<SquishOptions, SquishError> {
+    let message = if args.message_stdin {
+        git_squish_core::read_message(std::io::stdin().lock())?
+    } else if let Some(url) = &args.message_url {
+        git_squish_integrations::fetch_message(url)?
+    } else {
+        return Ok(options);
+    };
+    Ok(options.message(message))
+}
+
+/// Parse a `--commit-header` value into the header name and value.
+fn parse_commit_header(header: &str) -> Result<(String, String), String> {
+    header
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| "expected NAME=VALUE".to_string())
+}
+
+/// Read a `--context` file: a JSON object whose string, number and boolean values become
+/// template variables.
+fn parse_context_file(path: &str) -> Result<Vec<(String, String)>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+    let Value::Object(object) = serde_json::from_str(&content).map_err(|e| e.to_string())? else {
+        return Err("expected a JSON object".to_string());
+    };
+    object
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key, value)),
+            Value::Number(_) | Value::Bool(_) => Ok((key, value.to_string())),
+            _ => Err(format!("'{key}' must be a string, number or boolean")),
+        })
+        .collect()
+}
+
+/// Check a `--trailer` value has the "Key: value" form of a git trailer.
+fn parse_trailer(trailer: &str) -> Result<String, String> {
+    match trailer.split_once(':') {
+        Some((key, _)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok(trailer.to_string())
+        }
+        _ => Err("expected \"Key: value\"".to_string()),
+    }
+}
+
+/// Check a `--skip-subject-pattern` value is a valid regular expression.
+fn parse_regex_arg(pattern: &str) -> Result<String, String> {
+    regex::Regex::new(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Parse a `--since-date`/`--until-date` value, relative to now.
+fn parse_date_arg(date: &str) -> Result<i64, String> {
+    git_squish_core::parse_date(date, unix_now()).map_err(|e| e.to_string())
+}
+
+impl OptionArgs {
+    fn to_options(&self) -> SquishOptions {
+        let mut options = SquishOptions::new()
+            .allow_empty(self.allow_empty)
+            .allow_empty_message(self.allow_empty_message)
+            .allow_protected(self.allow_protected)
+            .amend(self.amend)
+            .fetch(self.fetch)
+            .force(self.force)
+            .pause_on_conflict(self.pause_on_conflict)
+            .push(self.push)
+            .set_upstream(self.set_upstream)
+            .split_auto(self.split_auto)
+            .split_markers(self.split_markers)
+            .keep_first(self.keep_first)
+            .reference_footer(self.references)
+            .annotate_sources(self.annotate_sources)
+            .record_provenance(self.record_provenance)
+            .strict(self.strict)
+            .verify(self.verify);
+        if let Some(cleanup) = self.cleanup {
+            options = options.cleanup(cleanup);
+        }
+        options = options.normalize_message(
+            MessageNormalization::new()
+                .trailing_newline(self.trailing_newline)
+                .collapse_blank_lines(self.collapse_blank_lines)
+                .crlf_to_lf(self.crlf_to_lf)
+                .wrap_body(self.wrap_body),
+        );
+        if let Some(strategy_option) = self.strategy_option {
+            options = options.strategy_option(strategy_option);
+        }
+        if let Some(level) = self.bump {
+            options = options.tree_transformer(VersionBump::new(level));
+        }
+        if self.autostash || self.no_autostash {
+            options = options.autostash(self.autostash);
+        }
+        if self.verify_hooks || self.no_verify {
+            options = options.verify_hooks(self.verify_hooks);
+        }
+        for command in &self.exec {
+            options = options.exec(command.as_str());
+        }
+        if let Some(command) = &self.message_command {
+            options = options.message_command(command.as_str());
+        }
+        if self.sign || self.no_sign {
+            options = options.sign(self.sign);
+        }
+        if let Some(check) = self.verify_signatures {
+            options = options.verify_signatures(check);
+        }
+        if let Some(upstream_id) = self.expect_upstream {
+            options = options.expect_upstream(upstream_id);
+        }
+        for pattern in &self.only_author {
+            options = options.only_author(pattern.as_str());
+        }
+        for pattern in &self.exclude_author {
+            options = options.exclude_author(pattern.as_str());
+        }
+        for rev in &self.skip {
+            options = options.skip_commit(rev.as_str());
+        }
+        for pattern in &self.skip_subject_pattern {
+            options = options.skip_subject_pattern(pattern.as_str());
+        }
+        for pathspec in &self.split {
+            options = options.split_path(pathspec.as_str());
+        }
+        for pattern in &self.generated {
+            options = options.generated_path(pattern.as_str());
+        }
+        for (name, value) in &self.commit_header {
+            options = options.header(name, value);
+        }
+        for (name, value) in self.context.iter().flatten() {
+            options = options.template_variable(name, value);
+        }
+        for trailer in &self.trailer {
+            options = options.trailer(trailer);
+        }
+        if let Some(since_date) = self.since_date {
+            options = options.since_date(since_date);
+        }
+        if let Some(until_date) = self.until_date {
+            options = options.until_date(until_date);
+        }
+        options
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Finish a squish which paused on a conflict, once the conflicts are resolved and staged
+    Continue {
+        /// Print the outcome as a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Abandon a squish which paused on a conflict, restoring HEAD and the worktree
+    Abort,
+    /// Show the squish in progress and its unresolved conflicts
+    Status,
+    /// Point a branch back at the commits it had before it was last squished
+    Undo {
+        /// The branch to restore, defaults to the current branch
+        #[arg(value_name = "BRANCH-REFNAME")]
+        branch_refname: Option<String>,
+    },
+    /// Recreate a branch at the commits a squash replaced, from the original tip recorded
+    /// with --record-provenance
+    Restore {
+        /// The squash commit to restore the original commits of
+        #[arg(value_name = "COMMIT")]
+        commit: String,
+        /// The branch to create, defaults to the squished branch's name plus "-restored"
+        #[arg(value_name = "NEW-BRANCH")]
+        new_branch: Option<String>,
+    },
+    /// Pick the commits to squash and edit the message in a full screen picker, then squash
+    #[cfg(feature = "tui")]
+    Pick {
+        #[command(flatten)]
+        options: OptionArgs,
+
+        #[command(flatten)]
+        refs: RefArgs,
+    },
+    /// Show the commits which would be squashed, labeled by kind, suggested split points,
+    /// likely conflicts, the squash message and a diffstat, without changing anything
+    #[command(alias = "preview")]
+    Plan {
+        /// Print the plan as a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        refs: RefArgs,
+    },
+    /// Report hygiene findings on the branch (large files, secrets, unexpected authors) and
+    /// which of them squashing cleans up
+    Advise {
+        #[command(flatten)]
+        refs: RefArgs,
+    },
+    /// List local branches with ahead/behind counts and predicted conflicts
+    List {
+        /// The upstream to compare against
+        #[arg(value_name = "UPSTREAM-SPEC")]
+        upstream_spec: String,
+    },
+    /// Summarize the squishes recorded in the local branches' reflogs: how many, commits
+    /// folded per squash, conflict rate, squishes per month and the most squished branches
+    Stats {
+        /// Print the statistics as a JSON document instead of tables
+        #[arg(long)]
+        json: bool,
+        /// Only count squishes at or after DATE (e.g. 2024-05-01, "4 weeks ago")
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        since: Option<i64>,
+    },
+    /// Squash several branches, each onto the upstream on its own, reporting every branch in
+    /// the order given and exiting non-zero if any failed
+    Batch {
+        /// Only create the squashed commits, leaving the branches alone, like --queue
+        #[arg(long)]
+        queue: bool,
+        /// How many branches to squash at once with --queue, defaults to the number of CPUs
+        #[arg(long, value_name = "N", requires = "queue")]
+        jobs: Option<usize>,
+        /// Print the outcomes as a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+        /// The upstream to squash onto
+        #[arg(value_name = "UPSTREAM-SPEC")]
+        upstream_spec: String,
+        /// The branches to squash
+        #[arg(value_name = "BRANCH-REFNAME", required = true)]
+        branches: Vec<String>,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Squash a branch with the version in the message, then tag the squash commit with a
+    /// signed annotated tag named after the version
+    #[command(
+        after_help = "Examples:\n  git squish release --version v1.2.0 release main\n  git squish release --version v1.2.0 --push --message-template 'chore: release {version}' main"
+    )]
+    Release {
+        /// The version to release, which is also the tag's name (e.g. v1.2.0)
+        #[arg(long, value_name = "VERSION")]
+        version: String,
+        /// The squash message, with {version} and {message} (the usual squash message)
+        /// filled in
+        #[arg(long, value_name = "TEMPLATE")]
+        message_template: Option<String>,
+        #[command(flatten)]
+        options: OptionArgs,
+
+        #[command(flatten)]
+        refs: RefArgs,
+    },
+    /// List past squishes, newest first: when, which branch, how it moved and how many
+    /// commits were folded
+    Log {
+        /// Print the squishes as a JSON document instead of a table
+        #[arg(long)]
+        json: bool,
+        /// Only list squishes at or after DATE (e.g. 2024-05-01, "4 weeks ago")
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        since: Option<i64>,
+        /// Only list squishes of this branch
+        #[arg(value_name = "BRANCH")]
+        branch: Option<String>,
+    },
+    /// Check that a commit created with --queue is still the squash of the branch onto the
+    /// upstream tip, exiting non-zero if either has moved
+    Verify {
+        /// The squashed commit to verify
+        commit: Oid,
+        /// The branch it was created from
+        #[arg(value_name = "BRANCH-REFNAME")]
+        branch_refname: String,
+        /// The upstream it was created on
+        #[arg(value_name = "UPSTREAM-SPEC")]
+        upstream_spec: String,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Keep a branch squished onto an upstream, re-squashing whenever the upstream moves
+    Watch {
+        /// How often to poll the upstream, in seconds
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+        /// The branch to keep squished
+        #[arg(value_name = "BRANCH-REFNAME")]
+        branch_refname: String,
+        /// The upstream to watch
+        #[arg(value_name = "UPSTREAM-SPEC")]
+        upstream_spec: String,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Print a shell completion script for git-squish
+    Completions {
+        /// The shell to generate completions for
+        shell: Shell,
+    },
+}
+
+/// Writes log records, i.e. libgit2's trace output, to stderr.
+struct StderrLogger;
+
+static STDERR_LOGGER: StderrLogger = StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{} [{}] {}", record.target(), record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            // Keep exit code 2 for "already squashed" rather than clap's usage error code.
+            let _ = e.print();
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(level) = cli
+        .trace_libgit2
+        .as_deref()
+        .and_then(|level| level.parse().ok())
+        && log::set_logger(&STDERR_LOGGER).is_ok()
+    {
+        log::set_max_level(level);
+        git_squish_core::enable_libgit2_trace(level);
+    }
+
+    let json = cli.json();
+    let output = cli.output();
+    match run(cli, &output) {
+        Ok(()) => {}
+        Err(e) if json => {
+            println!("{:#}", error_json(&e));
+            std::process::exit(match e {
+                SquishError::AlreadySquashed { .. } => EXIT_ALREADY_SQUASHED,
+                SquishError::NothingToSquash { .. } => EXIT_NOTHING_TO_SQUASH,
+                _ => 1,
+            });
+        }
+        Err(e @ SquishError::AlreadySquashed { .. }) => {
+            output.info(format!("✅ {e}, nothing to do."));
+            std::process::exit(EXIT_ALREADY_SQUASHED);
+        }
+        Err(e @ SquishError::NothingToSquash { .. }) => {
+            output.error(format!("{e} (use --allow-empty to create an empty commit)"));
+            std::process::exit(EXIT_NOTHING_TO_SQUASH);
+        }
+        Err(e) => {
+            output.error(e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(cli: Cli, output: &Output) -> Result<(), SquishError> {
+    // Like git, an explicit GIT_DIR takes precedence over discovering the repository, and
+    // a relative one is resolved against -C.
+    let base = cli.repo.unwrap_or_else(|| PathBuf::from("."));
+    let repo_path = match std::env::var_os("GIT_DIR") {
+        Some(git_dir) => base.join(git_dir),
+        None => base,
+    };
+    let repo_path = repo_path.as_path();
+    if cli.force_unlock {
+        let message = git_squish_core::force_unlock(repo_path)?;
+        // On stderr, so --json output stays a single document.
+        if output.verbosity >= Verbosity::Normal {
+            eprintln!("{message}");
+        }
+    }
+
+    let Some(command) = cli.command else {
+        return squish(repo_path, cli.squish, output);
+    };
+    match command {
+        Command::Continue { json } => {
+            let result = git_squish_core::continue_squash(repo_path)?;
+            if json {
+                println!("{:#}", result_json(repo_path, &result)?);
+            } else {
+                output.squashed(repo_path, &result)?;
+            }
+        }
+        Command::Abort => output.info(git_squish_core::abort_squash(repo_path)?),
+        Command::Status => println!("{}", git_squish_core::squash_status(repo_path)?),
+        Command::Undo { branch_refname } => {
+            let branch_refname = match branch_refname {
+                Some(branch_refname) => branch_refname,
+                None => {
+                    let repo = git_squish_core::open_repository(repo_path)?;
+                    git_squish_core::current_branch_refname(&repo)?
+                }
+            };
+            output.info(git_squish_core::undo_squash(repo_path, branch_refname)?);
+        }
+        Command::Restore { commit, new_branch } => {
+            output.info(git_squish_core::restore_squash(
+                repo_path,
+                commit,
+                new_branch.as_deref(),
+            )?);
+        }
+        Command::Plan { json, refs } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let (branch_refname, upstream_spec) = resolve_refs(&repo, refs)?;
+            let commits =
+                git_squish_core::classify_commits(&repo, &branch_refname, &upstream_spec)?;
+            let groups = git_squish_core::recommend_splits(&repo, &branch_refname, &upstream_spec)?;
+            let plan = SquishPlan::compute(&repo, &branch_refname, &upstream_spec)?;
+            if json {
+                println!("{:#}", plan_json(&plan, &commits));
+                return Ok(());
+            }
+            println!("Squashing {branch_refname} onto {upstream_spec} would combine:");
+            print!("{}", git_squish_core::format_classification(&commits));
+            print!("{}", git_squish_core::format_split_recommendation(&groups));
+            if !plan.conflicts.is_empty() {
+                println!("Likely to conflict in:\n  {}", plan.conflicts.join("\n  "));
+            }
+            println!("Squash message:");
+            for line in plan.message.lines() {
+                println!("    {line}");
+            }
+            println!();
+            print!("{}", git_squish_core::format_diffstat(&plan.files));
+        }
+        #[cfg(feature = "tui")]
+        Command::Pick { options, refs } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let (branch_refname, upstream_spec) = resolve_refs(&repo, refs)?;
+            let mut squish_options = with_message(options.to_options(), &options)?;
+            let plan = SquishPlan::compute_with_options(
+                &repo,
+                &branch_refname,
+                &upstream_spec,
+                &squish_options,
+            )?;
+            let Some(picked) = tui::pick(&repo, &plan)? else {
+                output.info("Squish cancelled, nothing was changed.");
+                return Ok(());
+            };
+            for id in picked.excluded {
+                squish_options = squish_options.exclude_commit(id);
+            }
+            if let Some(message) = picked.message {
+                squish_options = squish_options.message(message);
+            }
+            let result = git_squish_core::squash_branch_with_options(
+                repo_path,
+                branch_refname,
+                upstream_spec,
+                &squish_options,
+            )?;
+            output.squashed(repo_path, &result)?;
+        }
+        Command::Advise { refs } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let (branch_refname, upstream_spec) = resolve_refs(&repo, refs)?;
+            let advice = git_squish_core::advise_branch(&repo, &branch_refname, &upstream_spec)?;
+            println!("Squashing {branch_refname} onto {upstream_spec}:");
+            print!("{}", git_squish_core::format_advice(&advice));
+        }
+        Command::List { upstream_spec } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let summaries = git_squish_core::list_squashable_branches(&repo, &upstream_spec)?;
+            print!(
+                "{}",
+                git_squish_core::format_branch_table(&summaries, unix_now())
+            );
+        }
+        Command::Stats { json, since } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let records = git_squish_core::squash_history(&repo, since)?;
+            let stats = git_squish_core::squash_stats(&records);
+            if json {
+                println!("{:#}", stats_json(&stats));
+            } else {
+                print!("{}", git_squish_core::format_stats(&stats));
+            }
+        }
+        Command::Log {
+            json,
+            since,
+            branch,
+        } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let mut records = git_squish_core::squash_history(&repo, since)?;
+            if let Some(branch) = branch {
+                let branch = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+                records.retain(|record| record.branch == branch);
+            }
+            if json {
+                println!("{:#}", log_json(&records));
+            } else if records.is_empty() {
+                output.info("No squishes found.");
+            } else {
+                print!("{}", git_squish_core::format_squash_log(&records));
+            }
+        }
+        Command::Batch {
+            queue,
+            jobs,
+            json,
+            upstream_spec,
+            branches,
+            options,
+        } => {
+            let options = options.to_options();
+            let (entries, success) = if queue {
+                let jobs = jobs
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from));
+                let result = git_squish_core::create_squash_commits(
+                    repo_path,
+                    &branches,
+                    &upstream_spec,
+                    &options,
+                    jobs,
+                )?;
+                let success = result.is_success();
+                let entries = result
+                    .entries
+                    .iter()
+                    .map(|entry| match &entry.outcome {
+                        Ok(commit_id) => {
+                            if !json {
+                                println!("{commit_id} {}", entry.branch);
+                            }
+                            json!({
+                                "status": "queued",
+                                "branch": entry.branch,
+                                "new": commit_id.to_string(),
+                            })
+                        }
+                        Err(e) => batch_failure(&entry.branch, e, json, output),
+                    })
+                    .collect();
+                (entries, success)
+            } else {
+                let result = git_squish_core::squash_branches(
+                    repo_path,
+                    &branches,
+                    &upstream_spec,
+                    &options,
+                )?;
+                let mut entries = Vec::new();
+                for entry in &result.entries {
+                    entries.push(match &entry.outcome {
+                        Ok(squashed) if json => result_json(repo_path, squashed)?,
+                        Ok(squashed) => {
+                            output.squashed(repo_path, squashed)?;
+                            Value::Null
+                        }
+                        Err(e) => batch_failure(&entry.branch, e, json, output),
+                    });
+                }
+                (entries, result.is_success())
+            };
+            if json {
+                println!("{:#}", json!({ "branches": entries }));
+            }
+            if !success {
+                std::process::exit(1);
+            }
+        }
+        Command::Release {
+            version,
+            message_template,
+            options,
+            refs,
+        } => {
+            let repo = git_squish_core::open_repository(repo_path)?;
+            let (branch_refname, upstream_spec) = resolve_refs(&repo, refs)?;
+            let mut release = git_squish_core::ReleaseOptions::new()
+                .sign_tag(!options.no_sign)
+                .push(options.push)
+                .squish(options.to_options());
+            if let Some(template) = message_template {
+                release = release.message_template(template);
+            }
+            let result = git_squish_core::release_branch(
+                repo_path,
+                &branch_refname,
+                &upstream_spec,
+                &version,
+                &release,
+            )?;
+            output.squashed(repo_path, &result.squash)?;
+            output.info(&result.message);
+        }
+        Command::Verify {
+            commit,
+            branch_refname,
+            upstream_spec,
+            options,
+        } => {
+            let verification = git_squish_core::verify_squash_commit(
+                repo_path,
+                commit,
+                &branch_refname,
+                &upstream_spec,
+                &options.to_options(),
+            )?;
+            match verification {
+                git_squish_core::SquashVerification::Valid => {
+                    output.info(format!(
+                        "✅ {} is up to date with {} and {}",
+                        output.paint(commit, style::COMMIT),
+                        output.paint(&branch_refname, style::BRANCH),
+                        output.paint(&upstream_spec, style::BRANCH)
+                    ));
+                    return Ok(());
+                }
+                git_squish_core::SquashVerification::UpstreamMoved => {
+                    eprintln!("❌ {upstream_spec} has moved since {commit} was created")
+                }
+                git_squish_core::SquashVerification::BranchChanged => {
+                    eprintln!("❌ {branch_refname} has changed since {commit} was created")
+                }
+                _ => eprintln!(
+                    "❌ {commit} is no longer the squash of {branch_refname} onto {upstream_spec}"
+                ),
+            }
+            std::process::exit(1);
+        }
+        Command::Watch {
+            interval,
+            branch_refname,
+            upstream_spec,
+            options,
+        } => {
+            let mut watcher = git_squish_core::UpstreamWatcher::new(
+                repo_path,
+                &branch_refname,
+                &upstream_spec,
+                with_message(options.to_options(), &options)?,
+            )?;
+            output.info(format!(
+                "👀 Watching {} to keep {} squished (Ctrl-C to stop)",
+                output.paint(&upstream_spec, style::BRANCH),
+                output.paint(&branch_refname, style::BRANCH)
+            ));
+            loop {
+                std::thread::sleep(std::time::Duration::from_secs(interval));
+                // Report failures and keep watching, the next upstream move may apply cleanly.
+                match watcher.poll() {
+                    Ok(Some(result)) => output.squashed(repo_path, &result)?,
+                    Ok(None) => {}
+                    Err(e) => output.error(e),
+                }
+            }
+        }
+        Command::Completions { shell } => {
+            // Generated for the git-squish binary, which git runs for `git squish`.
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "git-squish",
+                &mut std::io::stdout(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The default action: squash the branch onto the upstream.
+fn squish(repo_path: &Path, args: SquishArgs, output: &Output) -> Result<(), SquishError> {
+    let options = with_message(args.options.to_options(), &args.options)?;
+    // Prompts need stdin, so never ask anything when the message was read from it.
+    let can_prompt = !args.options.message_stdin
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal();
+    let interactive = args.refs.first.is_none() && !args.json && can_prompt;
+    let repo = git_squish_core::open_repository(repo_path)?;
+    let (branch_refname, upstream_spec) = if interactive {
+        // No arguments on a terminal, let the user pick what to squash
+        let stdin = std::io::stdin();
+        match pick_branch(&repo, &mut stdin.lock(), &mut std::io::stdout())? {
+            Some(selection) => selection,
+            None => return Ok(()),
+        }
+    } else {
+        resolve_refs(&repo, args.refs)?
+    };
+
+    // Only create the squashed commit, leaving the branch alone
+    if args.queue {
+        let commit_id = git_squish_core::create_squash_commit(
+            repo_path,
+            &branch_refname,
+            upstream_spec,
+            &options,
+        )?;
+        if args.json {
+            let document = json!({
+                "status": "queued",
+                "branch": git_squish_core::normalize_branch_refname(&repo, &branch_refname)?,
+                "new": commit_id.to_string(),
+            });
+            println!("{document:#}");
+        } else {
+            println!("{commit_id}");
+        }
+        return Ok(());
+    }
+
+    // On a terminal, show what is about to be rewritten and ask first (the picker already
+    // asked). A plan which cannot be computed is left for the squish to report.
+    if !interactive
+        && !args.yes
+        && !args.json
+        && can_prompt
+        && let Ok(plan) =
+            SquishPlan::compute_with_options(&repo, &branch_refname, &upstream_spec, &options)
+    {
+        let stdin = std::io::stdin();
+        if !confirm_squash(&repo, &plan, &mut stdin.lock(), &mut std::io::stdout())? {
+            output.info("Squish cancelled, nothing was changed.");
+            return Ok(());
+        }
+    }
+
+    // Perform the squash operation
+    let result = git_squish_core::squash_branch_with_options(
+        repo_path,
+        &branch_refname,
+        upstream_spec,
+        &options,
+    )?;
+    if args.json {
+        println!("{:#}", result_json(repo_path, &result)?);
+        return Ok(());
+    }
+    output.squashed(repo_path, &result)?;
+
+    // Show what was created
+    if let Some(format) = args.show {
+        let patch = format == "patch";
+        print!(
+            "\n{}",
+            git_squish_core::render_commit(&repo, result.new_id, patch)?
+        );
+    }
+    Ok(())
+}
+
+/// Report a branch of a batch which failed, returning its entry in the batch's JSON document.
+fn batch_failure(branch: &str, error: &SquishError, json: bool, output: &Output) -> Value {
+    if !json {
+        output.error(format!("{branch}: {error}"));
+    }
+    let mut document = error_json(error);
+    document["branch"] = json!(branch);
+    document
+}
+
+/// The JSON document describing a finished squish.
+fn result_json(repo_path: &Path, result: &SquashResult) -> Result<Value, SquishError> {
+    let repo = git_squish_core::open_repository(repo_path)?;
+    let commit = repo.find_commit(result.new_id)?;
+    Ok(json!({
+        "status": "squashed",
+        "branch": result.branch,
+        "old": result.old_id.to_string(),
+        "new": result.new_id.to_string(),
+        "commits": result.commits.iter().map(Oid::to_string).collect::<Vec<_>>(),
+        "message": commit.message().unwrap_or_default(),
+        "warnings": result.warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+    }))
+}
+
+/// The JSON document describing what squishing would do, for `plan --json`.
+fn plan_json(plan: &SquishPlan, commits: &[git_squish_core::ClassifiedCommit]) -> Value {
+    json!({
+        "branch": plan.branch,
+        "upstream": plan.upstream,
+        "upstream_id": plan.upstream_id.to_string(),
+        "merge_base": plan.merge_base.to_string(),
+        "commits": commits
+            .iter()
+            .map(|commit| json!({
+                "id": commit.id.to_string(),
+                "kind": commit.kind.as_str(),
+                "summary": commit.summary,
+            }))
+            .collect::<Vec<_>>(),
+        "message": plan.message,
+        "conflicts": plan.conflicts,
+        "files": plan
+            .files
+            .iter()
+            .map(|file| json!({
+                "path": file.path,
+                "old_path": file.old_path,
+                "insertions": file.insertions,
+                "deletions": file.deletions,
+                "binary": file.binary,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// The JSON document summarizing squash activity, for `stats --json`.
+fn stats_json(stats: &SquashStats) -> Value {
+    let counts = |counts: &[(String, usize)], key: &str| {
+        counts
+            .iter()
+            .map(|(name, count)| json!({ key: name, "squashes": count }))
+            .collect::<Vec<_>>()
+    };
+    json!({
+        "squashes": stats.squashes,
+        "average_commits": stats.average_commits,
+        "conflict_rate": stats.conflict_rate,
+        "by_month": counts(&stats.by_month, "month"),
+        "top_branches": counts(&stats.top_branches, "branch"),
+    })
+}
+
+/// The JSON document listing past squishes, newest first, for `log --json`.
+fn log_json(records: &[SquashRecord]) -> Value {
+    let squashes: Vec<Value> = records
+        .iter()
+        .rev()
+        .map(|record| {
+            json!({
+                "branch": record.branch,
+                "old": record.old_id.to_string(),
+                "new": record.new_id.to_string(),
+                "time": record.time,
+                "commits": record.commits,
+                "conflicts": record.conflicts,
+            })
+        })
+        .collect();
+    json!({ "squashes": squashes })
+}
+
+/// The JSON document describing why a squish failed (or paused), including the conflicted
+/// files if that is why.
+fn error_json(error: &SquishError) -> Value {
+    let status = match error {
+        SquishError::AlreadySquashed { .. } => "already_squashed",
+        SquishError::NothingToSquash { .. } => "nothing_to_squash",
+        SquishError::Conflict { .. } | SquishError::TrainConflict { .. } => "conflict",
+        SquishError::Paused { .. } => "paused",
+        SquishError::UpstreamMoved { .. } => "upstream_moved",
+        _ => "error",
+    };
+    let mut document = json!({
+        "status": status,
+        "error": error.to_string(),
+    });
+    if let SquishError::UpstreamMoved {
+        expected, actual, ..
+    } = error
+    {
+        document["expected"] = json!(expected.to_string());
+        document["actual"] = json!(actual.to_string());
+        return document;
+    }
+    let oid = |id: Option<Oid>| id.map(|id| id.to_string());
+    let (commit, conflicts) = match error {
+        SquishError::Conflict { commit, files }
+        | SquishError::TrainConflict { commit, files, .. } => {
+            let conflicts = files
+                .iter()
+                .map(|file| {
+                    json!({
+                        "path": file.path,
+                        "ancestor": oid(file.ancestor),
+                        "ours": oid(file.ours),
+                        "theirs": oid(file.theirs),
+                    })
+                })
+                .collect::<Vec<_>>();
+            (commit, conflicts)
+        }
+        SquishError::Paused { commit, files, .. } => {
+            let conflicts = files.iter().map(|path| json!({ "path": path })).collect();
+            (commit, conflicts)
+        }
+        _ => return document,
+    };
+    document["commit"] = json!(commit.to_string());
+    document["conflicts"] = Value::Array(conflicts);
+    document
+}
+
+/// Work out the branch and upstream from the positional arguments: both, only the upstream
+/// (squashing the current branch), or neither (the current branch onto its tracked upstream).
+fn resolve_refs(repo: &Repository, refs: RefArgs) -> Result<(String, String), SquishError> {
+    match (refs.first, refs.second) {
+        // Normalized by the library, which also accepts remote-tracking branches.
+        (Some(branch_refname), Some(upstream_spec)) => Ok((branch_refname, upstream_spec)),
+        (Some(upstream_spec), None) => Ok((
+            git_squish_core::current_branch_refname(repo)?,
+            upstream_spec,
+        )),
+        _ => {
+            let branch_refname = git_squish_core::current_branch_refname(repo)?;
+            let upstream_spec = git_squish_core::detect_upstream(repo, &branch_refname)?;
+            Ok((branch_refname, upstream_spec))
+        }
+    }
+}
+
+/// Interactively choose the upstream and the branch to squash onto it, then confirm.
+///
+/// # Returns
+/// The branch refname and upstream spec, or None if the user cancelled.
+fn pick_branch(
+    repo: &Repository,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<(String, String)>, SquishError> {
+    // Prefer what the current branch tracks, then whichever of main or master exists.
+    let default_upstream = git_squish_core::current_branch_refname(repo)
+        .and_then(|branch| git_squish_core::detect_upstream(repo, &branch))
+        .unwrap_or_else(|_| {
+            ["main", "master"]
+                .into_iter()
+                .find(|name| repo.find_branch(name, BranchType::Local).is_ok())
+                .unwrap_or("main")
+                .to_string()
+        });
+    let upstream_spec = match prompt(input, output, &format!("Upstream [{default_upstream}]: "))? {
+        Some(answer) if !answer.is_empty() => answer,
+        Some(_) => default_upstream.to_string(),
+        None => return Ok(None),
+    };
+
+    let summaries: Vec<_> = git_squish_core::list_squashable_branches(repo, &upstream_spec)?
+        .into_iter()
+        .filter(|summary| summary.ahead > 0)
+        .collect();
+    if summaries.is_empty() {
+        writeln!(
+            output,
+            "No branches have commits to squash onto {upstream_spec}."
+        )
+        .map_err(terminal_error)?;
+        return Ok(None);
+    }
+
+    // Number the rows of the list table so a branch can be picked by index.
+    let table = git_squish_core::format_branch_table(&summaries, unix_now());
+    for (index, line) in table.lines().enumerate() {
+        match index {
+            0 => writeln!(output, "     {line}").map_err(terminal_error)?,
+            _ => writeln!(output, "{index:>3}  {line}").map_err(terminal_error)?,
+        }
+    }
+
+    let summary = loop {
+        let Some(answer) = prompt(
+            input,
+            output,
+            &format!("Branch to squash [1-{}]: ", summaries.len()),
+        )?
+        else {
+            return Ok(None);
+        };
+        let picked = match answer.parse::<usize>() {
+            Ok(number) => number.checked_sub(1).and_then(|index| summaries.get(index)),
+            Err(_) => summaries.iter().find(|summary| summary.name == answer),
+        };
+        match picked {
+            Some(summary) => break summary,
+            None => writeln!(output, "No such branch: {answer}").map_err(terminal_error)?,
+        }
+    };
+
+    let question = format!("Squash {} onto {upstream_spec}? [y/N]: ", summary.name);
+    match prompt(input, output, &question)?.as_deref() {
+        Some("y" | "Y" | "yes") => Ok(Some((summary.refname.clone(), upstream_spec))),
+        _ => Ok(None),
+    }
+}
+
+/// Show the commits about to be collapsed and where they go, then ask whether to go ahead.
+/// A branch which is already squashed is not asked about, as squishing leaves it alone.
+fn confirm_squash(
+    repo: &Repository,
+    plan: &SquishPlan,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<bool, SquishError> {
+    if plan.commits.len() == 1 && plan.merge_base == plan.upstream_id {
+        return Ok(true);
+    }
+    writeln!(
+        output,
+        "Squashing {} onto {} ({:.7}) will collapse:",
+        plan.branch, plan.upstream, plan.upstream_id
+    )
+    .map_err(terminal_error)?;
+    for commit_id in &plan.commits {
+        let commit = repo.find_commit(*commit_id)?;
+        writeln!(
+            output,
+            "  {commit_id:.7}  {}",
+            commit.summary().unwrap_or_default()
+        )
+        .map_err(terminal_error)?;
+    }
+    let question = format!("Rewrite {}? [y/N]: ", plan.branch);
+    Ok(matches!(
+        prompt(input, output, &question)?.as_deref(),
+        Some("y" | "Y" | "yes")
+    ))
+}
+
+/// Ask a question and read a trimmed answer, or None at end of input.
+fn prompt(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+) -> Result<Option<String>, SquishError> {
+    write!(output, "{question}").map_err(terminal_error)?;
+    output.flush().map_err(terminal_error)?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer).map_err(terminal_error)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(answer.trim().to_string()))
+}
+
+fn terminal_error(source: std::io::Error) -> SquishError {
+    SquishError::Io {
+        message: "Failed to prompt on the terminal".to_string(),
+        source,
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git_squish_core::test_utils::{
+        change_to_branch, commit_file, create_branch, init_test_repo, topic_repo,
+    };
+    use std::io::Cursor;
+
+    /// A repository with a first commit on main, with no branches to squash.
+    fn main_repo() -> (PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    /// Run `pick_branch` with `input` typed in, returning its result and what it printed.
+    fn pick(repo: &Repository, input: &str) -> (Option<(String, String)>, String) {
+        let mut output = Vec::new();
+        let picked = pick_branch(repo, &mut Cursor::new(input), &mut output).unwrap();
+        (picked, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_pick_branch_cancels_at_end_of_input() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        assert_eq!(pick(&repo, "").0, None);
+        assert_eq!(pick(&repo, "\n").0, None);
+        assert_eq!(pick(&repo, "\n1\n").0, None);
+        assert_eq!(pick(&repo, "\n1\nn\n").0, None);
+    }
+
+    #[test]
+    fn test_pick_branch_by_number_or_name() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let picked = Some(("refs/heads/topic".to_string(), "main".to_string()));
+
+        let (by_number, output) = pick(&repo, "\n1\ny\n");
+        assert_eq!(by_number, picked);
+        assert!(output.starts_with("Upstream [main]: "), "{output}");
+        assert!(output.contains("  1  topic"), "{output}");
+        assert!(
+            output.ends_with("Squash topic onto main? [y/N]: "),
+            "{output}"
+        );
+
+        assert_eq!(pick(&repo, "main\ntopic\nyes\n").0, picked);
+    }
+
+    #[test]
+    fn test_pick_branch_asks_again_after_invalid_pick() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let (picked, output) = pick(&repo, "\n0\n7\nnope\n1\ny\n");
+
+        assert_eq!(
+            picked,
+            Some(("refs/heads/topic".to_string(), "main".to_string()))
+        );
+        for answer in ["0", "7", "nope"] {
+            assert!(output.contains(&format!("No such branch: {answer}\n")));
+        }
+        assert_eq!(output.matches("Branch to squash [1-1]: ").count(), 4);
+    }
+
+    #[test]
+    fn test_pick_branch_without_branches_to_squash() {
+        let (repo_path, _temp_dir) = main_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let (picked, output) = pick(&repo, "\n");
+
+        assert_eq!(picked, None);
+        assert!(output.ends_with("No branches have commits to squash onto main.\n"));
+    }
+
+    #[test]
+    fn test_confirm_squash() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let plan = SquishPlan::compute(&repo, "refs/heads/topic", "main").unwrap();
+        let confirm = |input: &str| {
+            let mut output = Vec::new();
+            let confirmed =
+                confirm_squash(&repo, &plan, &mut Cursor::new(input), &mut output).unwrap();
+            (confirmed, String::from_utf8(output).unwrap())
+        };
+
+        let (confirmed, output) = confirm("y\n");
+        assert!(confirmed);
+        assert!(output.starts_with("Squashing refs/heads/topic onto main ("));
+        assert!(output.contains("  Add a\n") && output.contains("  Fix a\n"));
+        assert!(output.ends_with("Rewrite refs/heads/topic? [y/N]: "));
+        assert!(!confirm("n\n").0);
+        assert!(!confirm("").0);
+    }
+
+    #[test]
+    fn test_confirm_squash_skips_squashed_branch() {
+        let (repo_path, _temp_dir) = main_repo();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Add a").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let plan = SquishPlan::compute(&repo, "refs/heads/topic", "main").unwrap();
+
+        let mut output = Vec::new();
+        let confirmed = confirm_squash(&repo, &plan, &mut Cursor::new(""), &mut output).unwrap();
+
+        assert!(confirmed);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_parse_trailer() {
+        assert_eq!(
+            parse_trailer("Ticket: {ticket}"),
+            Ok("Ticket: {ticket}".to_string())
+        );
+        assert_eq!(
+            parse_trailer("Co-authored-by:A <a@example.com>"),
+            Ok("Co-authored-by:A <a@example.com>".to_string())
+        );
+        for trailer in ["Ticket", ": ABC-123", "Fixed ticket: ABC-123"] {
+            assert!(parse_trailer(trailer).is_err(), "{trailer}");
+        }
+    }
+
+    #[test]
+    fn test_parse_context_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+
+        let path = write(
+            "context.json",
+            r#"{"ticket": "ABC-123", "build_number": 42, "release": true}"#,
+        );
+        let mut variables = parse_context_file(&path).unwrap();
+        variables.sort();
+        assert_eq!(
+            variables,
+            [
+                ("build_number".to_string(), "42".to_string()),
+                ("release".to_string(), "true".to_string()),
+                ("ticket".to_string(), "ABC-123".to_string()),
+            ]
+        );
+
+        let error = parse_context_file(&write("list.json", "[1, 2]")).unwrap_err();
+        assert_eq!(error, "expected a JSON object");
+        let error = parse_context_file(&write("nested.json", r#"{"a": {"b": 1}}"#)).unwrap_err();
+        assert_eq!(error, "'a' must be a string, number or boolean");
+        assert!(parse_context_file(&write("broken.json", "{")).is_err());
+        let missing = temp_dir.path().join("missing.json");
+        let error = parse_context_file(missing.to_str().unwrap()).unwrap_err();
+        assert!(error.starts_with("cannot read "), "{error}");
+    }
+
+    #[test]
+    fn test_error_json() {
+        let id = |byte: u8| Oid::from_bytes(&[byte; 20]).unwrap();
+
+        let document = error_json(&SquishError::AlreadySquashed {
+            branch: "refs/heads/topic".to_string(),
+            upstream: "main".to_string(),
+        });
+        assert_eq!(document["status"], "already_squashed");
+        assert!(document["error"].is_string());
+        assert!(document.get("commit").is_none());
+
+        let document = error_json(&SquishError::UpstreamMoved {
+            upstream: "main".to_string(),
+            expected: id(1),
+            actual: id(2),
+        });
+        assert_eq!(document["status"], "upstream_moved");
+        assert_eq!(document["expected"], id(1).to_string());
+        assert_eq!(document["actual"], id(2).to_string());
+
+        let document = error_json(&SquishError::Paused {
+            commit: id(3),
+            summary: "Change text".to_string(),
+            files: vec!["text.txt".to_string()],
+        });
+        assert_eq!(document["status"], "paused");
+        assert_eq!(document["commit"], id(3).to_string());
+        assert_eq!(document["conflicts"], json!([{ "path": "text.txt" }]));
+    }
+
+    #[test]
+    fn test_error_json_for_conflict() {
+        let (repo_path, _temp_dir) = main_repo();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "base.txt", "topic\n", "Topic change").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "base.txt", "main\n", "Main change").unwrap();
+
+        let error =
+            git_squish_core::squash_branch(repo_path.to_str().unwrap(), "refs/heads/topic", "main")
+                .unwrap_err();
+        let document = error_json(&error);
+
+        assert_eq!(document["status"], "conflict");
+        let SquishError::Conflict { commit, .. } = error else {
+            panic!("unexpected error: {error}");
+        };
+        assert_eq!(document["commit"], commit.to_string());
+        let conflict = &document["conflicts"][0];
+        assert_eq!(conflict["path"], "base.txt");
+        for side in ["ancestor", "ours", "theirs"] {
+            assert!(conflict[side].is_string(), "{side}");
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use git_squish_core::{SquishError, SquishPlan};
+use git2::{Oid, Repository};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+/// What the user picked: the commits to keep out of the squash and, if they edited it, the
+/// squash message.
+pub struct Picked {
+    pub excluded: Vec<Oid>,
+    pub message: Option<String>,
+}
+
+/// Show the commits of `plan` full screen, letting the user deselect commits and edit the
+/// message, until they squash (Some) or quit (None).
+pub fn pick(repo: &Repository, plan: &SquishPlan) -> Result<Option<Picked>, SquishError> {
+    let mut state = PickerState::new(repo, plan)?;
+    let mut terminal = ratatui::init();
+    let outcome = run(&mut terminal, &mut state, plan);
+    ratatui::restore();
+    match outcome.map_err(|source| SquishError::Io {
+        message: "Failed to run the commit picker".to_string(),
+        source,
+    })? {
+        Outcome::Squash => Ok(Some(state.picked())),
+        Outcome::Cancel | Outcome::Continue => Ok(None),
+    }
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    state: &mut PickerState,
+    plan: &SquishPlan,
+) -> std::io::Result<Outcome> {
+    loop {
+        terminal.draw(|frame| render(frame, state, plan))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match state.handle_key(key) {
+                Outcome::Continue => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+}
+
+fn render(frame: &mut Frame, state: &PickerState, plan: &SquishPlan) {
+    let [commits_area, message_area, help_area] = Layout::vertical([
+        Constraint::Percentage(50),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let focused = |focus| {
+        if state.focus == focus {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::DIM)
+        }
+    };
+
+    let items: Vec<ListItem> = state
+        .commits
+        .iter()
+        .map(|commit| {
+            let mark = if commit.selected { "[x]" } else { "[ ]" };
+            ListItem::new(format!("{mark} {:.7}  {}", commit.id, commit.summary))
+        })
+        .collect();
+    let commits = List::new(items)
+        .block(
+            Block::bordered()
+                .title(format!(" Squash {} onto {} ", plan.branch, plan.upstream))
+                .border_style(focused(Focus::Commits)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(state.cursor));
+    frame.render_stateful_widget(commits, commits_area, &mut list_state);
+
+    // Keep the cursor line in view once the message is taller than its box.
+    let scroll = state
+        .row
+        .saturating_sub(message_area.height.saturating_sub(3) as usize);
+    let message = Paragraph::new(
+        state
+            .message
+            .iter()
+            .map(|line| Line::raw(line.as_str()))
+            .collect::<Vec<_>>(),
+    )
+    .block(
+        Block::bordered()
+            .title(" Message ")
+            .border_style(focused(Focus::Message)),
+    )
+    .scroll((scroll as u16, 0));
+    frame.render_widget(message, message_area);
+    if state.focus == Focus::Message {
+        frame.set_cursor_position(Position::new(
+            message_area.x + 1 + state.col as u16,
+            message_area.y + 1 + (state.row - scroll) as u16,
+        ));
+    }
+
+    let help = match state.focus {
+        Focus::Commits => {
+            "↑/↓ move · space keep out of the squash · tab edit message · enter squash · q quit"
+        }
+        Focus::Message => "type to edit · tab/esc back to the commits",
+    };
+    frame.render_widget(Line::raw(help), help_area);
+}
+
+/// What to do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Continue,
+    Cancel,
+    Squash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Commits,
+    Message,
+}
+
+struct PickCommit {
+    id: Oid,
+    summary: String,
+    selected: bool,
+}
+
+/// The picker's state, kept apart from the terminal so key handling can be tested.
+struct PickerState {
+    commits: Vec<PickCommit>,
+    cursor: usize,
+    focus: Focus,
+    message: Vec<String>,
+    message_edited: bool,
+    /// Position of the cursor in the message, in lines and characters.
+    row: usize,
+    col: usize,
+}
+
+impl PickerState {
+    fn new(repo: &Repository, plan: &SquishPlan) -> Result<Self, SquishError> {
+        let commits = plan
+            .commits
+            .iter()
+            .map(|&id| {
+                Ok(PickCommit {
+                    id,
+                    summary: repo
+                        .find_commit(id)?
+                        .summary()
+                        .unwrap_or_default()
+                        .to_string(),
+                    selected: true,
+                })
+            })
+            .collect::<Result<_, SquishError>>()?;
+        let mut message: Vec<String> = plan.message.lines().map(str::to_string).collect();
+        if message.is_empty() {
+            message.push(String::new());
+        }
+        Ok(PickerState {
+            commits,
+            cursor: 0,
+            focus: Focus::Commits,
+            message,
+            message_edited: false,
+            row: 0,
+            col: 0,
+        })
+    }
+
+    fn picked(&self) -> Picked {
+        Picked {
+            excluded: self
+                .commits
+                .iter()
+                .filter(|commit| !commit.selected)
+                .map(|commit| commit.id)
+                .collect(),
+            message: self
+                .message_edited
+                .then(|| format!("{}\n", self.message.join("\n"))),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Outcome {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Outcome::Cancel;
+        }
+        match self.focus {
+            Focus::Commits => self.handle_commits_key(key.code),
+            Focus::Message => {
+                self.handle_message_key(key.code);
+                Outcome::Continue
+            }
+        }
+    }
+
+    fn handle_commits_key(&mut self, code: KeyCode) -> Outcome {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.cursor = (self.cursor + 1).min(self.commits.len().saturating_sub(1))
+            }
+            KeyCode::Char(' ') => {
+                // Something has to be squashed, so the last selected commit stays selected.
+                let selected = self.commits.iter().filter(|c| c.selected).count();
+                if let Some(commit) = self.commits.get_mut(self.cursor)
+                    && (!commit.selected || selected > 1)
+                {
+                    commit.selected = !commit.selected;
+                }
+            }
+            KeyCode::Tab => self.focus = Focus::Message,
+            KeyCode::Enter => return Outcome::Squash,
+            KeyCode::Char('q') | KeyCode::Esc => return Outcome::Cancel,
+            _ => {}
+        }
+        Outcome::Continue
+    }
+
+    fn handle_message_key(&mut self, code: KeyCode) {
+        let line_len = |state: &Self, row: usize| state.message[row].chars().count();
+        let byte_index = |line: &str, col: usize| {
+            line.char_indices()
+                .nth(col)
+                .map_or(line.len(), |(index, _)| index)
+        };
+        match code {
+            KeyCode::Tab | KeyCode::Esc => self.focus = Focus::Commits,
+            KeyCode::Char(c) => {
+                let line = &mut self.message[self.row];
+                line.insert(byte_index(line, self.col), c);
+                self.col += 1;
+                self.message_edited = true;
+            }
+            KeyCode::Enter => {
+                let line = &mut self.message[self.row];
+                let rest = line.split_off(byte_index(line, self.col));
+                self.message.insert(self.row + 1, rest);
+                self.row += 1;
+                self.col = 0;
+                self.message_edited = true;
+            }
+            KeyCode::Backspace if self.col > 0 => {
+                let line = &mut self.message[self.row];
+                line.remove(byte_index(line, self.col - 1));
+                self.col -= 1;
+                self.message_edited = true;
+            }
+            KeyCode::Backspace if self.row > 0 => {
+                let line = self.message.remove(self.row);
+                self.row -= 1;
+                self.col = line_len(self, self.row);
+                self.message[self.row].push_str(&line);
+                self.message_edited = true;
+            }
+            KeyCode::Left if self.col > 0 => self.col -= 1,
+            KeyCode::Right if self.col < line_len(self, self.row) => self.col += 1,
+            KeyCode::Up if self.row > 0 => {
+                self.row -= 1;
+                self.col = self.col.min(line_len(self, self.row));
+            }
+            KeyCode::Down if self.row + 1 < self.message.len() => {
+                self.row += 1;
+                self.col = self.col.min(line_len(self, self.row));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> PickerState {
+        let commit = |byte: u8, summary: &str| PickCommit {
+            id: Oid::from_bytes(&[byte; 20]).unwrap(),
+            summary: summary.to_string(),
+            selected: true,
+        };
+        PickerState {
+            commits: vec![commit(1, "First"), commit(2, "Second")],
+            cursor: 0,
+            focus: Focus::Commits,
+            message: vec!["First".to_string()],
+            message_edited: false,
+            row: 0,
+            col: 0,
+        }
+    }
+
+    fn press(state: &mut PickerState, codes: &[KeyCode]) -> Outcome {
+        let mut outcome = Outcome::Continue;
+        for &code in codes {
+            outcome = state.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        outcome
+    }
+
+    #[test]
+    fn test_deselect_commits() {
+        let mut state = state();
+        assert_eq!(
+            press(&mut state, &[KeyCode::Down, KeyCode::Char(' ')]),
+            Outcome::Continue
+        );
+        // The last selected commit cannot be deselected.
+        press(&mut state, &[KeyCode::Up, KeyCode::Char(' ')]);
+        assert_eq!(press(&mut state, &[KeyCode::Enter]), Outcome::Squash);
+        let picked = state.picked();
+        assert_eq!(picked.excluded, [state.commits[1].id]);
+        assert_eq!(picked.message, None);
+        assert_eq!(press(&mut state, &[KeyCode::Char('q')]), Outcome::Cancel);
+    }
+
+    #[test]
+    fn test_edit_message() {
+        let mut state = state();
+        press(
+            &mut state,
+            &[
+                KeyCode::Tab,
+                KeyCode::Right,
+                KeyCode::Right,
+                KeyCode::Right,
+                KeyCode::Right,
+                KeyCode::Right,
+                KeyCode::Char('!'),
+                KeyCode::Enter,
+                KeyCode::Enter,
+                KeyCode::Char('B'),
+                KeyCode::Char('x'),
+                KeyCode::Backspace,
+                KeyCode::Tab,
+            ],
+        );
+        assert_eq!(state.focus, Focus::Commits);
+        assert_eq!(state.picked().message.as_deref(), Some("First!\n\nB\n"));
+
+        // Backspace at the start of a line joins it onto the previous one.
+        press(
+            &mut state,
+            &[KeyCode::Tab, KeyCode::Left, KeyCode::Backspace],
+        );
+        assert_eq!(state.message, ["First!", "B"]);
+    }
+}
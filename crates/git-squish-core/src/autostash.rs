@@ -0,0 +1,121 @@
+use git2::{ErrorCode, Oid, Repository, StashFlags};
+
+use crate::{SquishError, SquishWarning};
+
+/// Whether local changes should be stashed around the squish: the `autostash` option when
+/// given, otherwise the `rebase.autoStash` config.
+pub(crate) fn is_enabled(repo: &Repository, autostash: Option<bool>) -> bool {
+    autostash.unwrap_or_else(|| {
+        repo.config()
+            .and_then(|config| config.get_bool("rebase.autoStash"))
+            .unwrap_or(false)
+    })
+}
+
+/// Stash the local changes (untracked files are left alone, like `git rebase --autostash`).
+///
+/// # Returns
+/// The id of the stash commit, or None if there was nothing to stash.
+pub(crate) fn stash(repo: &mut Repository) -> Result<Option<Oid>, SquishError> {
+    let sig = repo.signature()?;
+    match repo.stash_save(&sig, "git-squish autostash", Some(StashFlags::DEFAULT)) {
+        Ok(stash_id) => Ok(Some(stash_id)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Re-apply the stash created by `stash` and drop it.
+///
+/// # Returns
+/// A warning if the changes could not be applied and were left in the stash.
+pub(crate) fn apply(
+    repo: &mut Repository,
+    stash_id: Oid,
+) -> Result<Option<SquishWarning>, SquishError> {
+    let mut position = None;
+    repo.stash_foreach(|index, _, id| {
+        if *id == stash_id {
+            position = Some(index);
+        }
+        position.is_none()
+    })?;
+    let Some(position) = position else {
+        return Ok(Some(SquishWarning::AutostashMissing { stash: stash_id }));
+    };
+
+    // Apply and drop separately: `stash_pop` drops the stash even when it leaves conflicts.
+    match repo.stash_apply(position, None) {
+        Ok(()) if repo.index()?.has_conflicts() => {
+            return Ok(Some(SquishWarning::AutostashConflict));
+        }
+        Ok(()) => {}
+        Err(e) if matches!(e.code(), ErrorCode::Conflict | ErrorCode::MergeConflict) => {
+            return Ok(Some(SquishWarning::AutostashConflict));
+        }
+        Err(e) => return Err(e.into()),
+    }
+    repo.stash_drop(position)?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, init_test_repo};
+    use std::fs;
+
+    #[test]
+    fn test_stash_and_apply() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        let mut repo = Repository::open(&repo_path).unwrap();
+        assert!(!is_enabled(&repo, None));
+        assert!(is_enabled(&repo, Some(true)));
+
+        // A clean worktree has nothing to stash.
+        assert_eq!(stash(&mut repo).unwrap(), None);
+
+        fs::write(repo_path.join("a.txt"), "local\n").unwrap();
+        let stash_id = stash(&mut repo).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(repo_path.join("a.txt")).unwrap(), "a\n");
+
+        assert_eq!(apply(&mut repo, stash_id).unwrap(), None);
+        assert_eq!(
+            fs::read_to_string(repo_path.join("a.txt")).unwrap(),
+            "local\n"
+        );
+        // Applying again finds nothing to apply.
+        assert!(apply(&mut repo, stash_id).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_apply_conflict_keeps_stash() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        let mut repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_bool("rebase.autoStash", true)
+            .unwrap();
+        assert!(is_enabled(&repo, None));
+        assert!(!is_enabled(&repo, Some(false)));
+
+        fs::write(repo_path.join("a.txt"), "local\n").unwrap();
+        let stash_id = stash(&mut repo).unwrap().unwrap();
+        commit_file(&repo_path, "a.txt", "committed\n", "Change a").unwrap();
+        let mut repo = Repository::open(&repo_path).unwrap();
+
+        assert_eq!(
+            apply(&mut repo, stash_id).unwrap(),
+            Some(SquishWarning::AutostashConflict)
+        );
+        let mut stashes = Vec::new();
+        repo.stash_foreach(|_, _, id| {
+            stashes.push(*id);
+            true
+        })
+        .unwrap();
+        assert_eq!(stashes, vec![stash_id]);
+    }
+}
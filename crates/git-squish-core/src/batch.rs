@@ -0,0 +1,265 @@
+use git2::Oid;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    SquashResult, SquishError, SquishOptions, capabilities, create_squash_commit, open_repository,
+    squash_branch_with_options,
+};
+
+/// The outcome for one branch of a batch.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct BatchEntry<T> {
+    /// The branch, as it was given.
+    pub branch: String,
+    /// What happened to it. A failed branch does not stop the rest of the batch.
+    pub outcome: Result<T, SquishError>,
+}
+
+/// The outcomes of a batch operation, one entry per branch given.
+///
+/// Entries are always in the order the branches were given, however many branches were
+/// processed at once and whichever finished first, so reports from two runs over the same
+/// branches can be diffed line by line. A branch given twice gets two entries.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct BatchResult<T> {
+    /// The outcome for each branch, in input order.
+    pub entries: Vec<BatchEntry<T>>,
+}
+
+impl<T> BatchResult<T> {
+    /// Whether every branch succeeded.
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(|entry| entry.outcome.is_ok())
+    }
+
+    /// The entries of the branches which failed, in input order.
+    pub fn failures(&self) -> impl Iterator<Item = &BatchEntry<T>> {
+        self.entries.iter().filter(|entry| entry.outcome.is_err())
+    }
+}
+
+/// Squash several branches, each onto `upstream_spec` on its own, one after another.
+///
+/// Squishes move refs and may check out the worktree, so they never run in parallel.
+/// Conflicts fail the branch rather than pausing, since a paused squish would block the
+/// rest of the batch. The upstream is fetched once up front when `options` ask for it.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branches` - The branches to squash (e.g., "feature" or "refs/heads/feature")
+/// * `upstream_spec` - The upstream to squash onto (e.g., "main" or "origin/main")
+/// * `options` - Options used for every squash (see `SquishOptions`)
+///
+/// # Returns
+/// The outcome for each branch, in the order given, or a SquishError if the upstream
+/// could not be fetched.
+pub fn squash_branches(
+    repo_path: impl AsRef<Path>,
+    branches: &[impl AsRef<str> + Sync],
+    upstream_spec: &str,
+    options: &SquishOptions,
+) -> Result<BatchResult<SquashResult>, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let options = fetch_once(repo_path, upstream_spec, options)?.pause_on_conflict(false);
+    Ok(run_batch(branches, 1, |branch| {
+        squash_branch_with_options(repo_path, branch, upstream_spec, &options)
+    }))
+}
+
+/// Create the squashed commit for several branches, each onto `upstream_spec`, without
+/// moving any ref or touching the worktree (see `create_squash_commit`).
+///
+/// Nothing but objects is written, so up to `jobs` branches are squashed at once (one at a
+/// time if libgit2 was built without thread support). The result is in input order all the
+/// same.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branches` - The branches to squash (e.g., "feature" or "refs/heads/feature")
+/// * `upstream_spec` - The upstream to squash onto (e.g., "main" or "origin/main")
+/// * `options` - Options used for every squash (see `SquishOptions`)
+/// * `jobs` - How many branches to squash at once, at least 1
+///
+/// # Returns
+/// The squashed commit for each branch, in the order given, or a SquishError if the
+/// upstream could not be fetched.
+pub fn create_squash_commits(
+    repo_path: impl AsRef<Path>,
+    branches: &[impl AsRef<str> + Sync],
+    upstream_spec: &str,
+    options: &SquishOptions,
+    jobs: usize,
+) -> Result<BatchResult<Oid>, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let options = fetch_once(repo_path, upstream_spec, options)?;
+    let jobs = if capabilities().threads { jobs } else { 1 };
+    Ok(run_batch(branches, jobs, |branch| {
+        create_squash_commit(repo_path, branch, upstream_spec, &options)
+    }))
+}
+
+/// Fetch the upstream if `options` ask for it, returning options which do not fetch again
+/// for every branch.
+fn fetch_once(
+    repo_path: &Path,
+    upstream_spec: &str,
+    options: &SquishOptions,
+) -> Result<SquishOptions, SquishError> {
+    if options.fetch {
+        let repo = open_repository(repo_path)?;
+        crate::remote::fetch_upstream(&repo, upstream_spec)?;
+    }
+    Ok(options.clone().fetch(false))
+}
+
+/// Run `operation` for every branch on up to `jobs` threads, collecting the outcomes in
+/// input order.
+fn run_batch<T: Send>(
+    branches: &[impl AsRef<str> + Sync],
+    jobs: usize,
+    operation: impl Fn(&str) -> Result<T, SquishError> + Sync,
+) -> BatchResult<T> {
+    let next = AtomicUsize::new(0);
+    let outcomes: Vec<Mutex<Option<Result<T, SquishError>>>> =
+        branches.iter().map(|_| Mutex::new(None)).collect();
+    let work = || {
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let Some(branch) = branches.get(index) else {
+                break;
+            };
+            let outcome = operation(branch.as_ref());
+            *outcomes[index].lock().unwrap() = Some(outcome);
+        }
+    };
+    std::thread::scope(|scope| {
+        for _ in 1..jobs.clamp(1, branches.len().max(1)) {
+            scope.spawn(work);
+        }
+        work();
+    });
+
+    let entries = branches
+        .iter()
+        .zip(outcomes)
+        .map(|(branch, outcome)| BatchEntry {
+            branch: branch.as_ref().to_string(),
+            outcome: outcome
+                .into_inner()
+                .unwrap()
+                .expect("every branch is processed"),
+        })
+        .collect();
+    BatchResult { entries }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    /// A repo with main and the given branches off it, each adding two commits.
+    fn batch_repo(branches: &[&str]) -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        for branch in branches {
+            change_to_branch(&repo_path, "main").unwrap();
+            create_branch(&repo_path, branch).unwrap();
+            for step in 1..=2 {
+                let file = format!("{branch}-{step}.txt");
+                commit_file(&repo_path, &file, branch, &format!("{branch} {step}")).unwrap();
+            }
+        }
+        change_to_branch(&repo_path, "main").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_run_batch_keeps_input_order() {
+        let branches: Vec<String> = (0..32).map(|i| format!("branch-{i}")).collect();
+        let result = run_batch(&branches, 8, |branch| {
+            let index: u64 = branch.trim_start_matches("branch-").parse().unwrap();
+            // Let later branches finish first.
+            std::thread::sleep(std::time::Duration::from_millis(32 - index));
+            if index.is_multiple_of(5) {
+                Err(SquishError::Other {
+                    message: branch.to_string(),
+                })
+            } else {
+                Ok(index)
+            }
+        });
+
+        let order: Vec<&str> = result.entries.iter().map(|e| e.branch.as_str()).collect();
+        assert_eq!(order, branches);
+        for (index, entry) in result.entries.iter().enumerate() {
+            match &entry.outcome {
+                Ok(value) => assert_eq!(*value, index as u64),
+                Err(e) => assert_eq!(e.to_string(), entry.branch),
+            }
+        }
+        let failures: Vec<&str> = result.failures().map(|e| e.branch.as_str()).collect();
+        assert_eq!(failures[..3], ["branch-0", "branch-5", "branch-10"]);
+        assert!(!result.is_success());
+    }
+
+    #[test]
+    fn test_create_squash_commits_in_parallel() {
+        let (repo_path, _temp_dir) = batch_repo(&["c", "a", "b"]);
+        let branches = ["c", "missing", "a", "b", "c"];
+
+        let result =
+            create_squash_commits(&repo_path, &branches, "main", &SquishOptions::new(), 4).unwrap();
+
+        let repo = open_repository(&repo_path).unwrap();
+        let order: Vec<&str> = result.entries.iter().map(|e| e.branch.as_str()).collect();
+        assert_eq!(order, branches);
+        for entry in &result.entries {
+            if entry.branch == "missing" {
+                assert!(entry.outcome.is_err());
+                continue;
+            }
+            let commit = repo.find_commit(*entry.outcome.as_ref().unwrap()).unwrap();
+            assert_eq!(
+                commit.summary(),
+                Some(format!("{} 1", entry.branch).as_str())
+            );
+        }
+        // The same branch squashes to the same tree wherever it appears.
+        let tree = |index: usize| {
+            let id = *result.entries[index].outcome.as_ref().unwrap();
+            repo.find_commit(id).unwrap().tree_id()
+        };
+        assert_eq!(tree(0), tree(4));
+    }
+
+    #[test]
+    fn test_squash_branches_continues_after_failure() {
+        let (repo_path, _temp_dir) = batch_repo(&["b", "a"]);
+
+        let result = squash_branches(
+            &repo_path,
+            &["b", "main", "a"],
+            "main",
+            &SquishOptions::new(),
+        )
+        .unwrap();
+
+        let order: Vec<&str> = result.entries.iter().map(|e| e.branch.as_str()).collect();
+        assert_eq!(order, ["b", "main", "a"]);
+        assert_eq!(
+            result.entries[0].outcome.as_ref().unwrap().branch,
+            "refs/heads/b"
+        );
+        assert!(result.entries[1].outcome.is_err());
+        assert_eq!(
+            result.entries[2].outcome.as_ref().unwrap().branch,
+            "refs/heads/a"
+        );
+        assert_eq!(result.failures().count(), 1);
+    }
+}
@@ -0,0 +1,108 @@
+use git2::Version;
+
+use crate::SquishError;
+
+/// What the libgit2 git-squish is linked against supports. Distribution packages of
+/// libgit2 are sometimes built without SSH or TLS, which otherwise only shows up as a
+/// confusing transport error halfway through a fetch or push.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The libgit2 version as `(major, minor, revision)`.
+    pub libgit2_version: (u32, u32, u32),
+    /// Whether libgit2 can reach `https://` remotes.
+    pub https: bool,
+    /// Whether libgit2 can reach `ssh://` and `user@host:path` remotes.
+    pub ssh: bool,
+    /// Whether libgit2 is safe to use from several threads at once.
+    pub threads: bool,
+}
+
+/// Report what the linked libgit2 supports.
+///
+/// # Example
+/// ```
+/// let capabilities = git_squish_core::capabilities();
+/// if !capabilities.ssh {
+///     eprintln!("SSH remotes are not supported, use https remotes instead");
+/// }
+/// ```
+pub fn capabilities() -> Capabilities {
+    let version = Version::get();
+    Capabilities {
+        libgit2_version: version.libgit2_version(),
+        https: version.https(),
+        ssh: version.ssh(),
+        threads: version.threads(),
+    }
+}
+
+/// Fail with `UnsupportedTransport` if reaching `url` needs a transport `capabilities`
+/// lacks. Local paths, `file://`, `http://` and `git://` URLs never do.
+pub(crate) fn ensure_transport(url: &str, capabilities: &Capabilities) -> Result<(), SquishError> {
+    let transport = match url.split_once("://") {
+        Some((scheme, _)) => match scheme.to_ascii_lowercase().as_str() {
+            "https" if !capabilities.https => "HTTPS",
+            "ssh" | "ssh+git" | "git+ssh" if !capabilities.ssh => "SSH",
+            _ => return Ok(()),
+        },
+        // scp-like syntax (`git@github.com:org/repo.git`) is SSH, as long as the colon
+        // comes before any slash and is not a drive letter (`C:\repo`).
+        None => match url.split_once(':') {
+            Some((host, _)) if !capabilities.ssh && !host.contains('/') && host.len() > 1 => "SSH",
+            _ => return Ok(()),
+        },
+    };
+    Err(SquishError::UnsupportedTransport {
+        url: url.to_string(),
+        transport: transport.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_transport() {
+        let none = Capabilities {
+            libgit2_version: (1, 8, 1),
+            https: false,
+            ssh: false,
+            threads: true,
+        };
+        let all = Capabilities {
+            https: true,
+            ssh: true,
+            ..none
+        };
+        let missing = |url: &str| match ensure_transport(url, &none) {
+            Err(SquishError::UnsupportedTransport { transport, .. }) => Some(transport),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(()) => None,
+        };
+
+        assert_eq!(missing("https://github.com/org/repo.git").unwrap(), "HTTPS");
+        assert_eq!(missing("ssh://git@github.com/org/repo.git").unwrap(), "SSH");
+        assert_eq!(missing("git+ssh://github.com/org/repo.git").unwrap(), "SSH");
+        assert_eq!(missing("git@github.com:org/repo.git").unwrap(), "SSH");
+        assert_eq!(missing("http://example.com/repo.git"), None);
+        assert_eq!(missing("git://example.com/repo.git"), None);
+        assert_eq!(missing("file:///srv/repo.git"), None);
+        assert_eq!(missing("/srv/repo.git"), None);
+        assert_eq!(missing("../repo:with-colon"), None);
+        assert_eq!(missing("C:\\repo"), None);
+        for url in [
+            "https://github.com/org/repo.git",
+            "git@github.com:org/repo.git",
+        ] {
+            assert!(ensure_transport(url, &all).is_ok());
+        }
+
+        let error = ensure_transport("git@github.com:org/repo.git", &none).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot reach git@github.com:org/repo.git, libgit2 was built without SSH support; use an https remote instead"
+        );
+    }
+}
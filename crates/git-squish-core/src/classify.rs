@@ -0,0 +1,288 @@
+use git2::{Commit, Repository};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{SquishError, find_branch, find_upstream};
+
+/// What kind of change a commit makes, as shown in previews.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum CommitKind {
+    /// A new feature (`feat:`)
+    Feature,
+    /// A bug fix (`fix:`)
+    Fix,
+    /// Restructuring without behavior changes (`refactor:`, `perf:`, `style:`)
+    Refactor,
+    /// Test-only changes (`test:`, or only test files touched)
+    Test,
+    /// Documentation-only changes (`docs:`, or only documentation touched)
+    Docs,
+    /// Build, CI and housekeeping (`chore:`, `build:`, `ci:`, or only such files touched)
+    Chore,
+    /// Anything which could not be classified
+    Other,
+}
+
+impl CommitKind {
+    /// The label used for this kind in previews.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommitKind::Feature => "feature",
+            CommitKind::Fix => "fix",
+            CommitKind::Refactor => "refactor",
+            CommitKind::Test => "test",
+            CommitKind::Docs => "docs",
+            CommitKind::Chore => "chore",
+            CommitKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for CommitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A commit in the range to squash together with its classification.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ClassifiedCommit {
+    /// The commit id.
+    pub id: git2::Oid,
+    /// The commit subject.
+    pub summary: String,
+    /// What kind of change the commit makes.
+    pub kind: CommitKind,
+}
+
+/// Classify every commit which squishing `branch_refname` onto `upstream_spec` would fold
+/// together, oldest first.
+///
+/// Commits are classified by their conventional commit prefix (`feat:`, `fix(ui):`, ...)
+/// and otherwise by the paths they touch (only tests, only docs, only build files).
+pub fn classify_commits(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+) -> Result<Vec<ClassifiedCommit>, SquishError> {
+    let branch_tip = find_branch(repo, branch_refname)?.peel_to_commit()?;
+    let upstream = find_upstream(repo, upstream_spec)?.peel_to_commit()?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(branch_tip.id())?;
+    revwalk.hide(upstream.id())?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+    let mut commits = Vec::new();
+    for commit_id in revwalk {
+        let commit = repo.find_commit(commit_id?)?;
+        commits.push(ClassifiedCommit {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            kind: classify_commit(repo, &commit)?,
+        });
+    }
+    Ok(commits)
+}
+
+/// Render classified commits as a list followed by a per-kind breakdown.
+pub fn format_classification(commits: &[ClassifiedCommit]) -> String {
+    let mut out = String::new();
+    for commit in commits {
+        out.push_str(&format!(
+            "{:.7}  {:<8}  {}\n",
+            commit.id, commit.kind, commit.summary
+        ));
+    }
+
+    let mut counts: Vec<(CommitKind, usize)> = Vec::new();
+    for commit in commits {
+        match counts.iter_mut().find(|(kind, _)| *kind == commit.kind) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((commit.kind, 1)),
+        }
+    }
+    counts.sort();
+    let breakdown: Vec<String> = counts
+        .iter()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .collect();
+    out.push_str(&format!(
+        "{} commit{}: {}\n",
+        commits.len(),
+        if commits.len() == 1 { "" } else { "s" },
+        breakdown.join(", ")
+    ));
+    out
+}
+
+fn classify_commit(repo: &Repository, commit: &Commit) -> Result<CommitKind, SquishError> {
+    if let Some(kind) = conventional_kind(commit.summary().unwrap_or_default()) {
+        return Ok(kind);
+    }
+
+    let paths = changed_paths(repo, commit)?;
+    if paths.is_empty() {
+        return Ok(CommitKind::Other);
+    }
+
+    let kind = [
+        (CommitKind::Test, is_test_path as fn(&Path) -> bool),
+        (CommitKind::Docs, is_docs_path),
+        (CommitKind::Chore, is_chore_path),
+    ]
+    .into_iter()
+    .find(|(_, matches)| paths.iter().all(|path| matches(path)))
+    .map(|(kind, _)| kind);
+    Ok(kind.unwrap_or(CommitKind::Other))
+}
+
+/// The paths a commit changes relative to its first parent.
+pub(crate) fn changed_paths(
+    repo: &Repository,
+    commit: &Commit,
+) -> Result<Vec<PathBuf>, SquishError> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+        .map(Path::to_path_buf)
+        .collect())
+}
+
+/// Parse a conventional commit subject like `feat(parser)!: ...`.
+fn conventional_kind(summary: &str) -> Option<CommitKind> {
+    let (prefix, _) = summary.split_once(':')?;
+    let commit_type = prefix.split(['(', '!']).next()?.trim().to_lowercase();
+    let kind = match commit_type.as_str() {
+        "feat" | "feature" => CommitKind::Feature,
+        "fix" | "bugfix" => CommitKind::Fix,
+        "refactor" | "perf" | "style" => CommitKind::Refactor,
+        "test" | "tests" => CommitKind::Test,
+        "docs" | "doc" => CommitKind::Docs,
+        "chore" | "build" | "ci" | "deps" => CommitKind::Chore,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn is_test_path(path: &Path) -> bool {
+    let in_test_dir = path.components().any(|component| {
+        matches!(
+            component.as_os_str().to_str(),
+            Some("test" | "tests" | "__tests__" | "spec")
+        )
+    });
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let stem = file_name.split('.').next().unwrap_or_default();
+    in_test_dir
+        || stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || file_name.contains(".test.")
+        || file_name.contains(".spec.")
+}
+
+fn is_docs_path(path: &Path) -> bool {
+    path.starts_with("docs")
+        || path.starts_with("doc")
+        || matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("md" | "rst" | "adoc")
+        )
+}
+
+fn is_chore_path(path: &Path) -> bool {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    path.starts_with(".github")
+        || path.starts_with(".cargo")
+        || matches!(
+            file_name,
+            "Cargo.toml"
+                | "Cargo.lock"
+                | "package.json"
+                | "package-lock.json"
+                | "Makefile"
+                | "Dockerfile"
+                | ".gitignore"
+                | ".gitattributes"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    #[test]
+    fn test_conventional_kind() {
+        assert_eq!(
+            conventional_kind("feat: add login"),
+            Some(CommitKind::Feature)
+        );
+        assert_eq!(conventional_kind("fix(ui)!: crash"), Some(CommitKind::Fix));
+        assert_eq!(
+            conventional_kind("perf: faster"),
+            Some(CommitKind::Refactor)
+        );
+        assert_eq!(conventional_kind("CI: cache deps"), Some(CommitKind::Chore));
+        assert_eq!(conventional_kind("Add login"), None);
+        assert_eq!(conventional_kind("Note: something"), None);
+    }
+
+    #[test]
+    fn test_path_heuristics() {
+        assert!(is_test_path(Path::new("tests/cli.rs")));
+        assert!(is_test_path(Path::new("src/parser_test.go")));
+        assert!(is_test_path(Path::new("web/app.spec.ts")));
+        assert!(!is_test_path(Path::new("src/latest.rs")));
+        assert!(is_docs_path(Path::new("README.md")));
+        assert!(is_docs_path(Path::new("docs/guide.html")));
+        assert!(is_chore_path(Path::new("Cargo.toml")));
+        assert!(is_chore_path(Path::new(".github/workflows/ci.yml")));
+        assert!(!is_chore_path(Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn test_classify_commits() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "src/login.rs", "login\n", "feat: add login").unwrap();
+        commit_file(&repo_path, "tests/login.rs", "test\n", "Cover login").unwrap();
+        commit_file(&repo_path, "README.md", "docs\n", "Describe login").unwrap();
+        commit_file(&repo_path, "src/login.rs", "fixed\n", "Tweak login").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let commits = classify_commits(&repo, "refs/heads/topic", "main").unwrap();
+        let kinds: Vec<CommitKind> = commits.iter().map(|commit| commit.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CommitKind::Feature,
+                CommitKind::Test,
+                CommitKind::Docs,
+                CommitKind::Other
+            ]
+        );
+
+        let formatted = format_classification(&commits);
+        assert!(formatted.contains(&format!(
+            "{:.7}  feature   feat: add login\n",
+            commits[0].id
+        )));
+        assert!(formatted.ends_with("4 commits: 1 feature, 1 test, 1 docs, 1 other\n"));
+    }
+}
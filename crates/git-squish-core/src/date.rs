@@ -0,0 +1,189 @@
+use crate::SquishError;
+
+/// Parse a point in time given on the command line, in seconds since the Unix epoch.
+///
+/// Accepted forms are `@<seconds>`, an ISO 8601 date (`2024-05-01`, midnight UTC), date
+/// and time (`2024-05-01 13:30`, `2024-05-01T13:30:00+02:00`, UTC unless an offset or `Z`
+/// is given) and relative times like `2 weeks ago`, measured back from `now`.
+pub fn parse_date(date: &str, now: i64) -> Result<i64, SquishError> {
+    let date = date.trim();
+    let invalid = || SquishError::Other {
+        message: format!(
+            "Invalid date '{date}', expected e.g. 2024-05-01, 2024-05-01T13:30:00Z, @1714521600 or '2 weeks ago'"
+        ),
+    };
+
+    if let Some(seconds) = date.strip_prefix('@') {
+        return seconds.parse().map_err(|_| invalid());
+    }
+    if let Some(ago) = date.strip_suffix(" ago") {
+        return parse_relative(ago)
+            .map(|seconds| now - seconds)
+            .ok_or_else(invalid);
+    }
+    parse_iso(date).ok_or_else(invalid)
+}
+
+/// Seconds in a relative duration like "3 days".
+fn parse_relative(duration: &str) -> Option<i64> {
+    let (value, unit) = duration.trim().split_once(' ')?;
+    let value: i64 = value.parse().ok()?;
+    let unit = unit.trim();
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    let seconds = match unit {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 3_600,
+        "day" => 86_400,
+        "week" => 604_800,
+        "month" => 2_592_000,
+        "year" => 31_536_000,
+        _ => return None,
+    };
+    value.checked_mul(seconds)
+}
+
+/// Seconds since the epoch of an ISO 8601 date with optional time and UTC offset.
+fn parse_iso(date: &str) -> Option<i64> {
+    let (day, time) = match date.split_once(['T', ' ']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date, None),
+    };
+
+    let mut parts = day.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) * 86_400;
+
+    if let Some(time) = time {
+        let (time, offset) = split_offset(time)?;
+        let mut parts = time.splitn(3, ':');
+        let hour: i64 = parts.next()?.parse().ok()?;
+        let minute: i64 = parts.next()?.parse().ok()?;
+        let second: i64 = match parts.next() {
+            Some(second) => second.parse().ok()?,
+            None => 0,
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        seconds += hour * 3_600 + minute * 60 + second - offset;
+    }
+    Some(seconds)
+}
+
+/// Split a trailing `Z`, `+HH:MM`, `-HH:MM` or `+HHMM` from a time, returning the offset
+/// from UTC in seconds.
+fn split_offset(time: &str) -> Option<(&str, i64)> {
+    if let Some(time) = time.strip_suffix('Z') {
+        return Some((time, 0));
+    }
+    let Some(index) = time.rfind(['+', '-']) else {
+        return Some((time, 0));
+    };
+    let (time, offset) = time.split_at(index);
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+    let digits = offset[1..].replace(':', "");
+    if digits.len() != 4 {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some((time, sign * (hours * 3_600 + minutes * 60)))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The UTC month (e.g. "2024-05") of a time in seconds since the Unix epoch.
+pub(crate) fn format_month(time: i64) -> String {
+    let (year, month, _) = civil_from_days(time.div_euclid(86_400));
+    format!("{year:04}-{month:02}")
+}
+
+/// The UTC date and time (e.g. "2024-05-01 13:30") of a time in seconds since the Unix
+/// epoch.
+pub(crate) fn format_datetime(time: i64) -> String {
+    let (year, month, day) = civil_from_days(time.div_euclid(86_400));
+    let minutes = time.rem_euclid(86_400) / 60;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// The proleptic Gregorian date of a day since 1970-01-01, the inverse of
+/// `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let now = 1_714_521_600; // 2024-05-01T00:00:00Z
+        assert_eq!(parse_date("@1714521600", now).unwrap(), now);
+        assert_eq!(parse_date("2024-05-01", now).unwrap(), now);
+        assert_eq!(parse_date("1970-01-01", now).unwrap(), 0);
+        assert_eq!(parse_date("2000-03-01", now).unwrap(), 951_868_800);
+        assert_eq!(
+            parse_date("2024-05-01T13:30:00Z", now).unwrap(),
+            now + 13 * 3_600 + 30 * 60
+        );
+        assert_eq!(parse_date("2024-05-01 13:30", now).unwrap(), now + 48_600);
+        assert_eq!(parse_date("2024-05-01T02:00:00+02:00", now).unwrap(), now);
+        assert_eq!(parse_date("2024-04-30T19:00-0500", now).unwrap(), now);
+        assert_eq!(parse_date("2 weeks ago", now).unwrap(), now - 1_209_600);
+        assert_eq!(parse_date("1 day ago", now).unwrap(), now - 86_400);
+
+        for invalid in ["", "yesterday", "2024-13-01", "2024-05", "3 fortnights ago"] {
+            assert!(parse_date(invalid, now).is_err(), "{invalid} parsed");
+        }
+    }
+
+    #[test]
+    fn test_format_month() {
+        assert_eq!(format_month(0), "1970-01");
+        assert_eq!(format_month(1_714_521_600), "2024-05");
+        assert_eq!(format_month(1_714_521_599), "2024-04");
+        assert_eq!(format_month(951_868_800 - 1), "2000-02");
+        assert_eq!(format_month(-1), "1969-12");
+        assert_eq!(format_datetime(1_714_570_200), "2024-05-01 13:30");
+        assert_eq!(format_datetime(-60), "1969-12-31 23:59");
+        for days in [-800_000, -1, 0, 59, 11_016, 19_844, 2_000_000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
@@ -0,0 +1,662 @@
+use git2::{ErrorClass, ErrorCode, Index, Oid};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::SquishWarning;
+
+/// Message shown when the squish stops on a conflict.
+const CONFLICT_MESSAGE: &str = "There was a conflict during this squish, please retry using git rebase -i and resolve the conflicts";
+
+/// A file which could not be merged automatically, with the blob ids of each side.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ConflictedFile {
+    /// Path of the file relative to the repository root
+    pub path: String,
+    /// Blob id of the common ancestor version, if the file existed there
+    pub ancestor: Option<Oid>,
+    /// Blob id of the upstream ("ours") version, if the file exists there
+    pub ours: Option<Oid>,
+    /// Blob id of the branch ("theirs") version, if the file exists there
+    pub theirs: Option<Oid>,
+}
+
+impl ConflictedFile {
+    /// Collect the conflicted files from a merge index.
+    pub(crate) fn from_index(index: &Index) -> Result<Vec<ConflictedFile>, git2::Error> {
+        let mut files = Vec::new();
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let path = [&conflict.our, &conflict.their, &conflict.ancestor]
+                .into_iter()
+                .flatten()
+                .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                .next()
+                .unwrap_or_default();
+            files.push(ConflictedFile {
+                path,
+                ancestor: conflict.ancestor.map(|entry| entry.id),
+                ours: conflict.our.map(|entry| entry.id),
+                theirs: conflict.their.map(|entry| entry.id),
+            });
+        }
+        Ok(files)
+    }
+}
+
+/// Custom error type for git-squish operations
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SquishError {
+    /// The branch to squash does not exist
+    BranchNotFound {
+        /// The branch reference which was looked up
+        branch: String,
+        source: git2::Error,
+    },
+    /// A short branch name matches several references, e.g. a branch and a tag
+    AmbiguousBranch {
+        /// The name which was given
+        branch: String,
+        /// The full refnames it could mean
+        candidates: Vec<String>,
+    },
+    /// The upstream could not be resolved to a commit
+    UpstreamNotFound {
+        /// The upstream spec which was looked up
+        upstream: String,
+        source: git2::Error,
+    },
+    /// A commit could not be applied onto the upstream without conflicts
+    Conflict {
+        /// The original commit which failed to apply
+        commit: Oid,
+        /// The files which conflicted
+        files: Vec<ConflictedFile>,
+    },
+    /// A commit conflicted with `pause_on_conflict` set, so the squish paused with the
+    /// conflicts written to the worktree, waiting for `continue_squash` or `abort_squash`
+    Paused {
+        /// The original commit which failed to apply
+        commit: Oid,
+        /// Its subject
+        summary: String,
+        /// Paths of the conflicted files
+        files: Vec<String>,
+    },
+    /// A branch in a train could not be applied on top of the branches before it
+    TrainConflict {
+        /// The branch which introduced the conflict
+        branch: String,
+        /// The original commit which failed to apply
+        commit: Oid,
+        /// The files which conflicted
+        files: Vec<ConflictedFile>,
+    },
+    /// Local changes in the worktree would be overwritten by the squish
+    DirtyWorktree {
+        /// Paths with uncommitted changes
+        files: Vec<String>,
+    },
+    /// The branch and the upstream have no common ancestor, so rebasing would replay the
+    /// branch's whole history
+    UnrelatedHistories {
+        /// The branch which was squished
+        branch: String,
+        /// The upstream it shares no history with
+        upstream: String,
+    },
+    /// The branch moved after a `SquishPlan` was computed, so executing it would not do
+    /// what was previewed
+    PlanOutdated {
+        /// The branch the plan was computed for
+        branch: String,
+        /// The upstream the plan was computed for
+        upstream: String,
+    },
+    /// Something committed to the branch while it was being squished, so it was left alone
+    /// rather than losing those commits
+    BranchMoved {
+        /// The branch which was squished
+        branch: String,
+        /// The tip the squish started from
+        expected: Oid,
+        /// Where the branch points now
+        actual: Oid,
+    },
+    /// The upstream no longer points where the squish expected it to (see
+    /// `SquishOptions::expect_upstream`), so the branch was not squashed onto a stale base
+    UpstreamMoved {
+        /// The upstream as given
+        upstream: String,
+        /// Where it was expected to point
+        expected: Oid,
+        /// Where it points now
+        actual: Oid,
+    },
+    /// The squash message is empty once cleaned up and `allow_empty_message` is not set
+    EmptyMessage,
+    /// The branch has no commits which are not already on the upstream
+    NothingToSquash {
+        /// The branch which was squished
+        branch: String,
+    },
+    /// The branch is already a single commit on top of the upstream, so squishing would
+    /// not change anything
+    AlreadySquashed {
+        /// The branch which was squished
+        branch: String,
+        /// The upstream it is already squashed onto
+        upstream: String,
+    },
+    /// The branch matches a protected branch pattern and rewriting it was not allowed
+    ProtectedBranch {
+        /// The branch which was squished
+        branch: String,
+        /// The protected branch pattern it matched
+        pattern: String,
+    },
+    /// Pushing the squashed branch was rejected, e.g. because the remote branch moved since
+    /// it was last fetched
+    PushRejected {
+        /// The branch (or tag) which was pushed
+        branch: String,
+        /// The remote it was pushed to
+        remote: String,
+        /// Why the push was rejected
+        reason: String,
+    },
+    /// Strict mode is on and the squish ran into issues which would otherwise be warnings
+    Strict {
+        /// The issues which stopped the squish
+        warnings: Vec<SquishWarning>,
+    },
+    /// No committer identity is configured (`user.name` and `user.email`), so no commit
+    /// can be written
+    MissingIdentity { source: git2::Error },
+    /// Another squish is running in the repository, or a git lock file shows another git
+    /// process is running (or crashed without cleaning up)
+    Locked {
+        /// The lock file which is in the way
+        path: PathBuf,
+        /// The process holding the squish lock, None for a git lock file
+        pid: Option<u32>,
+        /// When the squish lock was taken, in seconds since the Unix epoch, if known
+        since: Option<i64>,
+    },
+    /// The repository uses a format extension (`extensions.*` config) which squishing
+    /// cannot handle safely, such as reftable ref storage or SHA-256 object ids
+    UnsupportedExtension {
+        /// The extension, lowercased like git config keys (e.g. "refstorage")
+        extension: String,
+        /// Its configured value (e.g. "reftable")
+        value: String,
+    },
+    /// A remote's URL needs a transport the linked libgit2 was built without (see
+    /// `capabilities`)
+    UnsupportedTransport {
+        /// The remote's URL
+        url: String,
+        /// The missing transport, "SSH" or "HTTPS"
+        transport: String,
+    },
+    /// The squash does not add up to the branch's content: the tree of the squash commit
+    /// differs from the tree of the branch rebased onto the upstream (see `verify_squash`)
+    TreeMismatch {
+        /// The squash commit which was checked
+        commit: Oid,
+        /// The tree of the rebased branch
+        expected: Oid,
+        /// The tree of the squash commit
+        actual: Oid,
+    },
+    /// A git hook run against the squash (see `SquishOptions::verify_hooks`) rejected it
+    HookFailed {
+        /// The hook (e.g. "pre-commit")
+        hook: String,
+        /// What the hook printed
+        output: String,
+    },
+    /// A command run against the squashed tree (see `SquishOptions::exec`) failed
+    ExecFailed {
+        /// The command
+        command: String,
+        /// What the command printed
+        output: String,
+    },
+    /// A commit's signature did not verify (see `SquishOptions::verify_signatures`)
+    UnverifiedSignature {
+        /// The commit, the upstream tip or one of the branch's
+        commit: Oid,
+        /// Its subject
+        summary: String,
+        /// What is wrong with its signature (e.g. "it is not signed")
+        reason: String,
+    },
+    /// HEAD is detached and no branch points at it, so there is no branch to squash
+    DetachedHead,
+    /// A filesystem operation failed
+    Io {
+        /// What was being done when the error occurred
+        message: String,
+        source: std::io::Error,
+    },
+    /// Git operation error with optional enhanced context
+    Git {
+        message: String,
+        source: git2::Error,
+    },
+    /// Other errors
+    Other { message: String },
+}
+
+impl fmt::Display for SquishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SquishError::BranchNotFound { branch, .. } => write!(f, "Branch {branch} not found"),
+            SquishError::AmbiguousBranch { branch, candidates } => write!(
+                f,
+                "Branch name {branch} is ambiguous, it could be {}; pass the full refname",
+                candidates.join(" or ")
+            ),
+            SquishError::UpstreamNotFound { upstream, .. } => {
+                write!(f, "Upstream {upstream} not found")
+            }
+            SquishError::Conflict { commit, files } => {
+                write!(f, "{CONFLICT_MESSAGE}")?;
+                if !files.is_empty() {
+                    write!(f, "\nConflicting files when applying {commit:.7}:")?;
+                    for file in files {
+                        write!(f, "\n  {}", file.path)?;
+                    }
+                }
+                Ok(())
+            }
+            SquishError::Paused {
+                commit,
+                summary,
+                files,
+            } => write!(
+                f,
+                "Could not apply {commit:.7} ({summary}), conflicts in:\n  {}\nResolve the conflicts, stage them with git add, then run `git squish continue` (or `git squish abort`).",
+                files.join("\n  ")
+            ),
+            SquishError::TrainConflict {
+                branch,
+                commit,
+                files,
+            } => {
+                write!(
+                    f,
+                    "{branch} conflicts with the branches before it in the train when applying {commit:.7}:"
+                )?;
+                for file in files {
+                    write!(f, "\n  {}", file.path)?;
+                }
+                Ok(())
+            }
+            SquishError::DirtyWorktree { files } => {
+                write!(
+                    f,
+                    "You have uncommitted changes, commit or stash them first (or use --force):"
+                )?;
+                for file in files {
+                    write!(f, "\n  {file}")?;
+                }
+                Ok(())
+            }
+            SquishError::UnrelatedHistories { branch, upstream } => write!(
+                f,
+                "{branch} and {upstream} have no common ancestor, so there is nothing to squash onto; check the upstream is the branch this one was started from"
+            ),
+            SquishError::PlanOutdated { branch, upstream } => write!(
+                f,
+                "{branch} moved since the squish onto {upstream} was planned, plan it again"
+            ),
+            SquishError::BranchMoved {
+                branch,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{branch} moved from {expected:.7} to {actual:.7} while it was being squished, squish it again"
+            ),
+            SquishError::UpstreamMoved {
+                upstream,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{upstream} moved from {expected:.7} to {actual:.7}, so nothing was squashed onto it; check the new upstream and squish again"
+            ),
+            SquishError::EmptyMessage => write!(
+                f,
+                "Aborting squish due to empty commit message (use --allow-empty-message to squash anyway)"
+            ),
+            SquishError::NothingToSquash { branch } => {
+                write!(f, "{branch} has no commits to squash")
+            }
+            SquishError::AlreadySquashed { branch, upstream } => {
+                write!(
+                    f,
+                    "{branch} is already a single commit on top of {upstream}"
+                )
+            }
+            SquishError::ProtectedBranch { branch, pattern } => write!(
+                f,
+                "{branch} is protected (matches '{pattern}'), pass --allow-protected to squish it anyway"
+            ),
+            SquishError::PushRejected {
+                branch,
+                remote,
+                reason,
+            } => write!(f, "Pushing {branch} to {remote} was rejected: {reason}"),
+            SquishError::Strict { warnings } => {
+                write!(f, "Stopped by --strict:")?;
+                for warning in warnings {
+                    write!(f, "\n  {warning}")?;
+                }
+                Ok(())
+            }
+            SquishError::MissingIdentity { source } => write!(
+                f,
+                "No committer identity is configured ({}), set one with `git config user.name` and `git config user.email`",
+                source.message()
+            ),
+            SquishError::Locked {
+                path,
+                pid: Some(pid),
+                since,
+            } => {
+                write!(f, "Another git-squish (pid {pid}")?;
+                if let Some(since) = since {
+                    write!(f, ", started {} UTC", crate::date::format_datetime(*since))?;
+                }
+                write!(
+                    f,
+                    ") is running in this repository; if it is not, run again with --force-unlock or remove {}",
+                    path.display()
+                )
+            }
+            SquishError::Locked {
+                path, pid: None, ..
+            } => write!(
+                f,
+                "{} exists, so another git process is running or one crashed; if none is running, remove it",
+                path.display()
+            ),
+            SquishError::UnsupportedExtension { extension, value } => {
+                let reason = match extension.as_str() {
+                    "refstorage" => "its refs cannot be updated without corrupting them",
+                    "objectformat" => "only SHA-1 object ids are supported",
+                    _ => "the extension is unknown to git-squish",
+                };
+                write!(
+                    f,
+                    "This repository uses extensions.{extension} = {value}, which git-squish does not support ({reason}); squash it with git rebase instead"
+                )
+            }
+            SquishError::UnsupportedTransport { url, transport } => {
+                let hint = match transport.as_str() {
+                    "SSH" => "use an https remote instead",
+                    _ => "use an ssh remote or build git-squish with the vendored-openssl feature",
+                };
+                write!(
+                    f,
+                    "Cannot reach {url}, libgit2 was built without {transport} support; {hint}"
+                )
+            }
+            SquishError::TreeMismatch {
+                commit,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Verification failed: squash commit {commit:.7} has tree {actual:.7}, but the rebased branch has tree {expected:.7}"
+            ),
+            SquishError::HookFailed { hook, output } if output.is_empty() => {
+                write!(f, "The {hook} hook rejected the squash")
+            }
+            SquishError::HookFailed { hook, output } => {
+                write!(f, "The {hook} hook rejected the squash:\n{output}")
+            }
+            SquishError::ExecFailed { command, output } if output.is_empty() => {
+                write!(f, "`{command}` failed on the squashed tree")
+            }
+            SquishError::ExecFailed { command, output } => {
+                write!(f, "`{command}` failed on the squashed tree:\n{output}")
+            }
+            SquishError::UnverifiedSignature {
+                commit,
+                summary,
+                reason,
+            } => write!(
+                f,
+                "Signature verification failed for {commit:.7} ({summary}): {reason}"
+            ),
+            SquishError::DetachedHead => write!(
+                f,
+                "Cannot determine current branch - HEAD is detached and no branch points to current commit"
+            ),
+            SquishError::Io { message, source } => write!(f, "{message}: {source}"),
+            SquishError::Git { message, .. } => write!(f, "{message}"),
+            SquishError::Other { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for SquishError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SquishError::BranchNotFound { source, .. }
+            | SquishError::UpstreamNotFound { source, .. }
+            | SquishError::MissingIdentity { source }
+            | SquishError::Git { source, .. } => Some(source),
+            SquishError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<git2::Error> for SquishError {
+    fn from(error: git2::Error) -> Self {
+        // Check if this is a conflict-related error
+        let is_conflict = match (error.class(), error.code()) {
+            (ErrorClass::Merge, ErrorCode::Conflict) => true,
+            (ErrorClass::Merge, ErrorCode::MergeConflict) => true,
+            (ErrorClass::Index, ErrorCode::Unmerged) => true,
+            (ErrorClass::Checkout, ErrorCode::Conflict) => true,
+            _ => {
+                // Also check the error message for conflict-related keywords
+                let msg = error.message().to_lowercase();
+                msg.contains("conflict") || (msg.contains("merge") && msg.contains("failed"))
+            }
+        };
+
+        let message = if is_conflict {
+            CONFLICT_MESSAGE.to_string()
+        } else {
+            error.message().to_string()
+        };
+
+        SquishError::Git {
+            message,
+            source: error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Error;
+    use std::error::Error as StdError;
+
+    #[test]
+    fn test_squish_error_display() {
+        let git_error = SquishError::from(Error::from_str("Test git error"));
+        assert_eq!(format!("{}", git_error), "Test git error");
+
+        let not_found = SquishError::BranchNotFound {
+            branch: "refs/heads/missing".to_string(),
+            source: Error::from_str("reference not found"),
+        };
+        assert_eq!(
+            format!("{}", not_found),
+            "Branch refs/heads/missing not found"
+        );
+
+        let io_error = SquishError::Io {
+            message: "Failed to write state".to_string(),
+            source: std::io::Error::other("disk full"),
+        };
+        assert_eq!(format!("{}", io_error), "Failed to write state: disk full");
+
+        let other_error = SquishError::Other {
+            message: "Test other error".to_string(),
+        };
+        assert_eq!(format!("{}", other_error), "Test other error");
+    }
+
+    #[test]
+    fn test_conflict_error_display() {
+        let commit = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        let error = SquishError::Conflict {
+            commit,
+            files: vec![
+                ConflictedFile {
+                    path: "src/lib.rs".to_string(),
+                    ancestor: None,
+                    ours: Some(commit),
+                    theirs: Some(commit),
+                },
+                ConflictedFile {
+                    path: "README.md".to_string(),
+                    ancestor: None,
+                    ours: None,
+                    theirs: Some(commit),
+                },
+            ],
+        };
+
+        assert_eq!(
+            format!("{}", error),
+            "There was a conflict during this squish, please retry using git rebase -i and resolve the conflicts\nConflicting files when applying 0123456:\n  src/lib.rs\n  README.md"
+        );
+    }
+
+    #[test]
+    fn test_squish_error_debug() {
+        let error = SquishError::from(Error::from_str("Debug test"));
+        let debug_output = format!("{:?}", error);
+        assert!(debug_output.contains("Git"));
+        assert!(debug_output.contains("Debug test"));
+    }
+
+    #[test]
+    fn test_from_git2_conflict_error() {
+        // Create a mock conflict error
+        let git_error = Error::from_str("merge conflict in file.txt");
+        let squish_error = SquishError::from(git_error);
+
+        if let SquishError::Git { message, .. } = squish_error {
+            assert_eq!(
+                message,
+                "There was a conflict during this squish, please retry using git rebase -i and resolve the conflicts"
+            );
+        } else {
+            panic!("Expected Git error variant");
+        }
+    }
+
+    #[test]
+    fn test_from_git2_non_conflict_error() {
+        let git_error = Error::from_str("repository not found");
+        let squish_error = SquishError::from(git_error);
+
+        if let SquishError::Git { message, .. } = squish_error {
+            assert_eq!(message, "repository not found");
+        } else {
+            panic!("Expected Git error variant");
+        }
+    }
+
+    #[test]
+    fn test_from_git2_merge_failed_error() {
+        let git_error = Error::from_str("merge failed due to conflicts");
+        let squish_error = SquishError::from(git_error);
+
+        if let SquishError::Git { message, .. } = squish_error {
+            assert_eq!(
+                message,
+                "There was a conflict during this squish, please retry using git rebase -i and resolve the conflicts"
+            );
+        } else {
+            panic!("Expected Git error variant");
+        }
+    }
+
+    #[test]
+    fn test_conflict_detection_keywords() {
+        let test_cases = vec![
+            ("conflict in file", true),
+            ("CONFLICT in file", true),
+            ("merge failed", true),
+            ("repository not found", false),
+            ("invalid reference", false),
+            ("nothing to merge failed", true), // contains "merge" and "failed"
+        ];
+
+        for (error_msg, should_be_conflict) in test_cases {
+            let git_error = Error::from_str(error_msg);
+            let squish_error = SquishError::from(git_error);
+
+            if let SquishError::Git { message, .. } = squish_error {
+                if should_be_conflict {
+                    assert_eq!(
+                        message,
+                        "There was a conflict during this squish, please retry using git rebase -i and resolve the conflicts",
+                        "Error message '{}' should be detected as conflict",
+                        error_msg
+                    );
+                } else {
+                    assert_eq!(
+                        message, error_msg,
+                        "Error message '{}' should not be detected as conflict",
+                        error_msg
+                    );
+                }
+            } else {
+                panic!("Expected Git error variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_trait_implementation() {
+        let error = SquishError::Other {
+            message: "test error".to_string(),
+        };
+
+        // Test that it implements std::error::Error
+        let _error_ref: &dyn std::error::Error = &error;
+
+        // Errors without an underlying cause have no source
+        assert!(StdError::source(&error).is_none());
+    }
+
+    #[test]
+    fn test_error_source_chains_to_git2() {
+        let error = SquishError::UpstreamNotFound {
+            upstream: "origin/missing".to_string(),
+            source: Error::from_str("revspec 'origin/missing' not found"),
+        };
+        let source = StdError::source(&error).unwrap();
+        let git_error = source.downcast_ref::<Error>().unwrap();
+        assert_eq!(git_error.message(), "revspec 'origin/missing' not found");
+
+        let error = SquishError::from(Error::from_str("merge conflict in file.txt"));
+        let source = StdError::source(&error).unwrap();
+        assert!(source.downcast_ref::<Error>().is_some());
+    }
+}
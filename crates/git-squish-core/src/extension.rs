@@ -0,0 +1,103 @@
+use git2::Config;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use crate::SquishError;
+
+/// Extensions libgit2 does not know, but which squishing is safe with. `preciousObjects`
+/// only forbids deleting objects, which a squish never does (old commits stay reachable
+/// from the reflog).
+const ALLOWED_EXTENSIONS: [&str; 1] = ["preciousobjects"];
+/// Extensions libgit2 handles itself.
+const BUILTIN_EXTENSIONS: [&str; 2] = ["noop", "worktreeconfig"];
+
+/// Tell libgit2 about `ALLOWED_EXTENSIONS`, once per process, so repositories using them
+/// can be opened.
+pub(crate) fn allow_extensions() {
+    static ALLOW: Once = Once::new();
+    ALLOW.call_once(|| {
+        // SAFETY: libgit2's extension list is global; it is only set here, once, before
+        // git-squish opens its first repository.
+        if let Err(e) = unsafe { git2::opts::set_extensions(&ALLOWED_EXTENSIONS) } {
+            log::warn!("Failed to register repository extensions: {e}");
+        }
+    });
+}
+
+/// Find the extension which kept the repository containing `repo_path` from opening, if
+/// any: an `extensions.*` config entry which is neither built into libgit2 nor allowed
+/// (`objectFormat` only with `sha1`).
+pub(crate) fn unsupported_extension(
+    repo_path: &Path,
+    ceiling_dirs: &[PathBuf],
+) -> Option<SquishError> {
+    let git_dir = git2::Repository::discover_path(repo_path, ceiling_dirs).ok()?;
+    // A linked worktree's git dir points at the shared one holding the config.
+    let common_dir = match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir,
+    };
+    let config = Config::open(&common_dir.join("config")).ok()?;
+    let mut entries = config.entries(Some("extensions\\..*")).ok()?;
+    while let Some(Ok(entry)) = entries.next() {
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let extension = name.trim_start_matches("extensions.").to_ascii_lowercase();
+        let supported = BUILTIN_EXTENSIONS.contains(&extension.as_str())
+            || ALLOWED_EXTENSIONS.contains(&extension.as_str())
+            || (extension == "objectformat" && value.eq_ignore_ascii_case("sha1"));
+        if !supported {
+            return Some(SquishError::UnsupportedExtension {
+                extension,
+                value: value.to_string(),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_repository;
+    use crate::test_utils::init_test_repo;
+
+    /// A repository with `extensions.<name> = <value>`.
+    fn repo_with_extension(name: &str, value: &str) -> (PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        let mut config = git2::Repository::open(&repo_path)
+            .unwrap()
+            .config()
+            .unwrap();
+        config.set_i32("core.repositoryformatversion", 1).unwrap();
+        config
+            .set_str(&format!("extensions.{name}"), value)
+            .unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_open_repository_with_extensions() {
+        let (repo_path, _temp_dir) = repo_with_extension("preciousObjects", "true");
+        open_repository(&repo_path).unwrap();
+        let (repo_path, _temp_dir) = repo_with_extension("worktreeConfig", "true");
+        open_repository(&repo_path).unwrap();
+
+        // Found from a subdirectory too, like the repository itself.
+        let (repo_path, _temp_dir) = repo_with_extension("refStorage", "reftable");
+        std::fs::create_dir(repo_path.join("src")).unwrap();
+        match open_repository(repo_path.join("src")).err() {
+            Some(SquishError::UnsupportedExtension { extension, value }) => {
+                assert_eq!(extension, "refstorage");
+                assert_eq!(value, "reftable");
+            }
+            other => panic!("Expected an unsupported extension, got {other:?}"),
+        }
+        let (repo_path, _temp_dir) = repo_with_extension("objectFormat", "sha256");
+        assert!(matches!(
+            open_repository(&repo_path),
+            Err(SquishError::UnsupportedExtension { .. })
+        ));
+    }
+}
@@ -0,0 +1,514 @@
+use git2::{Commit, MergeOptions, Oid, Repository, Signature};
+use regex::Regex;
+
+use crate::ignore_revs::IgnoredRevs;
+use crate::message::{add_sources, squash_message};
+use crate::warning::Warnings;
+use crate::{
+    ConflictedFile, SquishError, SquishOptions, SquishWarning, find_branch, write_commit,
+    write_squash_commit,
+};
+
+/// Whether the options restrict which commits are folded into the squash commit.
+pub(crate) fn is_filtering(options: &SquishOptions) -> bool {
+    options.keep_first
+        || !options.only_authors.is_empty()
+        || !options.exclude_authors.is_empty()
+        || !options.exclude_commits.is_empty()
+        || !options.skip_commits.is_empty()
+        || !options.skip_subject_patterns.is_empty()
+        || options.since_date.is_some()
+        || options.until_date.is_some()
+}
+
+/// Whether `commit` is folded into the squash commit rather than kept as it is.
+pub(crate) fn is_selected(options: &SquishOptions, commit: &Commit) -> bool {
+    if options.exclude_commits.contains(&commit.id()) {
+        return false;
+    }
+
+    // Author dates survive rebases, unlike commit dates.
+    let time = commit.author().when().seconds();
+    if options.since_date.is_some_and(|since| time < since)
+        || options.until_date.is_some_and(|until| time > until)
+    {
+        return false;
+    }
+
+    let author = commit.author();
+    let author = format!(
+        "{} <{}>",
+        author.name().unwrap_or_default(),
+        author.email().unwrap_or_default()
+    );
+    let matches = |patterns: &[String]| {
+        patterns
+            .iter()
+            .any(|pattern| author.contains(pattern.as_str()))
+    };
+    (options.only_authors.is_empty() || matches(&options.only_authors))
+        && !matches(&options.exclude_authors)
+}
+
+/// The commits dropped from the branch altogether with `skip_commit` and
+/// `skip_subject_pattern`, like `drop` in an interactive rebase.
+struct Skips {
+    ids: Vec<Oid>,
+    subjects: Vec<Regex>,
+}
+
+impl Skips {
+    /// Resolve the skipped commits, which must be among the branch's `commits`, and compile
+    /// the subject patterns.
+    fn new(
+        repo: &Repository,
+        branch_refname: &str,
+        commits: &[Commit],
+        options: &SquishOptions,
+    ) -> Result<Self, SquishError> {
+        let mut ids = Vec::new();
+        for rev in &options.skip_commits {
+            let id = repo
+                .revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(|_| SquishError::Other {
+                    message: format!("Cannot skip {rev}, it is not a commit"),
+                })?
+                .id();
+            if !commits.iter().any(|commit| commit.id() == id) {
+                return Err(SquishError::Other {
+                    message: format!(
+                        "Cannot skip {rev}, it is not one of {branch_refname}'s commits"
+                    ),
+                });
+            }
+            ids.push(id);
+        }
+        let subjects = options
+            .skip_subject_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| SquishError::Other {
+                    message: format!("Invalid skip subject pattern '{pattern}': {e}"),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Skips { ids, subjects })
+    }
+
+    /// Whether `commit` is dropped.
+    fn contains(&self, commit: &Commit) -> bool {
+        let subject = commit.summary().unwrap_or_default();
+        self.ids.contains(&commit.id()) || self.subjects.iter().any(|re| re.is_match(subject))
+    }
+}
+
+/// Squash the selected commits of the branch into one commit and replay the other commits,
+/// in order, around it: those before the first selected commit below it, the rest on top.
+/// Skipped commits are dropped. Returns the new tip without updating any ref. When every
+/// commit is selected this is a plain squash.
+pub(crate) fn write_filtered_commits(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_id: Oid,
+    rebased_tip_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    let branch_tip_id = find_branch(repo, branch_refname)?.peel_to_commit()?.id();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push(branch_tip_id)?;
+    revwalk.hide(upstream_id)?;
+
+    // Like the rebase, merge commits are flattened away.
+    let mut commits = Vec::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        if commit.parent_count() <= 1 {
+            commits.push(commit);
+        }
+    }
+    let skips = Skips::new(repo, branch_refname, &commits, options)?;
+
+    let mut before = Vec::new();
+    let mut selected = Vec::new();
+    let mut kept = Vec::new();
+    let mut skipped = false;
+    let mut first = options.keep_first;
+    for commit in commits {
+        if skips.contains(&commit) {
+            skipped = true;
+        } else if std::mem::take(&mut first) {
+            before.push(commit);
+        } else if is_selected(options, &commit) {
+            selected.push(commit);
+        } else if selected.is_empty() {
+            before.push(commit);
+        } else {
+            kept.push(commit);
+        }
+    }
+    if selected.is_empty() {
+        return Err(SquishError::NothingToSquash {
+            branch: branch_refname.to_string(),
+        });
+    }
+    // The rebased tip still has the skipped commits in it.
+    if before.is_empty() && kept.is_empty() && !skipped {
+        return write_squash_commit(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        );
+    }
+
+    let sig = repo.signature()?;
+    let mut base = repo.find_commit(upstream_id)?;
+    for commit in &before {
+        if let Some(id) = pick(repo, commit, &base, &sig, options, warnings)? {
+            base = repo.find_commit(id)?;
+        }
+    }
+
+    // Replay the selected commits on their own for the tree and message of the squash.
+    let mut tip = base.clone();
+    for commit in &selected {
+        if let Some(id) = pick(repo, commit, &tip, &sig, options, warnings)? {
+            tip = repo.find_commit(id)?;
+        }
+    }
+    if tip.id() != base.id() {
+        let ignored = IgnoredRevs::load(repo)?;
+        let message = squash_message(repo, &base, &tip, &ignored, options)?;
+        let message = add_sources(message, &selected, options)?;
+        let squash_id = write_commit(repo, &message, &tip.tree()?, &base, options, warnings)?;
+        tip = repo.find_commit(squash_id)?;
+    }
+
+    for commit in &kept {
+        if let Some(id) = pick(repo, commit, &tip, &sig, options, warnings)? {
+            tip = repo.find_commit(id)?;
+        }
+    }
+    Ok(tip.id())
+}
+
+/// Cherry-pick `commit` onto `onto` in memory, keeping its author and message. A commit
+/// which becomes empty is dropped with a warning, returning None.
+fn pick(
+    repo: &Repository,
+    commit: &Commit,
+    onto: &Commit,
+    committer: &Signature,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Option<Oid>, SquishError> {
+    let mut merge_opts = MergeOptions::new();
+    if let Some(strategy_option) = options.strategy_option {
+        merge_opts.file_favor(strategy_option.file_favor());
+    }
+    let mut index = repo.cherrypick_commit(commit, onto, 0, Some(&merge_opts))?;
+    if index.has_conflicts() {
+        return Err(SquishError::Conflict {
+            commit: commit.id(),
+            files: ConflictedFile::from_index(&index)?,
+        });
+    }
+
+    let tree_id = index.write_tree_to(repo)?;
+    if tree_id == onto.tree_id() {
+        warnings.push(SquishWarning::EmptyCommitDropped {
+            commit: commit.id(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+        });
+        return Ok(None);
+    }
+    let tree = repo.find_tree(tree_id)?;
+    let id = repo.commit(
+        None,
+        &commit.author(),
+        committer,
+        commit.message().unwrap_or_default(),
+        &tree,
+        &[onto],
+    )?;
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{
+        change_to_branch, commit_file, create_branch, init_test_repo, read_head_file,
+    };
+
+    /// Commit as `name` by switching the repository's configured user.
+    fn commit_as(repo_path: &std::path::PathBuf, name: &str, filename: &str, message: &str) -> Oid {
+        let repo = Repository::open(repo_path).unwrap();
+        repo.config().unwrap().set_str("user.name", name).unwrap();
+        commit_file(repo_path, filename, message, message).unwrap()
+    }
+
+    #[test]
+    fn test_squash_only_author() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_as(&repo_path, "dependabot[bot]", "a.lock", "Bump a");
+        let feature = commit_as(&repo_path, "Alice", "feature.rs", "Add feature");
+        commit_as(&repo_path, "dependabot[bot]", "b.lock", "Bump b");
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let options = SquishOptions::new().only_author("dependabot");
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        // The human commit is kept as it was, on top of the squashed bot commits.
+        assert_ne!(tip.id(), feature);
+        assert_eq!(tip.summary(), Some("Add feature"));
+        assert_eq!(tip.author().name(), Some("Alice"));
+        let squash = tip.parent(0).unwrap();
+        assert_eq!(squash.summary(), Some("Bump a"));
+        let squash_tree = squash.tree().unwrap();
+        assert!(squash_tree.get_path(std::path::Path::new("b.lock")).is_ok());
+        assert!(
+            squash_tree
+                .get_path(std::path::Path::new("feature.rs"))
+                .is_err()
+        );
+        assert_eq!(
+            squash.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+
+        change_to_branch(&repo_path, "topic").unwrap();
+        assert_eq!(
+            read_head_file(&repo_path, "feature.rs").unwrap(),
+            "Add feature"
+        );
+    }
+
+    /// Commit with the author date set to `time` (seconds since the epoch).
+    fn commit_at(repo_path: &std::path::PathBuf, time: i64, filename: &str, message: &str) -> Oid {
+        let id = commit_file(repo_path, filename, message, message).unwrap();
+        let repo = Repository::open(repo_path).unwrap();
+        let author =
+            git2::Signature::new("Test User", "test@example.com", &git2::Time::new(time, 0))
+                .unwrap();
+        let commit = repo.find_commit(id).unwrap();
+        commit
+            .amend(Some("HEAD"), Some(&author), None, None, None, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_squash_date_window() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_at(&repo_path, 1_000, "reviewed.rs", "Reviewed before");
+        commit_at(&repo_path, 2_000, "wip.rs", "WIP 1");
+        commit_at(&repo_path, 3_000, "wip.rs", "WIP 2");
+        commit_at(&repo_path, 4_000, "later.rs", "Reviewed after");
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let options = SquishOptions::new().since_date(1_500).until_date(3_500);
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        // Commits outside the window keep their place on either side of the squash.
+        assert_eq!(tip.summary(), Some("Reviewed after"));
+        assert_eq!(tip.author().when().seconds(), 4_000);
+        let squash = tip.parent(0).unwrap();
+        assert_eq!(squash.summary(), Some("WIP 1"));
+        let before = squash.parent(0).unwrap();
+        assert_eq!(before.summary(), Some("Reviewed before"));
+        assert_eq!(
+            before.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+
+        change_to_branch(&repo_path, "topic").unwrap();
+        assert_eq!(read_head_file(&repo_path, "wip.rs").unwrap(), "WIP 2");
+    }
+
+    #[test]
+    fn test_squash_keep_first() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "vendor/lib.rs", "lib\n", "Import vendored lib").unwrap();
+        commit_file(&repo_path, "app.rs", "1\n", "Use the lib").unwrap();
+        commit_file(&repo_path, "app.rs", "2\n", "Fix typo").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let options = SquishOptions::new().keep_first(true);
+        let squash = || {
+            squash_branch_with_options(
+                repo_path.to_str().unwrap(),
+                "refs/heads/topic",
+                "main",
+                &options,
+            )
+        };
+        squash().unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(tip.summary(), Some("Use the lib"));
+        let first = tip.parent(0).unwrap();
+        assert_eq!(first.summary(), Some("Import vendored lib"));
+        assert!(
+            first
+                .tree()
+                .unwrap()
+                .get_path(std::path::Path::new("app.rs"))
+                .is_err()
+        );
+        assert_eq!(
+            first.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+
+        // The scaffold plus one squashed commit is left alone.
+        assert!(matches!(
+            squash().unwrap_err(),
+            SquishError::AlreadySquashed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_squash_skips_commits() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "feature.rs", "1\n", "Add feature").unwrap();
+        let debug = commit_file(&repo_path, "debug.rs", "dbg!\n", "Add debug logging").unwrap();
+        commit_file(&repo_path, "feature.rs", "2\n", "WIP tweak").unwrap();
+        commit_file(&repo_path, "notes.txt", "notes\n", "Add notes").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let squash = |options: &SquishOptions| {
+            squash_branch_with_options(
+                repo_path.to_str().unwrap(),
+                "refs/heads/topic",
+                "main",
+                options,
+            )
+        };
+        // Only the branch's own commits can be skipped.
+        let error = squash(&SquishOptions::new().skip_commit("main")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("not one of refs/heads/topic's commits")
+        );
+        let error = squash(&SquishOptions::new().skip_subject_pattern("(")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid skip subject pattern")
+        );
+
+        let options = SquishOptions::new()
+            .skip_commit(debug.to_string()[..7].to_string())
+            .skip_subject_pattern("(?i)^wip");
+        squash(&options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(tip.summary(), Some("Add feature"));
+        assert_eq!(
+            tip.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+        let tree = tip.tree().unwrap();
+        assert!(tree.get_path(std::path::Path::new("notes.txt")).is_ok());
+        assert!(tree.get_path(std::path::Path::new("debug.rs")).is_err());
+        change_to_branch(&repo_path, "topic").unwrap();
+        assert_eq!(read_head_file(&repo_path, "feature.rs").unwrap(), "1\n");
+    }
+
+    #[test]
+    fn test_is_selected() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let id = commit_as(&repo_path, "dependabot[bot]", "a.lock", "Bump a");
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(id).unwrap();
+
+        assert!(is_selected(&SquishOptions::new(), &commit));
+        assert!(is_selected(
+            &SquishOptions::new().only_author("dependabot"),
+            &commit
+        ));
+        assert!(is_selected(
+            &SquishOptions::new().only_author("test@example.com"),
+            &commit
+        ));
+        assert!(!is_selected(
+            &SquishOptions::new().only_author("Alice"),
+            &commit
+        ));
+        assert!(!is_selected(
+            &SquishOptions::new().exclude_author("[bot]"),
+            &commit
+        ));
+        assert!(!is_selected(
+            &SquishOptions::new().exclude_commit(commit.id()),
+            &commit
+        ));
+        assert!(!is_filtering(&SquishOptions::new()));
+        assert!(is_filtering(
+            &SquishOptions::new().skip_subject_pattern("^wip")
+        ));
+
+        let time = commit.author().when().seconds();
+        assert!(is_selected(
+            &SquishOptions::new().since_date(time).until_date(time),
+            &commit
+        ));
+        assert!(!is_selected(
+            &SquishOptions::new().since_date(time + 1),
+            &commit
+        ));
+        assert!(!is_selected(
+            &SquishOptions::new().until_date(time - 1),
+            &commit
+        ));
+    }
+}
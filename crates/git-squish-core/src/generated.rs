@@ -0,0 +1,219 @@
+use git2::{Index, IndexEntry, IndexTime, Repository, Tree, TreeEntry};
+
+use crate::protect::glob_match;
+use crate::{SquishError, SquishOptions};
+
+/// Multi-valued config key holding generated path patterns (e.g. `*.lock` or `dist/`).
+const GENERATED_CONFIG: &str = "squish.generated";
+/// Subject of the trailing commit holding the changes to generated paths.
+pub(crate) const GENERATED_SUBJECT: &str = "Regenerate artifacts";
+
+/// The generated path patterns: any `squish.generated` config values followed by the ones
+/// passed with `SquishOptions::generated_path`.
+pub(crate) fn generated_patterns(
+    repo: &Repository,
+    options: &SquishOptions,
+) -> Result<Vec<String>, SquishError> {
+    let mut patterns = Vec::new();
+    let config = repo.config()?;
+    if let Ok(entries) = config.multivar(GENERATED_CONFIG, None) {
+        entries.for_each(|entry| {
+            if let Some(value) = entry.value() {
+                patterns.push(value.to_string());
+            }
+        })?;
+    }
+    patterns.extend(options.generated_paths.iter().cloned());
+    Ok(patterns)
+}
+
+/// Whether `path` matches a generated path pattern. Like `.gitignore`, a pattern without a
+/// slash matches the file name in any directory and a trailing slash matches everything
+/// below a directory.
+pub(crate) fn is_generated(patterns: &[String], path: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('/') {
+            Some(dir) => path
+                .match_indices('/')
+                .any(|(end, _)| matches_path(dir, &path[..end])),
+            None => matches_path(pattern, path),
+        })
+}
+
+/// Match a path against a pattern, anchored at the root when the pattern contains a slash
+/// and against the last path component otherwise.
+fn matches_path(pattern: &str, path: &str) -> bool {
+    match pattern.strip_prefix('/') {
+        Some(anchored) => glob_match(anchored, path),
+        None if pattern.contains('/') => glob_match(pattern, path),
+        None => glob_match(pattern, path.rsplit('/').next().unwrap_or(path)),
+    }
+}
+
+/// Split the changes from `base` to `tree` into hand-written and generated ones.
+///
+/// # Returns
+/// The tree with only the hand-written changes applied and the generated paths which
+/// changed, or None when the changes are not a mix of both (so there is nothing to split).
+pub(crate) fn split_generated<'r>(
+    repo: &'r Repository,
+    base: &Tree,
+    tree: &Tree,
+    patterns: &[String],
+) -> Result<Option<(Tree<'r>, Vec<String>)>, SquishError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let diff = repo.diff_tree_to_tree(Some(base), Some(tree), None)?;
+    let mut generated = Vec::new();
+    let mut hand_written = false;
+    for delta in diff.deltas() {
+        let Some(path) = delta
+            .new_file()
+            .path()
+            .or(delta.old_file().path())
+            .and_then(|path| path.to_str())
+        else {
+            hand_written = true;
+            continue;
+        };
+        if is_generated(patterns, path) {
+            generated.push(path.to_string());
+        } else {
+            hand_written = true;
+        }
+    }
+    if generated.is_empty() || !hand_written {
+        return Ok(None);
+    }
+
+    // Start from the full result and put the generated paths back the way they were.
+    let mut index = Index::new()?;
+    index.read_tree(tree)?;
+    for path in &generated {
+        match base.get_path(std::path::Path::new(path)) {
+            Ok(entry) => index.add(&index_entry(path, &entry))?,
+            Err(_) => index.remove_path(std::path::Path::new(path))?,
+        }
+    }
+    let tree_id = index.write_tree_to(repo)?;
+    Ok(Some((repo.find_tree(tree_id)?, generated)))
+}
+
+/// An index entry putting the blob (or submodule commit) of `entry` at `path`.
+pub(crate) fn index_entry(path: &str, entry: &TreeEntry) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: entry.filemode() as u32,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id: entry.id(),
+        flags: path.len().min(0xfff) as u16,
+        flags_extended: 0,
+        path: path.as_bytes().to_vec(),
+    }
+}
+
+/// The message of the trailing commit holding the changes to `paths`.
+pub(crate) fn generated_message(paths: &[String]) -> String {
+    let mut message = format!("{GENERATED_SUBJECT}\n\n");
+    for path in paths {
+        message.push_str(&format!("- {path}\n"));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    #[test]
+    fn test_is_generated() {
+        let patterns =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let lock = patterns(&["*.lock"]);
+        assert!(is_generated(&lock, "Cargo.lock"));
+        assert!(is_generated(&lock, "web/yarn.lock"));
+        assert!(!is_generated(&lock, "src/lock.rs"));
+
+        let dist = patterns(&["dist/"]);
+        assert!(is_generated(&dist, "dist/app.js"));
+        assert!(is_generated(&dist, "web/dist/css/app.css"));
+        assert!(!is_generated(&dist, "dist"));
+        assert!(!is_generated(&dist, "distribution/app.js"));
+
+        let anchored = patterns(&["/gen/*.pb.go", "/vendor/"]);
+        assert!(is_generated(&anchored, "gen/api.pb.go"));
+        assert!(!is_generated(&anchored, "src/gen/api.pb.go"));
+        assert!(is_generated(&anchored, "vendor/lib/lib.go"));
+        assert!(!is_generated(&anchored, "src/vendor/lib.go"));
+    }
+
+    #[test]
+    fn test_squash_moves_generated_changes_to_trailing_commit() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "Cargo.lock", "v1\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "src/main.rs", "fn main() {}\n", "Add main").unwrap();
+        commit_file(&repo_path, "Cargo.lock", "v2\n", "Update lockfile").unwrap();
+        commit_file(&repo_path, "dist/app.js", "app\n", "Build").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_multivar("squish.generated", "^$", "*.lock")
+            .unwrap();
+        let options = SquishOptions::new().generated_path("dist/");
+        let squash =
+            || squash_branch_with_options(&repo_path, "refs/heads/topic", "main", &options);
+        squash().unwrap();
+
+        let tip = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(
+            tip.message(),
+            Some("Regenerate artifacts\n\n- Cargo.lock\n- dist/app.js\n")
+        );
+        let squash_commit = tip.parent(0).unwrap();
+        assert_eq!(squash_commit.summary(), Some("Add main"));
+        let tree = squash_commit.tree().unwrap();
+        assert!(tree.get_path(std::path::Path::new("src/main.rs")).is_ok());
+        assert!(tree.get_path(std::path::Path::new("dist")).is_err());
+        let main_lock = repo
+            .find_reference("refs/heads/main")
+            .unwrap()
+            .peel_to_tree()
+            .unwrap()
+            .get_path(std::path::Path::new("Cargo.lock"))
+            .unwrap()
+            .id();
+        assert_eq!(
+            tree.get_path(std::path::Path::new("Cargo.lock"))
+                .unwrap()
+                .id(),
+            main_lock
+        );
+        assert_eq!(
+            squash_commit.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+
+        // Squashing again leaves the two commits alone.
+        assert!(matches!(
+            squash().unwrap_err(),
+            SquishError::AlreadySquashed { .. }
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use git2::{Oid, Repository, Tree, build::CheckoutBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::warning::Warnings;
+use crate::worktree::common_dir;
+use crate::{SquishError, SquishOptions, SquishWarning};
+
+/// Config key turning on running hooks against the squashed tree when `verify_hooks` is
+/// not given.
+const VERIFY_HOOKS_CONFIG: &str = "squish.verifyHooks";
+
+/// Whether hooks run against the squashed tree: the `verify_hooks` option when given,
+/// otherwise the `squish.verifyHooks` config.
+fn is_enabled(repo: &Repository, options: &SquishOptions) -> bool {
+    options.verify_hooks.unwrap_or_else(|| {
+        repo.config()
+            .and_then(|config| config.get_bool(VERIFY_HOOKS_CONFIG))
+            .unwrap_or(false)
+    })
+}
+
+/// The executable hook called `name`, from `core.hooksPath` or the hooks directory of the
+/// repository, if there is one.
+fn find_hook(repo: &Repository, name: &str) -> Result<Option<PathBuf>, SquishError> {
+    let hooks_dir = match repo.config()?.get_path("core.hooksPath") {
+        Ok(path) if path.is_relative() => repo.workdir().unwrap_or(repo.path()).join(path),
+        Ok(path) => path,
+        Err(_) => common_dir(repo)?.join("hooks"),
+    };
+    let hook = hooks_dir.join(name);
+    let Ok(metadata) = fs::metadata(&hook) else {
+        return Ok(None);
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Like git, a hook which is not executable is ignored.
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Ok(None);
+        }
+    }
+    Ok(metadata.is_file().then_some(hook))
+}
+
+/// Run the repository's `pre-commit` hook against `tree` when hooks are turned on (see
+/// `SquishOptions::verify_hooks`), as if the squash commit was about to be made with
+/// `git commit` on top of `upstream_id`, then the `exec` commands.
+///
+/// Both run in a temporary linked worktree with `tree` checked out and staged and HEAD
+/// detached at `upstream_id`, so `git diff --cached` shows the squash and the files on
+/// disk are the squashed ones. The repository's own worktree and index are not touched.
+pub(crate) fn check_squashed_tree(
+    repo: &Repository,
+    upstream_id: Oid,
+    tree: &Tree,
+    options: &SquishOptions,
+) -> Result<(), SquishError> {
+    let hook = if is_enabled(repo, options) {
+        find_hook(repo, "pre-commit")?
+    } else {
+        None
+    };
+    if hook.is_none() && options.exec.is_empty() {
+        return Ok(());
+    }
+    let checkout = TempCheckout::create(repo, upstream_id, tree)?;
+    if let Some(hook) = hook
+        && let Err(output) = run_hook(&hook, checkout.workdir(), &[])?
+    {
+        return Err(SquishError::HookFailed {
+            hook: "pre-commit".to_string(),
+            output,
+        });
+    }
+    for command in &options.exec {
+        let output = shell_command(command)
+            .current_dir(checkout.workdir())
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env_remove("GIT_INDEX_FILE")
+            .output()
+            .map_err(|source| SquishError::Io {
+                message: format!("Failed to run `{command}`"),
+                source,
+            })?;
+        if !output.status.success() {
+            return Err(SquishError::ExecFailed {
+                command: command.clone(),
+                output: output_text(&output),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `command` run by the shell, as `git rebase --exec` does.
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// What a process printed, stdout then stderr, without trailing whitespace.
+fn output_text(output: &std::process::Output) -> String {
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text.trim_end().to_string()
+}
+
+/// Run the repository's `post-squish` hook, if it has one, after `branch_refname` moved
+/// from `old_id` to `new_id`.
+///
+/// The hook is given the old tip, the new commit and the branch name (e.g. "feature") as
+/// arguments and runs from the top of the worktree, like git's own post-commit hooks. The
+/// squish is already done by then, so a failing hook is only warned about.
+pub(crate) fn run_post_squish(
+    repo: &Repository,
+    old_id: Oid,
+    new_id: Oid,
+    branch_refname: &str,
+    warnings: &mut Warnings,
+) -> Result<(), SquishError> {
+    let Some(hook) = find_hook(repo, "post-squish")? else {
+        return Ok(());
+    };
+    let branch = branch_refname
+        .strip_prefix("refs/heads/")
+        .unwrap_or(branch_refname);
+    let args = [old_id.to_string(), new_id.to_string(), branch.to_string()];
+    let dir = repo.workdir().unwrap_or(repo.path());
+    if let Err(output) = run_hook(&hook, dir, &args)? {
+        warnings.push(SquishWarning::HookFailed {
+            hook: "post-squish".to_string(),
+            output,
+        });
+    }
+    Ok(())
+}
+
+/// Run `hook` in `dir` with `args`, returning what it printed if it failed.
+fn run_hook(hook: &Path, dir: &Path, args: &[String]) -> Result<Result<(), String>, SquishError> {
+    let output = Command::new(hook)
+        .args(args)
+        .current_dir(dir)
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE")
+        .output()
+        .map_err(|source| SquishError::Io {
+            message: format!("Failed to run {}", hook.display()),
+            source,
+        })?;
+    if output.status.success() {
+        return Ok(Ok(()));
+    }
+    Ok(Err(output_text(&output)))
+}
+
+/// A linked worktree in a temporary directory, laid out like `git worktree add --detach`
+/// makes them, removed again when dropped.
+struct TempCheckout {
+    workdir: tempfile::TempDir,
+    admin_dir: PathBuf,
+}
+
+impl TempCheckout {
+    fn create(repo: &Repository, head: Oid, tree: &Tree) -> Result<Self, SquishError> {
+        let io_error = |message: String| move |source| SquishError::Io { message, source };
+        let workdir = tempfile::tempdir().map_err(io_error(
+            "Failed to create a temporary worktree".to_string(),
+        ))?;
+        let name = format!(
+            "squish-hooks-{}",
+            workdir
+                .path()
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        );
+        let admin_dir = common_dir(repo)?.join("worktrees").join(name);
+        let checkout = TempCheckout { workdir, admin_dir };
+
+        let write = |path: &Path, content: String| {
+            fs::write(path, content)
+                .map_err(io_error(format!("Failed to write {}", path.display())))
+        };
+        fs::create_dir_all(&checkout.admin_dir).map_err(io_error(format!(
+            "Failed to create {}",
+            checkout.admin_dir.display()
+        )))?;
+        let dot_git = checkout.workdir().join(".git");
+        write(&checkout.admin_dir.join("HEAD"), format!("{head}\n"))?;
+        write(&checkout.admin_dir.join("commondir"), "../..\n".to_string())?;
+        write(
+            &checkout.admin_dir.join("gitdir"),
+            format!("{}\n", dot_git.display()),
+        )?;
+        write(
+            &dot_git,
+            format!("gitdir: {}\n", checkout.admin_dir.display()),
+        )?;
+
+        let worktree_repo = Repository::open(checkout.workdir())?;
+        let tree = worktree_repo.find_tree(tree.id())?;
+        worktree_repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().force()))?;
+        Ok(checkout)
+    }
+
+    fn workdir(&self) -> &Path {
+        self.workdir.path()
+    }
+}
+
+impl Drop for TempCheckout {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.admin_dir) {
+            log::warn!("Failed to remove {}: {e}", self.admin_dir.display());
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+    use crate::{continue_squash, squash_branch_with_options};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A repo with a topic branch of two commits and a `name` hook running `script`.
+    fn hooked_repo(name: &str, script: &str) -> (PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "debug\n", "Add a").unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let hooks = repo_path.join(".git/hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        let hook = hooks.join(name);
+        fs::write(&hook, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_pre_commit_hook_rejects_squash() {
+        let script = "git diff --cached --name-only > \"$(git rev-parse --git-common-dir)/staged\"\n\
+                      if grep -q debug a.txt; then echo 'a.txt has debug output' >&2; exit 1; fi";
+        let (repo_path, _temp_dir) = hooked_repo("pre-commit", script);
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        // Hooks only run when asked to.
+        let squash = |options: &SquishOptions| {
+            squash_branch_with_options(&repo_path, "topic", "main", options)
+        };
+        let error = squash(&SquishOptions::new().verify_hooks(true)).unwrap_err();
+
+        match error {
+            SquishError::HookFailed { hook, output } => {
+                assert_eq!(hook, "pre-commit");
+                assert_eq!(output, "a.txt has debug output");
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+        let staged = fs::read_to_string(repo_path.join(".git/staged")).unwrap();
+        assert_eq!(staged, "a.txt\nb.txt\n");
+        // The temporary worktree is gone again.
+        assert!(repo.worktrees().unwrap().is_empty());
+
+        let mut config = repo.config().unwrap();
+        config.set_bool(VERIFY_HOOKS_CONFIG, true).unwrap();
+        assert!(squash(&SquishOptions::new()).is_err());
+        squash(&SquishOptions::new().verify_hooks(false)).unwrap();
+    }
+
+    #[test]
+    fn test_pre_commit_hook_accepts_squash() {
+        let (repo_path, _temp_dir) = hooked_repo("pre-commit", "test -f b.txt");
+        let options = SquishOptions::new().verify_hooks(true);
+        squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+    }
+
+    #[test]
+    fn test_pre_commit_hook_gates_continue() {
+        let script = "if grep -q debug a.txt; then echo 'a.txt has debug output' >&2; exit 1; fi";
+        let (repo_path, _temp_dir) = hooked_repo("pre-commit", script);
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "a.txt", "main\n", "Add a on main").unwrap();
+        change_to_branch(&repo_path, "topic").unwrap();
+        let options = SquishOptions::new()
+            .verify_hooks(true)
+            .pause_on_conflict(true);
+        let error = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap_err();
+        assert!(matches!(error, SquishError::Paused { .. }), "{error}");
+
+        // The resolution keeps the debug output, which the hook still catches.
+        fs::write(repo_path.join("a.txt"), "main\ndebug\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let error = continue_squash(&repo_path).unwrap_err();
+        assert!(matches!(error, SquishError::HookFailed { .. }), "{error}");
+    }
+
+    #[test]
+    fn test_post_squish_hook_gets_old_and_new_tips() {
+        let script = "echo \"$@\" > \"$(git rev-parse --git-dir)/post-squish-args\"";
+        let (repo_path, _temp_dir) = hooked_repo("post-squish", script);
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &SquishOptions::new()).unwrap();
+
+        let args = fs::read_to_string(repo_path.join(".git/post-squish-args")).unwrap();
+        assert_eq!(args, format!("{tip} {} topic\n", result.new_id));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_failing_post_squish_hook_warns() {
+        let (repo_path, _temp_dir) = hooked_repo("post-squish", "echo 'no CI today' >&2; exit 1");
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &SquishOptions::new()).unwrap();
+
+        assert_eq!(
+            repo.refname_to_id("refs/heads/topic").unwrap(),
+            result.new_id
+        );
+        assert_eq!(
+            result.warnings,
+            [SquishWarning::HookFailed {
+                hook: "post-squish".to_string(),
+                output: "no CI today".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_exec_runs_against_squashed_tree() {
+        let (repo_path, _temp_dir) = hooked_repo("post-squish", "true");
+        let options = SquishOptions::new()
+            .exec("test -f a.txt && test -f b.txt")
+            .exec("git diff --cached --name-only > \"$(git rev-parse --git-common-dir)/staged\"");
+
+        squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let staged = fs::read_to_string(repo_path.join(".git/staged")).unwrap();
+        assert_eq!(staged, "a.txt\nb.txt\n");
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(repo.worktrees().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failing_exec_leaves_branch_alone() {
+        let (repo_path, _temp_dir) = hooked_repo("post-squish", "touch ran");
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new().exec("echo 'tests failed'; exit 1");
+
+        let error = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap_err();
+
+        match error {
+            SquishError::ExecFailed { command, output } => {
+                assert_eq!(command, "echo 'tests failed'; exit 1");
+                assert_eq!(output, "tests failed");
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+        // The post-squish hook did not run either.
+        assert!(!repo_path.join("ran").exists());
+        assert!(repo.worktrees().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_exec_gates_continue() {
+        let (repo_path, _temp_dir) = hooked_repo("post-squish", "true");
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "a.txt", "main\n", "Add a on main").unwrap();
+        change_to_branch(&repo_path, "topic").unwrap();
+        let options = SquishOptions::new()
+            .exec("! grep -q debug a.txt")
+            .pause_on_conflict(true);
+        let error = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap_err();
+        assert!(matches!(error, SquishError::Paused { .. }), "{error}");
+
+        // The resolution keeps the debug output, which the command still catches.
+        fs::write(repo_path.join("a.txt"), "main\ndebug\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let error = continue_squash(&repo_path).unwrap_err();
+        assert!(matches!(error, SquishError::ExecFailed { .. }), "{error}");
+    }
+}
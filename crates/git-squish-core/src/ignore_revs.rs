@@ -0,0 +1,118 @@
+use git2::{Commit, Oid, Repository};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::SquishError;
+
+/// Commits which should not contribute to generated squash messages, read from the file
+/// named by `squish.ignoreRevsFile` (falling back to `blame.ignoreRevsFile`).
+#[derive(Debug, Default)]
+pub(crate) struct IgnoredRevs {
+    ids: HashSet<Oid>,
+    /// Author and message of each ignored commit. Rebasing rewrites commit ids but keeps
+    /// both, so this recognizes rebased copies of ignored commits.
+    fingerprints: HashSet<(String, String, i64, String)>,
+}
+
+impl IgnoredRevs {
+    /// Load the ignore-revs file configured for the repository, if any.
+    ///
+    /// The file uses the `git blame --ignore-revs-file` format: one full commit id per
+    /// line, with `#` comments and blank lines ignored.
+    pub fn load(repo: &Repository) -> Result<Self, SquishError> {
+        let config = repo.config()?;
+        let path = match config
+            .get_path("squish.ignoreRevsFile")
+            .or_else(|_| config.get_path("blame.ignoreRevsFile"))
+        {
+            Ok(path) => path,
+            Err(_) => return Ok(Self::default()),
+        };
+        let path = match repo.workdir() {
+            Some(workdir) if path.is_relative() => workdir.join(path),
+            _ => path,
+        };
+        Self::from_file(repo, &path)
+    }
+
+    fn from_file(repo: &Repository, path: &Path) -> Result<Self, SquishError> {
+        let contents = fs::read_to_string(path).map_err(|source| SquishError::Io {
+            message: format!("Failed to read {}", path.display()),
+            source,
+        })?;
+
+        let mut ignored = Self::default();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let id = Oid::from_str(line)
+                .ok()
+                .filter(|_| line.len() == 40)
+                .ok_or_else(|| SquishError::Other {
+                    message: format!("Invalid commit id '{line}' in {}", path.display()),
+                })?;
+            ignored.ids.insert(id);
+            // Commits missing from this clone can still be matched by id.
+            if let Ok(commit) = repo.find_commit(id) {
+                ignored.fingerprints.insert(fingerprint(&commit));
+            }
+        }
+        Ok(ignored)
+    }
+
+    /// Whether `commit` (or the commit it was rebased from) is ignored.
+    pub fn contains(&self, commit: &Commit) -> bool {
+        self.ids.contains(&commit.id()) || self.fingerprints.contains(&fingerprint(commit))
+    }
+}
+
+fn fingerprint(commit: &Commit) -> (String, String, i64, String) {
+    let author = commit.author();
+    (
+        author.name().unwrap_or_default().to_string(),
+        author.email().unwrap_or_default().to_string(),
+        author.when().seconds(),
+        commit.message().unwrap_or_default().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, init_test_repo};
+
+    #[test]
+    fn test_load_ignored_revs() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let first = commit_file(&repo_path, "a.txt", "a\n", "Format everything").unwrap();
+        let second = commit_file(&repo_path, "a.txt", "b\n", "Real change").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        // Nothing configured, nothing ignored.
+        let ignored = IgnoredRevs::load(&repo).unwrap();
+        assert!(!ignored.contains(&repo.find_commit(first).unwrap()));
+
+        fs::write(
+            repo_path.join(".git-blame-ignore-revs"),
+            format!("# Formatting\n{first} # rustfmt\n\n"),
+        )
+        .unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("blame.ignoreRevsFile", ".git-blame-ignore-revs")
+            .unwrap();
+        let ignored = IgnoredRevs::load(&repo).unwrap();
+        assert!(ignored.contains(&repo.find_commit(first).unwrap()));
+        assert!(!ignored.contains(&repo.find_commit(second).unwrap()));
+
+        fs::write(repo_path.join("revs"), "abc123\n").unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("squish.ignoreRevsFile", "revs")
+            .unwrap();
+        assert!(IgnoredRevs::load(&repo).is_err());
+    }
+}
@@ -0,0 +1,2530 @@
+use git2::{Oid, Repository, RepositoryOpenFlags};
+use std::path::Path;
+
+mod advise;
+mod autostash;
+mod batch;
+mod capabilities;
+mod classify;
+mod date;
+mod error;
+mod extension;
+mod filter;
+mod generated;
+mod hooks;
+mod ignore_revs;
+mod list;
+mod lock;
+mod message;
+mod message_transform;
+mod options;
+mod plan;
+mod protect;
+mod provenance;
+mod queue;
+mod rebase;
+mod release;
+mod remote;
+mod rerere;
+mod resquash;
+mod show;
+mod sign;
+mod signatures;
+mod split;
+mod state;
+mod stats;
+mod trace;
+mod transform;
+mod unsigned;
+mod verify;
+mod version;
+mod warning;
+mod watch;
+mod worktree;
+pub use advise::{Advice, AdviceKind, advise_branch, format_advice};
+pub use batch::{BatchEntry, BatchResult, create_squash_commits, squash_branches};
+pub use capabilities::{Capabilities, capabilities};
+pub use classify::{ClassifiedCommit, CommitKind, classify_commits, format_classification};
+pub use date::parse_date;
+pub use error::{ConflictedFile, SquishError};
+pub use list::{BranchSummary, format_branch_table, list_squashable_branches};
+pub use message::{
+    CleanupMode, MAX_MESSAGE_BYTES, MessageNormalization, cleanup_message, expand_template,
+    normalize_message, read_message,
+};
+pub use message_transform::{MessageCommand, MessageTransformer};
+pub use options::{SquishOptions, StrategyOption};
+pub use plan::{FileStat, SquishPlan, format_diffstat};
+pub use provenance::{SourceCommit, SquashProvenance, restore_squash, squash_provenance};
+pub use queue::{
+    SquashVerification, TrainMode, create_squash_commit, squash_train, verify_squash_commit,
+};
+pub use release::{
+    MESSAGE_PLACEHOLDER, ReleaseOptions, ReleaseResult, VERSION_PLACEHOLDER, release_branch,
+};
+pub use resquash::SQUASH_NOTES_REF;
+pub use show::render_commit;
+pub use sign::{CommitSigner, ProgramSigner};
+pub use signatures::SignatureCheck;
+pub use split::{GROUP_MARKER, SplitGroup, format_split_recommendation, recommend_splits};
+pub use stats::{
+    SquashRecord, SquashStats, format_squash_log, format_stats, squash_history, squash_stats,
+};
+pub use trace::{LIBGIT2_LOG_TARGET, enable_libgit2_trace};
+pub use transform::TreeTransformer;
+pub use unsigned::UnsignedSquash;
+pub use verify::verify_squash;
+pub use version::{BumpLevel, VersionBump};
+pub use warning::{SquashResult, SquishWarning};
+pub use watch::UpstreamWatcher;
+
+use lock::{BranchUpdate, SquishLock};
+use rebase::{RebaseOutcome, rebase_commits};
+use state::SquishState;
+use warning::Warnings;
+
+#[cfg(test)]
+mod oracle;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+/// Reflog message recorded when a squish moves a branch, used to find squishes to undo.
+const SQUASH_REFLOG_MESSAGE: &str = "squash commits into single commit";
+/// Reflog message recorded instead when a squish which paused on a conflict is finished.
+const RESOLVED_SQUASH_REFLOG_MESSAGE: &str =
+    "squash commits into single commit after resolving conflicts";
+
+/// Open the repository containing `repo_path`, honoring the environment variables git
+/// itself does: `GIT_WORK_TREE`, `GIT_COMMON_DIR`, `GIT_INDEX_FILE`, `GIT_OBJECT_DIRECTORY`,
+/// `GIT_CEILING_DIRECTORIES` and `GIT_DISCOVERY_ACROSS_FILESYSTEM`.
+///
+/// `repo_path` may be a worktree (or any directory inside it), a linked worktree, a
+/// worktree whose `.git` is a file pointing to a separate git dir, or a git dir itself. To
+/// honor `GIT_DIR` like git does, pass its value as `repo_path`.
+///
+/// Repositories using `extensions.preciousObjects` open normally, since squishing never
+/// deletes objects. Extensions which cannot be handled safely, such as reftable ref storage
+/// (`extensions.refStorage`) or SHA-256 object ids, fail with `UnsupportedExtension`.
+pub fn open_repository(repo_path: impl AsRef<Path>) -> Result<Repository, SquishError> {
+    let ceiling_dirs: Vec<std::path::PathBuf> = std::env::var_os("GIT_CEILING_DIRECTORIES")
+        .map(|dirs| std::env::split_paths(&dirs).collect())
+        .unwrap_or_default();
+    extension::allow_extensions();
+    Repository::open_ext(
+        repo_path.as_ref(),
+        RepositoryOpenFlags::FROM_ENV,
+        &ceiling_dirs,
+    )
+    .map_err(|e| {
+        extension::unsupported_extension(repo_path.as_ref(), &ceiling_dirs)
+            .unwrap_or_else(|| e.into())
+    })
+}
+
+/// Squash a branch onto an upstream branch, replacing the branch history with a single commit.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_refname` - The branch to squash (e.g., "feature" or "refs/heads/feature")
+/// * `upstream_spec` - The upstream to rebase onto (e.g., "main" or "origin/main")
+///
+/// # Returns
+/// The outcome on completion, or a SquishError if the operation fails.
+pub fn squash_branch(
+    repo_path: impl AsRef<Path>,
+    branch_refname: impl AsRef<str>,
+    upstream_spec: impl AsRef<str>,
+) -> Result<SquashResult, SquishError> {
+    squash_branch_with_options(
+        repo_path,
+        branch_refname,
+        upstream_spec,
+        &SquishOptions::default(),
+    )
+}
+
+/// Squash a branch onto an upstream branch using the provided options.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_refname` - The branch to squash (e.g., "feature" or "refs/heads/feature")
+/// * `upstream_spec` - The upstream to rebase onto (e.g., "main" or "origin/main")
+/// * `options` - Options controlling the squash (see `SquishOptions`)
+///
+/// # Returns
+/// The outcome, including any warnings, on completion, or a SquishError if the operation
+/// fails (or ran into a warning with `strict`).
+pub fn squash_branch_with_options(
+    repo_path: impl AsRef<Path>,
+    branch_refname: impl AsRef<str>,
+    upstream_spec: impl AsRef<str>,
+    options: &SquishOptions,
+) -> Result<SquashResult, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let branch_refname = branch_refname.as_ref();
+    let upstream_spec = upstream_spec.as_ref().to_string();
+    let repo = open_repository(repo_path)?;
+    let _lock = SquishLock::acquire(&repo)?;
+    if SquishState::exists(&repo) {
+        return Err(SquishError::Other {
+            message:
+                "A squish is already in progress, run `git squish continue` or `git squish abort`"
+                    .to_string(),
+        });
+    }
+
+    // A remote-tracking branch (e.g. a colleague's origin/feature) is squished through a
+    // local branch created from it, which is removed again if the squish fails.
+    let Some((local_refname, remote_branch)) =
+        remote::create_tracking_branch(&repo, branch_refname)?
+    else {
+        let branch_refname = normalize_branch_refname(&repo, branch_refname)?;
+        return squash_local_branch(repo_path, branch_refname, upstream_spec, options);
+    };
+    match squash_local_branch(repo_path, local_refname.clone(), upstream_spec, options) {
+        Ok(mut result) => {
+            result.message = format!(
+                "Created {local_refname} tracking {remote_branch}.\n{}",
+                result.message
+            );
+            Ok(result)
+        }
+        Err(e) => {
+            // Cleaning up is best effort, the squish error is what matters.
+            if !matches!(e, SquishError::Paused { .. })
+                && let Ok(reference) = repo.find_reference(&local_refname)
+            {
+                let _ = git2::Branch::wrap(reference).delete();
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Squash a local branch, given by its full refname, once any squish in progress has been
+/// ruled out.
+fn squash_local_branch(
+    repo_path: &Path,
+    branch_refname: String,
+    upstream_spec: String,
+    options: &SquishOptions,
+) -> Result<SquashResult, SquishError> {
+    let mut repo = open_repository(repo_path)?;
+    // Clear out anything an interrupted squish left behind.
+    SquishState::remove(&repo)?;
+    state::check_resumable(options)?;
+
+    if options.fetch {
+        remote::fetch_upstream(&repo, &upstream_spec)?;
+    }
+
+    // Rewriting a shared branch like main is almost never what was meant.
+    if !options.allow_protected {
+        protect::ensure_not_protected(&repo, &branch_refname)?;
+    }
+
+    // Rewriting a branch checked out in another worktree would leave that worktree stale.
+    worktree::ensure_not_checked_out_elsewhere(&repo, &branch_refname)?;
+
+    // Without a merge base the rebase would replay the whole history and fail confusingly.
+    ensure_related_histories(&repo, &branch_refname, &upstream_spec)?;
+
+    // A branch which is already one commit on top of the upstream tip (two with
+    // `keep_first`, plus any trailing commit of generated changes) has nothing to rewrite,
+    // so leave it alone rather than replacing it with an identical commit.
+    let already_squashed = {
+        let mut squash = find_branch(&repo, &branch_refname)?.peel_to_commit()?;
+        let upstream_tip = find_upstream(&repo, &upstream_spec)?.peel_to_commit()?;
+        if squash.summary() == Some(generated::GENERATED_SUBJECT) && squash.parent_count() == 1 {
+            squash = squash.parent(0)?;
+        }
+        squash.parent_ids().eq([upstream_tip.id()])
+            || (options.keep_first
+                && squash.parent_count() == 1
+                && squash.parent(0)?.parent_ids().eq([upstream_tip.id()]))
+    };
+    if already_squashed {
+        return Err(SquishError::AlreadySquashed {
+            branch: branch_refname,
+            upstream: upstream_spec,
+        });
+    }
+
+    // Identity problems (common in CI containers) should stop the squish before it starts.
+    let mut warnings = Warnings::new();
+    check_identity(&repo, options, &mut warnings)?;
+    // Nothing unverified goes into the squash when signatures are checked.
+    signatures::check_signatures(
+        &repo,
+        find_branch(&repo, &branch_refname)?.peel_to_commit()?.id(),
+        find_upstream(&repo, &upstream_spec)?.peel_to_commit()?.id(),
+        options,
+        &mut warnings,
+    )?;
+    warnings.check_strict(options)?;
+
+    // Uncommitted changes could end up mixed into the result: stash them or refuse to start.
+    let mut autostash_id = None;
+    if !repo.is_bare() {
+        let files = worktree::dirty_files(&repo)?;
+        if !files.is_empty() {
+            if autostash::is_enabled(&repo, options.autostash) {
+                autostash_id = autostash::stash(&mut repo)?;
+            } else if !options.force {
+                return Err(SquishError::DirtyWorktree { files });
+            } else if options.pause_on_conflict {
+                // Pausing writes the conflicted files over the worktree, and continuing or
+                // aborting resets it, so the local changes would be lost.
+                return Err(SquishError::Other {
+                    message: format!(
+                        "Cannot pause on conflicts over uncommitted changes, commit or stash them first (or use --autostash):\n  {}",
+                        files.join("\n  ")
+                    ),
+                });
+            } else {
+                warnings.push(SquishWarning::UncommittedChanges { files });
+                warnings.check_strict(options)?;
+            }
+        }
+    }
+
+    let result = rebase_and_squash(
+        &repo,
+        branch_refname.clone(),
+        upstream_spec.clone(),
+        options,
+        autostash_id,
+        &mut warnings,
+    );
+    let (mut result, autostash_warning) = finish_autostash(repo_path, autostash_id, result)?;
+    warnings.extend(autostash_warning);
+    // The branch has already moved, but strict callers still want to know something is off.
+    warnings.check_strict(options)?;
+
+    if options.push {
+        let pushed =
+            remote::push_branch(&repo, &branch_refname, &upstream_spec, options.set_upstream)?;
+        result.message = format!("{}\n{pushed}", result.message);
+    }
+    hooks::run_post_squish(
+        &repo,
+        result.old_id,
+        result.new_id,
+        &result.branch,
+        &mut warnings,
+    )?;
+    result.warnings = warnings.into_vec();
+    Ok(result)
+}
+
+/// Rebase the branch onto the upstream and squash it, pausing on conflicts if requested.
+fn rebase_and_squash(
+    repo: &Repository,
+    branch_refname: String,
+    upstream_spec: String,
+    options: &SquishOptions,
+    autostash_id: Option<Oid>,
+    warnings: &mut Warnings,
+) -> Result<SquashResult, SquishError> {
+    // Resolve the branch head to an AnnotatedCommit.
+    let branch_ref = find_branch(repo, &branch_refname)?;
+    let branch_annot = repo.reference_to_annotated_commit(&branch_ref)?;
+
+    // Resolve upstream (you may pass "main" or "origin/main" etc.).
+    let upstream_obj = find_upstream(repo, &upstream_spec)?;
+    let upstream_id = upstream_obj.id();
+    check_expected_upstream(&upstream_spec, upstream_id, options)?;
+    let upstream_annot = repo.find_annotated_commit(upstream_id)?;
+
+    // --- 1) Standard rebase to linearize the topic branch onto upstream ---
+    // `continue` would squash the whole branch, so never pause when filtering commits, nor
+    // when squashing groups of commits, which are rebased again from the original commits.
+    let pausable = !filter::is_filtering(options) && !options.split_auto && !options.split_markers;
+    let rebase_options = options
+        .clone()
+        .pause_on_conflict(options.pause_on_conflict && pausable);
+    let rebased_tip_id = match rebase_commits(
+        repo,
+        &branch_annot,
+        &upstream_annot,
+        None,
+        &rebase_options,
+        warnings,
+    )? {
+        RebaseOutcome::Complete(rebased_tip_id) => rebased_tip_id,
+        RebaseOutcome::Conflict {
+            stopped_id,
+            rebased_tip_id,
+            index,
+        } => {
+            let state = SquishState {
+                branch_refname,
+                upstream_id,
+                upstream_spec,
+                orig_tip_id: branch_annot.id(),
+                orig_head: state::current_head(repo)?,
+                rebased_tip_id,
+                stopped_id,
+                options: options.clone(),
+                rerere: Vec::new(),
+                autostash: autostash_id,
+            };
+            return Err(pause_squash(repo, &state, &index)?);
+        }
+    };
+
+    // Stop before anything is written if strict mode objects to how the rebase went.
+    warnings.check_strict(options)?;
+
+    // --- 2) "Squash" by replacing the rebased linear series with ONE commit ---
+    let new_id = commit_squash(
+        repo,
+        &branch_refname,
+        branch_annot.id(),
+        upstream_id,
+        rebased_tip_id,
+        options,
+        warnings,
+        SQUASH_REFLOG_MESSAGE,
+    )?;
+
+    Ok(SquashResult {
+        message: format!("✅ Successfully rebased and updated {branch_refname}."),
+        commits: squashed_commits(repo, branch_annot.id(), upstream_id)?,
+        branch: branch_refname,
+        old_id: branch_annot.id(),
+        new_id,
+        warnings: Vec::new(),
+    })
+}
+
+/// The commits of the branch at `orig_tip_id` which squashing onto `upstream_id` folds
+/// together, oldest first.
+pub(crate) fn squashed_commits(
+    repo: &Repository,
+    orig_tip_id: Oid,
+    upstream_id: Oid,
+) -> Result<Vec<Oid>, SquishError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push(orig_tip_id)?;
+    revwalk.hide(upstream_id)?;
+    Ok(revwalk.collect::<Result<_, _>>()?)
+}
+
+/// Resume a squish which was paused on a conflict.
+///
+/// The conflicts must have been resolved and staged (`git add`) in the worktree. The
+/// resolution is committed, the remaining commits are rebased and the branch is squashed.
+/// If another commit conflicts, the squish pauses again.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// The outcome on completion, or a SquishError if the operation fails or pauses again.
+pub fn continue_squash(repo_path: impl AsRef<Path>) -> Result<SquashResult, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let repo = open_repository(repo_path)?;
+    let _lock = SquishLock::acquire(&repo)?;
+    let mut state = SquishState::load(&repo)?;
+    worktree::ensure_not_checked_out_elsewhere(&repo, &state.branch_refname)?;
+
+    // Finish with the options the squish was started with.
+    let options = state.options.clone();
+    let mut warnings = Warnings::new();
+    check_identity(&repo, &options, &mut warnings)?;
+
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(SquishError::Other {
+            message: "Unresolved conflicts remain, resolve them and stage the result with git add"
+                .to_string(),
+        });
+    }
+
+    // Record the resolutions for rerere so the same conflicts resolve themselves next time.
+    for record in &state.rerere {
+        if let Some(entry) = index.get_path(std::path::Path::new(&record.path), 0) {
+            let blob = repo.find_blob(entry.id)?;
+            rerere::record_postimage(&repo, record, blob.content())?;
+        }
+    }
+
+    // Commit the resolution on top of the commits rebased so far, keeping the original
+    // author and message. A resolution which leaves nothing to commit drops the commit.
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let stopped = repo.find_commit(state.stopped_id)?;
+    let parent = repo.find_commit(state.rebased_tip_id)?;
+    let resolved_id = if tree.id() == parent.tree_id() {
+        parent.id()
+    } else {
+        let sig = repo.signature()?;
+        let message = stopped.message().unwrap_or_default();
+        repo.commit(None, &stopped.author(), &sig, message, &tree, &[&parent])?
+    };
+
+    // Rebase whatever came after the stopped commit onto the resolution.
+    let orig_tip_annot = repo.find_annotated_commit(state.orig_tip_id)?;
+    let stopped_annot = repo.find_annotated_commit(state.stopped_id)?;
+    let onto_annot = repo.find_annotated_commit(resolved_id)?;
+
+    let rebased_tip_id = match rebase_commits(
+        &repo,
+        &orig_tip_annot,
+        &stopped_annot,
+        Some(&onto_annot),
+        &options,
+        &mut warnings,
+    )? {
+        RebaseOutcome::Complete(rebased_tip_id) => rebased_tip_id,
+        RebaseOutcome::Conflict {
+            stopped_id,
+            rebased_tip_id,
+            index,
+        } => {
+            state.stopped_id = stopped_id;
+            state.rebased_tip_id = rebased_tip_id;
+            return Err(pause_squash(&repo, &state, &index)?);
+        }
+    };
+    warnings.check_strict(&options)?;
+
+    let new_id = commit_squash(
+        &repo,
+        &state.branch_refname,
+        state.orig_tip_id,
+        state.upstream_id,
+        rebased_tip_id,
+        &options,
+        &mut warnings,
+        RESOLVED_SQUASH_REFLOG_MESSAGE,
+    )?;
+    state::restore_head(&repo, &state.orig_head)?;
+    SquishState::remove(&repo)?;
+
+    let result = SquashResult {
+        message: format!(
+            "✅ Successfully rebased and updated {}.",
+            state.branch_refname
+        ),
+        commits: squashed_commits(&repo, state.orig_tip_id, state.upstream_id)?,
+        branch: state.branch_refname,
+        old_id: state.orig_tip_id,
+        new_id,
+        warnings: Vec::new(),
+    };
+    let (mut result, autostash_warning) = finish_autostash(repo_path, state.autostash, Ok(result))?;
+    warnings.extend(autostash_warning);
+    warnings.check_strict(&options)?;
+
+    if options.push {
+        let pushed = remote::push_branch(
+            &repo,
+            &result.branch,
+            &state.upstream_spec,
+            options.set_upstream,
+        )?;
+        result.message = format!("{}\n{pushed}", result.message);
+    }
+    hooks::run_post_squish(
+        &repo,
+        result.old_id,
+        result.new_id,
+        &result.branch,
+        &mut warnings,
+    )?;
+    result.warnings = warnings.into_vec();
+    Ok(result)
+}
+
+/// Remove the squish lock (`.git/squish.lock`) whoever holds it, for a lock left behind
+/// which is not noticed as stale on its own, e.g. one taken by a squish on another machine
+/// sharing the checkout. Make sure no squish is still running first.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A message naming the process which held the lock, or saying there was no lock.
+pub fn force_unlock(repo_path: impl AsRef<Path>) -> Result<String, SquishError> {
+    let repo = open_repository(repo_path)?;
+    Ok(match lock::force_unlock(&repo)? {
+        None => "No squish lock to remove.".to_string(),
+        Some(owner) => {
+            let mut message = "Removed the squish lock".to_string();
+            if let Some(pid) = owner.pid {
+                message.push_str(&format!(" held by pid {pid}"));
+            }
+            if let Some(since) = owner.since {
+                message.push_str(&format!(" since {} UTC", date::format_datetime(since)));
+            }
+            message + "."
+        }
+    })
+}
+
+/// Abandon a squish which was paused on a conflict, restoring HEAD and the worktree to
+/// their state before the squish started. The branch itself is never moved while paused.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A success message on completion, or a SquishError if no squish is in progress.
+pub fn abort_squash(repo_path: impl AsRef<Path>) -> Result<String, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let repo = open_repository(repo_path)?;
+    let _lock = SquishLock::acquire(&repo)?;
+    let state = match SquishState::load(&repo) {
+        Ok(state) => state,
+        // A squish which crashed while pausing can leave state behind which cannot be
+        // read; clearing it is the only way to get going again.
+        Err(e) if SquishState::exists(&repo) => {
+            SquishState::remove(&repo)?;
+            return Ok(format!(
+                "✅ Removed the squish state, which could not be read ({e}). HEAD and the worktree were left as they are."
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    state::restore_head(&repo, &state.orig_head)?;
+    SquishState::remove(&repo)?;
+
+    let (message, autostash_warning) = finish_autostash(
+        repo_path,
+        state.autostash,
+        Ok(format!("✅ Aborted squish of {}.", state.branch_refname)),
+    )?;
+    Ok(match autostash_warning {
+        Some(warning) => format!("{message}\n{warning}"),
+        None => message,
+    })
+}
+
+/// Undo the last squish of a branch, pointing it back at the commits it had before.
+///
+/// The previous tip is taken from the branch's reflog, so this only works while the most
+/// recent update of the branch is the squish itself.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_refname` - The branch to restore (e.g., "feature" or "refs/heads/feature")
+///
+/// # Returns
+/// A success message on completion, or a SquishError if the branch was not just squished.
+pub fn undo_squash(
+    repo_path: impl AsRef<Path>,
+    branch_refname: impl AsRef<str>,
+) -> Result<String, SquishError> {
+    let repo = open_repository(repo_path)?;
+    let _lock = SquishLock::acquire(&repo)?;
+    let branch_refname = normalize_branch_refname(&repo, branch_refname.as_ref())?;
+    let branch_tip = find_branch(&repo, &branch_refname)?.peel_to_commit()?.id();
+
+    let reflog = repo.reflog(&branch_refname)?;
+    let previous_tip = match reflog.get(0) {
+        Some(entry) if is_squash_reflog_entry(&entry) && entry.id_new() == branch_tip => {
+            entry.id_old()
+        }
+        _ => {
+            return Err(SquishError::Other {
+                message: format!("The last update of {branch_refname} was not a squish"),
+            });
+        }
+    };
+
+    let update = BranchUpdate::lock(&repo, &branch_refname, branch_tip)?;
+    worktree::checkout_if_current(&repo, &branch_refname, previous_tip, false)?;
+    update.commit(previous_tip, "undo squash")?;
+
+    Ok(format!(
+        "✅ Restored {branch_refname} to {previous_tip:.7}."
+    ))
+}
+
+/// Whether a reflog entry records a squish.
+fn is_squash_reflog_entry(entry: &git2::ReflogEntry) -> bool {
+    matches!(
+        entry.message(),
+        Some(SQUASH_REFLOG_MESSAGE | RESOLVED_SQUASH_REFLOG_MESSAGE)
+    )
+}
+
+/// Describe the squish in progress, if any: what is being squished, the commit it paused
+/// on and the conflicts which are still unresolved.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+pub fn squash_status(repo_path: impl AsRef<Path>) -> Result<String, SquishError> {
+    let repo = open_repository(repo_path)?;
+    if !SquishState::exists(&repo) {
+        return Ok("No squish in progress.".to_string());
+    }
+    let state = SquishState::load(&repo)?;
+
+    let stopped = repo.find_commit(state.stopped_id)?;
+    let mut status = format!(
+        "Squishing {} onto {:.7}, paused on {:.7} ({}).",
+        state.branch_refname,
+        state.upstream_id,
+        state.stopped_id,
+        stopped.summary().unwrap_or_default()
+    );
+    let conflicts = ConflictedFile::from_index(&repo.index()?)?;
+    if conflicts.is_empty() {
+        status.push_str("\nAll conflicts are resolved, run `git squish continue` to finish.");
+    } else {
+        status.push_str("\nUnresolved conflicts:");
+        for file in &conflicts {
+            status.push_str(&format!("\n  {}", file.path));
+        }
+        status.push_str(
+            "\nResolve them, stage them with git add, then run `git squish continue` (or `git squish abort`).",
+        );
+    }
+    Ok(status)
+}
+
+/// Re-apply the autostash once the squish is over, returning a warning alongside the
+/// result if the changes were left in the stash. A paused squish keeps the stash until it
+/// is continued or aborted.
+fn finish_autostash<T>(
+    repo_path: &Path,
+    autostash_id: Option<Oid>,
+    result: Result<T, SquishError>,
+) -> Result<(T, Option<SquishWarning>), SquishError> {
+    let Some(stash_id) = autostash_id else {
+        return result.map(|value| (value, None));
+    };
+    let mut repo = open_repository(repo_path)?;
+    if SquishState::exists(&repo) {
+        return result.map(|value| (value, None));
+    }
+
+    match (result, autostash::apply(&mut repo, stash_id)) {
+        (Ok(value), Ok(warning)) => Ok((value, warning)),
+        (Ok(_), Err(e)) | (Err(e), _) => Err(e),
+    }
+}
+
+/// Persist `state`, write the conflicted `index` into the worktree and build the
+/// `SquishError::Paused` explaining how to proceed.
+fn pause_squash(
+    repo: &Repository,
+    state: &SquishState,
+    index: &git2::Index,
+) -> Result<SquishError, SquishError> {
+    let files = state::materialize_conflict(repo, state.rebased_tip_id, index)?;
+
+    // Remember the conflicts so `continue` can record how they were resolved.
+    let mut state = state.clone();
+    state.rerere.clear();
+    let saved = (|| {
+        if rerere::is_enabled(repo) {
+            let workdir = repo.workdir().ok_or_else(|| SquishError::Other {
+                message: "Cannot pause a squish in a bare repository".to_string(),
+            })?;
+            for file in &files {
+                if let Ok(contents) = std::fs::read(workdir.join(file))
+                    && let Some(record) = rerere::record_preimage(repo, file, &contents)?
+                {
+                    state.rerere.push(record);
+                }
+            }
+        }
+        state.save(repo)
+    })();
+    // Without its state the pause could be neither continued nor aborted, so undo it.
+    if let Err(e) = saved {
+        state::restore_head(repo, &state.orig_head)?;
+        SquishState::remove(repo)?;
+        return Err(e);
+    }
+
+    let stopped = repo.find_commit(state.stopped_id)?;
+    Ok(SquishError::Paused {
+        commit: state.stopped_id,
+        summary: stopped.summary().unwrap_or_default().to_string(),
+        files,
+    })
+}
+
+/// Look up the branch to squash, reporting a missing branch as `BranchNotFound`. Short
+/// names are accepted like everywhere else (see `normalize_branch_refname`).
+pub(crate) fn find_branch<'r>(
+    repo: &'r Repository,
+    branch_refname: &str,
+) -> Result<git2::Reference<'r>, SquishError> {
+    let branch_refname = &normalize_branch_refname(repo, branch_refname)?;
+    repo.find_reference(branch_refname)
+        .map_err(|source| match source.code() {
+            git2::ErrorCode::NotFound | git2::ErrorCode::InvalidSpec => {
+                SquishError::BranchNotFound {
+                    branch: branch_refname.to_string(),
+                    source,
+                }
+            }
+            _ => source.into(),
+        })
+}
+
+/// Resolve the upstream spec, reporting an unknown revision as `UpstreamNotFound`.
+pub(crate) fn find_upstream<'r>(
+    repo: &'r Repository,
+    upstream_spec: &str,
+) -> Result<git2::Object<'r>, SquishError> {
+    repo.revparse_single(upstream_spec)
+        .map_err(|source| match source.code() {
+            git2::ErrorCode::NotFound | git2::ErrorCode::InvalidSpec => {
+                SquishError::UpstreamNotFound {
+                    upstream: upstream_spec.to_string(),
+                    source,
+                }
+            }
+            _ => source.into(),
+        })
+}
+
+/// Fail with `UpstreamMoved` when `options` expect the upstream somewhere other than
+/// `upstream_id`, where it points now.
+pub(crate) fn check_expected_upstream(
+    upstream_spec: &str,
+    upstream_id: Oid,
+    options: &SquishOptions,
+) -> Result<(), SquishError> {
+    match options.expect_upstream {
+        Some(expected) if expected != upstream_id => Err(SquishError::UpstreamMoved {
+            upstream: upstream_spec.to_string(),
+            expected,
+            actual: upstream_id,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Fail with `UnrelatedHistories` when the branch and the upstream have no merge base.
+pub(crate) fn ensure_related_histories(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+) -> Result<(), SquishError> {
+    let branch_id = find_branch(repo, branch_refname)?.peel_to_commit()?.id();
+    let upstream_id = find_upstream(repo, upstream_spec)?.peel_to_commit()?.id();
+    match repo.merge_base(branch_id, upstream_id) {
+        Ok(_) => Ok(()),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Err(SquishError::UnrelatedHistories {
+            branch: branch_refname.to_string(),
+            upstream: upstream_spec.to_string(),
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the rebased linear series with new commits on top of `upstream_id` and point
+/// the branch, which must still be at `orig_tip_id`, at them, recording `reflog_message`.
+/// The commits written depend on the options:
+/// - filtering (by author or date, keeping the first commit or skipping commits): one of
+///   the selected commits, with the others kept around it;
+/// - `split_auto` or `split_markers`: one per recommended split or marker group;
+/// - `split_path`: one per pathspec, plus one for the remaining changes;
+/// - otherwise: ONE commit.
+#[allow(clippy::too_many_arguments)]
+fn commit_squash(
+    repo: &Repository,
+    branch_refname: &str,
+    orig_tip_id: Oid,
+    upstream_id: Oid,
+    rebased_tip_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+    reflog_message: &str,
+) -> Result<Oid, SquishError> {
+    verify::check_verifiable(options)?;
+    let new_commit_id = if filter::is_filtering(options) {
+        filter::write_filtered_commits(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        )?
+    } else if options.split_auto || options.split_markers {
+        split::write_split_commits(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        )?
+    } else if !options.split_paths.is_empty() {
+        split::write_pathspec_commits(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        )?
+    } else {
+        write_squash_commit(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        )?
+    };
+    // The commits are only written so far; leave the branch alone if they lost content, the
+    // pre-commit hook or an exec command rejects them or strict mode objects.
+    if options.verify {
+        verify::verify_transformed(repo, new_commit_id, rebased_tip_id, warnings)?;
+    }
+    let new_tree = repo.find_commit(new_commit_id)?.tree()?;
+    hooks::check_squashed_tree(repo, upstream_id, &new_tree, options)?;
+    warnings.check_strict(options)?;
+
+    // Lock the branch so nothing commits to it while the worktree is brought along. If it
+    // is checked out here, the index and files move before the ref does (HEAD follows the
+    // branch ref on its own).
+    let update = BranchUpdate::lock(repo, branch_refname, orig_tip_id)?;
+    worktree::checkout_if_current(repo, branch_refname, new_commit_id, options.force)?;
+    update.commit(new_commit_id, reflog_message)?;
+    provenance::record_squash(
+        repo,
+        new_commit_id,
+        branch_refname,
+        upstream_id,
+        orig_tip_id,
+        options,
+    )?;
+
+    Ok(new_commit_id)
+}
+
+/// Write ONE commit with the tree of `rebased_tip_id` on top of `upstream_id`, without
+/// updating any ref.
+pub(crate) fn write_squash_commit(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_id: Oid,
+    rebased_tip_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    // Every commit was already on the upstream (or dropped as empty while rebasing).
+    if rebased_tip_id == upstream_id {
+        if !options.allow_empty {
+            return Err(SquishError::NothingToSquash {
+                branch: branch_refname.to_string(),
+            });
+        }
+        let upstream_parent = repo.find_commit(upstream_id)?;
+        let message = options
+            .custom_message()
+            .unwrap_or_else(|| format!("Empty squash of {branch_refname}\n"));
+        let tree = transform::transform_tree(repo, upstream_parent.tree()?, options, warnings)?;
+        return write_commit(repo, &message, &tree, &upstream_parent, options, warnings);
+    }
+
+    // Fetch the rebased branch tip and its tree, as changed by any tree transformers.
+    let rebased_tip = repo.find_commit(rebased_tip_id)?;
+    let rebased_tree = transform::transform_tree(repo, rebased_tip.tree()?, options, warnings)?;
+
+    // Parent of the squash commit is the upstream commit we rebased onto.
+    let upstream_parent = repo.find_commit(upstream_id)?;
+
+    // Compose a sensible commit message:
+    //   - take the first (oldest) commit's subject + append shortened list
+    //     of included commits (optional, tweak as you like).
+    let (message, author) = resquash::squash_message_and_author(
+        repo,
+        branch_refname,
+        &upstream_parent,
+        &rebased_tip,
+        options,
+    )?;
+
+    // Changes to generated paths go into a commit of their own on top, keeping the squash
+    // commit down to the hand-written changes.
+    let patterns = generated::generated_patterns(repo, options)?;
+    let upstream_tree = upstream_parent.tree()?;
+    if let Some((hand_written_tree, paths)) =
+        generated::split_generated(repo, &upstream_tree, &rebased_tree, &patterns)?
+    {
+        let squash_id = write_commit_as(
+            repo,
+            &message,
+            &hand_written_tree,
+            &upstream_parent,
+            author.as_ref(),
+            options,
+            warnings,
+        )?;
+        return write_commit(
+            repo,
+            &generated::generated_message(&paths),
+            &rebased_tree,
+            &repo.find_commit(squash_id)?,
+            options,
+            warnings,
+        );
+    }
+
+    write_commit_as(
+        repo,
+        &message,
+        &rebased_tree,
+        &upstream_parent,
+        author.as_ref(),
+        options,
+        warnings,
+    )
+}
+
+/// Load the key to sign commits with, unless `options` say not to sign: the signer given
+/// in `options`, or the program selected by `squish.signingBackend`, or else the
+/// configured git signing key when `options` ask to sign or, by default, when
+/// `commit.gpgsign` is set. A key which cannot
+/// be loaded is reported as `SigningSkipped` and the commit is written unsigned, unless
+/// signing was asked for explicitly.
+fn signing_key(
+    repo: &Repository,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Option<Box<dyn CommitSigner>>, SquishError> {
+    if options.sign == Some(false) {
+        return Ok(None);
+    }
+    if let Some(signer) = &options.signer {
+        return Ok(Some(Box::new(signer.clone())));
+    }
+    if let Some(signer) = sign::backend_signer(repo)? {
+        return Ok(Some(Box::new(signer)));
+    }
+    let sign = match options.sign {
+        Some(sign) => sign,
+        None => repo.config()?.get_bool("commit.gpgsign").unwrap_or(false),
+    };
+    if !sign {
+        return Ok(None);
+    }
+    match sign::user_signer(repo) {
+        Ok(signer) => Ok(Some(signer)),
+        Err(e) if options.sign == Some(true) => Err(SquishError::Other {
+            message: format!("Cannot sign the squash commit: {}", e.message()),
+        }),
+        Err(e) => {
+            warnings.push(SquishWarning::SigningSkipped {
+                reason: e.message().to_string(),
+            });
+            Ok(None)
+        }
+    }
+}
+
+/// Make sure commits can be written before anything is changed: fail with
+/// `MissingIdentity` when no committer identity is configured, and report a signing key
+/// which cannot be loaded now rather than halfway through.
+pub(crate) fn check_identity(
+    repo: &Repository,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<(), SquishError> {
+    repo.signature()
+        .map_err(|source| SquishError::MissingIdentity { source })?;
+    signing_key(repo, options, warnings)?;
+    Ok(())
+}
+
+/// Add the custom trailers to `message`, clean it up like `git commit -F` would, honoring
+/// commit.cleanup, run it through any message transformers and apply the configured
+/// message normalization.
+pub(crate) fn finish_message(
+    repo: &Repository,
+    message: &str,
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    let (config_cleanup, comment_char) = message::cleanup_config(&repo.config()?)?;
+    let cleanup = options.cleanup.unwrap_or(config_cleanup);
+    let message = message::add_custom_trailers(message.to_string(), options);
+    let message = cleanup_message(&message, cleanup, &comment_char, false);
+    let message = message_transform::transform_message(repo, message, options)?;
+    let normalization =
+        message::normalization_config(&repo.config()?)?.union(options.normalization);
+    Ok(normalize_message(&message, &normalization))
+}
+
+/// The raw commit object for a commit with `tree` on top of `parent`, with `message`
+/// cleaned up and any custom headers added: exactly what gets signed.
+pub(crate) fn commit_buffer(
+    repo: &Repository,
+    message: &str,
+    tree: &git2::Tree,
+    parent: &git2::Commit,
+    author: Option<&git2::Signature>,
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    let message = finish_message(repo, message, options)?;
+    if message.trim().is_empty() && !options.allow_empty_message {
+        return Err(SquishError::EmptyMessage);
+    }
+
+    // The new commit has the exact tree given (i.e., all changes combined) and a single
+    // parent: the upstream base. Extra headers go into the raw commit object before it is
+    // signed, so the signature covers them like it covers the standard ones.
+    let sig = repo.signature()?;
+    let author = author.unwrap_or(&sig);
+    let content = repo.commit_create_buffer(author, &sig, &message, tree, &[parent])?;
+    let content = content.as_str().ok_or_else(|| SquishError::Other {
+        message: "The commit object is not valid UTF-8".to_string(),
+    })?;
+    add_commit_headers(content, &options.headers)
+}
+
+/// Write a commit with `tree` on top of `parent`, cleaning up `message` and signing the
+/// commit as configured, without updating any ref. A signing key which cannot be loaded
+/// adds a warning and the commit is written unsigned.
+pub(crate) fn write_commit(
+    repo: &Repository,
+    message: &str,
+    tree: &git2::Tree,
+    parent: &git2::Commit,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    write_commit_as(repo, message, tree, parent, None, options, warnings)
+}
+
+/// Write a commit like `write_commit`, by `author` instead of the committer when given.
+fn write_commit_as(
+    repo: &Repository,
+    message: &str,
+    tree: &git2::Tree,
+    parent: &git2::Commit,
+    author: Option<&git2::Signature>,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    let content = commit_buffer(repo, message, tree, parent, author, options)?;
+    match signing_key(repo, options, warnings)? {
+        Some(signer) => Ok(repo.commit_signed(&content, &signer.sign(&content)?, None)?),
+        None => Ok(repo
+            .odb()?
+            .write(git2::ObjectType::Commit, content.as_bytes())?),
+    }
+}
+
+/// Headers git itself writes or interprets, which must not be added as custom headers.
+const RESERVED_COMMIT_HEADERS: [&str; 8] = [
+    "tree",
+    "parent",
+    "author",
+    "committer",
+    "encoding",
+    "gpgsig",
+    "gpgsig-sha256",
+    "mergetag",
+];
+
+/// Insert `headers` after the standard headers of the raw commit object `content`. Values
+/// spanning several lines are written with continuation lines, like `gpgsig`.
+fn add_commit_headers(content: &str, headers: &[(String, String)]) -> Result<String, SquishError> {
+    if headers.is_empty() {
+        return Ok(content.to_string());
+    }
+    let (standard, message) = content.split_once("\n\n").unwrap_or((content, ""));
+    let mut out = standard.to_string();
+    for (name, value) in headers {
+        if name.is_empty()
+            || name.contains(|c: char| c.is_whitespace() || c.is_control())
+            || RESERVED_COMMIT_HEADERS.contains(&name.as_str())
+        {
+            return Err(SquishError::Other {
+                message: format!("Cannot add a commit header named '{name}'"),
+            });
+        }
+        out.push_str(&format!("\n{name} {}", value.replace('\n', "\n ")));
+    }
+    out.push_str("\n\n");
+    out.push_str(message);
+    Ok(out)
+}
+
+/// Detect the upstream to squash a branch onto when none is given: the branch's configured
+/// upstream (`branch.<name>.remote` and `branch.<name>.merge`, i.e. `@{upstream}`), falling
+/// back to the branch `origin/HEAD` points at.
+///
+/// # Returns
+/// The full reference name of the upstream (e.g., "refs/remotes/origin/main").
+pub fn detect_upstream(repo: &Repository, branch_refname: &str) -> Result<String, SquishError> {
+    let branch_refname = &normalize_branch_refname(repo, branch_refname)?;
+    if let Ok(upstream) = repo.branch_upstream_name(branch_refname)
+        && let Some(upstream) = upstream.as_str()
+    {
+        return Ok(upstream.to_string());
+    }
+
+    if let Ok(origin_head) = repo.find_reference("refs/remotes/origin/HEAD")
+        && let Some(target) = origin_head.symbolic_target()
+    {
+        return Ok(target.to_string());
+    }
+
+    Err(SquishError::Other {
+        message: format!(
+            "No upstream is configured for {branch_refname} and origin/HEAD is not set, pass the upstream explicitly"
+        ),
+    })
+}
+
+/// Turn the name of the branch to squash into its full refname: `feature` becomes
+/// "refs/heads/feature", `HEAD` the current branch, and full refnames are kept as they are.
+///
+/// A short name which also names a tag or a remote-tracking branch is rejected with
+/// `SquishError::AmbiguousBranch` rather than guessing which one was meant.
+pub fn normalize_branch_refname(repo: &Repository, branch: &str) -> Result<String, SquishError> {
+    if branch == "HEAD" {
+        return current_branch_refname(repo);
+    }
+    if branch.starts_with("refs/") {
+        return Ok(branch.to_string());
+    }
+
+    let refname = format!("refs/heads/{branch}");
+    if repo.find_reference(&refname).is_ok() {
+        let others: Vec<String> = [
+            format!("refs/tags/{branch}"),
+            format!("refs/remotes/{branch}"),
+        ]
+        .into_iter()
+        .filter(|other| repo.find_reference(other).is_ok())
+        .collect();
+        if !others.is_empty() {
+            return Err(SquishError::AmbiguousBranch {
+                branch: branch.to_string(),
+                candidates: std::iter::once(refname).chain(others).collect(),
+            });
+        }
+    }
+    Ok(refname)
+}
+
+/// Get the current branch name from the repository's HEAD.
+/// Returns the full reference name (e.g., "refs/heads/feature").
+#[deprecated(since = "0.2.0", note = "renamed to `current_branch_refname`")]
+pub fn get_current_branch_name(repo: &Repository) -> Result<String, SquishError> {
+    current_branch_refname(repo)
+}
+
+/// The full reference name of the branch checked out in the repository (e.g.,
+/// "refs/heads/feature"). With a detached HEAD, the branch pointing at the HEAD commit is
+/// used instead.
+pub fn current_branch_refname(repo: &Repository) -> Result<String, SquishError> {
+    let head = repo.head()?;
+
+    if head.is_branch()
+        && let Some(name) = head.name()
+    {
+        Ok(name.to_string())
+    } else {
+        // HEAD is detached, get the current commit and find which branch points to it
+        let head_commit = head.target().ok_or_else(|| SquishError::Other {
+            message: "HEAD does not point to a valid commit".to_string(),
+        })?;
+
+        // Look for a branch that points to the same commit
+        let mut branches = repo.branches(Some(git2::BranchType::Local))?;
+        for branch_result in &mut branches {
+            let (branch, _) = branch_result?;
+            if let Some(target) = branch.get().target()
+                && target == head_commit
+                && let Some(branch_name) = branch.get().name()
+            {
+                return Ok(branch_name.to_string());
+            }
+        }
+
+        Err(SquishError::DetachedHead)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        change_to_branch, clone_test_repo, commit_file, create_branch, get_current_commit_message,
+        init_test_repo, read_head_file, topic_repo,
+    };
+    use std::fs;
+
+    /// Read the contents of a file in the repository.
+    fn read_file_contents(
+        repo_path: &std::path::Path,
+        filename: &str,
+    ) -> Result<String, SquishError> {
+        let file_path = repo_path.join(filename);
+        fs::read_to_string(file_path).map_err(|e| SquishError::Other {
+            message: format!("Failed to read file {}: {}", filename, e),
+        })
+    }
+
+    #[test]
+    fn test_squish_topic_branch_workflow() {
+        // Clone the test repository
+        let (repo_path, _temp_dir) = clone_test_repo().expect("Failed to clone test repository");
+
+        // Checkout the topic branch
+        change_to_branch(&repo_path, "topic").expect("Failed to checkout topic branch");
+
+        // Get the current branch name (should be refs/heads/topic)
+        let repo = Repository::open(&repo_path).expect("Failed to open repository");
+        let branch_refname =
+            current_branch_refname(&repo).expect("Failed to get current branch name");
+
+        // Squish the topic branch against main
+        let repo_path_str = repo_path.to_str().expect("Invalid repo path");
+        let result = squash_branch(repo_path_str, branch_refname, "main");
+
+        assert!(
+            result.is_ok(),
+            "Squash operation failed: {:?}",
+            result.err()
+        );
+
+        // Verify the log message is "Topic Branch Start"
+        let commit_message =
+            get_current_commit_message(&repo_path).expect("Failed to get commit message");
+
+        assert_eq!(
+            commit_message.trim(),
+            "Topic Branch Start",
+            "Expected commit message 'Topic Branch Start', got: '{}'",
+            commit_message
+        );
+
+        // Verify the contents of text.txt
+        let file_contents =
+            read_file_contents(&repo_path, "text.txt").expect("Failed to read text.txt");
+
+        let expected_contents = "\
+Thu Aug 14 15:10:43 EDT 2025
+Thu Aug 14 15:11:01 EDT 2025
+Thu Aug 14 15:11:04 EDT 2025
+Thu Aug 14 15:11:07 EDT 2025
+Thu Aug 14 15:49:25 EDT 2025
+";
+
+        assert_eq!(
+            file_contents, expected_contents,
+            "text.txt contents don't match expected values.\nExpected:\n{}\nActual:\n{}",
+            expected_contents, file_contents
+        );
+    }
+
+    #[test]
+    fn test_squish_conflict_branch_should_fail() {
+        // Clone the test repository
+        let (repo_path, _temp_dir) = clone_test_repo().expect("Failed to clone test repository");
+
+        // Checkout the conflict branch
+        change_to_branch(&repo_path, "conflict").expect("Failed to checkout conflict branch");
+
+        // Get the current branch name (should be refs/heads/conflict)
+        let repo = Repository::open(&repo_path).expect("Failed to open repository");
+        let branch_refname =
+            current_branch_refname(&repo).expect("Failed to get current branch name");
+
+        // First, make sure we have the topic branch locally
+        change_to_branch(&repo_path, "topic").expect("Failed to ensure topic branch exists");
+        change_to_branch(&repo_path, "conflict").expect("Failed to return to conflict branch");
+
+        // Try to squish the conflict branch against topic - this should fail with a merge conflict
+        let repo_path_str = repo_path.to_str().expect("Invalid repo path");
+        let result = squash_branch(repo_path_str, branch_refname, "topic");
+
+        // Assert that the operation failed
+        assert!(
+            result.is_err(),
+            "Expected squash operation to fail due to merge conflict, but it succeeded"
+        );
+
+        // Verify that it's a conflict error listing the conflicted files
+        let error = result.unwrap_err();
+        assert!(
+            error.to_string().contains("conflict"),
+            "Expected conflict-related error message, got: '{}'",
+            error
+        );
+        match error {
+            SquishError::Conflict { files, .. } => {
+                assert!(!files.is_empty(), "Expected conflicted files to be listed");
+            }
+            _ => panic!("Expected SquishError::Conflict, got: {:?}", error),
+        }
+    }
+
+    /// Build a repository where `main` and `topic` both rewrite the same line of text.txt.
+    fn conflicting_repo() -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().expect("Failed to init test repository");
+        commit_file(&repo_path, "text.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "text.txt", "topic\n", "Topic change").unwrap();
+        commit_file(&repo_path, "topic.txt", "topic only\n", "Topic file").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "text.txt", "main\n", "Main change").unwrap();
+        commit_file(&repo_path, "main.txt", "main only\n", "Main file").unwrap();
+        change_to_branch(&repo_path, "topic").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_squish_conflict_without_strategy_option_fails() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let topic_change = repo.find_commit(topic_tip).unwrap().parent_id(0).unwrap();
+
+        let result = squash_branch(repo_path.to_str().unwrap(), "refs/heads/topic", "main");
+
+        match result {
+            Err(SquishError::Conflict { commit, files }) => {
+                assert_eq!(commit, topic_change);
+                assert_eq!(files.len(), 1);
+                let file = &files[0];
+                assert_eq!(file.path, "text.txt");
+                assert!(file.ancestor.is_some());
+                let blob = |id: Option<git2::Oid>| {
+                    let blob = repo.find_blob(id.unwrap()).unwrap();
+                    String::from_utf8_lossy(blob.content()).to_string()
+                };
+                assert_eq!(blob(file.ours), "main\n");
+                assert_eq!(blob(file.theirs), "topic\n");
+            }
+            other => panic!("Expected SquishError::Conflict, got: {:?}", other),
+        }
+        // Nothing was rewritten.
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+    }
+
+    #[test]
+    fn test_squish_conflict_with_strategy_option() {
+        let cases = [
+            (StrategyOption::Theirs, "topic\n", "Topic change\n"),
+            // Favoring upstream empties the first commit, so it is dropped from the squash.
+            (StrategyOption::Ours, "main\n", "Topic file\n"),
+        ];
+
+        for (strategy_option, expected, expected_message) in cases {
+            let (repo_path, _temp_dir) = conflicting_repo();
+            let options = SquishOptions::new().strategy_option(strategy_option);
+
+            let result = squash_branch_with_options(
+                repo_path.to_str().unwrap(),
+                "refs/heads/topic",
+                "main",
+                &options,
+            );
+
+            assert!(
+                result.is_ok(),
+                "Squash with {:?} failed: {:?}",
+                strategy_option,
+                result.err()
+            );
+            assert_eq!(read_head_file(&repo_path, "text.txt").unwrap(), expected);
+            assert_eq!(
+                read_head_file(&repo_path, "topic.txt").unwrap(),
+                "topic only\n"
+            );
+            // Upstream changes must survive the squash.
+            assert_eq!(
+                read_head_file(&repo_path, "main.txt").unwrap(),
+                "main only\n"
+            );
+            assert_eq!(
+                get_current_commit_message(&repo_path).unwrap(),
+                expected_message
+            );
+        }
+    }
+
+    #[test]
+    fn test_pause_on_conflict_then_abort() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let orig_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        let options = SquishOptions::new().pause_on_conflict(true);
+        let result =
+            squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options);
+
+        let error = result.expect_err("Expected the squash to pause");
+        assert!(error.to_string().contains("text.txt"));
+        assert!(SquishState::exists(&repo));
+        assert!(repo.head_detached().unwrap());
+        assert!(repo.index().unwrap().has_conflicts());
+        let worktree_file = fs::read_to_string(repo_path.join("text.txt")).unwrap();
+        assert!(worktree_file.contains("<<<<<<<"));
+        // Nothing looks like a `git rebase` in progress; the squish keeps to its own refs.
+        assert!(!repo.path().join("ORIG_HEAD").exists());
+        assert!(!repo.path().join("REBASE_HEAD").exists());
+        assert!(!repo.path().join("rebase-merge").exists());
+        assert_eq!(
+            repo.refname_to_id("refs/worktree/squish/orig-tip").unwrap(),
+            orig_tip
+        );
+
+        // A new squish must not start while one is paused.
+        assert!(squash_branch(repo_path_str, "refs/heads/topic", "main").is_err());
+
+        abort_squash(repo_path_str).expect("Abort failed");
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(!SquishState::exists(&repo));
+        assert!(
+            repo.find_reference("refs/worktree/squish/orig-tip")
+                .is_err()
+        );
+        assert_eq!(state::current_head(&repo).unwrap(), "refs/heads/topic");
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), orig_tip);
+        assert!(!repo.index().unwrap().has_conflicts());
+        assert_eq!(
+            fs::read_to_string(repo_path.join("text.txt")).unwrap(),
+            "topic\n"
+        );
+    }
+
+    #[test]
+    fn test_pause_on_conflict_then_continue() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+        let orig_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let topic_change = repo.find_commit(orig_tip).unwrap().parent_id(0).unwrap();
+
+        let options = SquishOptions::new().pause_on_conflict(true);
+        let result =
+            squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options);
+        match result {
+            Err(SquishError::Paused { commit, files, .. }) => {
+                assert_eq!(commit, topic_change);
+                assert_eq!(files, vec!["text.txt".to_string()]);
+            }
+            other => panic!("Expected the squash to pause, got {other:?}"),
+        }
+
+        // Continuing before resolving must fail and keep the state around.
+        assert!(continue_squash(repo_path_str).is_err());
+        assert!(SquishState::exists(&repo));
+
+        fs::write(repo_path.join("text.txt"), "resolved\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("text.txt")).unwrap();
+        index.write().unwrap();
+
+        let result = continue_squash(repo_path_str).expect("Continue failed");
+
+        assert!(!SquishState::exists(&repo));
+        assert_eq!(state::current_head(&repo).unwrap(), "refs/heads/topic");
+        let head_commit = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(result.branch, "refs/heads/topic");
+        assert_eq!(result.old_id, orig_tip);
+        assert_eq!(result.new_id, head_commit.id());
+        assert_eq!(result.commits, vec![topic_change, orig_tip]);
+        assert_eq!(head_commit.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(head_commit.message(), Some("Topic change\n"));
+        assert_eq!(
+            read_head_file(&repo_path, "text.txt").unwrap(),
+            "resolved\n"
+        );
+        assert_eq!(
+            read_head_file(&repo_path, "topic.txt").unwrap(),
+            "topic only\n"
+        );
+        assert_eq!(
+            read_head_file(&repo_path, "main.txt").unwrap(),
+            "main only\n"
+        );
+
+        // The squish is recorded as one which had to resolve conflicts.
+        let records = squash_history(&repo, None).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].conflicts);
+    }
+
+    #[test]
+    fn test_pause_refuses_uncommitted_changes_under_force() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let orig_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        fs::write(repo_path.join("topic.txt"), "local edit\n").unwrap();
+
+        let options = SquishOptions::new().force(true).pause_on_conflict(true);
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        );
+
+        match result {
+            Err(SquishError::Other { message }) => {
+                assert!(message.contains("topic.txt"), "{message}");
+            }
+            other => panic!("Expected the squash to refuse to start, got {other:?}"),
+        }
+        assert!(!SquishState::exists(&repo));
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), orig_tip);
+        assert_eq!(
+            fs::read_to_string(repo_path.join("topic.txt")).unwrap(),
+            "local edit\n"
+        );
+    }
+
+    /// Squash the topic branch of `conflicting_repo` with `options` (which must pause on
+    /// conflicts), and resolve and stage the conflict it pauses on.
+    fn pause_and_resolve(repo_path: &std::path::Path, options: &SquishOptions) {
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            options,
+        );
+        let repo = Repository::open(repo_path).unwrap();
+        assert!(
+            SquishState::exists(&repo),
+            "Expected the squash to pause, got {result:?}"
+        );
+        fs::write(repo_path.join("text.txt"), "resolved\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("text.txt")).unwrap();
+        index.write().unwrap();
+    }
+
+    #[test]
+    fn test_strict_applies_on_continue() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        // Main already has this change, so the commit is dropped as empty when rebased.
+        commit_file(&repo_path, "main.txt", "main only\n", "Add main.txt too").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new().pause_on_conflict(true).strict(true);
+        pause_and_resolve(&repo_path, &options);
+
+        let error = continue_squash(repo_path.to_str().unwrap()).unwrap_err();
+
+        assert!(
+            matches!(error, SquishError::Strict { .. }),
+            "unexpected error: {error}"
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+    }
+
+    #[test]
+    fn test_continue_keeps_options() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let options = SquishOptions::new()
+            .pause_on_conflict(true)
+            .header("X-Ticket", "ABC-123")
+            .annotate_sources(true);
+        pause_and_resolve(&repo_path, &options);
+
+        let result = continue_squash(&repo_path).expect("Continue failed");
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(result.new_id).unwrap();
+        let header = commit.header_field_bytes("X-Ticket").unwrap();
+        assert_eq!(header.as_str(), Some("ABC-123"));
+        assert!(commit.message().unwrap().contains("\nSquashed-from: "));
+    }
+
+    #[test]
+    fn test_continue_keeps_message() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let options = SquishOptions::new()
+            .pause_on_conflict(true)
+            .message("Change {what}\n\nResolved on main.\n")
+            .template_variable("what", "the text")
+            .trailer("Build: {build}")
+            .template_variable("build", "42");
+        pause_and_resolve(&repo_path, &options);
+
+        let result = continue_squash(&repo_path).expect("Continue failed");
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(
+            commit.message(),
+            Some("Change the text\n\nResolved on main.\n\nBuild: 42\n")
+        );
+    }
+
+    #[test]
+    fn test_pause_rejects_options_continue_cannot_run() {
+        /// Leaves the tree as it is.
+        struct Unchanged;
+
+        impl TreeTransformer for Unchanged {
+            fn name(&self) -> String {
+                "unchanged".to_string()
+            }
+
+            fn transform(
+                &self,
+                _repo: &Repository,
+                _tree: &git2::Tree,
+            ) -> Result<Option<Oid>, SquishError> {
+                Ok(None)
+            }
+        }
+
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let pausing = SquishOptions::new().pause_on_conflict(true);
+
+        for options in [
+            pausing.clone().tree_transformer(Unchanged),
+            pausing
+                .clone()
+                .message_transformer(MessageCommand::new("cat")),
+        ] {
+            let error = squash_branch_with_options(&repo_path, "topic", "main", &options)
+                .unwrap_err()
+                .to_string();
+
+            assert!(error.contains("Cannot pause on conflicts"), "{error}");
+            assert!(!SquishState::exists(&repo));
+        }
+    }
+
+    #[test]
+    fn test_split_auto_never_pauses() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let options = SquishOptions::new()
+            .split_auto(true)
+            .pause_on_conflict(true);
+
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        );
+
+        assert!(
+            matches!(result, Err(SquishError::Conflict { .. })),
+            "Expected a conflict, got {result:?}"
+        );
+        assert!(!SquishState::exists(&repo));
+    }
+
+    #[test]
+    fn test_continue_and_abort_without_squish_in_progress() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+
+        assert!(continue_squash(repo_path_str).is_err());
+        assert!(abort_squash(repo_path_str).is_err());
+    }
+
+    #[test]
+    fn test_abort_clears_unreadable_state() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let state_dir = SquishState::dir(&repo);
+        std::fs::create_dir_all(&state_dir).unwrap();
+        std::fs::write(state_dir.join("state"), "branch refs/heads/topic\n").unwrap();
+        assert!(continue_squash(&repo_path).is_err());
+
+        abort_squash(&repo_path).unwrap();
+        assert!(!SquishState::exists(&repo));
+        squash_branch_with_options(
+            &repo_path,
+            "topic",
+            "main",
+            &SquishOptions::new().strategy_option(StrategyOption::Theirs),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_squish_updates_current_worktree() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let options = SquishOptions::new().strategy_option(StrategyOption::Theirs);
+
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect("Squash failed");
+
+        // The upstream file is brought into the worktree and nothing shows as modified.
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo_path.join("main.txt")).unwrap(),
+            "main only\n"
+        );
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_squish_branch_checked_out_in_linked_worktree() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let worktree_dir = tempfile::tempdir().unwrap();
+        let worktree_path = worktree_dir.path().join("topic-wt");
+        let topic_ref = repo.find_reference("refs/heads/topic").unwrap();
+        let mut add_opts = git2::WorktreeAddOptions::new();
+        add_opts.reference(Some(&topic_ref));
+        repo.worktree("topic-wt", &worktree_path, Some(&add_opts))
+            .unwrap();
+        let options = SquishOptions::new().strategy_option(StrategyOption::Theirs);
+
+        // Squishing from the main worktree must not rewrite the branch under the linked one.
+        let error = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect_err("Expected the squash to be refused");
+        assert!(error.to_string().contains("another worktree"));
+
+        // Squishing from the linked worktree updates its HEAD and files.
+        squash_branch_with_options(
+            worktree_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect("Squash from linked worktree failed");
+
+        let worktree_repo = Repository::open(&worktree_path).unwrap();
+        let head = worktree_repo.head().unwrap();
+        assert_eq!(head.name(), Some("refs/heads/topic"));
+        assert_eq!(head.peel_to_commit().unwrap().parent_count(), 1);
+        assert_eq!(
+            fs::read_to_string(worktree_path.join("main.txt")).unwrap(),
+            "main only\n"
+        );
+        assert!(worktree_repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rerere_reuses_resolution_recorded_on_continue() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_bool("rerere.enabled", true)
+            .unwrap();
+        let orig_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        // Resolve the conflict once through pause and continue, which records it.
+        let options = SquishOptions::new().pause_on_conflict(true);
+        let result =
+            squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options);
+        assert!(result.is_err(), "Expected the squash to pause");
+        fs::write(repo_path.join("text.txt"), "resolved\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("text.txt")).unwrap();
+        index.write().unwrap();
+        continue_squash(repo_path_str).expect("Continue failed");
+
+        // Put the branch back and squish again: the recorded resolution applies itself.
+        let mut topic = repo.find_reference("refs/heads/topic").unwrap();
+        topic.set_target(orig_tip, "reset topic").unwrap();
+        let head_commit = repo.find_commit(orig_tip).unwrap();
+        repo.reset(head_commit.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+
+        let result = squash_branch(repo_path_str, "refs/heads/topic", "main");
+        assert!(result.is_ok(), "Squash failed: {:?}", result.err());
+        assert_eq!(
+            read_head_file(&repo_path, "text.txt").unwrap(),
+            "resolved\n"
+        );
+        assert_eq!(
+            read_head_file(&repo_path, "topic.txt").unwrap(),
+            "topic only\n"
+        );
+    }
+
+    #[test]
+    fn test_squish_missing_branch_or_upstream() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+
+        let error = squash_branch(repo_path_str, "refs/heads/missing", "main").unwrap_err();
+        assert!(
+            matches!(error, SquishError::BranchNotFound { ref branch, .. } if branch == "refs/heads/missing")
+        );
+        assert!(std::error::Error::source(&error).is_some());
+
+        let error = squash_branch(repo_path_str, "refs/heads/topic", "origin/missing").unwrap_err();
+        assert!(
+            matches!(error, SquishError::UpstreamNotFound { ref upstream, .. } if upstream == "origin/missing")
+        );
+    }
+
+    #[test]
+    fn test_squish_unrelated_histories() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let signature = repo.signature().unwrap();
+        let tree = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_tree()
+            .unwrap();
+        repo.commit(
+            Some("refs/heads/orphan"),
+            &signature,
+            &signature,
+            "Unrelated root",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        let error =
+            squash_branch(repo_path.to_str().unwrap(), "refs/heads/topic", "orphan").unwrap_err();
+        assert!(matches!(
+            error,
+            SquishError::UnrelatedHistories { ref branch, ref upstream }
+                if branch == "refs/heads/topic" && upstream == "orphan"
+        ));
+    }
+
+    #[test]
+    fn test_squish_nothing_to_squash() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        change_to_branch(&repo_path, "main").unwrap();
+        create_branch(&repo_path, "empty").unwrap();
+
+        let error =
+            squash_branch(repo_path.to_str().unwrap(), "refs/heads/empty", "main").unwrap_err();
+        assert!(
+            matches!(error, SquishError::NothingToSquash { ref branch } if branch == "refs/heads/empty")
+        );
+    }
+
+    #[test]
+    fn test_squish_protected_branch() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+        let upstream = repo.find_commit(main_tip).unwrap().parent_id(0).unwrap();
+        let upstream = upstream.to_string();
+
+        let error = squash_branch(repo_path_str, "refs/heads/main", upstream.clone()).unwrap_err();
+        assert!(matches!(error, SquishError::ProtectedBranch { .. }));
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), main_tip);
+
+        // main is one commit on top of its parent, so once allowed there is nothing to do.
+        let error = squash_branch_with_options(
+            repo_path_str,
+            "refs/heads/main",
+            upstream,
+            &SquishOptions::new().allow_protected(true),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SquishError::AlreadySquashed { .. }));
+    }
+
+    #[test]
+    fn test_squish_empty_message() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "text.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "").unwrap();
+        let tip = commit_file(&repo_path, "b.txt", "b\n", "Second").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let squash = |options: &SquishOptions| {
+            squash_branch_with_options(&repo_path, "topic", "main", options)
+        };
+
+        assert!(matches!(
+            squash(&SquishOptions::new()).unwrap_err(),
+            SquishError::EmptyMessage
+        ));
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+
+        let result = squash(&SquishOptions::new().allow_empty_message(true)).unwrap();
+        assert_eq!(repo.find_commit(result.new_id).unwrap().message(), Some(""));
+    }
+
+    #[test]
+    fn test_squish_with_given_message() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let options = SquishOptions::new()
+            .strategy_option(StrategyOption::Theirs)
+            .message("Release 1.0\n\n\n\n- Topic change\n");
+        let repo = Repository::open(&repo_path).unwrap();
+        let plan = SquishPlan::compute_with_options(&repo, "topic", "main", &options).unwrap();
+        assert_eq!(plan.message, "Release 1.0\n\n- Topic change\n");
+
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        assert_eq!(
+            repo.find_commit(result.new_id).unwrap().message(),
+            Some(plan.message.as_str())
+        );
+    }
+
+    #[test]
+    fn test_squish_with_commit_headers() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let squash = |options: SquishOptions| {
+            squash_branch_with_options(
+                &repo_path,
+                "topic",
+                "main",
+                &options.strategy_option(StrategyOption::Theirs),
+            )
+        };
+
+        assert!(squash(SquishOptions::new().header("parent", "x")).is_err());
+        let result = squash(
+            SquishOptions::new()
+                .header("squish-version", "0.1.4")
+                .header("x-provider", "line one\nline two"),
+        )
+        .unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(
+            commit
+                .header_field_bytes("squish-version")
+                .unwrap()
+                .as_str(),
+            Some("0.1.4")
+        );
+        assert_eq!(
+            commit.header_field_bytes("x-provider").unwrap().as_str(),
+            Some("line one\nline two")
+        );
+        assert_eq!(commit.message(), Some("Topic change\n"));
+        assert_eq!(
+            commit.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_squish_nothing_to_squash_allow_empty() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        change_to_branch(&repo_path, "main").unwrap();
+        create_branch(&repo_path, "empty").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/empty",
+            "main",
+            &SquishOptions::new().allow_empty(true),
+        )
+        .expect("Squash failed");
+
+        let commit = repo
+            .find_commit(repo.refname_to_id("refs/heads/empty").unwrap())
+            .unwrap();
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(
+            commit.tree_id(),
+            repo.find_commit(main_tip).unwrap().tree_id()
+        );
+        assert_eq!(commit.message(), Some("Empty squash of refs/heads/empty\n"));
+    }
+
+    #[test]
+    fn test_squish_without_identity_fails_before_changing_anything() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        fs::write(repo_path.join("text.txt"), "local edit\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        // An empty name shadows any global identity, like a CI container without one.
+        repo.config().unwrap().set_str("user.name", "").unwrap();
+
+        let options = SquishOptions::new()
+            .strategy_option(StrategyOption::Ours)
+            .autostash(true);
+        let error = squash_branch_with_options(&repo_path, "refs/heads/topic", "main", &options)
+            .unwrap_err();
+
+        assert!(matches!(error, SquishError::MissingIdentity { .. }));
+        assert!(error.to_string().contains("git config user.name"));
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+        assert!(repo.find_reference("refs/stash").is_err());
+        assert_eq!(
+            fs::read_to_string(repo_path.join("text.txt")).unwrap(),
+            "local edit\n"
+        );
+    }
+
+    #[test]
+    fn test_squish_refuses_to_overwrite_local_changes() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        fs::write(repo_path.join("text.txt"), "local edit\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new().strategy_option(StrategyOption::Ours);
+
+        let error = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap_err();
+
+        match error {
+            SquishError::DirtyWorktree { files } => assert_eq!(files, vec!["text.txt"]),
+            other => panic!("Expected SquishError::DirtyWorktree, got: {:?}", other),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+        assert_eq!(
+            fs::read_to_string(repo_path.join("text.txt")).unwrap(),
+            "local edit\n"
+        );
+    }
+
+    #[test]
+    fn test_squish_with_force_keeps_local_changes() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        fs::write(repo_path.join("text.txt"), "local edit\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new()
+            .strategy_option(StrategyOption::Ours)
+            .force(true);
+
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect("Squash failed");
+
+        assert_ne!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+        assert_eq!(
+            fs::read_to_string(repo_path.join("text.txt")).unwrap(),
+            "local edit\n"
+        );
+        assert_eq!(
+            fs::read_to_string(repo_path.join("main.txt")).unwrap(),
+            "main only\n"
+        );
+    }
+
+    #[test]
+    fn test_squish_strict_fails_on_warnings() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        fs::write(repo_path.join("text.txt"), "local edit\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new()
+            .strategy_option(StrategyOption::Ours)
+            .force(true);
+
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options.clone().strict(true),
+        );
+        match result {
+            Err(SquishError::Strict { warnings }) => {
+                assert_eq!(
+                    warnings,
+                    vec![SquishWarning::UncommittedChanges {
+                        files: vec!["text.txt".to_string()]
+                    }]
+                );
+            }
+            other => panic!("Expected a strict error, got {other:?}"),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect("Squash failed");
+        assert_eq!(result.warnings.len(), 2);
+        assert!(matches!(
+            &result.warnings[1],
+            SquishWarning::EmptyCommitDropped { summary, .. } if summary == "Topic change"
+        ));
+    }
+
+    #[test]
+    fn test_squish_skips_commits_already_applied_upstream() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        let hotfix = commit_file(&repo_path, "fix.txt", "fix\n", "Hotfix").unwrap();
+        commit_file(&repo_path, "feature.txt", "feature\n", "Add feature").unwrap();
+
+        // The hotfix landed upstream on its own and was built on there, so replaying it
+        // would conflict.
+        change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut index = repo
+            .cherrypick_commit(&repo.find_commit(hotfix).unwrap(), &main_tip, 0, None)
+            .unwrap();
+        let tree = repo.find_tree(index.write_tree_to(&repo).unwrap()).unwrap();
+        let signature = repo.signature().unwrap();
+        let landed = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Hotfix (cherry picked)",
+                &tree,
+                &[&main_tip],
+            )
+            .unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        commit_file(&repo_path, "fix.txt", "better fix\n", "Improve hotfix").unwrap();
+
+        let result = squash_branch(repo_path.to_str().unwrap(), "refs/heads/topic", "main")
+            .expect("Squash failed");
+        assert_eq!(
+            result.warnings,
+            vec![SquishWarning::AlreadyApplied {
+                commit: hotfix,
+                summary: "Hotfix".to_string(),
+                upstream_commit: landed,
+            }]
+        );
+        let squashed = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(squashed.message(), Some("Add feature\n"));
+        assert_eq!(
+            squashed.parent_id(0).unwrap(),
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_squish_with_autostash() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        fs::write(repo_path.join("topic.txt"), "local edit\n").unwrap();
+        let options = SquishOptions::new()
+            .strategy_option(StrategyOption::Theirs)
+            .autostash(true);
+
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .expect("Squash failed");
+
+        let mut repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo_path.join("topic.txt")).unwrap(),
+            "local edit\n"
+        );
+        assert_eq!(
+            fs::read_to_string(repo_path.join("main.txt")).unwrap(),
+            "main only\n"
+        );
+        let mut stashes = 0;
+        repo.stash_foreach(|_, _, _| {
+            stashes += 1;
+            true
+        })
+        .unwrap();
+        assert_eq!(stashes, 0);
+    }
+
+    #[test]
+    fn test_autostash_kept_until_paused_squish_is_aborted() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        fs::write(repo_path.join("topic.txt"), "local edit\n").unwrap();
+        let options = SquishOptions::new().pause_on_conflict(true).autostash(true);
+
+        assert!(
+            squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options,)
+                .is_err()
+        );
+        // The local edit stays stashed while the conflict is being resolved.
+        assert!(
+            fs::read_to_string(repo_path.join("text.txt"))
+                .unwrap()
+                .contains("<<<<<<<")
+        );
+
+        abort_squash(repo_path_str).expect("Abort failed");
+        assert_eq!(
+            fs::read_to_string(repo_path.join("topic.txt")).unwrap(),
+            "local edit\n"
+        );
+        assert_eq!(
+            fs::read_to_string(repo_path.join("text.txt")).unwrap(),
+            "topic\n"
+        );
+    }
+
+    #[test]
+    fn test_squish_already_squashed_branch_is_left_alone() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        let options = SquishOptions::new().strategy_option(StrategyOption::Theirs);
+
+        squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options)
+            .expect("Squash failed");
+        let repo = Repository::open(&repo_path).unwrap();
+        let squashed = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        let error = squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options)
+            .unwrap_err();
+        assert!(matches!(error, SquishError::AlreadySquashed { .. }));
+        assert_eq!(
+            error.to_string(),
+            "refs/heads/topic is already a single commit on top of main"
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), squashed);
+    }
+
+    #[test]
+    fn test_squish_skips_ignored_revs_in_message() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        let formatting = commit_file(&repo_path, "base.txt", "base \n", "Run rustfmt").unwrap();
+        commit_file(&repo_path, "topic.txt", "topic\n", "Add topic").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "main.txt", "main\n", "Main change").unwrap();
+        fs::write(repo_path.join("ignore-revs"), format!("{formatting}\n")).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("squish.ignoreRevsFile", "ignore-revs")
+            .unwrap();
+
+        squash_branch(repo_path.to_str().unwrap(), "refs/heads/topic", "main")
+            .expect("Squash failed");
+
+        // The formatting commit was rebased (new id) but is still recognized and skipped.
+        let squashed = repo
+            .find_commit(repo.refname_to_id("refs/heads/topic").unwrap())
+            .unwrap();
+        assert_eq!(squashed.message(), Some("Add topic\n"));
+    }
+
+    #[test]
+    fn test_detect_upstream() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(detect_upstream(&repo, "refs/heads/topic").is_err());
+
+        // origin/HEAD is the fallback.
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+        repo.reference("refs/remotes/origin/main", main_tip, false, "test")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            false,
+            "test",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_upstream(&repo, "refs/heads/topic").unwrap(),
+            "refs/remotes/origin/main"
+        );
+
+        // The branch's tracking configuration wins.
+        let mut config = repo.config().unwrap();
+        config.set_str("branch.topic.remote", ".").unwrap();
+        config
+            .set_str("branch.topic.merge", "refs/heads/main")
+            .unwrap();
+        assert_eq!(
+            detect_upstream(&repo, "refs/heads/topic").unwrap(),
+            "refs/heads/main"
+        );
+    }
+
+    #[test]
+    fn test_current_branch_refname_detached_head() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let detached = repo.find_commit(topic_tip).unwrap().parent_id(0).unwrap();
+        repo.set_head_detached(detached).unwrap();
+
+        assert!(matches!(
+            current_branch_refname(&repo),
+            Err(SquishError::DetachedHead)
+        ));
+    }
+
+    #[test]
+    fn test_normalize_branch_refname() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        for name in ["topic", "refs/heads/topic", "HEAD"] {
+            assert_eq!(
+                normalize_branch_refname(&repo, name).unwrap(),
+                "refs/heads/topic"
+            );
+        }
+        assert_eq!(
+            normalize_branch_refname(&repo, "missing").unwrap(),
+            "refs/heads/missing"
+        );
+
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+        repo.reference("refs/tags/main", main_tip, false, "test")
+            .unwrap();
+        let error = normalize_branch_refname(&repo, "main").unwrap_err();
+        assert!(matches!(
+            error,
+            SquishError::AmbiguousBranch { ref candidates, .. }
+                if candidates == &["refs/heads/main", "refs/tags/main"]
+        ));
+        assert_eq!(
+            normalize_branch_refname(&repo, "refs/heads/main").unwrap(),
+            "refs/heads/main"
+        );
+
+        // Short names work for the whole squish, which reports the full refname.
+        let result = squash_branch_with_options(
+            &repo_path,
+            "topic",
+            "refs/heads/main",
+            &SquishOptions::new().strategy_option(StrategyOption::Theirs),
+        )
+        .unwrap();
+        assert_eq!(result.branch, "refs/heads/topic");
+    }
+
+    #[test]
+    fn test_squish_separate_git_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let git_dir = temp_dir.path().join("repo.git");
+        let work_dir = temp_dir.path().join("work");
+        let mut init_opts = git2::RepositoryInitOptions::new();
+        init_opts.initial_head("main").workdir_path(&work_dir);
+        let repo = Repository::init_opts(&git_dir, &init_opts).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test User").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        assert!(work_dir.join(".git").is_file());
+
+        commit_file(&work_dir, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&work_dir, "topic").unwrap();
+        commit_file(&work_dir, "src/a.txt", "a\n", "Add a").unwrap();
+        commit_file(&work_dir, "src/b.txt", "b\n", "Add b").unwrap();
+
+        // The repository is found from the worktree, a directory inside it and the git dir.
+        for repo_path in [&work_dir, &work_dir.join("src"), &git_dir] {
+            let repo = open_repository(repo_path.to_str().unwrap()).unwrap();
+            assert_eq!(
+                repo.workdir().unwrap().canonicalize().unwrap(),
+                work_dir.canonicalize().unwrap()
+            );
+        }
+
+        squash_branch(
+            work_dir.join("src").to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+        )
+        .unwrap();
+
+        let repo = open_repository(git_dir.to_str().unwrap()).unwrap();
+        let tip = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(tip.summary(), Some("Add a"));
+        assert_eq!(tip.parent_count(), 1);
+        assert_eq!(
+            fs::read_to_string(work_dir.join("src/b.txt")).unwrap(),
+            "b\n"
+        );
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo_squash() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo_path_str = repo_path.to_str().unwrap();
+        let orig_tip = Repository::open(&repo_path)
+            .unwrap()
+            .refname_to_id("refs/heads/topic")
+            .unwrap();
+
+        // Nothing to undo before the branch was squished.
+        assert!(undo_squash(repo_path_str, "refs/heads/topic").is_err());
+
+        squash_branch(repo_path_str, "refs/heads/topic", "main").unwrap();
+        let message = undo_squash(repo_path_str, "refs/heads/topic").unwrap();
+        assert_eq!(
+            message,
+            format!("✅ Restored refs/heads/topic to {orig_tip:.7}.")
+        );
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), orig_tip);
+        assert!(repo.statuses(None).unwrap().is_empty());
+
+        // The undo itself is not a squish, so it can't be undone again.
+        assert!(undo_squash(repo_path_str, "refs/heads/topic").is_err());
+    }
+
+    #[test]
+    fn test_squash_status() {
+        let (repo_path, _temp_dir) = conflicting_repo();
+        let repo_path_str = repo_path.to_str().unwrap();
+        assert_eq!(
+            squash_status(repo_path_str).unwrap(),
+            "No squish in progress."
+        );
+
+        let options = SquishOptions::new().pause_on_conflict(true);
+        squash_branch_with_options(repo_path_str, "refs/heads/topic", "main", &options)
+            .unwrap_err();
+        let status = squash_status(repo_path_str).unwrap();
+        assert!(status.starts_with("Squishing refs/heads/topic onto "));
+        assert!(status.contains("(Topic change)"));
+        assert!(status.contains("Unresolved conflicts:\n  text.txt\n"));
+
+        fs::write(repo_path.join("text.txt"), "resolved\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("text.txt")).unwrap();
+        index.write().unwrap();
+        assert!(
+            squash_status(repo_path_str)
+                .unwrap()
+                .ends_with("All conflicts are resolved, run `git squish continue` to finish.")
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use git2::{BranchType, Repository};
+
+use crate::SquishError;
+
+/// Summary of a local branch relative to an upstream, as returned by
+/// `list_squashable_branches`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct BranchSummary {
+    /// Full reference name (e.g., "refs/heads/feature").
+    pub refname: String,
+    /// Short branch name (e.g., "feature").
+    pub name: String,
+    /// Number of commits on the branch which are not on the upstream.
+    pub ahead: usize,
+    /// Number of commits on the upstream which are not on the branch.
+    pub behind: usize,
+    /// Whether combining the branch with the upstream is predicted to conflict.
+    pub conflicts: bool,
+    /// Commit time of the branch tip, in seconds since the Unix epoch.
+    pub last_activity: i64,
+}
+
+impl BranchSummary {
+    /// Whether squishing would change the branch: it has commits to squash and is not
+    /// already a single commit on top of the upstream.
+    pub fn is_squashable(&self) -> bool {
+        self.ahead > 1 || (self.ahead == 1 && self.behind > 0)
+    }
+}
+
+/// List every local branch with the data needed to decide whether to squish it onto
+/// `upstream_spec`: ahead/behind counts, predicted conflicts and last activity.
+///
+/// Conflicts are predicted with an in-memory merge of the branch and upstream tips, which
+/// writes nothing to the repository. Branches are returned sorted by name.
+///
+/// # Arguments
+/// * `repo` - The repository to inspect
+/// * `upstream_spec` - The upstream to compare against (e.g., "main" or "origin/main")
+pub fn list_squashable_branches(
+    repo: &Repository,
+    upstream_spec: &str,
+) -> Result<Vec<BranchSummary>, SquishError> {
+    let upstream = crate::find_upstream(repo, upstream_spec)?.peel_to_commit()?;
+
+    let mut summaries = Vec::new();
+    for branch_result in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch_result?;
+        let reference = branch.get();
+        let (Some(refname), Some(name)) = (reference.name(), branch.name()?) else {
+            // Skip branches whose names are not valid UTF-8.
+            continue;
+        };
+        let tip = reference.peel_to_commit()?;
+
+        let (ahead, behind) = repo.graph_ahead_behind(tip.id(), upstream.id())?;
+        let conflicts = if ahead == 0 || behind == 0 {
+            // One side contains the other, so there is nothing to conflict with.
+            false
+        } else {
+            repo.merge_commits(&upstream, &tip, None)?.has_conflicts()
+        };
+
+        summaries.push(BranchSummary {
+            refname: refname.to_string(),
+            name: name.to_string(),
+            ahead,
+            behind,
+            conflicts,
+            last_activity: tip.time().seconds(),
+        });
+    }
+
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(summaries)
+}
+
+/// Render branch summaries as a plain text table.
+///
+/// # Arguments
+/// * `summaries` - The branches to render
+/// * `now` - The current time in seconds since the Unix epoch, used for relative times
+pub fn format_branch_table(summaries: &[BranchSummary], now: i64) -> String {
+    let headers = ["BRANCH", "AHEAD", "BEHIND", "CONFLICTS", "LAST ACTIVITY"];
+    let rows: Vec<[String; 5]> = summaries
+        .iter()
+        .map(|summary| {
+            [
+                summary.name.clone(),
+                summary.ahead.to_string(),
+                summary.behind.to_string(),
+                if summary.conflicts { "yes" } else { "no" }.to_string(),
+                format_relative_time(now - summary.last_activity),
+            ]
+        })
+        .collect();
+    format_table(headers, &rows)
+}
+
+/// Render rows as a plain text table with left-aligned columns under `headers`.
+pub(crate) fn format_table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    let mut push_row = |cells: &[&str]| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    };
+    push_row(&headers);
+    for row in rows {
+        push_row(&row.each_ref().map(String::as_str));
+    }
+    table
+}
+
+/// Format a duration in seconds the way `git log --date=relative` roughly does.
+fn format_relative_time(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    let (value, unit) = match seconds {
+        0..60 => (seconds, "second"),
+        60..3_600 => (seconds / 60, "minute"),
+        3_600..86_400 => (seconds / 3_600, "hour"),
+        86_400..2_592_000 => (seconds / 86_400, "day"),
+        2_592_000..31_536_000 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{value} {unit}{plural} ago")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    #[test]
+    fn test_list_squashable_branches() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "text.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "clean").unwrap();
+        commit_file(&repo_path, "clean.txt", "1\n", "Clean 1").unwrap();
+        commit_file(&repo_path, "clean.txt", "2\n", "Clean 2").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        create_branch(&repo_path, "conflict").unwrap();
+        commit_file(&repo_path, "text.txt", "conflict\n", "Conflict").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "text.txt", "main\n", "Main change").unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let summaries = list_squashable_branches(&repo, "main").unwrap();
+
+        let names: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["clean", "conflict", "main"]);
+
+        let clean = &summaries[0];
+        assert_eq!(clean.refname, "refs/heads/clean");
+        assert_eq!((clean.ahead, clean.behind), (2, 1));
+        assert!(!clean.conflicts);
+        assert!(clean.is_squashable());
+        assert!(clean.last_activity > 0);
+
+        let conflict = &summaries[1];
+        assert_eq!((conflict.ahead, conflict.behind), (1, 1));
+        assert!(conflict.conflicts);
+        assert!(conflict.is_squashable());
+
+        let main = &summaries[2];
+        assert_eq!((main.ahead, main.behind), (0, 0));
+        assert!(!main.is_squashable());
+    }
+
+    #[test]
+    fn test_format_branch_table() {
+        let summaries = vec![
+            BranchSummary {
+                refname: "refs/heads/feature".to_string(),
+                name: "feature".to_string(),
+                ahead: 12,
+                behind: 3,
+                conflicts: true,
+                last_activity: 1_000 - 7_200,
+            },
+            BranchSummary {
+                refname: "refs/heads/x".to_string(),
+                name: "x".to_string(),
+                ahead: 1,
+                behind: 0,
+                conflicts: false,
+                last_activity: 1_000 - 1,
+            },
+        ];
+
+        assert_eq!(
+            format_branch_table(&summaries, 1_000),
+            "\
+BRANCH   AHEAD  BEHIND  CONFLICTS  LAST ACTIVITY
+feature  12     3       yes        2 hours ago
+x        1      0       no         1 second ago
+"
+        );
+    }
+
+    #[test]
+    fn test_format_relative_time() {
+        assert_eq!(format_relative_time(-5), "0 seconds ago");
+        assert_eq!(format_relative_time(59), "59 seconds ago");
+        assert_eq!(format_relative_time(60), "1 minute ago");
+        assert_eq!(format_relative_time(86_400 * 3), "3 days ago");
+        assert_eq!(format_relative_time(31_536_000 * 2), "2 years ago");
+    }
+}
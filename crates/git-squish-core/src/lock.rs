@@ -0,0 +1,381 @@
+use git2::{ErrorCode, Oid, Repository, Transaction};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{SquishError, worktree};
+
+/// Name of the lock file (inside the common git dir) held while a squish changes the repository.
+const LOCK_FILE: &str = "squish.lock";
+/// Lock files git (and libgit2) leave behind when they crash, relative to the git dir.
+const GIT_LOCK_FILES: [&str; 2] = ["index.lock", "HEAD.lock"];
+
+/// Keeps other squishes out of the repository while one is running. The lock file holds
+/// the owner's process id and when it took the lock, so a lock left behind by a squish
+/// which crashed is noticed and taken over instead of blocking every later squish.
+/// Released when dropped.
+pub(crate) struct SquishLock {
+    path: PathBuf,
+}
+
+impl SquishLock {
+    /// Take the lock, removing a stale one whose process is gone.
+    ///
+    /// The lock lives in the git dir shared by all worktrees, since they share the branches
+    /// a squish rewrites. It is written to a file of its own and then linked into place, so
+    /// the lock file always holds its owner and one being written is never taken for stale.
+    ///
+    /// Fails with `Locked` when another squish holds the lock (or a lock with no owner
+    /// recorded, which only the user can tell is stale), or when git lock files
+    /// (`index.lock`, `HEAD.lock`) show another git process is running
+    /// or one crashed, which only the user can tell apart.
+    pub fn acquire(repo: &Repository) -> Result<SquishLock, SquishError> {
+        let path = worktree::common_dir(repo)?.join(LOCK_FILE);
+        let pending = path.with_extension(format!("lock.{}", std::process::id()));
+        fs::write(&pending, format!("{} {}\n", std::process::id(), now())).map_err(|source| {
+            SquishError::Io {
+                message: format!("Failed to write {}", pending.display()),
+                source,
+            }
+        })?;
+        let linked = loop {
+            match fs::hard_link(&pending, &path) {
+                Ok(()) => break Ok(()),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let owner = LockOwner::read(&path);
+                    let Some(pid) = owner.pid.filter(|pid| !is_running(*pid)) else {
+                        break Err(SquishError::Locked {
+                            path: path.clone(),
+                            pid: owner.pid,
+                            since: owner.since,
+                        });
+                    };
+                    log::warn!(
+                        "Removing {} left behind by a crashed squish (pid {pid})",
+                        path.display()
+                    );
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::NotFound => {}
+                        Err(source) => {
+                            break Err(SquishError::Io {
+                                message: format!("Failed to remove {}", path.display()),
+                                source,
+                            });
+                        }
+                    }
+                }
+                Err(source) => {
+                    break Err(SquishError::Io {
+                        message: format!("Failed to create {}", path.display()),
+                        source,
+                    });
+                }
+            }
+        };
+        let _ = fs::remove_file(&pending);
+        linked?;
+        let lock = SquishLock { path };
+
+        for git_lock in GIT_LOCK_FILES.iter().map(|name| repo.path().join(name)) {
+            if git_lock.exists() {
+                return Err(SquishError::Locked {
+                    path: git_lock,
+                    pid: None,
+                    since: None,
+                });
+            }
+        }
+        Ok(lock)
+    }
+}
+
+/// Remove the squish lock of `repo` whoever holds it, for a lock which is not noticed as
+/// stale on its own (e.g. one taken by a squish on another machine sharing the checkout).
+///
+/// # Returns
+/// Who held the lock removed, or None if there was none.
+pub(crate) fn force_unlock(repo: &Repository) -> Result<Option<LockOwner>, SquishError> {
+    let path = worktree::common_dir(repo)?.join(LOCK_FILE);
+    let owner = LockOwner::read(&path);
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(Some(owner)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(SquishError::Io {
+            message: format!("Failed to remove {}", path.display()),
+            source,
+        }),
+    }
+}
+
+/// The squish which took a lock, as recorded in the lock file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LockOwner {
+    /// The squish's process id
+    pub pid: Option<u32>,
+    /// When it took the lock, in seconds since the Unix epoch
+    pub since: Option<i64>,
+}
+
+impl LockOwner {
+    /// Read the lock file at `path` (`<pid> <time>`, or only the pid in locks written by
+    /// older versions), leaving out whatever cannot be read.
+    fn read(path: &Path) -> LockOwner {
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        let mut fields = contents.split_whitespace();
+        LockOwner {
+            pid: fields.next().and_then(|pid| pid.parse().ok()),
+            since: fields.next().and_then(|since| since.parse().ok()),
+        }
+    }
+}
+
+/// The current time in seconds since the Unix epoch.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+impl Drop for SquishLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A move of a branch ref, holding git's own lock on it (`refs/heads/<name>.lock`) from
+/// when it is taken until the branch points at its new commit, so nothing else can update
+/// the branch in between. Dropping it without committing leaves the branch as it was.
+///
+/// The ref and its reflog (and HEAD's, when HEAD follows the branch) are written together
+/// when the update is committed, so a squish which dies before then leaves the branch
+/// where it was, and git reports the leftover lock file like any other.
+pub(crate) struct BranchUpdate<'repo> {
+    transaction: Transaction<'repo>,
+    branch_refname: String,
+}
+
+impl<'repo> BranchUpdate<'repo> {
+    /// Lock `branch_refname`, making sure it still points at `expected_id`, the tip the
+    /// squish started from.
+    ///
+    /// Fails with `BranchMoved` when something committed to the branch meanwhile, as
+    /// squishing it would lose those commits, or with `Locked` when git is updating it.
+    pub fn lock(
+        repo: &'repo Repository,
+        branch_refname: &str,
+        expected_id: Oid,
+    ) -> Result<BranchUpdate<'repo>, SquishError> {
+        let mut transaction = repo.transaction()?;
+        if let Err(e) = transaction.lock_ref(branch_refname) {
+            if e.code() == ErrorCode::Locked {
+                return Err(SquishError::Locked {
+                    path: worktree::common_dir(repo)?.join(format!("{branch_refname}.lock")),
+                    pid: None,
+                    since: None,
+                });
+            }
+            return Err(e.into());
+        }
+        let actual_id = repo.refname_to_id(branch_refname)?;
+        if actual_id != expected_id {
+            return Err(SquishError::BranchMoved {
+                branch: branch_refname.to_string(),
+                expected: expected_id,
+                actual: actual_id,
+            });
+        }
+        Ok(BranchUpdate {
+            transaction,
+            branch_refname: branch_refname.to_string(),
+        })
+    }
+
+    /// Point the branch at `new_id`, recording `reflog_message`, and release the lock.
+    pub fn commit(mut self, new_id: Oid, reflog_message: &str) -> Result<(), SquishError> {
+        self.transaction
+            .set_target(&self.branch_refname, new_id, None, reflog_message)?;
+        self.transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Whether a process with the given id is running.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks whether the process exists (and could be signalled).
+    // SAFETY: kill with signal 0 sends nothing and has no memory safety requirements.
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Whether a process with the given id is running. There is no cheap check here, so the
+/// lock is always assumed to be held and the user is told how to remove it.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+    #[test]
+    fn test_lock_is_exclusive_and_released_on_drop() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let lock_path = repo.path().join(LOCK_FILE);
+
+        let lock = SquishLock::acquire(&repo).unwrap();
+        let owner = LockOwner::read(&lock_path);
+        assert_eq!(owner.pid, Some(std::process::id()));
+        assert!(owner.since.is_some_and(|since| (now() - since).abs() < 60));
+        assert!(matches!(
+            SquishLock::acquire(&repo),
+            Err(SquishError::Locked { pid: Some(pid), since: Some(_), .. })
+                if pid == std::process::id()
+        ));
+
+        drop(lock);
+        assert!(!lock_path.exists());
+        SquishLock::acquire(&repo).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let lock_path = repo.path().join(LOCK_FILE);
+
+        // Beyond any pid_max, so no such process can exist.
+        fs::write(&lock_path, "2147483647 1714570200\n").unwrap();
+        let _lock = SquishLock::acquire(&repo).unwrap();
+        assert_eq!(LockOwner::read(&lock_path).pid, Some(std::process::id()));
+    }
+
+    #[test]
+    fn test_lock_without_owner_is_held() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let lock_path = repo.path().join(LOCK_FILE);
+
+        // Such as one an older version had created but not yet written its pid to.
+        fs::write(&lock_path, "").unwrap();
+        assert!(matches!(
+            SquishLock::acquire(&repo),
+            Err(SquishError::Locked { ref path, pid: None, .. }) if *path == lock_path
+        ));
+        assert!(lock_path.exists());
+        // Nor is the file it was to be linked from left behind.
+        let pending = lock_path.with_extension(format!("lock.{}", std::process::id()));
+        assert!(!pending.exists());
+    }
+
+    #[test]
+    fn test_lock_is_shared_by_worktrees() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        crate::test_utils::change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let worktree_dir = tempfile::tempdir().unwrap();
+        let worktree_path = worktree_dir.path().join("topic");
+        let topic = repo.find_reference("refs/heads/topic").unwrap();
+        let mut opts = git2::WorktreeAddOptions::new();
+        opts.reference(Some(&topic));
+        let worktree = repo.worktree("topic", &worktree_path, Some(&opts)).unwrap();
+        let worktree_repo = Repository::open_from_worktree(&worktree).unwrap();
+
+        let _lock = SquishLock::acquire(&worktree_repo).unwrap();
+        assert!(repo.path().join(LOCK_FILE).exists());
+        assert!(matches!(
+            SquishLock::acquire(&repo),
+            Err(SquishError::Locked { pid: Some(pid), .. }) if pid == std::process::id()
+        ));
+    }
+
+    #[test]
+    fn test_force_unlock_removes_a_held_lock() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        // A lock written by an older version, with only a pid.
+        fs::write(repo.path().join(LOCK_FILE), "1\n").unwrap();
+
+        assert_eq!(
+            force_unlock(&repo).unwrap(),
+            Some(LockOwner {
+                pid: Some(1),
+                since: None
+            })
+        );
+        assert_eq!(force_unlock(&repo).unwrap(), None);
+        SquishLock::acquire(&repo).unwrap();
+    }
+
+    #[test]
+    fn test_leftover_git_lock_is_reported() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let index_lock = repo.path().join("index.lock");
+        fs::write(&index_lock, "").unwrap();
+
+        assert!(matches!(
+            SquishLock::acquire(&repo),
+            Err(SquishError::Locked { ref path, pid: None, .. }) if *path == index_lock
+        ));
+        // The squish lock is not kept when giving up.
+        assert!(!repo.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn test_branch_update_moves_branch_and_head_reflog() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let first = commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        let second = commit_file(&repo_path, "a.txt", "b\n", "Second").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let update = BranchUpdate::lock(&repo, "refs/heads/main", second).unwrap();
+        assert!(repo.path().join("refs/heads/main.lock").exists());
+        update.commit(first, "squash").unwrap();
+
+        assert!(!repo.path().join("refs/heads/main.lock").exists());
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), first);
+        for refname in ["refs/heads/main", "HEAD"] {
+            let reflog = repo.reflog(refname).unwrap();
+            let entry = reflog.get(0).unwrap();
+            assert_eq!(entry.message(), Some("squash"), "{refname}");
+            assert_eq!((entry.id_old(), entry.id_new()), (second, first));
+        }
+    }
+
+    #[test]
+    fn test_branch_update_rejects_moved_or_locked_branch() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let first = commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        let second = commit_file(&repo_path, "a.txt", "b\n", "Second").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        assert!(matches!(
+            BranchUpdate::lock(&repo, "refs/heads/topic", first),
+            Err(SquishError::BranchMoved { expected, actual, .. })
+                if expected == first && actual == second
+        ));
+        // A failed check does not keep the ref locked.
+        let update = BranchUpdate::lock(&repo, "refs/heads/topic", second).unwrap();
+        assert!(matches!(
+            BranchUpdate::lock(&repo, "refs/heads/topic", second),
+            Err(SquishError::Locked { ref path, pid: None, .. })
+                if *path == repo.path().join("refs/heads/topic.lock")
+        ));
+        drop(update);
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), second);
+        assert!(!repo.path().join("refs/heads/topic.lock").exists());
+    }
+}
@@ -0,0 +1,1006 @@
+use git2::{Commit, Config, Repository};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::ignore_revs::IgnoredRevs;
+use crate::{SquishError, SquishOptions};
+
+/// The line `git commit --verbose` uses to mark where the message ends.
+const SCISSORS_LINE: &str = "------------------------ >8 ------------------------";
+
+/// Largest squash message accepted by `read_message`, in bytes. Messages from elsewhere
+/// (e.g. `git_squish_integrations::fetch_message`) are held to the same limit.
+pub const MAX_MESSAGE_BYTES: u64 = 1024 * 1024;
+
+/// How the squash commit message is cleaned up before committing, mirroring the
+/// `commit.cleanup` config and `git commit --cleanup=<mode>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupMode {
+    /// Strip whitespace, and also comments when the message was edited.
+    Default,
+    /// Strip leading/trailing blank lines, trailing whitespace, comments and collapse
+    /// consecutive blank lines.
+    Strip,
+    /// Same as `Strip` except comment lines are kept.
+    Whitespace,
+    /// Do not change the message at all.
+    Verbatim,
+    /// Same as `Whitespace`, but everything from the scissors line onwards is removed.
+    Scissors,
+}
+
+impl CleanupMode {
+    /// The name used for this mode in git config and on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CleanupMode::Default => "default",
+            CleanupMode::Strip => "strip",
+            CleanupMode::Whitespace => "whitespace",
+            CleanupMode::Verbatim => "verbatim",
+            CleanupMode::Scissors => "scissors",
+        }
+    }
+}
+
+impl FromStr for CleanupMode {
+    type Err = SquishError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(CleanupMode::Default),
+            "strip" => Ok(CleanupMode::Strip),
+            "whitespace" => Ok(CleanupMode::Whitespace),
+            "verbatim" => Ok(CleanupMode::Verbatim),
+            "scissors" => Ok(CleanupMode::Scissors),
+            _ => Err(SquishError::Other {
+                message: format!("Invalid cleanup mode '{s}'"),
+            }),
+        }
+    }
+}
+
+/// Read the cleanup mode and comment string from git config (`commit.cleanup` and
+/// `core.commentChar`), falling back to git's defaults.
+pub(crate) fn cleanup_config(config: &Config) -> Result<(CleanupMode, String), SquishError> {
+    let mode = match config.get_string("commit.cleanup") {
+        Ok(mode) => mode.parse()?,
+        Err(_) => CleanupMode::Default,
+    };
+    let comment_char = match config.get_string("core.commentChar") {
+        // "auto" only has meaning when git picks a character for the editor template.
+        Ok(comment_char) if !comment_char.is_empty() && comment_char != "auto" => comment_char,
+        _ => "#".to_string(),
+    };
+    Ok((mode, comment_char))
+}
+
+/// Clean up a commit message the way `git commit` does.
+///
+/// # Arguments
+/// * `message` - The message to clean up
+/// * `mode` - The cleanup mode to apply
+/// * `comment_char` - The comment prefix (usually `#`, see `core.commentChar`)
+/// * `edited` - Whether the message came from an editor; `CleanupMode::Default` strips
+///   comments only in that case
+///
+/// # Example
+/// ```
+/// use git_squish_core::{CleanupMode, cleanup_message};
+///
+/// let message = "Subject  \n\n\n# Please enter the commit message\nBody\n\n";
+/// assert_eq!(
+///     cleanup_message(message, CleanupMode::Strip, "#", false),
+///     "Subject\n\nBody\n"
+/// );
+/// ```
+pub fn cleanup_message(
+    message: &str,
+    mode: CleanupMode,
+    comment_char: &str,
+    edited: bool,
+) -> String {
+    let strip_comments = match mode {
+        CleanupMode::Verbatim => return message.to_string(),
+        CleanupMode::Strip => true,
+        CleanupMode::Default => edited,
+        CleanupMode::Whitespace | CleanupMode::Scissors => false,
+    };
+
+    let scissors = format!("{comment_char} {SCISSORS_LINE}");
+    let mut cleaned = String::new();
+    let mut pending_blank = false;
+    for line in message.lines() {
+        if mode == CleanupMode::Scissors && line == scissors {
+            break;
+        }
+        if strip_comments && line.starts_with(comment_char) {
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            // Only keep a blank line if more content follows, and never at the start.
+            pending_blank = !cleaned.is_empty();
+            continue;
+        }
+        if pending_blank {
+            cleaned.push('\n');
+            pending_blank = false;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+/// Extra normalizations applied to the squash message after it was cleaned up. Each one
+/// can also be turned on with git config: `squish.trailingNewline`,
+/// `squish.collapseBlankLines`, `squish.crlfToLf` and `squish.wrapBody` (a column count).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MessageNormalization {
+    /// End the message with exactly one newline.
+    pub trailing_newline: bool,
+    /// Collapse runs of more than two consecutive blank lines down to two.
+    pub collapse_blank_lines: bool,
+    /// Convert CRLF line endings to LF.
+    pub crlf_to_lf: bool,
+    /// Hard-wrap body lines longer than this many columns at word boundaries. The subject
+    /// and indented lines (code, quotes) are left alone.
+    pub wrap_body: Option<usize>,
+}
+
+impl MessageNormalization {
+    /// Create a normalization which changes nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// End the message with exactly one newline.
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Collapse runs of more than two consecutive blank lines down to two.
+    pub fn collapse_blank_lines(mut self, collapse_blank_lines: bool) -> Self {
+        self.collapse_blank_lines = collapse_blank_lines;
+        self
+    }
+
+    /// Convert CRLF line endings to LF.
+    pub fn crlf_to_lf(mut self, crlf_to_lf: bool) -> Self {
+        self.crlf_to_lf = crlf_to_lf;
+        self
+    }
+
+    /// Hard-wrap body lines longer than `wrap_body` columns, or leave them alone with None.
+    pub fn wrap_body(mut self, wrap_body: Option<usize>) -> Self {
+        self.wrap_body = wrap_body;
+        self
+    }
+
+    /// Combine two sets of normalizations, applying everything either turns on. The wrap
+    /// column of `other` wins.
+    pub(crate) fn union(self, other: MessageNormalization) -> MessageNormalization {
+        MessageNormalization {
+            trailing_newline: self.trailing_newline || other.trailing_newline,
+            collapse_blank_lines: self.collapse_blank_lines || other.collapse_blank_lines,
+            crlf_to_lf: self.crlf_to_lf || other.crlf_to_lf,
+            wrap_body: other.wrap_body.or(self.wrap_body),
+        }
+    }
+}
+
+/// Read the message normalizations turned on in git config.
+pub(crate) fn normalization_config(config: &Config) -> Result<MessageNormalization, SquishError> {
+    let flag = |name: &str| config.get_bool(name).unwrap_or(false);
+    let wrap_body = match config.get_i64("squish.wrapBody") {
+        Ok(columns) if columns > 0 => Some(columns as usize),
+        _ => None,
+    };
+    Ok(MessageNormalization {
+        trailing_newline: flag("squish.trailingNewline"),
+        collapse_blank_lines: flag("squish.collapseBlankLines"),
+        crlf_to_lf: flag("squish.crlfToLf"),
+        wrap_body,
+    })
+}
+
+/// Apply `normalization` to a commit message.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{MessageNormalization, normalize_message};
+///
+/// let normalization = MessageNormalization::new()
+///     .trailing_newline(true)
+///     .crlf_to_lf(true);
+/// assert_eq!(
+///     normalize_message("Subject\r\n\r\nBody\r\n\r\n", &normalization),
+///     "Subject\n\nBody\n"
+/// );
+/// ```
+pub fn normalize_message(message: &str, normalization: &MessageNormalization) -> String {
+    let mut message = if normalization.crlf_to_lf {
+        message.replace("\r\n", "\n")
+    } else {
+        message.to_string()
+    };
+
+    if normalization.collapse_blank_lines || normalization.wrap_body.is_some() {
+        let ends_with_newline = message.ends_with('\n');
+        let mut lines: Vec<String> = Vec::new();
+        let mut blank_run = 0;
+        for (index, line) in message.lines().enumerate() {
+            if line.trim().is_empty() {
+                blank_run += 1;
+                if normalization.collapse_blank_lines && blank_run > 2 {
+                    continue;
+                }
+            } else {
+                blank_run = 0;
+            }
+            match normalization.wrap_body {
+                Some(width) if index > 0 => lines.extend(wrap_line(line, width)),
+                _ => lines.push(line.to_string()),
+            }
+        }
+        message = lines.join("\n");
+        if ends_with_newline {
+            message.push('\n');
+        }
+    }
+
+    if normalization.trailing_newline {
+        let trimmed = message.trim_end_matches(['\n', '\r']).len();
+        message.truncate(trimmed);
+        message.push('\n');
+    }
+    message
+}
+
+/// Break `line` at spaces so no piece is longer than `width`, unless it is indented or a
+/// single word is longer than that.
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    if line.chars().count() <= width || line.starts_with(char::is_whitespace) {
+        return vec![line.to_string()];
+    }
+    let mut wrapped = Vec::new();
+    let mut current = String::new();
+    for word in line.split(' ').filter(|word| !word.is_empty()) {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            wrapped.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    wrapped.push(current);
+    wrapped
+}
+
+/// The kinds of commits `git commit --fixup` creates for `git rebase --autosquash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Autosquash {
+    /// `fixup!`: only the changes count, the message is dropped.
+    Fixup,
+    /// `squash!`: the body is added to the message of the commit it squashes into.
+    Squash,
+    /// `amend!`: the body replaces the message of the commit it amends.
+    Amend,
+}
+
+/// Whether `subject` marks an autosquash commit, and the subject (or commit id) it targets
+/// once every prefix is stripped (`fixup! fixup! Subject` targets `Subject`).
+fn autosquash(subject: &str) -> Option<(Autosquash, &str)> {
+    let prefixes = [
+        ("fixup! ", Autosquash::Fixup),
+        ("squash! ", Autosquash::Squash),
+        ("amend! ", Autosquash::Amend),
+    ];
+    let (prefix, kind) = prefixes
+        .into_iter()
+        .find(|(prefix, _)| subject.starts_with(prefix))?;
+    let mut target = &subject[prefix.len()..];
+    while let Some(rest) = prefixes
+        .iter()
+        .find_map(|(prefix, _)| target.strip_prefix(prefix))
+    {
+        target = rest;
+    }
+    Some((kind, target))
+}
+
+/// Everything after a message's subject line, without the blank lines separating them.
+fn message_body(message: &str) -> &str {
+    message
+        .split_once('\n')
+        .map_or("", |(_, body)| body.trim_start_matches(['\n', '\r']))
+}
+
+/// Build a squash message using the message from the first commit.
+/// This scans commits reachable from `rebased_tip` back to (but excluding) `upstream_parent`
+/// and returns the full message from the first (oldest) commit which is not ignored,
+/// falling back to the first commit if every commit is ignored.
+///
+/// Autosquash commits are handled like `git rebase --autosquash` would: `fixup!` and
+/// `squash!` commits never provide the message, an `amend!` commit targeting the first
+/// commit replaces its message with the amend's body, and the bodies of `squash!` commits
+/// are appended to it.
+pub(crate) fn build_squash_message(
+    repo: &Repository,
+    upstream_parent: &Commit,
+    rebased_tip: &Commit,
+    ignored: &IgnoredRevs,
+) -> Result<String, SquishError> {
+    let commits = range_commits(repo, upstream_parent, rebased_tip)?;
+    message_from_commits(&commits, ignored)
+}
+
+/// The commits reachable from `rebased_tip` back to (but excluding) `upstream_parent`,
+/// oldest first.
+pub(crate) fn range_commits<'r>(
+    repo: &'r Repository,
+    upstream_parent: &Commit,
+    rebased_tip: &Commit,
+) -> Result<Vec<Commit<'r>>, SquishError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(rebased_tip.id())?;
+    revwalk.hide(upstream_parent.id())?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.map(|id| Ok(repo.find_commit(id?)?)).collect()
+}
+
+/// Build a squash message for `commits` (oldest first) like `build_squash_message` does
+/// for a range.
+pub(crate) fn message_from_commits(
+    commits: &[Commit],
+    ignored: &IgnoredRevs,
+) -> Result<String, SquishError> {
+    let is_autosquash =
+        |commit: &Commit| autosquash(commit.summary().unwrap_or_default()).is_some();
+
+    // Get the first commit in the range, skipping ignored ones (e.g. formatting commits)
+    // and autosquash commits, then settling for any commit which is not ignored.
+    let first = commits
+        .iter()
+        .find(|commit| !ignored.contains(commit) && !is_autosquash(commit))
+        .or_else(|| commits.iter().find(|commit| !ignored.contains(commit)))
+        .or(commits.first())
+        .ok_or_else(|| SquishError::Other {
+            message: "No commits found in the range to squash".to_string(),
+        })?;
+    let mut message = first
+        .message()
+        .ok_or_else(|| SquishError::Other {
+            message: "First commit has no message".to_string(),
+        })?
+        .to_string();
+
+    let first_subject = first.summary().unwrap_or_default();
+    let first_id = first.id().to_string();
+    for commit in commits {
+        let commit_message = commit.message().unwrap_or_default();
+        match autosquash(commit.summary().unwrap_or_default()) {
+            Some((Autosquash::Amend, target))
+                if target == first_subject
+                    || (target.len() >= 4 && first_id.starts_with(target)) =>
+            {
+                message = message_body(commit_message).to_string();
+            }
+            Some((Autosquash::Squash, _)) if commit.id() != first.id() => {
+                let body = message_body(commit_message);
+                if !body.trim().is_empty() {
+                    message = format!("{}\n\n{body}", message.trim_end());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(message)
+}
+
+/// The message for squashing the commits between `upstream_parent` and `rebased_tip`: the
+/// one given with `SquishOptions::message`, or else the one `build_squash_message` builds,
+/// followed by the `References:` footer when asked for.
+pub(crate) fn squash_message(
+    repo: &Repository,
+    upstream_parent: &Commit,
+    rebased_tip: &Commit,
+    ignored: &IgnoredRevs,
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    let message = match options.custom_message() {
+        Some(message) => message,
+        None => build_squash_message(repo, upstream_parent, rebased_tip, ignored)?,
+    };
+    add_references(repo, upstream_parent, rebased_tip, message, options)
+}
+
+/// Hosts whose `Link:` trailers point at a mail archive by Message-Id, as added by b4 and
+/// `git am` hooks (e.g. `https://lore.kernel.org/r/<id>`).
+const MESSAGE_ID_LINK_HOSTS: [&str; 2] = ["lore.kernel.org", "patch.msgid.link"];
+
+/// The email Message-Ids recorded in the trailers of `message`: `Message-Id:` trailers and
+/// `Link:` trailers into a mail archive.
+fn message_ids(message: &str) -> Vec<String> {
+    let Ok(trailers) = git2::message_trailers_strs(message) else {
+        return Vec::new();
+    };
+    trailers
+        .iter()
+        .filter_map(|(key, value)| {
+            if key.eq_ignore_ascii_case("Message-Id") {
+                return Some(value.trim().to_string());
+            }
+            let url = value
+                .trim()
+                .strip_prefix("https://")
+                .or_else(|| value.trim().strip_prefix("http://"))?;
+            let (host, path) = url.split_once('/')?;
+            if !key.eq_ignore_ascii_case("Link") || !MESSAGE_ID_LINK_HOSTS.contains(&host) {
+                return None;
+            }
+            let id = path.trim_end_matches('/').rsplit('/').next()?;
+            id.contains('@').then(|| format!("<{id}>"))
+        })
+        .collect()
+}
+
+/// Add a `References:` trailer listing the email Message-Ids of the commits between
+/// `upstream_parent` and `rebased_tip`, oldest first, to `message` when
+/// `SquishOptions::reference_footer` is set, so email-based review tools (patchwork, lore)
+/// can thread the squash commit back into the discussions of the original patches.
+pub(crate) fn add_references(
+    repo: &Repository,
+    upstream_parent: &Commit,
+    rebased_tip: &Commit,
+    message: String,
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    if !options.reference_footer {
+        return Ok(message);
+    }
+    let commits = range_commits(repo, upstream_parent, rebased_tip)?;
+    Ok(add_commit_references(&commits, message, options))
+}
+
+/// Add the `References:` trailer for `commits` (oldest first) like `add_references` does
+/// for a range.
+pub(crate) fn add_commit_references(
+    commits: &[Commit],
+    message: String,
+    options: &SquishOptions,
+) -> String {
+    if !options.reference_footer {
+        return message;
+    }
+    let mut ids: Vec<String> = Vec::new();
+    for commit in commits {
+        for message_id in message_ids(commit.message().unwrap_or_default()) {
+            if !ids.contains(&message_id) {
+                ids.push(message_id);
+            }
+        }
+    }
+    if ids.is_empty() {
+        return message;
+    }
+    append_trailers(&message, &[format!("References: {}", ids.join(" "))])
+}
+
+/// Key of the trailers listing the commits folded into a squash commit with
+/// `SquishOptions::annotate_sources`.
+const SOURCES_TRAILER: &str = "Squashed-from";
+
+/// Add a `Squashed-from:` trailer with the abbreviated id of each of `sources` (the
+/// branch's original commits, oldest first) to `message` when
+/// `SquishOptions::annotate_sources` is set, so where the squash commit came from can
+/// still be grepped for once the original commits are gone.
+pub(crate) fn add_sources(
+    message: String,
+    sources: &[Commit],
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    if !options.annotate_sources || sources.is_empty() {
+        return Ok(message);
+    }
+    let trailers = sources
+        .iter()
+        .map(|commit| {
+            let short_id = commit.as_object().short_id()?;
+            Ok(format!(
+                "{SOURCES_TRAILER}: {}",
+                short_id.as_str().unwrap_or_default()
+            ))
+        })
+        .collect::<Result<Vec<_>, SquishError>>()?;
+    Ok(append_trailers(&message, &trailers))
+}
+
+/// Fill in the template variables in `template`: each `{name}` with a value in `variables`
+/// is replaced with it. Placeholders without a value are left as they are, and the values
+/// themselves are not expanded.
+///
+/// # Example
+/// ```
+/// use std::collections::BTreeMap;
+/// use git_squish_core::expand_template;
+///
+/// let variables = BTreeMap::from([("ticket".to_string(), "ABC-123".to_string())]);
+/// assert_eq!(
+///     expand_template("Fix {ticket} in {module}", &variables),
+///     "Fix ABC-123 in {module}"
+/// );
+/// ```
+pub fn expand_template(template: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, variables.get(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                expanded.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Add the trailers given with `SquishOptions::trailer` to `message`, with template
+/// variables filled in, skipping any the message already has.
+pub(crate) fn add_custom_trailers(message: String, options: &SquishOptions) -> String {
+    let existing: Vec<(String, String)> = git2::message_trailers_strs(&message)
+        .map(|trailers| {
+            trailers
+                .iter()
+                .map(|(key, value)| (key.to_ascii_lowercase(), value.trim().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let trailers: Vec<String> = options
+        .trailers
+        .iter()
+        .map(|trailer| expand_template(trailer, &options.variables))
+        .filter(|trailer| {
+            let (key, value) = trailer.split_once(':').unwrap_or((trailer, ""));
+            !existing.contains(&(key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect();
+    if trailers.is_empty() {
+        return message;
+    }
+    append_trailers(&message, &trailers)
+}
+
+/// Append `trailers` to `message`, joining an existing trailer block rather than starting
+/// a second one.
+fn append_trailers(message: &str, trailers: &[String]) -> String {
+    let ends_with_trailers = git2::message_trailers_strs(message)
+        .map(|trailers| trailers.len() > 0)
+        .unwrap_or(false);
+    let separator = if ends_with_trailers { "\n" } else { "\n\n" };
+    format!("{}{separator}{}\n", message.trim_end(), trailers.join("\n"))
+}
+
+/// Read a squash message (e.g. from stdin), failing if it is larger than
+/// `MAX_MESSAGE_BYTES` or not UTF-8.
+pub fn read_message(reader: impl Read) -> Result<String, SquishError> {
+    let mut bytes = Vec::new();
+    reader
+        .take(MAX_MESSAGE_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|source| SquishError::Io {
+            message: "Failed to read the squash message".to_string(),
+            source,
+        })?;
+    if bytes.len() as u64 > MAX_MESSAGE_BYTES {
+        return Err(SquishError::Other {
+            message: format!("The squash message is larger than {MAX_MESSAGE_BYTES} bytes"),
+        });
+    }
+    String::from_utf8(bytes).map_err(|_| SquishError::Other {
+        message: "The squash message is not valid UTF-8".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "\n\nSubject   \n\n\n\nBody line\t\n# Please enter the commit message\n\n# ------------------------ >8 ------------------------\ndiff --git a/x b/x\n\n";
+
+    #[test]
+    fn test_cleanup_verbatim() {
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Verbatim, "#", true),
+            MESSAGE
+        );
+    }
+
+    #[test]
+    fn test_cleanup_whitespace() {
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Whitespace, "#", true),
+            "Subject\n\nBody line\n# Please enter the commit message\n\n# ------------------------ >8 ------------------------\ndiff --git a/x b/x\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_strip() {
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Strip, "#", false),
+            "Subject\n\nBody line\n\ndiff --git a/x b/x\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_scissors() {
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Scissors, "#", true),
+            "Subject\n\nBody line\n# Please enter the commit message\n"
+        );
+    }
+
+    #[test]
+    fn test_cleanup_default_depends_on_editing() {
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Default, "#", false),
+            cleanup_message(MESSAGE, CleanupMode::Whitespace, "#", false)
+        );
+        assert_eq!(
+            cleanup_message(MESSAGE, CleanupMode::Default, "#", true),
+            cleanup_message(MESSAGE, CleanupMode::Strip, "#", true)
+        );
+    }
+
+    #[test]
+    fn test_cleanup_custom_comment_char() {
+        assert_eq!(
+            cleanup_message("Subject\n; note\n# kept\n", CleanupMode::Strip, ";", false),
+            "Subject\n# kept\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_message() {
+        let all = MessageNormalization {
+            trailing_newline: true,
+            collapse_blank_lines: true,
+            crlf_to_lf: true,
+            wrap_body: Some(20),
+        };
+        assert_eq!(
+            normalize_message(
+                "A subject line longer than twenty\r\n\r\n\r\n\r\n\r\nThe body wraps at twenty columns\r\n    indented code stays as it is\r\n\r\n\r\n",
+                &all
+            ),
+            "A subject line longer than twenty\n\n\nThe body wraps at\ntwenty columns\n    indented code stays as it is\n"
+        );
+
+        let none = MessageNormalization::default();
+        assert_eq!(
+            normalize_message("Subject\r\n\n\n\n", &none),
+            "Subject\r\n\n\n\n"
+        );
+        let trailing = MessageNormalization {
+            trailing_newline: true,
+            ..none
+        };
+        assert_eq!(normalize_message("Subject", &trailing), "Subject\n");
+        assert_eq!(normalize_message("Subject\n\n\n", &trailing), "Subject\n");
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(wrap_line("short", 10), vec!["short"]);
+        assert_eq!(
+            wrap_line("a https://example.com/very/long b", 10),
+            vec!["a", "https://example.com/very/long", "b"]
+        );
+    }
+
+    #[test]
+    fn test_normalization_config() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config::open(&temp_dir.path().join("config")).unwrap();
+        assert_eq!(
+            normalization_config(&config).unwrap(),
+            MessageNormalization::default()
+        );
+
+        config.set_bool("squish.crlfToLf", true).unwrap();
+        config.set_i64("squish.wrapBody", 72).unwrap();
+        let normalization = normalization_config(&config).unwrap();
+        assert!(normalization.crlf_to_lf);
+        assert_eq!(normalization.wrap_body, Some(72));
+
+        let combined = normalization.union(MessageNormalization {
+            trailing_newline: true,
+            wrap_body: Some(50),
+            ..MessageNormalization::default()
+        });
+        assert!(combined.crlf_to_lf && combined.trailing_newline);
+        assert_eq!(combined.wrap_body, Some(50));
+    }
+
+    #[test]
+    fn test_cleanup_mode_from_str() {
+        assert_eq!("strip".parse::<CleanupMode>().unwrap(), CleanupMode::Strip);
+        assert_eq!(
+            "scissors".parse::<CleanupMode>().unwrap(),
+            CleanupMode::Scissors
+        );
+        assert!("bogus".parse::<CleanupMode>().is_err());
+    }
+
+    #[test]
+    fn test_cleanup_config() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = Config::open(&temp_dir.path().join("config")).unwrap();
+        assert_eq!(
+            cleanup_config(&config).unwrap(),
+            (CleanupMode::Default, "#".to_string())
+        );
+
+        config.set_str("commit.cleanup", "strip").unwrap();
+        config.set_str("core.commentChar", ";").unwrap();
+        assert_eq!(
+            cleanup_config(&config).unwrap(),
+            (CleanupMode::Strip, ";".to_string())
+        );
+    }
+
+    #[test]
+    fn test_autosquash() {
+        assert_eq!(
+            autosquash("fixup! Add parser"),
+            Some((Autosquash::Fixup, "Add parser"))
+        );
+        assert_eq!(
+            autosquash("squash! fixup! Add parser"),
+            Some((Autosquash::Squash, "Add parser"))
+        );
+        assert_eq!(
+            autosquash("amend! 1a2b3c4"),
+            Some((Autosquash::Amend, "1a2b3c4"))
+        );
+        assert_eq!(autosquash("fixup!Add parser"), None);
+        assert_eq!(autosquash("Fix the fixup! handling"), None);
+    }
+
+    #[test]
+    fn test_build_squash_message_with_autosquash_commits() {
+        use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let base = commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "1\n", "fixup! Add parser").unwrap();
+        commit_file(&repo_path, "a.txt", "2\n", "Add parser\n\nParses input.").unwrap();
+        commit_file(&repo_path, "a.txt", "3\n", "fixup! Add parser").unwrap();
+        commit_file(
+            &repo_path,
+            "a.txt",
+            "4\n",
+            "squash! Add parser\n\nAlso handles comments.",
+        )
+        .unwrap();
+        let tip = commit_file(&repo_path, "a.txt", "5\n", "squash! Add parser").unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let message = |tip| {
+            build_squash_message(
+                &repo,
+                &repo.find_commit(base).unwrap(),
+                &repo.find_commit(tip).unwrap(),
+                &IgnoredRevs::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            message(tip),
+            "Add parser\n\nParses input.\n\nAlso handles comments."
+        );
+
+        let tip = commit_file(
+            &repo_path,
+            "a.txt",
+            "6\n",
+            "amend! Add parser\n\nAdd a parser\n\nParses input and comments.",
+        )
+        .unwrap();
+        assert_eq!(message(tip), "Add a parser\n\nParses input and comments.");
+    }
+
+    #[test]
+    fn test_message_ids() {
+        assert_eq!(
+            message_ids(
+                "Fix parser\n\nBody.\n\nSigned-off-by: A <a@example.com>\nMessage-Id: <1-a@example.com>\nLink: https://lore.kernel.org/r/2-b@example.com\nLink: https://lore.kernel.org/git/3-c@example.com/\nLink: https://patch.msgid.link/4-d@example.com\nLink: https://github.com/org/repo/pull/5\n"
+            ),
+            [
+                "<1-a@example.com>",
+                "<2-b@example.com>",
+                "<3-c@example.com>",
+                "<4-d@example.com>"
+            ]
+        );
+        assert!(message_ids("Message-Id: <1-a@example.com>\n").is_empty());
+    }
+
+    #[test]
+    fn test_add_references() {
+        use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let base = commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(
+            &repo_path,
+            "a.txt",
+            "1\n",
+            "Add parser\n\nMessage-Id: <1-a@example.com>",
+        )
+        .unwrap();
+        commit_file(&repo_path, "a.txt", "2\n", "Tweak parser").unwrap();
+        let tip = commit_file(
+            &repo_path,
+            "a.txt",
+            "3\n",
+            "Fix parser\n\nLink: https://lore.kernel.org/r/2-b@example.com\nMessage-Id: <1-a@example.com>",
+        )
+        .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let add = |message: &str, options: &SquishOptions| {
+            add_references(
+                &repo,
+                &repo.find_commit(base).unwrap(),
+                &repo.find_commit(tip).unwrap(),
+                message.to_string(),
+                options,
+            )
+            .unwrap()
+        };
+        let options = SquishOptions::new().reference_footer(true);
+        assert_eq!(add("Add parser\n", &SquishOptions::new()), "Add parser\n");
+        assert_eq!(
+            add("Add parser\n", &options),
+            "Add parser\n\nReferences: <1-a@example.com> <2-b@example.com>\n"
+        );
+        assert_eq!(
+            add("Add parser\n\nSigned-off-by: A <a@example.com>\n", &options),
+            "Add parser\n\nSigned-off-by: A <a@example.com>\nReferences: <1-a@example.com> <2-b@example.com>\n"
+        );
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let variables = BTreeMap::from([
+            ("ticket".to_string(), "ABC-1".to_string()),
+            ("build_number".to_string(), "{ticket}".to_string()),
+        ]);
+        assert_eq!(
+            expand_template("{ticket}: build {build_number}", &variables),
+            "ABC-1: build {ticket}"
+        );
+        assert_eq!(
+            expand_template("{ {missing} {ticket", &variables),
+            "{ {missing} {ticket"
+        );
+        assert_eq!(expand_template("{{ticket}}", &variables), "{ABC-1}");
+    }
+
+    #[test]
+    fn test_add_custom_trailers() {
+        let options = SquishOptions::new()
+            .template_variable("ticket", "ABC-1")
+            .trailer("Ticket: {ticket}")
+            .trailer("Build: {build_number}");
+        assert_eq!(
+            add_custom_trailers("Add parser\n".to_string(), &options),
+            "Add parser\n\nTicket: ABC-1\nBuild: {build_number}\n"
+        );
+        assert_eq!(
+            add_custom_trailers("Add parser\n\nticket:  ABC-1\n".to_string(), &options),
+            "Add parser\n\nticket:  ABC-1\nBuild: {build_number}\n"
+        );
+        assert_eq!(
+            add_custom_trailers("Add parser\n".to_string(), &SquishOptions::new()),
+            "Add parser\n"
+        );
+    }
+
+    #[test]
+    fn test_squash_with_template_variables() {
+        use crate::squash_branch_with_options;
+        use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "1\n", "Add parser for {ticket}").unwrap();
+        commit_file(&repo_path, "a.txt", "2\n", "Fix parser").unwrap();
+
+        // Only the given message is a template, not the commits' own messages.
+        let options = SquishOptions::new()
+            .template_variable("ticket", "ABC-1")
+            .template_variable("build_number", "42")
+            .trailer("Ticket: {ticket}");
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(
+            repo.find_commit(result.new_id).unwrap().message(),
+            Some("Add parser for {ticket}\n\nTicket: ABC-1\n")
+        );
+
+        commit_file(&repo_path, "a.txt", "3\n", "Tweak parser").unwrap();
+        let options = options
+            .amend(true)
+            .message("{ticket}: parser (build {build_number})");
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        assert_eq!(
+            repo.find_commit(result.new_id).unwrap().message(),
+            Some("ABC-1: parser (build 42)\n\nTicket: ABC-1\n")
+        );
+    }
+
+    #[test]
+    fn test_squash_annotates_sources() {
+        use crate::squash_branch_with_options;
+        use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        let first = commit_file(&repo_path, "a.txt", "1\n", "Add parser").unwrap();
+        let second = commit_file(&repo_path, "a.txt", "2\n", "Fix parser").unwrap();
+
+        let options = SquishOptions::new()
+            .annotate_sources(true)
+            .message("Add parser\n\nSigned-off-by: A <a@example.com>\n");
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let short = |id: git2::Oid| {
+            let object = repo.find_object(id, None).unwrap();
+            object.short_id().unwrap().as_str().unwrap().to_string()
+        };
+        assert_eq!(
+            repo.find_commit(result.new_id).unwrap().message(),
+            Some(
+                format!(
+                    "Add parser\n\nSigned-off-by: A <a@example.com>\nSquashed-from: {}\nSquashed-from: {}\n",
+                    short(first),
+                    short(second)
+                )
+                .as_str()
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_message() {
+        assert_eq!(
+            read_message(&b"Subject\n\nBody\n"[..]).unwrap(),
+            "Subject\n\nBody\n"
+        );
+        let too_large = vec![b'x'; MAX_MESSAGE_BYTES as usize + 1];
+        assert!(read_message(&too_large[..]).is_err());
+        assert!(read_message(&[0xff, 0xfe][..]).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use git2::Repository;
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::{SquishError, SquishOptions};
+
+/// Config key holding a shell command to pipe every squash message through (see
+/// `MessageCommand`).
+const MESSAGE_COMMAND_KEY: &str = "squish.messageCommand";
+
+/// Rewrites a squash message once it is composed, e.g. to translate it or normalize its
+/// wording. Added with `SquishOptions::message_transformer`; `MessageCommand` runs an
+/// external command instead.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{MessageTransformer, SquishError, SquishOptions};
+///
+/// /// Prefixes every subject with a ticket number.
+/// struct Ticket(String);
+///
+/// impl MessageTransformer for Ticket {
+///     fn name(&self) -> String {
+///         format!("prefix {}", self.0)
+///     }
+///
+///     fn transform(&self, message: &str) -> Result<Option<String>, SquishError> {
+///         Ok(Some(format!("{}: {message}", self.0)))
+///     }
+/// }
+///
+/// let options = SquishOptions::new().message_transformer(Ticket("ABC-123".to_string()));
+/// ```
+pub trait MessageTransformer: Send + Sync {
+    /// What the transformer does (e.g. "translate to English").
+    fn name(&self) -> String;
+
+    /// Transform `message`, the squash message with its trailers added and cleaned up.
+    ///
+    /// # Returns
+    /// The message to commit instead, or None to leave it as it is. An error fails the
+    /// squish before anything is written.
+    fn transform(&self, message: &str) -> Result<Option<String>, SquishError>;
+}
+
+/// A `MessageTransformer` held by `SquishOptions`, which stay cloneable and printable.
+#[derive(Clone)]
+pub(crate) struct SharedMessageTransformer(pub(crate) Arc<dyn MessageTransformer>);
+
+impl fmt::Debug for SharedMessageTransformer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MessageTransformer")
+            .field(&self.0.name())
+            .finish()
+    }
+}
+
+/// A `MessageTransformer` which pipes the message through a shell command, such as a
+/// script which detects the language of the message and translates it to English. The
+/// command gets the message on stdin and prints the message to commit on stdout; a
+/// command which fails or prints nothing fails the squish.
+#[derive(Debug, Clone)]
+pub struct MessageCommand {
+    command: String,
+    dir: Option<std::path::PathBuf>,
+}
+
+impl MessageCommand {
+    /// Run `command` with `sh -c`, in the current directory.
+    pub fn new(command: impl Into<String>) -> Self {
+        MessageCommand {
+            command: command.into(),
+            dir: None,
+        }
+    }
+
+    /// Run the command in `dir` instead of the current directory.
+    pub fn dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+impl MessageTransformer for MessageCommand {
+    fn name(&self) -> String {
+        format!("`{}`", self.command)
+    }
+
+    fn transform(&self, message: &str) -> Result<Option<String>, SquishError> {
+        let failed = |reason: String| SquishError::Other {
+            message: format!(
+                "`{}` failed to rewrite the squash message: {reason}",
+                self.command
+            ),
+        };
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.dir {
+            shell.current_dir(dir);
+        }
+        let mut child = shell.spawn().map_err(|source| SquishError::Io {
+            message: format!("Failed to run `{}`", self.command),
+            source,
+        })?;
+        // The message is written on its own thread, since a command which prints as it reads
+        // would otherwise block on a full stdout pipe while we block on a full stdin pipe.
+        let output = std::thread::scope(|scope| {
+            if let Some(mut stdin) = child.stdin.take() {
+                scope.spawn(move || {
+                    // A command which does not read its input is still given the chance to
+                    // answer.
+                    let _ = stdin.write_all(message.as_bytes());
+                });
+            }
+            child.wait_with_output()
+        })
+        .map_err(|source| SquishError::Io {
+            message: format!("Failed to run `{}`", self.command),
+            source,
+        })?;
+        if !output.status.success() {
+            return Err(failed(
+                String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            ));
+        }
+        let rewritten = String::from_utf8(output.stdout)
+            .map_err(|_| failed("it printed invalid UTF-8".to_string()))?;
+        if rewritten.trim().is_empty() {
+            return Err(failed("it printed no message".to_string()));
+        }
+        Ok(Some(rewritten))
+    }
+}
+
+/// Run `message` through the transformers in `options`, in the order they were added, and
+/// then through the message command (`SquishOptions::message_command`, or else the
+/// `squish.messageCommand` config).
+pub(crate) fn transform_message(
+    repo: &Repository,
+    message: String,
+    options: &SquishOptions,
+) -> Result<String, SquishError> {
+    let command = match &options.message_command {
+        Some(command) => Some(command.clone()),
+        None => repo.config()?.get_string(MESSAGE_COMMAND_KEY).ok(),
+    }
+    .filter(|command| !command.trim().is_empty())
+    .map(|command| {
+        let dir = repo.workdir().unwrap_or(repo.path());
+        MessageCommand::new(command).dir(dir)
+    });
+
+    let mut transformers: Vec<&dyn MessageTransformer> = options
+        .message_transformers
+        .iter()
+        .map(|SharedMessageTransformer(transformer)| transformer.as_ref())
+        .collect();
+    transformers.extend(
+        command
+            .as_ref()
+            .map(|command| command as &dyn MessageTransformer),
+    );
+
+    let mut message = message;
+    for transformer in transformers {
+        if let Some(rewritten) = transformer.transform(&message)? {
+            message = rewritten;
+        }
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+    use std::path::Path;
+
+    /// Replaces the subject with a fixed one.
+    struct Subject(&'static str);
+
+    impl MessageTransformer for Subject {
+        fn name(&self) -> String {
+            format!("subject {}", self.0)
+        }
+
+        fn transform(&self, message: &str) -> Result<Option<String>, SquishError> {
+            let body = message.split_once('\n').map_or("", |(_, body)| body);
+            Ok(Some(format!("{}\n{body}", self.0)))
+        }
+    }
+
+    /// A topic branch whose commits have French subjects, as a team translating squash
+    /// messages to English would have.
+    fn french_repo() -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Ajouter a").unwrap();
+        commit_file(&repo_path, "a.txt", "A\n", "Corriger a").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    fn topic_message(repo_path: &Path) -> String {
+        let repo = Repository::open(repo_path).unwrap();
+        repo.find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_transformers_run_in_order() {
+        let (repo_path, _temp_dir) = french_repo();
+        let options = SquishOptions::new()
+            .message("Ajouter a")
+            .message_transformer(Subject("Add a"))
+            .message_transformer(Subject("Add file a"));
+
+        squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        assert_eq!(topic_message(&repo_path), "Add file a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_message_command_from_config_and_options() {
+        let (repo_path, _temp_dir) = french_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str(MESSAGE_COMMAND_KEY, "sed s/Ajouter/Add/")
+            .unwrap();
+        let options = SquishOptions::new().message("Ajouter a");
+
+        squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        assert_eq!(topic_message(&repo_path), "Add a\n");
+
+        // The option overrides the config, and an empty one turns it off.
+        commit_file(&repo_path, "b.txt", "b\n", "Ajouter b").unwrap();
+        let options = SquishOptions::new()
+            .message("Ajouter b")
+            .message_command("");
+        squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        assert_eq!(topic_message(&repo_path), "Ajouter b\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_message_command_with_long_message() {
+        // Longer than a pipe buffer, so the command is still writing while it is read.
+        let message = "Ajouter a\n\n".to_string() + &"Corriger a\n".repeat(10_000);
+
+        let rewritten = MessageCommand::new("cat").transform(&message).unwrap();
+
+        assert_eq!(rewritten, Some(message));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failing_message_command_leaves_branch_alone() {
+        let (repo_path, _temp_dir) = french_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        for command in ["echo 'no translator' >&2; exit 3", "cat >/dev/null"] {
+            let options = SquishOptions::new().message_command(command);
+            let error = squash_branch_with_options(&repo_path, "topic", "main", &options)
+                .unwrap_err()
+                .to_string();
+
+            assert!(
+                error.contains("failed to rewrite the squash message"),
+                "{error}"
+            );
+            assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+        }
+    }
+}
@@ -0,0 +1,632 @@
+use git2::{FileFavor, Oid};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::message_transform::SharedMessageTransformer;
+use crate::sign::SharedSigner;
+use crate::transform::SharedTransformer;
+use crate::{
+    CleanupMode, CommitSigner, MessageNormalization, MessageTransformer, SignatureCheck,
+    SquishError, TreeTransformer, expand_template,
+};
+
+/// Strategy used to automatically resolve conflicting hunks during the rebase.
+///
+/// This mirrors `git rebase --strategy-option`: "ours" favors the upstream side being
+/// rebased onto, while "theirs" favors the changes from the branch being squished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyOption {
+    /// Resolve conflicting hunks using the upstream version
+    Ours,
+    /// Resolve conflicting hunks using the branch version
+    Theirs,
+    /// Resolve conflicting hunks by keeping the lines from both sides
+    Union,
+}
+
+impl StrategyOption {
+    /// The name used for this option on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StrategyOption::Ours => "ours",
+            StrategyOption::Theirs => "theirs",
+            StrategyOption::Union => "union",
+        }
+    }
+
+    pub(crate) fn file_favor(self) -> FileFavor {
+        match self {
+            StrategyOption::Ours => FileFavor::Ours,
+            StrategyOption::Theirs => FileFavor::Theirs,
+            StrategyOption::Union => FileFavor::Union,
+        }
+    }
+}
+
+impl FromStr for StrategyOption {
+    type Err = SquishError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ours" => Ok(StrategyOption::Ours),
+            "theirs" => Ok(StrategyOption::Theirs),
+            "union" => Ok(StrategyOption::Union),
+            _ => Err(SquishError::Other {
+                message: format!("Unknown strategy option '{s}', expected ours, theirs or union"),
+            }),
+        }
+    }
+}
+
+/// Options controlling how a branch is squished.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{SquishOptions, StrategyOption};
+///
+/// let options = SquishOptions::new().strategy_option(StrategyOption::Theirs);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SquishOptions {
+    pub(crate) strategy_option: Option<StrategyOption>,
+    pub(crate) pause_on_conflict: bool,
+    pub(crate) cleanup: Option<CleanupMode>,
+    pub(crate) normalization: MessageNormalization,
+    pub(crate) force: bool,
+    pub(crate) autostash: Option<bool>,
+    pub(crate) allow_empty: bool,
+    pub(crate) allow_empty_message: bool,
+    pub(crate) message: Option<String>,
+    pub(crate) variables: BTreeMap<String, String>,
+    pub(crate) trailers: Vec<String>,
+    pub(crate) amend: bool,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) reference_footer: bool,
+    pub(crate) annotate_sources: bool,
+    pub(crate) record_provenance: bool,
+    pub(crate) allow_protected: bool,
+    pub(crate) fetch: bool,
+    pub(crate) push: bool,
+    pub(crate) split_auto: bool,
+    pub(crate) split_markers: bool,
+    pub(crate) split_paths: Vec<String>,
+    pub(crate) keep_first: bool,
+    pub(crate) set_upstream: bool,
+    pub(crate) strict: bool,
+    pub(crate) verify: bool,
+    pub(crate) verify_hooks: Option<bool>,
+    pub(crate) exec: Vec<String>,
+    pub(crate) sign: Option<bool>,
+    pub(crate) signer: Option<SharedSigner>,
+    pub(crate) verify_signatures: Option<SignatureCheck>,
+    pub(crate) only_authors: Vec<String>,
+    pub(crate) exclude_authors: Vec<String>,
+    pub(crate) exclude_commits: Vec<Oid>,
+    pub(crate) skip_commits: Vec<String>,
+    pub(crate) skip_subject_patterns: Vec<String>,
+    pub(crate) since_date: Option<i64>,
+    pub(crate) until_date: Option<i64>,
+    pub(crate) generated_paths: Vec<String>,
+    pub(crate) tree_transformers: Vec<SharedTransformer>,
+    pub(crate) message_transformers: Vec<SharedMessageTransformer>,
+    pub(crate) message_command: Option<String>,
+    pub(crate) expect_upstream: Option<Oid>,
+}
+
+impl SquishOptions {
+    /// Create options matching the default squish behavior.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The message given with `message`, with template variables filled in.
+    pub(crate) fn custom_message(&self) -> Option<String> {
+        self.message
+            .as_deref()
+            .map(|message| expand_template(message, &self.variables))
+    }
+
+    /// Automatically resolve conflicting hunks using the given strategy.
+    pub fn strategy_option(mut self, strategy_option: StrategyOption) -> Self {
+        self.strategy_option = Some(strategy_option);
+        self
+    }
+
+    /// Clean up the squash message with the given mode instead of `commit.cleanup`.
+    pub fn cleanup(mut self, cleanup: CleanupMode) -> Self {
+        self.cleanup = Some(cleanup);
+        self
+    }
+
+    /// Normalize the squash message after cleaning it up (see `MessageNormalization`), on
+    /// top of any normalizations turned on in git config.
+    pub fn normalize_message(mut self, normalization: MessageNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Stop on conflicts instead of failing, leaving the conflicted files in the worktree
+    /// to be resolved and finished with `continue_squash` (or rolled back with `abort_squash`).
+    /// The squish fails up front when combined with a tree transformer, message transformer
+    /// or signer, which `continue_squash` could not run, or with `force` over uncommitted
+    /// changes that are not autostashed, which pausing would overwrite.
+    pub fn pause_on_conflict(mut self, pause_on_conflict: bool) -> Self {
+        self.pause_on_conflict = pause_on_conflict;
+        self
+    }
+
+    /// Squish even when the worktree has uncommitted changes. Local modifications are kept
+    /// in the worktree rather than overwritten.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Stash uncommitted changes before squishing and re-apply them afterwards, overriding
+    /// the `rebase.autoStash` config.
+    pub fn autostash(mut self, autostash: bool) -> Self {
+        self.autostash = Some(autostash);
+        self
+    }
+
+    /// Create an empty commit on top of the upstream when the branch has nothing to squash,
+    /// instead of failing with `SquishError::NothingToSquash`.
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// Write the squash commit even when its message is empty once cleaned up, like
+    /// `git commit --allow-empty-message`, instead of failing with
+    /// `SquishError::EmptyMessage`. Meant for automation which fills the message in later.
+    pub fn allow_empty_message(mut self, allow_empty_message: bool) -> Self {
+        self.allow_empty_message = allow_empty_message;
+        self
+    }
+
+    /// When the branch was squished before and has gained commits since, fold them into
+    /// the earlier squash commit like `git commit --amend`, keeping its message and author,
+    /// instead of squashing everything afresh. Earlier squashes are found through the notes
+    /// every squish records in `SQUASH_NOTES_REF`.
+    pub fn amend(mut self, amend: bool) -> Self {
+        self.amend = amend;
+        self
+    }
+
+    /// Use `message` for the squash commit instead of the first commit's message. It is
+    /// still cleaned up and normalized. With `split_auto`, `split_markers` or `split_path`,
+    /// each commit keeps the message of its own group.
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Set a template variable: `{name}` in the message given with `message` and in
+    /// trailers added with `trailer` is replaced with `value`, so CI can pass metadata like
+    /// a ticket or build number (e.g. `{ticket}`). May be called repeatedly; setting a
+    /// variable again replaces its value. Placeholders of variables which are not set are
+    /// left as they are.
+    pub fn template_variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// End the squash message with `trailer` (e.g. "Ticket: {ticket}"), with template
+    /// variables filled in. May be called repeatedly. A trailer the message already has,
+    /// such as one kept from an earlier squash with `amend`, is not added again.
+    pub fn trailer(mut self, trailer: impl Into<String>) -> Self {
+        self.trailers.push(trailer.into());
+        self
+    }
+
+    /// Add a custom header to the squash commit object, after the standard ones, for
+    /// platforms which stamp commits with metadata outside the message (e.g. a
+    /// `squish-version` header). May be called repeatedly. A signed commit's signature
+    /// covers these headers too. Names git uses itself (`tree`, `parent`, `author`,
+    /// `committer`, `encoding`, `gpgsig`, `gpgsig-sha256`, `mergetag`) or which contain
+    /// whitespace are rejected when the commit is written.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// End the squash message with a `References:` trailer listing the email Message-Ids of
+    /// the squashed commits (from their `Message-Id:` trailers and `Link:` trailers into
+    /// lore.kernel.org or patch.msgid.link), so email-based review systems like patchwork
+    /// can thread the squash commit back into the original discussions.
+    pub fn reference_footer(mut self, reference_footer: bool) -> Self {
+        self.reference_footer = reference_footer;
+        self
+    }
+
+    /// End the squash message with a `Squashed-from:` trailer for each of the branch's
+    /// original commits, giving their abbreviated ids, so the squash commit's provenance
+    /// can be grepped for after the original commits are garbage collected.
+    pub fn annotate_sources(mut self, annotate_sources: bool) -> Self {
+        self.annotate_sources = annotate_sources;
+        self
+    }
+
+    /// Record the branch's original tip and the id and subject of each squashed commit in
+    /// the squash commit's `refs/notes/squish` note, where `squash_provenance` reads them
+    /// back, leaving the commit message alone.
+    pub fn record_provenance(mut self, record_provenance: bool) -> Self {
+        self.record_provenance = record_provenance;
+        self
+    }
+
+    /// Squish branches matching a protected pattern (`main`, `master` or `squish.protected`).
+    pub fn allow_protected(mut self, allow_protected: bool) -> Self {
+        self.allow_protected = allow_protected;
+        self
+    }
+
+    /// Fetch the upstream from its remote before squishing, so a remote-tracking upstream
+    /// like "origin/main" is up to date. Credentials come from the SSH agent or the
+    /// configured git credential helpers.
+    pub fn fetch(mut self, fetch: bool) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    /// Force-push the squashed branch to its remote afterwards, rejecting the push if the
+    /// remote branch moved since it was last fetched (like `git push --force-with-lease`).
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// When pushing a branch without tracking configuration, set it up to track the branch
+    /// it was pushed to (like `git push -u`).
+    pub fn set_upstream(mut self, set_upstream: bool) -> Self {
+        self.set_upstream = set_upstream;
+        self
+    }
+
+    /// Fail on anything which would otherwise only be reported as a `SquishWarning`, such as
+    /// dropped empty commits or uncommitted changes squished with `force`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Run the repository's `pre-commit` hook against the squashed tree before the branch
+    /// is moved, failing with `SquishError::HookFailed` if it rejects it, overriding the
+    /// `squish.verifyHooks` config. The hook runs in a temporary worktree with the squashed
+    /// tree checked out and staged on top of the upstream, as if the squash commit was
+    /// being made with `git commit`.
+    pub fn verify_hooks(mut self, verify_hooks: bool) -> Self {
+        self.verify_hooks = Some(verify_hooks);
+        self
+    }
+
+    /// Run the shell command `command` (e.g. "cargo test") against the squashed tree before
+    /// the branch is moved, like `git rebase --exec`, failing with `SquishError::ExecFailed`
+    /// if it exits non-zero. It runs in the same kind of temporary worktree as the
+    /// `pre-commit` hook (see `verify_hooks`). May be given several times; the commands run
+    /// in order and the first failure stops the squish.
+    pub fn exec(mut self, command: impl Into<String>) -> Self {
+        self.exec.push(command.into());
+        self
+    }
+
+    /// Sign the squash commits, or not, overriding the `commit.gpgsign` config. With
+    /// `false` nothing is signed, not even with a `squish.signingBackend`. With `true` a
+    /// signing key which cannot be loaded fails the squish instead of only warning.
+    pub fn sign(mut self, sign: bool) -> Self {
+        self.sign = Some(sign);
+        self
+    }
+
+    /// Check the signatures of the upstream tip and of every commit being squashed before
+    /// anything is changed, like `git merge --verify-signatures`: with
+    /// `SignatureCheck::Fail` a commit which is unsigned or whose signature does not verify
+    /// fails the squish, with `SignatureCheck::Warn` each is reported as a warning. Needs
+    /// `git` on the PATH, which checks the signatures with the user's own GPG, SSH and x509
+    /// setup.
+    pub fn verify_signatures(mut self, check: SignatureCheck) -> Self {
+        self.verify_signatures = Some(check);
+        self
+    }
+
+    /// Only squash onto the upstream if it still points at `upstream_id`, failing with
+    /// `SquishError::UpstreamMoved` otherwise, so automation which looked at the upstream
+    /// earlier never squashes onto a base it did not check. The upstream is resolved and
+    /// checked while the squish lock is held.
+    pub fn expect_upstream(mut self, upstream_id: Oid) -> Self {
+        self.expect_upstream = Some(upstream_id);
+        self
+    }
+
+    /// Sign the squash commits with `signer` (see `CommitSigner`) instead of the signing
+    /// key configured in git or `squish.signingBackend`, whatever `commit.gpgsign` says.
+    /// `sign(false)` still turns signing off.
+    pub fn signer(mut self, signer: impl CommitSigner + 'static) -> Self {
+        self.signer = Some(SharedSigner(Arc::new(signer)));
+        self
+    }
+
+    /// Run the squashed tree through `transformer` before the squash commit is written (see
+    /// `TreeTransformer`), e.g. to bump a version file in the same commit. May be called
+    /// repeatedly; transformers run in the order they were added. Changes to generated
+    /// paths still go into the trailing commit. Not applied when the squash is split into
+    /// several commits or filtered.
+    pub fn tree_transformer(mut self, transformer: impl TreeTransformer + 'static) -> Self {
+        self.tree_transformers
+            .push(SharedTransformer(Arc::new(transformer)));
+        self
+    }
+
+    /// Run every squash message through `transformer` once it is composed and cleaned up
+    /// (see `MessageTransformer`), e.g. to translate it. May be called repeatedly;
+    /// transformers run in the order they were added, before the message command.
+    pub fn message_transformer(mut self, transformer: impl MessageTransformer + 'static) -> Self {
+        self.message_transformers
+            .push(SharedMessageTransformer(Arc::new(transformer)));
+        self
+    }
+
+    /// Pipe every squash message through the shell command `command` (see
+    /// `MessageCommand`), overriding the `squish.messageCommand` config. An empty command
+    /// turns the configured one off.
+    pub fn message_command(mut self, command: impl Into<String>) -> Self {
+        self.message_command = Some(command.into());
+        self
+    }
+
+    /// Check the squash kept the branch's content before moving the branch: the tree of
+    /// the last commit written must be the tree of the branch rebased onto the upstream
+    /// (see `verify_squash`), as changed by any tree transformers, failing with
+    /// `SquishError::TreeMismatch` otherwise. Cannot be
+    /// combined with `skip_commit` or `skip_subject_pattern`, which drop changes on purpose.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Squash each group of commits recommended by `recommend_splits` into a commit of its
+    /// own instead of squashing the whole branch into one.
+    pub fn split_auto(mut self, split_auto: bool) -> Self {
+        self.split_auto = split_auto;
+        self
+    }
+
+    /// Squash the branch into one commit per group of commits, with every commit whose
+    /// subject starts with `GROUP_MARKER` (e.g. `!group: Add the parser`) starting a new
+    /// group. Each group's commit takes the message of its marker commit without the marker.
+    pub fn split_markers(mut self, split_markers: bool) -> Self {
+        self.split_markers = split_markers;
+        self
+    }
+
+    /// Squash the changes to paths matching `pathspec` (e.g. `docs/**`, matched like git
+    /// pathspecs) into a commit of their own. With several pathspecs, each gets a commit in
+    /// the order given (a path goes with the first pathspec it matches), followed by one for
+    /// the changes matching none of them.
+    pub fn split_path(mut self, pathspec: impl Into<String>) -> Self {
+        self.split_paths.push(pathspec.into());
+        self
+    }
+
+    /// Keep the branch's first commit (e.g. a generated scaffold or a vendored import) as it
+    /// is and squash everything after it into a second commit on top.
+    pub fn keep_first(mut self, keep_first: bool) -> Self {
+        self.keep_first = keep_first;
+        self
+    }
+
+    /// Only fold commits whose author ("Name <email>") contains `pattern` into the squash
+    /// commit; the branch's other commits are kept, in order, around it. May be given
+    /// several times to fold the commits of any of several authors.
+    pub fn only_author(mut self, pattern: impl Into<String>) -> Self {
+        self.only_authors.push(pattern.into());
+        self
+    }
+
+    /// Keep commits whose author ("Name <email>") contains `pattern` out of the squash
+    /// commit, replaying them unchanged around it. May be given several times.
+    pub fn exclude_author(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude_authors.push(pattern.into());
+        self
+    }
+
+    /// Keep the commit `id` (one of the branch's original commits) out of the squash commit,
+    /// replaying it unchanged around it like `exclude_author` does. May be given several
+    /// times.
+    pub fn exclude_commit(mut self, id: Oid) -> Self {
+        self.exclude_commits.push(id);
+        self
+    }
+
+    /// Drop the commit `rev` (a commit id or anything else `git rev-parse` understands,
+    /// naming one of the branch's commits) from the branch instead of folding it into the
+    /// squash commit, like `drop` in an interactive rebase. May be given several times.
+    pub fn skip_commit(mut self, rev: impl Into<String>) -> Self {
+        self.skip_commits.push(rev.into());
+        self
+    }
+
+    /// Drop commits whose subject matches the regular expression `pattern` (e.g. `^wip`)
+    /// from the branch, like `skip_commit`. May be given several times.
+    pub fn skip_subject_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.skip_subject_patterns.push(pattern.into());
+        self
+    }
+
+    /// Only fold commits authored at or after `since_date` (seconds since the Unix epoch,
+    /// see `parse_date`) into the squash commit, keeping older ones as they are.
+    pub fn since_date(mut self, since_date: i64) -> Self {
+        self.since_date = Some(since_date);
+        self
+    }
+
+    /// Only fold commits authored at or before `until_date` (seconds since the Unix epoch,
+    /// see `parse_date`) into the squash commit, keeping newer ones on top of it.
+    pub fn until_date(mut self, until_date: i64) -> Self {
+        self.until_date = Some(until_date);
+        self
+    }
+
+    /// Treat paths matching `pattern` (e.g. `*.lock`, `dist/` or `/gen/*.pb.go`, matched
+    /// like `.gitignore` patterns) as generated: their changes go into a trailing
+    /// "Regenerate artifacts" commit on top of the squash commit. Adds to the multi-valued
+    /// `squish.generated` config.
+    pub fn generated_path(mut self, pattern: impl Into<String>) -> Self {
+        self.generated_paths.push(pattern.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategy_option_from_str() {
+        assert_eq!(
+            "ours".parse::<StrategyOption>().unwrap(),
+            StrategyOption::Ours
+        );
+        assert_eq!(
+            "theirs".parse::<StrategyOption>().unwrap(),
+            StrategyOption::Theirs
+        );
+        assert_eq!(
+            "union".parse::<StrategyOption>().unwrap(),
+            StrategyOption::Union
+        );
+        assert!("recursive".parse::<StrategyOption>().is_err());
+    }
+
+    #[test]
+    fn test_squish_options_builder() {
+        let options = SquishOptions::new();
+        assert_eq!(options.strategy_option, None);
+        assert!(!options.pause_on_conflict);
+        assert_eq!(options.cleanup, None);
+        assert_eq!(options.normalization, MessageNormalization::default());
+        assert!(!options.force);
+        assert_eq!(options.autostash, None);
+        assert!(!options.allow_empty);
+        assert!(!options.allow_empty_message);
+        assert_eq!(options.message, None);
+        assert!(options.variables.is_empty());
+        assert!(options.trailers.is_empty());
+        assert!(!options.amend);
+        assert!(options.headers.is_empty());
+        assert!(!options.reference_footer);
+        assert!(!options.annotate_sources);
+        assert!(!options.record_provenance);
+        assert!(!options.allow_protected);
+        assert!(!options.fetch);
+        assert!(!options.push);
+        assert!(!options.split_auto);
+        assert!(!options.split_markers);
+        assert!(options.split_paths.is_empty());
+        assert!(!options.keep_first);
+        assert!(!options.set_upstream);
+        assert!(!options.strict);
+        assert!(!options.verify);
+        assert_eq!(options.verify_hooks, None);
+        assert!(options.exec.is_empty());
+        assert_eq!(options.sign, None);
+        assert_eq!(options.verify_signatures, None);
+        assert_eq!(options.expect_upstream, None);
+        assert!(options.only_authors.is_empty());
+        assert!(options.exclude_authors.is_empty());
+        assert!(options.exclude_commits.is_empty());
+        assert!(options.skip_commits.is_empty());
+        assert!(options.skip_subject_patterns.is_empty());
+        assert_eq!(options.since_date, None);
+        assert_eq!(options.until_date, None);
+        assert!(options.generated_paths.is_empty());
+
+        let options = options
+            .strategy_option(StrategyOption::Ours)
+            .pause_on_conflict(true)
+            .cleanup(CleanupMode::Strip)
+            .normalize_message(MessageNormalization {
+                trailing_newline: true,
+                ..MessageNormalization::default()
+            })
+            .force(true)
+            .autostash(false)
+            .allow_empty(true)
+            .allow_empty_message(true)
+            .message("Release 1.0")
+            .template_variable("ticket", "ABC-1")
+            .template_variable("ticket", "ABC-2")
+            .trailer("Ticket: {ticket}")
+            .amend(true)
+            .header("squish-version", "1")
+            .reference_footer(true)
+            .annotate_sources(true)
+            .record_provenance(true)
+            .allow_protected(true)
+            .fetch(true)
+            .push(true)
+            .split_auto(true)
+            .split_markers(true)
+            .split_path("docs/**")
+            .keep_first(true)
+            .set_upstream(true)
+            .strict(true)
+            .verify(true)
+            .verify_hooks(true)
+            .exec("cargo test")
+            .exec("make lint")
+            .sign(false)
+            .verify_signatures(SignatureCheck::Warn)
+            .only_author("dependabot")
+            .exclude_author("alice")
+            .exclude_author("bob")
+            .exclude_commit(Oid::zero())
+            .skip_commit("HEAD~1")
+            .skip_subject_pattern("^wip")
+            .since_date(100)
+            .until_date(200)
+            .generated_path("*.lock");
+        assert_eq!(options.strategy_option, Some(StrategyOption::Ours));
+        assert!(options.pause_on_conflict);
+        assert_eq!(options.cleanup, Some(CleanupMode::Strip));
+        assert!(options.normalization.trailing_newline);
+        assert!(options.force);
+        assert_eq!(options.autostash, Some(false));
+        assert!(options.allow_empty);
+        assert!(options.allow_empty_message);
+        assert_eq!(options.message.as_deref(), Some("Release 1.0"));
+        assert_eq!(options.variables["ticket"], "ABC-2");
+        assert_eq!(options.trailers, ["Ticket: {ticket}"]);
+        assert!(options.amend);
+        assert_eq!(
+            options.headers,
+            vec![("squish-version".to_string(), "1".to_string())]
+        );
+        assert!(options.reference_footer);
+        assert!(options.annotate_sources);
+        assert!(options.record_provenance);
+        assert!(options.allow_protected);
+        assert!(options.fetch);
+        assert!(options.push);
+        assert!(options.split_auto);
+        assert!(options.split_markers);
+        assert_eq!(options.split_paths, vec!["docs/**"]);
+        assert!(options.keep_first);
+        assert!(options.set_upstream);
+        assert!(options.strict);
+        assert!(options.verify);
+        assert_eq!(options.verify_hooks, Some(true));
+        assert_eq!(options.exec, ["cargo test", "make lint"]);
+        assert_eq!(options.sign, Some(false));
+        assert_eq!(options.verify_signatures, Some(SignatureCheck::Warn));
+        assert_eq!(options.only_authors, vec!["dependabot"]);
+        assert_eq!(options.exclude_authors, vec!["alice", "bob"]);
+        assert_eq!(options.exclude_commits, vec![Oid::zero()]);
+        assert_eq!(options.skip_commits, vec!["HEAD~1"]);
+        assert_eq!(options.skip_subject_patterns, vec!["^wip"]);
+        assert_eq!(options.since_date, Some(100));
+        assert_eq!(options.until_date, Some(200));
+        assert_eq!(options.generated_paths, vec!["*.lock"]);
+    }
+}
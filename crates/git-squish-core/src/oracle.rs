@@ -0,0 +1,304 @@
+//! Cross-checks squishes against the git CLI.
+//!
+//! Every fixture is squished twice: once by the library and once by real `git rebase` and
+//! `git merge --squash` commands, and the two trees must match. Options which change how
+//! the history is laid out but not what it ends up containing (splits, keeping the first
+//! commit) are checked against the tree at the new tip of the branch. The checks are
+//! skipped when no `git` executable is found.
+
+use git2::{Oid, Repository};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+use crate::{SquishOptions, StrategyOption, squash_branch_with_options};
+
+/// Whether a `git` executable is available to check against.
+fn git_available() -> bool {
+    Command::new("git")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Run git in `repo_path`, ignoring any config outside the repository, and return what
+/// it printed.
+fn git(repo_path: &Path, args: &[&str], envs: &[(&str, &str)]) -> String {
+    let output = Command::new("git")
+        .args([
+            "-c",
+            "commit.gpgSign=false",
+            "-c",
+            "core.hooksPath=/dev/null",
+        ])
+        .args(args)
+        .current_dir(repo_path)
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .envs(envs.iter().copied())
+        .output()
+        .expect("git runs");
+    assert!(
+        output.status.success(),
+        "git {} failed:\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// How the git CLI should replay the branch.
+#[derive(Default)]
+struct GitSquash {
+    /// Passed to `git rebase` as `-X`.
+    strategy_option: Option<StrategyOption>,
+    /// Commits left out of the rebase, as `drop` would in an interactive rebase.
+    drop: Vec<Oid>,
+}
+
+impl GitSquash {
+    /// Rebase a copy of `branch` onto `upstream` and squash-merge it there, returning the
+    /// tree git ends up with. Leaves `branch` and the worktree as they were.
+    fn tree(&self, repo_path: &Path, branch: &str, upstream: &str) -> Oid {
+        let head = git(repo_path, &["symbolic-ref", "--short", "HEAD"], &[]);
+        git(repo_path, &["checkout", "-q", "-B", "oracle", branch], &[]);
+
+        let mut rebase = vec!["rebase", "-q"];
+        if let Some(strategy_option) = self.strategy_option {
+            rebase.extend(["-X", strategy_option.as_str()]);
+        }
+        let todo_path = repo_path.join(".git/oracle-todo");
+        let editor = format!("cp '{}'", todo_path.display());
+        let mut envs = vec![];
+        if !self.drop.is_empty() {
+            let range = format!("{upstream}..{branch}");
+            let todo: String = git(repo_path, &["rev-list", "--reverse", &range], &[])
+                .lines()
+                .filter(|id| !self.drop.iter().any(|drop| drop.to_string() == *id))
+                .map(|id| format!("pick {id}\n"))
+                .collect();
+            std::fs::write(&todo_path, todo).unwrap();
+            rebase.push("-i");
+            envs.push(("GIT_SEQUENCE_EDITOR", editor.as_str()));
+        }
+        rebase.push(upstream);
+        git(repo_path, &rebase, &envs);
+
+        git(repo_path, &["checkout", "-q", "--detach", upstream], &[]);
+        git(repo_path, &["merge", "-q", "--squash", "oracle"], &[]);
+        let tree = git(repo_path, &["write-tree"], &[]);
+        git(repo_path, &["reset", "-q", "--hard"], &[]);
+        git(repo_path, &["checkout", "-q", &head], &[]);
+        git(repo_path, &["branch", "-q", "-D", "oracle"], &[]);
+        Oid::from_str(&tree).unwrap()
+    }
+}
+
+/// Squish `branch` onto `upstream` with both the library and the git CLI and check they
+/// end up with the same tree and that the branch now sits on `upstream`.
+fn assert_matches_git(
+    repo_path: &PathBuf,
+    branch: &str,
+    upstream: &str,
+    options: SquishOptions,
+    git_squash: GitSquash,
+) {
+    let expected = git_squash.tree(repo_path, branch, upstream);
+
+    let repo = Repository::open(repo_path).unwrap();
+    let upstream_id = repo.revparse_single(upstream).unwrap().id();
+    let result = squash_branch_with_options(repo_path, branch, upstream, &options)
+        .unwrap_or_else(|e| panic!("Squish of {branch} failed: {e}"));
+
+    let tip = repo.find_commit(result.new_id).unwrap();
+    assert_eq!(
+        tip.tree_id(),
+        expected,
+        "{branch} squashed to a different tree than git"
+    );
+    assert!(repo.graph_descendant_of(tip.id(), upstream_id).unwrap());
+    assert_eq!(git(repo_path, &["status", "--porcelain"], &[]), "");
+}
+
+/// Run a fixture check unless git is missing.
+macro_rules! require_git {
+    () => {
+        if !git_available() {
+            eprintln!("git not found, skipping");
+            return;
+        }
+    };
+}
+
+/// A repo whose topic branch edits, adds and deletes files while main moves on with
+/// unrelated changes.
+fn diverged_repo() -> (PathBuf, tempfile::TempDir) {
+    let (repo_path, temp_dir) = init_test_repo().unwrap();
+    commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+    commit_file(&repo_path, "doomed.txt", "doomed\n", "Add doomed").unwrap();
+    create_branch(&repo_path, "topic").unwrap();
+    commit_file(&repo_path, "a.txt", "a\ntopic\n", "Edit a").unwrap();
+    commit_file(&repo_path, "docs/guide.md", "guide\n", "Add guide").unwrap();
+    git(&repo_path, &["rm", "-q", "doomed.txt"], &[]);
+    git(&repo_path, &["commit", "-q", "-m", "Remove doomed"], &[]);
+    commit_file(&repo_path, "src/lib.rs", "fn main() {}\n", "Add lib").unwrap();
+    change_to_branch(&repo_path, "main").unwrap();
+    commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+    change_to_branch(&repo_path, "topic").unwrap();
+    (repo_path, temp_dir)
+}
+
+/// A repo whose topic branch changes the same line of text.txt as main.
+fn conflicting_repo() -> (PathBuf, tempfile::TempDir) {
+    let (repo_path, temp_dir) = init_test_repo().unwrap();
+    commit_file(
+        &repo_path,
+        "text.txt",
+        "one\ntwo\nthree\n",
+        "Initial commit",
+    )
+    .unwrap();
+    create_branch(&repo_path, "topic").unwrap();
+    commit_file(
+        &repo_path,
+        "text.txt",
+        "one\ntopic\nthree\n",
+        "Topic change",
+    )
+    .unwrap();
+    commit_file(&repo_path, "topic.txt", "topic only\n", "Topic file").unwrap();
+    commit_file(
+        &repo_path,
+        "text.txt",
+        "one\ntopic\nthree\nfour\n",
+        "Append",
+    )
+    .unwrap();
+    change_to_branch(&repo_path, "main").unwrap();
+    commit_file(&repo_path, "text.txt", "one\nmain\nthree\n", "Main change").unwrap();
+    change_to_branch(&repo_path, "topic").unwrap();
+    (repo_path, temp_dir)
+}
+
+#[test]
+fn test_matches_git_after_upstream_moved() {
+    require_git!();
+    let (repo_path, _temp_dir) = diverged_repo();
+
+    assert_matches_git(
+        &repo_path,
+        "topic",
+        "main",
+        SquishOptions::new(),
+        GitSquash::default(),
+    );
+}
+
+#[test]
+fn test_matches_git_with_renames_and_modes() {
+    require_git!();
+    let (repo_path, _temp_dir) = init_test_repo().unwrap();
+    let contents: String = (1..=20).map(|line| format!("line {line}\n")).collect();
+    commit_file(&repo_path, "old.txt", &contents, "Initial commit").unwrap();
+    commit_file(&repo_path, "run.sh", "echo run\n", "Add script").unwrap();
+    create_branch(&repo_path, "topic").unwrap();
+    let edited = contents.replace("line 20\n", "line 20 from topic\n");
+    commit_file(&repo_path, "old.txt", &edited, "Edit the last line").unwrap();
+    git(&repo_path, &["update-index", "--chmod=+x", "run.sh"], &[]);
+    git(
+        &repo_path,
+        &["commit", "-q", "-m", "Make run.sh executable"],
+        &[],
+    );
+    change_to_branch(&repo_path, "main").unwrap();
+    git(&repo_path, &["mv", "old.txt", "new.txt"], &[]);
+    git(&repo_path, &["commit", "-q", "-m", "Rename old.txt"], &[]);
+    change_to_branch(&repo_path, "topic").unwrap();
+
+    assert_matches_git(
+        &repo_path,
+        "topic",
+        "main",
+        SquishOptions::new(),
+        GitSquash::default(),
+    );
+    // The edit followed the rename rather than bringing old.txt back.
+    let repo = Repository::open(&repo_path).unwrap();
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_name("old.txt").is_none());
+    assert_eq!(tree.get_name("run.sh").unwrap().filemode(), 0o100755);
+}
+
+#[test]
+fn test_matches_git_with_commits_already_upstream() {
+    require_git!();
+    let (repo_path, _temp_dir) = diverged_repo();
+    let repo = Repository::open(&repo_path).unwrap();
+    let edit_a = repo.revparse_single("topic~3").unwrap().id();
+    change_to_branch(&repo_path, "main").unwrap();
+    git(&repo_path, &["cherry-pick", &edit_a.to_string()], &[]);
+    change_to_branch(&repo_path, "topic").unwrap();
+
+    assert_matches_git(
+        &repo_path,
+        "topic",
+        "main",
+        SquishOptions::new(),
+        GitSquash::default(),
+    );
+}
+
+#[test]
+fn test_matches_git_with_strategy_options() {
+    require_git!();
+    for strategy_option in [StrategyOption::Ours, StrategyOption::Theirs] {
+        let (repo_path, _temp_dir) = conflicting_repo();
+
+        assert_matches_git(
+            &repo_path,
+            "topic",
+            "main",
+            SquishOptions::new().strategy_option(strategy_option),
+            GitSquash {
+                strategy_option: Some(strategy_option),
+                ..GitSquash::default()
+            },
+        );
+    }
+}
+
+#[test]
+fn test_matches_git_with_skipped_commit() {
+    require_git!();
+    let (repo_path, _temp_dir) = diverged_repo();
+    let repo = Repository::open(&repo_path).unwrap();
+    let add_guide = repo.revparse_single("topic~2").unwrap().id();
+
+    assert_matches_git(
+        &repo_path,
+        "topic",
+        "main",
+        SquishOptions::new().skip_commit(add_guide.to_string()),
+        GitSquash {
+            drop: vec![add_guide],
+            ..GitSquash::default()
+        },
+    );
+}
+
+#[test]
+fn test_matches_git_when_history_is_laid_out_differently() {
+    require_git!();
+    let cases = [
+        SquishOptions::new().split_path("docs/"),
+        SquishOptions::new().keep_first(true),
+    ];
+    for options in cases {
+        let (repo_path, _temp_dir) = diverged_repo();
+
+        assert_matches_git(&repo_path, "topic", "main", options, GitSquash::default());
+    }
+}
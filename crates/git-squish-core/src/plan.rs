@@ -0,0 +1,443 @@
+use git2::{Diff, Oid, Patch, Repository};
+use std::path::PathBuf;
+
+use crate::ignore_revs::IgnoredRevs;
+use crate::message::{add_sources, range_commits, squash_message};
+use crate::show::detect_renames;
+use crate::{
+    SquashResult, SquishError, SquishOptions, check_expected_upstream, ensure_related_histories,
+    find_branch, find_upstream, finish_message, normalize_branch_refname,
+    squash_branch_with_options,
+};
+
+/// What squishing a branch would do, computed without changing anything so it can be
+/// shown before the branch is rewritten. Call `execute` to carry it out.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SquishPlan {
+    /// The branch to squash (e.g., "refs/heads/feature").
+    pub branch: String,
+    /// The upstream to squash onto, as given.
+    pub upstream: String,
+    /// Where the branch points.
+    pub branch_id: Oid,
+    /// The upstream tip the squash commit will sit on.
+    pub upstream_id: Oid,
+    /// Where the branch forked off the upstream.
+    pub merge_base: Oid,
+    /// The commits which would be squashed, oldest first.
+    pub commits: Vec<Oid>,
+    /// The predicted message of the squash commit (`SquishOptions::message` if set).
+    /// Filtering or skipping commits (`only_author`, `keep_first`, `skip_commit`, ...) or
+    /// splitting them changes which commits the message comes from.
+    pub message: String,
+    /// Paths which an in-memory merge of the branch and upstream tips left conflicted, so
+    /// the rebase is likely to stop on them. Empty when no conflicts are expected.
+    pub conflicts: Vec<String>,
+    /// The files the squash commit would change relative to the upstream tip.
+    pub files: Vec<FileStat>,
+    repo_path: PathBuf,
+    options: SquishOptions,
+}
+
+/// How one file would change, like a line of `git diff --stat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FileStat {
+    /// The path of the file after the change.
+    pub path: String,
+    /// The path the file had before, when it was renamed.
+    pub old_path: Option<String>,
+    /// Number of lines added.
+    pub insertions: usize,
+    /// Number of lines removed.
+    pub deletions: usize,
+    /// Whether the file is binary, in which case no lines are counted.
+    pub binary: bool,
+}
+
+impl SquishPlan {
+    /// Plan squashing `branch_refname` onto `upstream_spec` with the default options.
+    pub fn compute(
+        repo: &Repository,
+        branch_refname: &str,
+        upstream_spec: &str,
+    ) -> Result<SquishPlan, SquishError> {
+        Self::compute_with_options(repo, branch_refname, upstream_spec, &SquishOptions::new())
+    }
+
+    /// Plan squashing `branch_refname` onto `upstream_spec` with `options`, which are also
+    /// used when the plan is executed.
+    ///
+    /// Nothing is written: conflicts are predicted with an in-memory merge, like
+    /// `list_squashable_branches` does. A branch with no commits to squash fails with
+    /// `NothingToSquash`.
+    pub fn compute_with_options(
+        repo: &Repository,
+        branch_refname: &str,
+        upstream_spec: &str,
+        options: &SquishOptions,
+    ) -> Result<SquishPlan, SquishError> {
+        let branch = normalize_branch_refname(repo, branch_refname)?;
+        ensure_related_histories(repo, &branch, upstream_spec)?;
+        let branch_tip = find_branch(repo, &branch)?.peel_to_commit()?;
+        let upstream = find_upstream(repo, upstream_spec)?.peel_to_commit()?;
+        let merge_base = repo.merge_base(branch_tip.id(), upstream.id())?;
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(branch_tip.id())?;
+        revwalk.hide(upstream.id())?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        let commits = revwalk.collect::<Result<Vec<_>, _>>()?;
+        if commits.is_empty() {
+            return Err(SquishError::NothingToSquash { branch });
+        }
+
+        let ignored = IgnoredRevs::load(repo)?;
+        let message = squash_message(repo, &upstream, &branch_tip, &ignored, options)?;
+        let message = add_sources(
+            message,
+            &range_commits(repo, &upstream, &branch_tip)?,
+            options,
+        )?;
+        let message = finish_message(repo, &message, options)?;
+
+        // The squash commit's tree is what merging the branch into the upstream gives. When
+        // that conflicts, the branch's own changes are the best preview there is.
+        let mut conflicts = Vec::new();
+        let (old_tree, new_tree) = if merge_base == upstream.id() {
+            (upstream.tree()?, branch_tip.tree()?)
+        } else {
+            let mut index = repo.merge_commits(&upstream, &branch_tip, None)?;
+            if index.has_conflicts() {
+                for conflict in index.conflicts()? {
+                    let conflict = conflict?;
+                    if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                        conflicts.push(String::from_utf8_lossy(&entry.path).into_owned());
+                    }
+                }
+                (repo.find_commit(merge_base)?.tree()?, branch_tip.tree()?)
+            } else {
+                let merged = repo.find_tree(index.write_tree_to(repo)?)?;
+                (upstream.tree()?, merged)
+            }
+        };
+        let mut diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+        detect_renames(&repo.config()?, &mut diff)?;
+        let files = file_stats(&diff)?;
+
+        Ok(SquishPlan {
+            branch,
+            upstream: upstream_spec.to_string(),
+            branch_id: branch_tip.id(),
+            upstream_id: upstream.id(),
+            merge_base,
+            commits,
+            message,
+            conflicts,
+            files,
+            repo_path: repo.workdir().unwrap_or(repo.path()).to_path_buf(),
+            options: options.clone(),
+        })
+    }
+
+    /// Carry out the plan like `squash_branch_with_options` would.
+    ///
+    /// # Returns
+    /// The outcome of the squish, `UpstreamMoved` if the upstream moved since the plan was
+    /// computed, or `PlanOutdated` if the branch did.
+    pub fn execute(&self) -> Result<SquashResult, SquishError> {
+        let repo = crate::open_repository(&self.repo_path)?;
+        let upstream_id = find_upstream(&repo, &self.upstream)?.peel_to_commit()?.id();
+        check_expected_upstream(&self.upstream, upstream_id, &self.execute_options())?;
+        let branch_id = find_branch(&repo, &self.branch)?.peel_to_commit()?.id();
+        if branch_id != self.branch_id {
+            return Err(SquishError::PlanOutdated {
+                branch: self.branch.clone(),
+                upstream: self.upstream.clone(),
+            });
+        }
+        // Checked again once the squish holds its lock, in case the upstream moves now.
+        squash_branch_with_options(
+            &self.repo_path,
+            self.branch.clone(),
+            self.upstream.clone(),
+            &self.execute_options(),
+        )
+    }
+
+    /// The options to execute the plan with: those it was computed with, expecting the
+    /// upstream it was computed against.
+    fn execute_options(&self) -> SquishOptions {
+        self.options.clone().expect_upstream(self.upstream_id)
+    }
+}
+
+/// The per-file line counts of a diff.
+fn file_stats(diff: &Diff) -> Result<Vec<FileStat>, SquishError> {
+    let path = |file: git2::DiffFile| {
+        file.path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let mut files = Vec::new();
+    for (idx, delta) in diff.deltas().enumerate() {
+        let new_path = path(delta.new_file());
+        let old_path = path(delta.old_file());
+        let (insertions, deletions, binary) = match Patch::from_diff(diff, idx)? {
+            Some(patch) if !delta.flags().is_binary() => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                (insertions, deletions, false)
+            }
+            _ => (0, 0, true),
+        };
+        files.push(FileStat {
+            old_path: (delta.status() == git2::Delta::Renamed && old_path != new_path)
+                .then_some(old_path),
+            path: new_path,
+            insertions,
+            deletions,
+            binary,
+        });
+    }
+    Ok(files)
+}
+
+/// Render file changes like `git diff --stat`: one line per file with its change count and
+/// a bar of `+` and `-`, followed by a summary line.
+pub fn format_diffstat(files: &[FileStat]) -> String {
+    const MAX_BAR: usize = 40;
+    let names: Vec<String> = files
+        .iter()
+        .map(|file| match &file.old_path {
+            Some(old_path) => format!("{old_path} => {}", file.path),
+            None => file.path.clone(),
+        })
+        .collect();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let most_changes = files
+        .iter()
+        .map(|file| file.insertions + file.deletions)
+        .max()
+        .unwrap_or(0);
+    let count_width = most_changes.to_string().len().max(3);
+
+    let mut out = String::new();
+    for (file, name) in files.iter().zip(&names) {
+        if file.binary {
+            out.push_str(&format!(" {name:<name_width$} | {:>count_width$}\n", "Bin"));
+            continue;
+        }
+        let changes = file.insertions + file.deletions;
+        // Scale the bar down when the largest change would not fit, keeping at least one
+        // character for any change at all.
+        let scale = |lines: usize| match most_changes {
+            0..=MAX_BAR => lines,
+            _ if lines == 0 => 0,
+            _ => (lines * MAX_BAR / most_changes).max(1),
+        };
+        out.push_str(
+            format!(
+                " {name:<name_width$} | {changes:>count_width$} {}{}",
+                "+".repeat(scale(file.insertions)),
+                "-".repeat(scale(file.deletions))
+            )
+            .trim_end(),
+        );
+        out.push('\n');
+    }
+
+    let plural = |count: usize, one: &str, many: &str| {
+        format!("{count} {}", if count == 1 { one } else { many })
+    };
+    let insertions: usize = files.iter().map(|file| file.insertions).sum();
+    let deletions: usize = files.iter().map(|file| file.deletions).sum();
+    let mut summary = format!(" {}", plural(files.len(), "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        summary.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 {
+        summary.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    out.push_str(&summary);
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        change_to_branch, commit_file, create_branch, init_test_repo, topic_repo,
+    };
+
+    #[test]
+    fn test_plan_then_execute() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "text.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "text.txt", "topic\n", "Topic change").unwrap();
+        commit_file(&repo_path, "topic.txt", "topic\n", "Topic file").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let base_id = commit_file(&repo_path, "main.txt", "main\n", "Main file").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let plan = SquishPlan::compute(&repo, "topic", "main").unwrap();
+        assert_eq!(plan.branch, "refs/heads/topic");
+        assert_eq!(plan.upstream_id, base_id);
+        assert_eq!(plan.commits.len(), 2);
+        assert_eq!(plan.message, "Topic change\n");
+        assert!(plan.conflicts.is_empty());
+        let stat = |path: &str, insertions, deletions| FileStat {
+            path: path.to_string(),
+            old_path: None,
+            insertions,
+            deletions,
+            binary: false,
+        };
+        assert_eq!(
+            plan.files,
+            [stat("text.txt", 1, 1), stat("topic.txt", 1, 0)]
+        );
+        // Computing the plan changed nothing.
+        assert_eq!(
+            repo.refname_to_id("refs/heads/topic").unwrap(),
+            plan.branch_id
+        );
+
+        let result = plan.execute().unwrap();
+        assert_eq!(result.old_id, plan.branch_id);
+        assert_eq!(result.commits, plan.commits);
+        let squashed = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(squashed.message(), Some(plan.message.as_str()));
+        assert_eq!(
+            squashed.parent_ids().collect::<Vec<_>>(),
+            [plan.upstream_id]
+        );
+
+        // The branch has moved, so the plan no longer applies.
+        assert!(matches!(
+            plan.execute().unwrap_err(),
+            SquishError::PlanOutdated { .. }
+        ));
+    }
+
+    #[test]
+    fn test_execute_rejects_moved_upstream() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        let plan = SquishPlan::compute(&repo, "topic", "main").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let moved = commit_file(&repo_path, "main.txt", "main\n", "Main file").unwrap();
+
+        match plan.execute().unwrap_err() {
+            SquishError::UpstreamMoved {
+                upstream,
+                expected,
+                actual,
+            } => {
+                assert_eq!(upstream, "main");
+                assert_eq!((expected, actual), (plan.upstream_id, moved));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+
+        // The same check guards a squish given the upstream it expects directly.
+        let stale = SquishOptions::new().expect_upstream(plan.upstream_id);
+        assert!(matches!(
+            squash_branch_with_options(&repo_path, "topic", "main", &stale),
+            Err(SquishError::UpstreamMoved { .. })
+        ));
+        let current = SquishOptions::new().expect_upstream(moved);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &current).unwrap();
+        assert_eq!(
+            repo.find_commit(result.new_id)
+                .unwrap()
+                .parent_ids()
+                .collect::<Vec<_>>(),
+            [moved]
+        );
+    }
+
+    #[test]
+    fn test_format_diffstat() {
+        let files = [
+            FileStat {
+                path: "src/main.rs".to_string(),
+                old_path: None,
+                insertions: 3,
+                deletions: 1,
+                binary: false,
+            },
+            FileStat {
+                path: "src/app.rs".to_string(),
+                old_path: Some("src/lib.rs".to_string()),
+                insertions: 0,
+                deletions: 0,
+                binary: false,
+            },
+            FileStat {
+                path: "logo.png".to_string(),
+                old_path: None,
+                insertions: 0,
+                deletions: 0,
+                binary: true,
+            },
+        ];
+        assert_eq!(
+            format_diffstat(&files),
+            concat!(
+                " src/main.rs              |   4 +++-\n",
+                " src/lib.rs => src/app.rs |   0\n",
+                " logo.png                 | Bin\n",
+                " 3 files changed, 3 insertions(+), 1 deletion(-)\n",
+            )
+        );
+
+        let big = [FileStat {
+            path: "big.txt".to_string(),
+            old_path: None,
+            insertions: 100,
+            deletions: 0,
+            binary: false,
+        }];
+        assert_eq!(
+            format_diffstat(&big),
+            format!(
+                " big.txt | 100 {}\n 1 file changed, 100 insertions(+)\n",
+                "+".repeat(40)
+            )
+        );
+    }
+
+    #[test]
+    fn test_plan_predicts_conflicts() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "text.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "text.txt", "topic\n", "Topic change").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "text.txt", "main\n", "Main change").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let plan = SquishPlan::compute(&repo, "topic", "main").unwrap();
+        assert_eq!(plan.conflicts, ["text.txt"]);
+        assert_eq!(
+            plan.merge_base,
+            repo.revparse_single("main~1").unwrap().id()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use git2::Repository;
+
+use crate::SquishError;
+
+/// Branches which are always protected.
+const DEFAULT_PROTECTED: [&str; 2] = ["main", "master"];
+/// Multi-valued config key holding extra protected branch patterns (e.g. `release/*`).
+const PROTECTED_CONFIG: &str = "squish.protected";
+
+/// Fail if `branch_refname` matches a protected branch pattern: `main`, `master` or any
+/// `squish.protected` config value.
+pub(crate) fn ensure_not_protected(
+    repo: &Repository,
+    branch_refname: &str,
+) -> Result<(), SquishError> {
+    let name = branch_refname
+        .strip_prefix("refs/heads/")
+        .unwrap_or(branch_refname);
+
+    let mut patterns: Vec<String> = DEFAULT_PROTECTED.map(str::to_string).to_vec();
+    let config = repo.config()?;
+    if let Ok(entries) = config.multivar(PROTECTED_CONFIG, None) {
+        entries.for_each(|entry| {
+            if let Some(value) = entry.value() {
+                patterns.push(value.to_string());
+            }
+        })?;
+    }
+
+    if let Some(pattern) = patterns.iter().find(|pattern| glob_match(pattern, name)) {
+        return Err(SquishError::ProtectedBranch {
+            branch: branch_refname.to_string(),
+            pattern: pattern.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Match `text` against a glob where `*` matches any run of characters and `?` matches a
+/// single character.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the most recent `*`, if the current attempt fails.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_test_repo;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("main", "main2"));
+        assert!(glob_match("release/*", "release/1.0"));
+        assert!(!glob_match("release/*", "releases/1.0"));
+        assert!(glob_match("*-stable", "v2-stable"));
+        assert!(glob_match("v?.x", "v2.x"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+    }
+
+    #[test]
+    fn test_ensure_not_protected() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        assert!(ensure_not_protected(&repo, "refs/heads/main").is_err());
+        assert!(ensure_not_protected(&repo, "refs/heads/master").is_err());
+        assert!(ensure_not_protected(&repo, "refs/heads/release/1.0").is_ok());
+
+        repo.config()
+            .unwrap()
+            .set_multivar(PROTECTED_CONFIG, "^$", "release/*")
+            .unwrap();
+        match ensure_not_protected(&repo, "refs/heads/release/1.0") {
+            Err(SquishError::ProtectedBranch { branch, pattern }) => {
+                assert_eq!(branch, "refs/heads/release/1.0");
+                assert_eq!(pattern, "release/*");
+            }
+            other => panic!("Expected SquishError::ProtectedBranch, got: {:?}", other),
+        }
+        assert!(ensure_not_protected(&repo, "refs/heads/feature").is_ok());
+    }
+}
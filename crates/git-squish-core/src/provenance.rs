@@ -0,0 +1,260 @@
+use git2::{BranchType, Oid, Repository};
+use std::path::Path;
+
+use crate::lock::SquishLock;
+use crate::resquash::SQUASH_NOTES_REF;
+use crate::{SquishError, SquishOptions, open_repository, squashed_commits};
+
+/// One of the commits a squash replaced, as recorded in its note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SourceCommit {
+    /// The original commit's id.
+    pub id: Oid,
+    /// Its subject line.
+    pub summary: String,
+}
+
+/// What a squash commit was made from, read back from its `refs/notes/squish` note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SquashProvenance {
+    /// The branch which was squished (e.g., "refs/heads/feature").
+    pub branch: String,
+    /// The upstream commit it was squashed onto.
+    pub upstream_id: Oid,
+    /// Where the branch pointed before the squish. Only recorded with
+    /// `SquishOptions::record_provenance`.
+    pub original_tip: Option<Oid>,
+    /// The commits which were squashed, oldest first. Only recorded with
+    /// `SquishOptions::record_provenance`.
+    pub commits: Vec<SourceCommit>,
+}
+
+/// Record in `SQUASH_NOTES_REF` that `commit_id` is the tip a squish of `branch_refname`
+/// onto `upstream_id` left behind, along with the original tip and the squashed commits
+/// with `SquishOptions::record_provenance`.
+pub(crate) fn record_squash(
+    repo: &Repository,
+    commit_id: Oid,
+    branch_refname: &str,
+    upstream_id: Oid,
+    orig_tip_id: Oid,
+    options: &SquishOptions,
+) -> Result<(), SquishError> {
+    let mut note = format!("squished {branch_refname} onto {upstream_id}\n");
+    if options.record_provenance {
+        note.push_str(&format!("original-tip {orig_tip_id}\n"));
+        for id in squashed_commits(repo, orig_tip_id, upstream_id)? {
+            let commit = repo.find_commit(id)?;
+            let summary = commit.summary().unwrap_or_default();
+            note.push_str(&format!("squashed {id} {summary}\n"));
+        }
+    }
+
+    let sig = repo.signature()?;
+    repo.note(&sig, &sig, Some(SQUASH_NOTES_REF), commit_id, &note, true)?;
+    Ok(())
+}
+
+/// Read back what the squash commit `commit_id` was made from.
+///
+/// # Returns
+/// The provenance recorded when the commit was squashed, or None if it has no
+/// `refs/notes/squish` note (it was not made by a squish, or the notes were not fetched).
+pub fn squash_provenance(
+    repo: &Repository,
+    commit_id: Oid,
+) -> Result<Option<SquashProvenance>, SquishError> {
+    let Ok(note) = repo.find_note(Some(SQUASH_NOTES_REF), commit_id) else {
+        return Ok(None);
+    };
+    let invalid = || SquishError::Other {
+        message: format!("The {SQUASH_NOTES_REF} note of {commit_id} is not a squash record"),
+    };
+    let message = note.message().ok_or_else(invalid)?;
+    let mut lines = message.lines();
+    let (branch, upstream_id) = lines
+        .next()
+        .and_then(|line| line.strip_prefix("squished "))
+        .and_then(|line| line.rsplit_once(" onto "))
+        .ok_or_else(invalid)?;
+    let mut provenance = SquashProvenance {
+        branch: branch.to_string(),
+        upstream_id: Oid::from_str(upstream_id).map_err(|_| invalid())?,
+        original_tip: None,
+        commits: Vec::new(),
+    };
+    // Lines this version does not know are skipped, so notes written by newer versions
+    // can still be read.
+    for line in lines {
+        if let Some(id) = line.strip_prefix("original-tip ") {
+            provenance.original_tip = Some(Oid::from_str(id).map_err(|_| invalid())?);
+        } else if let Some(commit) = line.strip_prefix("squashed ") {
+            let (id, summary) = commit.split_once(' ').unwrap_or((commit, ""));
+            provenance.commits.push(SourceCommit {
+                id: Oid::from_str(id).map_err(|_| invalid())?,
+                summary: summary.to_string(),
+            });
+        }
+    }
+    Ok(Some(provenance))
+}
+
+/// Recreate a branch pointing at the commits a squash replaced, using the original tip
+/// recorded in the squash commit's note. Unlike `undo_squash`, this works for any squash
+/// made with `SquishOptions::record_provenance`, not just the latest update of a branch,
+/// and leaves the squashed branch alone.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit_spec` - The squash commit (e.g., "feature", "1a2b3c4" or "main~2")
+/// * `new_branch` - The branch to create, defaults to the squished branch's name with
+///   `-restored` appended
+///
+/// # Returns
+/// A success message, or a SquishError if the commit has no recorded original tip, the
+/// original commits were garbage collected or the branch already exists.
+pub fn restore_squash(
+    repo_path: impl AsRef<Path>,
+    commit_spec: impl AsRef<str>,
+    new_branch: Option<&str>,
+) -> Result<String, SquishError> {
+    let commit_spec = commit_spec.as_ref();
+    let repo = open_repository(repo_path)?;
+    let _lock = SquishLock::acquire(&repo)?;
+    let commit_id = repo.revparse_single(commit_spec)?.peel_to_commit()?.id();
+    let Some((original_tip, provenance)) = squash_provenance(&repo, commit_id)?
+        .and_then(|provenance| Some((provenance.original_tip?, provenance)))
+    else {
+        return Err(SquishError::Other {
+            message: format!(
+                "{commit_spec} has no original tip recorded, it was not squished with --record-provenance"
+            ),
+        });
+    };
+    let original = repo
+        .find_commit(original_tip)
+        .map_err(|_| SquishError::Other {
+            message: format!(
+                "The original commits of {commit_spec} ({original_tip:.7}) no longer exist, they were garbage collected"
+            ),
+        })?;
+
+    let squished = provenance
+        .branch
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&provenance.branch);
+    let name = match new_branch {
+        Some(name) => name.strip_prefix("refs/heads/").unwrap_or(name).to_string(),
+        None => format!("{squished}-restored"),
+    };
+    if repo.find_branch(&name, BranchType::Local).is_ok() {
+        return Err(SquishError::Other {
+            message: format!("A branch named {name} already exists, pass another name"),
+        });
+    }
+    repo.branch(&name, &original, false)?;
+    Ok(format!(
+        "✅ Created {name} at {original_tip:.7}, where {squished} pointed before it was squashed."
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo, topic_repo};
+
+    #[test]
+    fn test_record_and_read_provenance() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        let first = commit_file(&repo_path, "a.txt", "a\n", "Add a\n\nThe details.").unwrap();
+        let second = commit_file(&repo_path, "a.txt", "b\n", "Fix a").unwrap();
+
+        let result = squash_branch_with_options(
+            &repo_path,
+            "topic",
+            "main",
+            &SquishOptions::new().record_provenance(true),
+        )
+        .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let provenance = squash_provenance(&repo, result.new_id).unwrap().unwrap();
+        assert_eq!(provenance.branch, "refs/heads/topic");
+        assert_eq!(
+            provenance.upstream_id,
+            repo.refname_to_id("refs/heads/main").unwrap()
+        );
+        assert_eq!(provenance.original_tip, Some(second));
+        assert_eq!(
+            provenance.commits,
+            [
+                SourceCommit {
+                    id: first,
+                    summary: "Add a".to_string(),
+                },
+                SourceCommit {
+                    id: second,
+                    summary: "Fix a".to_string(),
+                },
+            ]
+        );
+        // The message is left alone.
+        assert_eq!(
+            repo.find_commit(result.new_id).unwrap().message(),
+            Some("Add a\n\nThe details.\n")
+        );
+    }
+
+    #[test]
+    fn test_restore_squash() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let base = repo.refname_to_id("refs/heads/main").unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let options = SquishOptions::new().record_provenance(true);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        // More work on top of the squash doesn't get in the way.
+        commit_file(&repo_path, "c.txt", "c\n", "Add c").unwrap();
+
+        let message = restore_squash(&repo_path, "topic~1", None).unwrap();
+        assert!(message.contains("topic-restored"), "{message}");
+        assert_eq!(
+            repo.refname_to_id("refs/heads/topic-restored").unwrap(),
+            tip
+        );
+        assert_ne!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+
+        let error = restore_squash(&repo_path, result.new_id.to_string(), None).unwrap_err();
+        assert!(error.to_string().contains("already exists"), "{error}");
+        restore_squash(
+            &repo_path,
+            result.new_id.to_string(),
+            Some("refs/heads/again"),
+        )
+        .unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/again").unwrap(), tip);
+
+        let error = restore_squash(&repo_path, base.to_string(), None).unwrap_err();
+        assert!(error.to_string().contains("no original tip"), "{error}");
+    }
+
+    #[test]
+    fn test_provenance_without_record() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let base = repo.refname_to_id("refs/heads/main").unwrap();
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &SquishOptions::new()).unwrap();
+
+        let provenance = squash_provenance(&repo, result.new_id).unwrap().unwrap();
+        assert_eq!(provenance.original_tip, None);
+        assert!(provenance.commits.is_empty());
+        assert_eq!(squash_provenance(&repo, base).unwrap(), None);
+    }
+}
@@ -0,0 +1,413 @@
+use git2::{Oid, Repository};
+use std::path::Path;
+
+use crate::rebase::{RebaseOutcome, rebase_commits};
+use crate::verify::verify_transformed;
+use crate::warning::Warnings;
+use crate::{
+    SquishError, SquishOptions, check_expected_upstream, check_identity, ensure_related_histories,
+    find_branch, find_upstream, normalize_branch_refname, open_repository, write_commit,
+    write_squash_commit,
+};
+
+/// How `squash_train` turns the branches of a train into commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainMode {
+    /// One commit holding every branch, with the branches' messages combined.
+    Combined,
+    /// One squashed commit per branch, stacked in train order.
+    PerBranch,
+}
+
+/// Result of re-checking a squashed commit created by `create_squash_commit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SquashVerification {
+    /// The commit sits on the current upstream tip and matches the branch; fast-forwarding
+    /// the upstream to it is safe.
+    Valid,
+    /// The upstream moved since the commit was created.
+    UpstreamMoved,
+    /// The branch no longer squashes to the same tree (it gained or lost changes).
+    BranchChanged,
+}
+
+/// Create the squashed commit for a branch without moving any ref or touching the worktree.
+///
+/// This is meant for merge queues which assemble and test candidate commits themselves and
+/// only fast-forward the upstream once they pass. Use `verify_squash_commit` to check the
+/// commit is still current before doing so. Conflicts always fail; pausing is not supported.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_refname` - The branch to squash (e.g., "feature" or "refs/heads/feature")
+/// * `upstream_spec` - The upstream to squash onto (e.g., "main" or "origin/main")
+/// * `options` - Options controlling the squash (see `SquishOptions`)
+///
+/// # Returns
+/// The id of the squashed commit, or a SquishError if the operation fails.
+pub fn create_squash_commit(
+    repo_path: impl AsRef<Path>,
+    branch_refname: impl AsRef<str>,
+    upstream_spec: impl AsRef<str>,
+    options: &SquishOptions,
+) -> Result<Oid, SquishError> {
+    let repo = open_repository(repo_path)?;
+    let branch_refname = normalize_branch_refname(&repo, branch_refname.as_ref())?;
+    let upstream_spec = upstream_spec.as_ref();
+    if options.fetch {
+        crate::remote::fetch_upstream(&repo, upstream_spec)?;
+    }
+    let mut warnings = Warnings::new();
+    check_identity(&repo, options, &mut warnings)?;
+    ensure_related_histories(&repo, &branch_refname, upstream_spec)?;
+    crate::signatures::check_signatures(
+        &repo,
+        find_branch(&repo, &branch_refname)?.peel_to_commit()?.id(),
+        find_upstream(&repo, upstream_spec)?.peel_to_commit()?.id(),
+        options,
+        &mut warnings,
+    )?;
+    warnings.check_strict(options)?;
+    let (upstream_id, rebased_tip_id) = rebase_onto_upstream(
+        &repo,
+        &branch_refname,
+        upstream_spec,
+        options,
+        &mut warnings,
+    )?;
+    let commit_id = write_squash_commit(
+        &repo,
+        &branch_refname,
+        upstream_id,
+        rebased_tip_id,
+        options,
+        &mut warnings,
+    )?;
+    if options.verify {
+        verify_transformed(&repo, commit_id, rebased_tip_id, &warnings)?;
+    }
+    let tree = repo.find_commit(commit_id)?.tree()?;
+    crate::hooks::check_squashed_tree(&repo, upstream_id, &tree, options)?;
+    warnings.check_strict(options)?;
+    Ok(commit_id)
+}
+
+/// Check that a commit created by `create_squash_commit` is still the squash of the branch
+/// onto the current upstream tip.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit_id` - The squashed commit to verify
+/// * `branch_refname` - The branch it was created from
+/// * `upstream_spec` - The upstream it was created on
+/// * `options` - The options it was created with
+pub fn verify_squash_commit(
+    repo_path: impl AsRef<Path>,
+    commit_id: Oid,
+    branch_refname: impl AsRef<str>,
+    upstream_spec: impl AsRef<str>,
+    options: &SquishOptions,
+) -> Result<SquashVerification, SquishError> {
+    let repo = open_repository(repo_path)?;
+    let branch_refname = normalize_branch_refname(&repo, branch_refname.as_ref())?;
+    let upstream_spec = upstream_spec.as_ref();
+    let commit = repo.find_commit(commit_id)?;
+    let upstream_id = find_upstream(&repo, upstream_spec)?.peel_to_commit()?.id();
+    if commit.parent_ids().collect::<Vec<_>>() != [upstream_id] {
+        return Ok(SquashVerification::UpstreamMoved);
+    }
+
+    let (_, rebased_tip_id) = match rebase_onto_upstream(
+        &repo,
+        &branch_refname,
+        upstream_spec,
+        options,
+        &mut Warnings::new(),
+    ) {
+        Ok(result) => result,
+        Err(SquishError::Conflict { .. }) => return Ok(SquashVerification::BranchChanged),
+        Err(e) => return Err(e),
+    };
+    if repo.find_commit(rebased_tip_id)?.tree_id() != commit.tree_id() {
+        return Ok(SquashVerification::BranchChanged);
+    }
+    Ok(SquashVerification::Valid)
+}
+
+/// Squash several branches, in order, onto an upstream: each branch's commits are applied
+/// on top of the branches before it. Like `create_squash_commit`, no ref is moved and the
+/// worktree is left alone.
+///
+/// # Arguments
+/// * `repo` - The repository holding the branches
+/// * `branches` - The branches of the train, in the order they should land
+/// * `upstream_spec` - The upstream to squash onto (e.g., "main" or "origin/main")
+/// * `mode` - Whether to produce one combined commit or one commit per branch
+/// * `options` - Options controlling the squash (see `SquishOptions`)
+///
+/// # Returns
+/// The created commits, oldest first; the last one is the tip of the train. A branch which
+/// conflicts with the branches before it fails with `SquishError::TrainConflict`.
+pub fn squash_train(
+    repo: &Repository,
+    branches: &[&str],
+    upstream_spec: &str,
+    mode: TrainMode,
+    options: &SquishOptions,
+) -> Result<Vec<Oid>, SquishError> {
+    let upstream_annot =
+        repo.find_annotated_commit(find_upstream(repo, upstream_spec)?.peel_to_commit()?.id())?;
+    let options = options.clone().pause_on_conflict(false);
+
+    let mut commits = Vec::new();
+    let mut warnings = Warnings::new();
+    let mut tip_id = upstream_annot.id();
+    for &branch_refname in branches {
+        let branch_ref = find_branch(repo, branch_refname)?;
+        let branch_annot = repo.reference_to_annotated_commit(&branch_ref)?;
+        let onto_annot = repo.find_annotated_commit(tip_id)?;
+
+        let rebased_tip_id = match rebase_commits(
+            repo,
+            &branch_annot,
+            &upstream_annot,
+            Some(&onto_annot),
+            &options,
+            &mut warnings,
+        ) {
+            Ok(RebaseOutcome::Complete(rebased_tip_id)) => rebased_tip_id,
+            Ok(RebaseOutcome::Conflict { .. }) => unreachable!("pausing is disabled"),
+            Err(SquishError::Conflict { commit, files }) => {
+                return Err(SquishError::TrainConflict {
+                    branch: branch_refname.to_string(),
+                    commit,
+                    files,
+                });
+            }
+            Err(e) => return Err(e),
+        };
+
+        tip_id = write_squash_commit(
+            repo,
+            branch_refname,
+            tip_id,
+            rebased_tip_id,
+            &options,
+            &mut warnings,
+        )?;
+        commits.push(tip_id);
+    }
+
+    if mode == TrainMode::Combined && commits.len() > 1 {
+        let mut messages = Vec::new();
+        for commit_id in &commits {
+            messages.push(
+                repo.find_commit(*commit_id)?
+                    .message()
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+        let tree = repo.find_commit(tip_id)?.tree()?;
+        let upstream = repo.find_commit(upstream_annot.id())?;
+        let combined_id = write_commit(
+            repo,
+            &messages.join("\n"),
+            &tree,
+            &upstream,
+            &options,
+            &mut warnings,
+        )?;
+        commits = vec![combined_id];
+    }
+    warnings.check_strict(&options)?;
+    Ok(commits)
+}
+
+/// Rebase the branch onto the upstream in memory, failing on any conflict.
+///
+/// # Returns
+/// The upstream commit id and the rebased tip id.
+pub(crate) fn rebase_onto_upstream(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<(Oid, Oid), SquishError> {
+    let branch_ref = find_branch(repo, branch_refname)?;
+    let branch_annot = repo.reference_to_annotated_commit(&branch_ref)?;
+    let upstream_id = find_upstream(repo, upstream_spec)?.peel_to_commit()?.id();
+    check_expected_upstream(upstream_spec, upstream_id, options)?;
+    let upstream_annot = repo.find_annotated_commit(upstream_id)?;
+
+    let options = options.clone().pause_on_conflict(false);
+    match rebase_commits(
+        repo,
+        &branch_annot,
+        &upstream_annot,
+        None,
+        &options,
+        warnings,
+    )? {
+        RebaseOutcome::Complete(rebased_tip_id) => Ok((upstream_id, rebased_tip_id)),
+        RebaseOutcome::Conflict { .. } => unreachable!("pausing is disabled"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    /// A repo with main and three branches off it: `a` and `b` each add their own file and
+    /// `c` changes the file `a` adds.
+    fn train_repo() -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        for (branch, file, contents) in [
+            ("a", "a.txt", "a"),
+            ("b", "b.txt", "b"),
+            ("c", "a.txt", "c"),
+        ] {
+            change_to_branch(&repo_path, "main").unwrap();
+            create_branch(&repo_path, branch).unwrap();
+            commit_file(
+                &repo_path,
+                file,
+                &format!("{contents}1\n"),
+                &format!("Branch {branch}"),
+            )
+            .unwrap();
+            commit_file(
+                &repo_path,
+                file,
+                &format!("{contents}2\n"),
+                &format!("More {branch}"),
+            )
+            .unwrap();
+        }
+        change_to_branch(&repo_path, "main").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_squash_train_per_branch() {
+        let (repo_path, _temp_dir) = train_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+
+        let commits = squash_train(
+            &repo,
+            &["refs/heads/a", "refs/heads/b"],
+            "main",
+            TrainMode::PerBranch,
+            &SquishOptions::new(),
+        )
+        .unwrap();
+
+        assert_eq!(commits.len(), 2);
+        let first = repo.find_commit(commits[0]).unwrap();
+        let second = repo.find_commit(commits[1]).unwrap();
+        assert_eq!(first.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(second.parent_ids().collect::<Vec<_>>(), vec![commits[0]]);
+        assert_eq!(first.message(), Some("Branch a\n"));
+        assert_eq!(second.message(), Some("Branch b\n"));
+        let tree = second.tree().unwrap();
+        assert!(tree.get_name("a.txt").is_some() && tree.get_name("b.txt").is_some());
+        // Nothing moved.
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), main_tip);
+    }
+
+    #[test]
+    fn test_squash_train_combined() {
+        let (repo_path, _temp_dir) = train_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let main_tip = repo.refname_to_id("refs/heads/main").unwrap();
+
+        let commits = squash_train(
+            &repo,
+            &["refs/heads/a", "refs/heads/b"],
+            "main",
+            TrainMode::Combined,
+            &SquishOptions::new(),
+        )
+        .unwrap();
+
+        assert_eq!(commits.len(), 1);
+        let combined = repo.find_commit(commits[0]).unwrap();
+        assert_eq!(combined.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(combined.message(), Some("Branch a\n\nBranch b\n"));
+        let tree = combined.tree().unwrap();
+        assert!(tree.get_name("a.txt").is_some() && tree.get_name("b.txt").is_some());
+    }
+
+    #[test]
+    fn test_squash_train_reports_conflicting_branch() {
+        let (repo_path, _temp_dir) = train_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let error = squash_train(
+            &repo,
+            &["refs/heads/a", "refs/heads/b", "refs/heads/c"],
+            "main",
+            TrainMode::Combined,
+            &SquishOptions::new(),
+        )
+        .unwrap_err();
+
+        match error {
+            SquishError::TrainConflict { branch, files, .. } => {
+                assert_eq!(branch, "refs/heads/c");
+                assert_eq!(files[0].path, "a.txt");
+            }
+            other => panic!("Expected SquishError::TrainConflict, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_and_verify_squash_commit() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo_path_str = repo_path.to_str().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "topic.txt", "1\n", "Topic 1").unwrap();
+        let topic_tip = commit_file(&repo_path, "topic.txt", "2\n", "Topic 2").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let main_tip = commit_file(&repo_path, "main.txt", "main\n", "Main change").unwrap();
+
+        let options = SquishOptions::new();
+        let commit_id =
+            create_squash_commit(repo_path_str, "refs/heads/topic", "main", &options).unwrap();
+
+        // Nothing moved.
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), main_tip);
+
+        let commit = repo.find_commit(commit_id).unwrap();
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(commit.message(), Some("Topic 1\n"));
+
+        let verify = || {
+            verify_squash_commit(
+                repo_path_str,
+                commit_id,
+                "refs/heads/topic",
+                "main",
+                &options,
+            )
+            .unwrap()
+        };
+        assert_eq!(verify(), SquashVerification::Valid);
+
+        change_to_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "topic.txt", "3\n", "Topic 3").unwrap();
+        assert_eq!(verify(), SquashVerification::BranchChanged);
+
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "main.txt", "moved\n", "Main moved").unwrap();
+        assert_eq!(verify(), SquashVerification::UpstreamMoved);
+    }
+}
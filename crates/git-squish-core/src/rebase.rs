@@ -0,0 +1,154 @@
+use git2::{AnnotatedCommit, Commit, Index, MergeOptions, Oid, RebaseOptions, Repository};
+use std::collections::HashMap;
+
+use crate::warning::Warnings;
+use crate::{ConflictedFile, GROUP_MARKER, SquishError, SquishOptions, SquishWarning, rerere};
+
+/// Result of replaying a branch's commits on top of a new base.
+pub(crate) enum RebaseOutcome {
+    /// Every commit was applied; holds the id of the rebased tip.
+    Complete(Oid),
+    /// A commit could not be applied cleanly and pausing was requested (without pausing,
+    /// `SquishError::Conflict` is returned instead).
+    Conflict {
+        /// The original commit which failed to apply.
+        stopped_id: Oid,
+        /// The tip of the commits rebased so far.
+        rebased_tip_id: Oid,
+        /// The in-memory index holding the conflicted merge result.
+        index: Index,
+    },
+}
+
+/// Replay the commits reachable from `branch` but not from `upstream` on top of `onto`
+/// (or `upstream` when `onto` is None) using an in-memory rebase.
+///
+/// The rebase never touches the worktree or the branch ref; callers decide what to do with
+/// the returned tip. Like `git rebase`, commits whose changes `upstream` already has (same
+/// patch-id, e.g. cherry-picked) are skipped before they can conflict, and commits which
+/// become empty are dropped; both are added to `warnings`.
+pub(crate) fn rebase_commits(
+    repo: &Repository,
+    branch: &AnnotatedCommit,
+    upstream: &AnnotatedCommit,
+    onto: Option<&AnnotatedCommit>,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<RebaseOutcome, SquishError> {
+    let mut opts = RebaseOptions::new();
+    // In-memory avoids touching the worktree while applying; safer for automation.
+    opts.inmemory(true);
+    if let Some(strategy_option) = options.strategy_option {
+        // Auto-resolve conflicting hunks instead of failing the rebase.
+        let mut merge_opts = MergeOptions::new();
+        merge_opts.file_favor(strategy_option.file_favor());
+        opts.merge_options(merge_opts);
+    }
+
+    let applied = upstream_patch_ids(repo, branch.id(), upstream.id())?;
+    let mut rebase = repo.rebase(Some(branch), Some(upstream), onto, Some(&mut opts))?;
+
+    // Apply each operation and commit it (in-memory), tracking the rebased tip as we go.
+    let sig = repo.signature()?;
+    let mut rebased_tip_id = onto.unwrap_or(upstream).id();
+    let rerere_enabled = rerere::is_enabled(repo);
+    while let Some(op_result) = rebase.next() {
+        let op = op_result?;
+
+        // Not committing the operation leaves it out of the rebased commits.
+        if !applied.is_empty() {
+            let commit = repo.find_commit(op.id())?;
+            if let Some(&upstream_commit) =
+                patch_id(repo, &commit)?.and_then(|patch_id| applied.get(&patch_id))
+            {
+                warnings.push(SquishWarning::AlreadyApplied {
+                    commit: op.id(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                    upstream_commit,
+                });
+                continue;
+            }
+        }
+
+        // The in-memory index is shared with the rebase, so resolutions applied here are
+        // picked up by the commit below.
+        let mut index = rebase.inmemory_index()?;
+        if rerere_enabled && index.has_conflicts() {
+            rerere::resolve_conflicts(repo, &mut index)?;
+        }
+
+        if index.has_conflicts() {
+            if !options.pause_on_conflict {
+                return Err(SquishError::Conflict {
+                    commit: op.id(),
+                    files: ConflictedFile::from_index(&index)?,
+                });
+            }
+            return Ok(RebaseOutcome::Conflict {
+                stopped_id: op.id(),
+                rebased_tip_id,
+                index,
+            });
+        }
+
+        match rebase.commit(Some(&sig), &sig, None) {
+            Ok(commit_id) => rebased_tip_id = commit_id,
+            // The commit became empty on top of upstream (e.g. resolved in favor of ours),
+            // drop it like `git rebase` does.
+            Err(e) if e.code() == git2::ErrorCode::Applied => {
+                let commit = repo.find_commit(op.id())?;
+                let summary = commit.summary().unwrap_or_default();
+                // Group markers are usually empty commits which only start a new group.
+                if !(options.split_markers && summary.starts_with(GROUP_MARKER)) {
+                    warnings.push(SquishWarning::EmptyCommitDropped {
+                        commit: op.id(),
+                        summary: summary.to_string(),
+                    });
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // Finalize the rebase. For in-memory rebases this does not move the branch ref,
+    // so we rely on the tip tracked above rather than re-reading the branch.
+    rebase.finish(None)?;
+
+    Ok(RebaseOutcome::Complete(rebased_tip_id))
+}
+
+/// The patch-ids of the commits on the upstream side of the branch (reachable from
+/// `upstream_id` but not `branch_id`), mapped to the commit with that patch-id.
+fn upstream_patch_ids(
+    repo: &Repository,
+    branch_id: Oid,
+    upstream_id: Oid,
+) -> Result<HashMap<Oid, Oid>, SquishError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(upstream_id)?;
+    revwalk.hide(branch_id)?;
+    let mut patch_ids = HashMap::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        if let Some(patch_id) = patch_id(repo, &commit)? {
+            patch_ids.insert(patch_id, commit.id());
+        }
+    }
+    Ok(patch_ids)
+}
+
+/// The patch-id of the changes `commit` makes to its parent (see `git patch-id`), or None
+/// for root and merge commits and commits which change nothing.
+fn patch_id(repo: &Repository, commit: &Commit) -> Result<Option<Oid>, SquishError> {
+    if commit.parent_count() != 1 {
+        return Ok(None);
+    }
+    let diff = repo.diff_tree_to_tree(
+        Some(&commit.parent(0)?.tree()?),
+        Some(&commit.tree()?),
+        None,
+    )?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(diff.patchid(None)?))
+}
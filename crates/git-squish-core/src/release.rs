@@ -0,0 +1,349 @@
+use git2::{ObjectType, Oid, Repository};
+use std::path::Path;
+
+use crate::CommitSigner;
+use crate::remote::push_tag;
+use crate::sign::backend_signer;
+use crate::{
+    SquashResult, SquishError, SquishOptions, SquishPlan, normalize_branch_refname,
+    open_repository, squash_branch_with_options,
+};
+
+/// Placeholder in a release message template replaced with the version.
+pub const VERSION_PLACEHOLDER: &str = "{version}";
+/// Placeholder in a release message template replaced with the squash message the branch
+/// would get without a release.
+pub const MESSAGE_PLACEHOLDER: &str = "{message}";
+/// The release message template used unless one is given.
+const DEFAULT_TEMPLATE: &str = "Release {version}\n\n{message}";
+
+/// Options controlling `release_branch`, built like `SquishOptions`.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{ReleaseOptions, SquishOptions};
+///
+/// let options = ReleaseOptions::new()
+///     .message_template("{version}: {message}")
+///     .squish(SquishOptions::new().fetch(true));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReleaseOptions {
+    pub(crate) message_template: String,
+    pub(crate) sign_tag: bool,
+    pub(crate) push: bool,
+    pub(crate) squish: SquishOptions,
+}
+
+impl Default for ReleaseOptions {
+    fn default() -> Self {
+        Self {
+            message_template: DEFAULT_TEMPLATE.to_string(),
+            sign_tag: true,
+            push: false,
+            squish: SquishOptions::default(),
+        }
+    }
+}
+
+impl ReleaseOptions {
+    /// Create options for a signed, unpushed release with the default message template,
+    /// `Release {version}` followed by the squash message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The squash commit's message, with `VERSION_PLACEHOLDER`, `MESSAGE_PLACEHOLDER` and
+    /// the squash options' template variables (see `SquishOptions::template_variable`)
+    /// filled in.
+    pub fn message_template(mut self, message_template: impl Into<String>) -> Self {
+        self.message_template = message_template.into();
+        self
+    }
+
+    /// Sign the release tag with the configured signing key (`user.signingKey` and
+    /// `gpg.format`, or gitsign with `squish.signingBackend`), failing before anything is
+    /// changed if it cannot be loaded. On by default.
+    pub fn sign_tag(mut self, sign_tag: bool) -> Self {
+        self.sign_tag = sign_tag;
+        self
+    }
+
+    /// Push the squashed branch (like `SquishOptions::push`) and then the tag to the
+    /// branch's push remote.
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Options for the squash itself. A message given with `SquishOptions::message` fills
+    /// in `MESSAGE_PLACEHOLDER`.
+    pub fn squish(mut self, squish: SquishOptions) -> Self {
+        self.squish = squish;
+        self
+    }
+}
+
+/// Outcome of a successful `release_branch`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ReleaseResult {
+    /// The squash of the branch.
+    pub squash: SquashResult,
+    /// The tag which was created (e.g., "refs/tags/v1.2.0").
+    pub tag: String,
+    /// The annotated tag object.
+    pub tag_id: Oid,
+    /// What was done with the tag, following the squash's own message.
+    pub message: String,
+}
+
+/// Cut a release: squash a branch onto an upstream with the version in the squash message,
+/// tag the squash commit with an annotated (and by default signed) tag named after the
+/// version and optionally push both.
+///
+/// The tag name, the signing key and the squash message are all checked before the branch
+/// is rewritten, so a release which cannot be tagged leaves the branch alone.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_refname` - The branch to release (e.g., "release" or "refs/heads/release")
+/// * `upstream_spec` - The upstream to squash onto (e.g., "main" or "origin/main")
+/// * `version` - The version, which is also the tag's name (e.g., "v1.2.0")
+/// * `options` - Options controlling the release (see `ReleaseOptions`)
+pub fn release_branch(
+    repo_path: impl AsRef<Path>,
+    branch_refname: impl AsRef<str>,
+    upstream_spec: impl AsRef<str>,
+    version: &str,
+    options: &ReleaseOptions,
+) -> Result<ReleaseResult, SquishError> {
+    let repo_path = repo_path.as_ref();
+    let upstream_spec = upstream_spec.as_ref();
+    let repo = open_repository(repo_path)?;
+    let branch_refname = normalize_branch_refname(&repo, branch_refname.as_ref())?;
+
+    let tag = format!("refs/tags/{version}");
+    if version.is_empty() || !git2::Reference::is_valid_name(&tag) {
+        return Err(SquishError::Other {
+            message: format!("'{version}' is not a valid tag name"),
+        });
+    }
+    if repo.find_reference(&tag).is_ok() {
+        return Err(SquishError::Other {
+            message: format!("Tag {version} already exists"),
+        });
+    }
+    let signer = if options.sign_tag {
+        Some(tag_signer(&repo, options)?)
+    } else {
+        None
+    };
+
+    // Trailers are only added once, when the release message is committed, and template
+    // variables are filled in then too.
+    let mut plan_options = options
+        .squish
+        .clone()
+        .annotate_sources(false)
+        .reference_footer(false);
+    plan_options.trailers.clear();
+    plan_options.variables.clear();
+    let plan =
+        SquishPlan::compute_with_options(&repo, &branch_refname, upstream_spec, &plan_options)?;
+    let message = options
+        .message_template
+        .replace(VERSION_PLACEHOLDER, version)
+        .replace(MESSAGE_PLACEHOLDER, plan.message.trim_end());
+    let squish = options
+        .squish
+        .clone()
+        .message(message)
+        .push(options.push || options.squish.push);
+    let squash = squash_branch_with_options(repo_path, &branch_refname, upstream_spec, &squish)?;
+
+    let tag_id = write_tag(&repo, version, squash.new_id, signer.as_deref())?;
+    repo.reference(&tag, tag_id, false, "release")?;
+    let mut message = format!("✅ Tagged {:.7} as {version}.", squash.new_id);
+    if options.push {
+        message.push('\n');
+        message.push_str(&push_tag(&repo, &branch_refname, upstream_spec, &tag)?);
+    }
+
+    Ok(ReleaseResult {
+        squash,
+        tag,
+        tag_id,
+        message,
+    })
+}
+
+/// Load the key to sign tags with, like `signing_key` does for commits but regardless of
+/// `commit.gpgsign`, since the release asked for a signature.
+fn tag_signer(
+    repo: &Repository,
+    options: &ReleaseOptions,
+) -> Result<Box<dyn CommitSigner>, SquishError> {
+    if let Some(signer) = &options.squish.signer {
+        return Ok(Box::new(signer.clone()));
+    }
+    if let Some(signer) = backend_signer(repo)? {
+        return Ok(Box::new(signer));
+    }
+    match crate::sign::user_signer(repo) {
+        Ok(signer) => Ok(signer),
+        Err(e) => Err(SquishError::Other {
+            message: format!(
+                "Cannot sign the release tag: {}; configure user.signingKey or pass --no-sign",
+                e.message()
+            ),
+        }),
+    }
+}
+
+/// Write an annotated tag object named `name` for `target_id`, with the version as its
+/// message, signed by `signer` like `git tag -s` signs: the signature follows the message.
+fn write_tag(
+    repo: &Repository,
+    name: &str,
+    target_id: Oid,
+    signer: Option<&dyn CommitSigner>,
+) -> Result<Oid, SquishError> {
+    let tagger = repo.signature()?;
+    let target = repo.find_object(target_id, Some(ObjectType::Commit))?;
+    let tag_id = repo.tag_annotation_create(name, &target, &tagger, &format!("{name}\n"))?;
+    let Some(signer) = signer else {
+        return Ok(tag_id);
+    };
+
+    let odb = repo.odb()?;
+    let object = odb.read(tag_id)?;
+    let content = std::str::from_utf8(object.data()).map_err(|_| SquishError::Other {
+        message: "The tag object is not valid UTF-8".to_string(),
+    })?;
+    let signature = signer.sign(content)?;
+    Ok(odb.write(ObjectType::Tag, format!("{content}{signature}").as_bytes())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+    fn release_repo() -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "release").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Add a\n\nThe details.").unwrap();
+        commit_file(&repo_path, "a.txt", "b\n", "Fix a").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_release_branch() {
+        let (repo_path, _temp_dir) = release_repo();
+        let options = ReleaseOptions::new().sign_tag(false);
+
+        let result = release_branch(&repo_path, "release", "main", "v1.2.0", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(result.squash.new_id).unwrap();
+        assert_eq!(
+            commit.message(),
+            Some("Release v1.2.0\n\nAdd a\n\nThe details.\n")
+        );
+        assert_eq!(result.tag, "refs/tags/v1.2.0");
+        let tag = repo.find_tag(result.tag_id).unwrap();
+        assert_eq!(tag.name(), Some("v1.2.0"));
+        assert_eq!(tag.target_id(), result.squash.new_id);
+        assert_eq!(
+            repo.refname_to_id("refs/tags/v1.2.0").unwrap(),
+            result.tag_id
+        );
+        assert!(result.message.contains("Tagged"), "{}", result.message);
+
+        // The tag exists now, so the same version can't be released again.
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let error = release_branch(&repo_path, "release", "main", "v1.2.0", &options).unwrap_err();
+        assert!(error.to_string().contains("already exists"), "{error}");
+        let error = release_branch(&repo_path, "release", "main", "v1..2", &options).unwrap_err();
+        assert!(
+            error.to_string().contains("not a valid tag name"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_release_template() {
+        let (repo_path, _temp_dir) = release_repo();
+        let options = ReleaseOptions::new()
+            .sign_tag(false)
+            .message_template("chore: release {version} ({ticket})")
+            .squish(
+                SquishOptions::new()
+                    .annotate_sources(true)
+                    .template_variable("ticket", "ABC-1")
+                    .trailer("Ticket: {ticket}"),
+            );
+
+        let result = release_branch(&repo_path, "release", "main", "2.0.0", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let message = repo
+            .find_commit(result.squash.new_id)
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string();
+        assert!(
+            message.starts_with("chore: release 2.0.0 (ABC-1)\n\nSquashed-from: "),
+            "{message}"
+        );
+        assert!(message.ends_with("\nTicket: ABC-1\n"), "{message}");
+        assert_eq!(message.matches("Squashed-from:").count(), 2);
+        assert_eq!(message.matches("Ticket:").count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_release_signed_tag() {
+        use crate::sign::{GITSIGN_PROGRAM_CONFIG, SIGNING_BACKEND_CONFIG};
+        use std::os::unix::fs::PermissionsExt;
+
+        let (repo_path, temp_dir) = release_repo();
+        // Stands in for gitsign, which needs an OIDC login and network access.
+        let program = temp_dir.path().join("fake-gitsign");
+        std::fs::write(
+            &program,
+            "#!/bin/sh\ncat > /dev/null\necho '[GNUPG:] SIG_CREATED ' >&2\nprintf '%s\\n' '-----BEGIN SIGNED MESSAGE-----' 'signed' '-----END SIGNED MESSAGE-----'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str(SIGNING_BACKEND_CONFIG, "gitsign").unwrap();
+        config
+            .set_str(GITSIGN_PROGRAM_CONFIG, program.to_str().unwrap())
+            .unwrap();
+
+        let result = release_branch(
+            &repo_path,
+            "release",
+            "main",
+            "v1.0.0",
+            &ReleaseOptions::new(),
+        )
+        .unwrap();
+
+        let odb = repo.odb().unwrap();
+        let object = odb.read(result.tag_id).unwrap();
+        let content = std::str::from_utf8(object.data()).unwrap();
+        assert!(
+            content.ends_with(
+                "\nv1.0.0\n-----BEGIN SIGNED MESSAGE-----\nsigned\n-----END SIGNED MESSAGE-----\n"
+            ),
+            "{content}"
+        );
+        assert_eq!(repo.find_tag(result.tag_id).unwrap().name(), Some("v1.0.0"));
+    }
+}
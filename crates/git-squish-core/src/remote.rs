@@ -0,0 +1,594 @@
+use git2::{
+    Branch, Config, Cred, CredentialType, Direction, FetchOptions, PushOptions, RemoteCallbacks,
+    Repository,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::SquishError;
+use crate::capabilities::{capabilities, ensure_transport};
+
+/// How many times credentials are offered before giving up, so a rejected credential
+/// doesn't make libgit2 retry forever.
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+/// Split an upstream spec like "origin/main" or "refs/remotes/origin/main" into the
+/// remote name and the branch on that remote.
+///
+/// Returns None when the spec does not name a remote-tracking branch of a configured remote.
+pub(crate) fn remote_branch(repo: &Repository, upstream_spec: &str) -> Option<(String, String)> {
+    let spec = upstream_spec
+        .strip_prefix("refs/remotes/")
+        .unwrap_or(upstream_spec);
+    let remotes = repo.remotes().ok()?;
+    // Remote names may contain slashes, so prefer the longest matching remote.
+    remotes
+        .iter()
+        .flatten()
+        .filter_map(|remote| {
+            let branch = spec.strip_prefix(remote)?.strip_prefix('/')?;
+            (!branch.is_empty()).then(|| (remote.to_string(), branch.to_string()))
+        })
+        .max_by_key(|(remote, _)| remote.len())
+}
+
+/// When `branch` names a remote-tracking branch (e.g. "origin/feature") rather than a local
+/// branch, create a local branch of the same name from it which tracks it, like
+/// `git checkout feature` does, so it can be squished (and pushed back).
+///
+/// # Returns
+/// The new branch's refname and the remote-tracking branch it was created from, or None
+/// when `branch` does not name a remote-tracking branch.
+pub(crate) fn create_tracking_branch(
+    repo: &Repository,
+    branch: &str,
+) -> Result<Option<(String, String)>, SquishError> {
+    if branch.starts_with("refs/") && !branch.starts_with("refs/remotes/") {
+        return Ok(None);
+    }
+    let shorthand = branch.strip_prefix("refs/remotes/").unwrap_or(branch);
+    // A local branch of that name wins, like it does for git.
+    if !branch.starts_with("refs/") && repo.find_reference(&format!("refs/heads/{branch}")).is_ok()
+    {
+        return Ok(None);
+    }
+    let Ok(remote_ref) = repo.find_reference(&format!("refs/remotes/{shorthand}")) else {
+        return Ok(None);
+    };
+    let Some((remote, name)) = remote_branch(repo, shorthand).filter(|(_, name)| name != "HEAD")
+    else {
+        return Ok(None);
+    };
+
+    if repo.find_branch(&name, git2::BranchType::Local).is_ok() {
+        return Err(SquishError::Other {
+            message: format!(
+                "A local branch {name} already exists, squish it instead of {shorthand}"
+            ),
+        });
+    }
+    let commit = remote_ref.peel_to_commit()?;
+    repo.branch(&name, &commit, false)?;
+    // Written directly rather than with `Branch::set_upstream`, which needs a fetch refspec
+    // mapping the remote-tracking branch.
+    let mut config = repo.config()?;
+    config.set_str(&format!("branch.{name}.remote"), &remote)?;
+    config.set_str(
+        &format!("branch.{name}.merge"),
+        &format!("refs/heads/{name}"),
+    )?;
+    Ok(Some((format!("refs/heads/{name}"), shorthand.to_string())))
+}
+
+/// Callbacks answering credential requests from the SSH agent, for SSH remotes, and from
+/// the configured git credential helpers, for HTTPS remotes (e.g. tokens).
+pub(crate) fn remote_callbacks(config: Config) -> RemoteCallbacks<'static> {
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        attempts += 1;
+        if attempts > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str(&format!(
+                "Authentication failed for {url}"
+            )));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::credential_helper(&config, url, username_from_url);
+        }
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username_from_url.unwrap_or("git"));
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+/// Fetch the branch named by `upstream_spec` from its remote, updating the
+/// remote-tracking ref so the squash uses the latest remote tip.
+pub(crate) fn fetch_upstream(repo: &Repository, upstream_spec: &str) -> Result<(), SquishError> {
+    let (remote_name, branch) =
+        remote_branch(repo, upstream_spec).ok_or_else(|| SquishError::Other {
+            message: format!("{upstream_spec} is not a remote-tracking branch, nothing to fetch"),
+        })?;
+
+    let mut remote = repo.find_remote(&remote_name)?;
+    ensure_transport(remote.url().unwrap_or_default(), &capabilities())?;
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(remote_callbacks(repo.config()?));
+    let refspec = format!("+refs/heads/{branch}:refs/remotes/{remote_name}/{branch}");
+    remote
+        .fetch(&[&refspec], Some(&mut fetch_options), None)
+        .map_err(|source| SquishError::Git {
+            message: format!(
+                "Failed to fetch {branch} from {remote_name}: {}",
+                source.message()
+            ),
+            source,
+        })
+}
+
+/// Work out where a branch is pushed: `branch.<name>.pushRemote`, `remote.pushDefault` or
+/// `branch.<name>.remote`, falling back to the remote of the upstream spec. The branch on
+/// the remote is `branch.<name>.merge` when pushing to the tracked remote, and the local
+/// branch name otherwise.
+pub(crate) fn push_target(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+) -> Result<(String, String), SquishError> {
+    let name = branch_refname
+        .strip_prefix("refs/heads/")
+        .ok_or_else(|| SquishError::Other {
+            message: format!("Cannot push {branch_refname}, it is not a local branch"),
+        })?;
+    let config = repo.config()?;
+    let get = |key: String| config.get_string(&key).ok().filter(|value| value != ".");
+
+    let tracked_remote = get(format!("branch.{name}.remote"));
+    let remote = get(format!("branch.{name}.pushRemote"))
+        .or_else(|| get("remote.pushDefault".to_string()))
+        .or_else(|| tracked_remote.clone())
+        .or_else(|| remote_branch(repo, upstream_spec).map(|(remote, _)| remote))
+        .ok_or_else(|| SquishError::Other {
+            message: format!("Cannot push {branch_refname}, no remote is configured for it"),
+        })?;
+
+    let merge = get(format!("branch.{name}.merge"))
+        .and_then(|merge| merge.strip_prefix("refs/heads/").map(str::to_string));
+    let destination = match merge {
+        Some(merge) if tracked_remote.as_deref() == Some(remote.as_str()) => merge,
+        _ => name.to_string(),
+    };
+    Ok((remote, destination))
+}
+
+/// Force-push a branch to its remote (see `push_target`) with `--force-with-lease`
+/// semantics: the push is rejected if the remote branch no longer matches the
+/// remote-tracking ref, i.e. someone pushed since we last fetched.
+///
+/// With `set_upstream`, a branch without tracking configuration is set up to track the
+/// branch it was pushed to afterwards, like `git push -u`.
+///
+/// Returns a message describing the push.
+pub(crate) fn push_branch(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+    set_upstream: bool,
+) -> Result<String, SquishError> {
+    let (remote_name, destination) = push_target(repo, branch_refname, upstream_spec)?;
+    let destination_ref = format!("refs/heads/{destination}");
+    let rejected = |reason: String| SquishError::PushRejected {
+        branch: branch_refname.to_string(),
+        remote: remote_name.clone(),
+        reason,
+    };
+
+    // The lease: what we believe the remote branch points at (nothing if never fetched).
+    let expected = repo
+        .refname_to_id(&format!("refs/remotes/{remote_name}/{destination}"))
+        .ok();
+
+    let mut remote = repo.find_remote(&remote_name)?;
+    let push_url = remote.pushurl().or(remote.url()).unwrap_or_default();
+    ensure_transport(push_url, &capabilities())?;
+    let actual = {
+        let connection = remote.connect_auth(
+            Direction::Push,
+            Some(remote_callbacks(repo.config()?)),
+            None,
+        )?;
+        connection
+            .list()?
+            .iter()
+            .find(|head| head.name() == destination_ref)
+            .map(|head| head.oid())
+    };
+    // libgit2 has no server-side lease, so this leaves a small window between the check
+    // and the push; it still catches the common case of pushing over unfetched work.
+    if actual != expected {
+        return Err(rejected(format!(
+            "stale info, {remote_name}/{destination} has changed since it was last fetched"
+        )));
+    }
+
+    let status = Rc::new(RefCell::new(None));
+    let mut callbacks = remote_callbacks(repo.config()?);
+    let update_status = Rc::clone(&status);
+    callbacks.push_update_reference(move |_refname, message| {
+        *update_status.borrow_mut() = message.map(str::to_string);
+        Ok(())
+    });
+    let mut push_options = PushOptions::new();
+    push_options.remote_callbacks(callbacks);
+    remote.push(
+        &[&format!("+{branch_refname}:{destination_ref}")],
+        Some(&mut push_options),
+    )?;
+    if let Some(reason) = status.take() {
+        return Err(rejected(reason));
+    }
+
+    let mut message = format!("✅ Pushed {branch_refname} to {remote_name}/{destination}.");
+    let mut branch = Branch::wrap(repo.find_reference(branch_refname)?);
+    if set_upstream && branch.upstream().is_err() {
+        let tracking = format!("{remote_name}/{destination}");
+        branch.set_upstream(Some(&tracking))?;
+        message.push_str(&format!("\n{branch_refname} now tracks {tracking}."));
+    }
+    Ok(message)
+}
+
+/// Push a tag to the remote `branch_refname` is pushed to (see `push_target`). Unlike
+/// branches, tags are never forced: a tag of the same name on the remote rejects the push.
+///
+/// Returns a message describing the push.
+pub(crate) fn push_tag(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+    tag_refname: &str,
+) -> Result<String, SquishError> {
+    let (remote_name, _) = push_target(repo, branch_refname, upstream_spec)?;
+    let mut remote = repo.find_remote(&remote_name)?;
+    let push_url = remote.pushurl().or(remote.url()).unwrap_or_default();
+    ensure_transport(push_url, &capabilities())?;
+
+    let status = Rc::new(RefCell::new(None));
+    let mut callbacks = remote_callbacks(repo.config()?);
+    let update_status = Rc::clone(&status);
+    callbacks.push_update_reference(move |_refname, message| {
+        *update_status.borrow_mut() = message.map(str::to_string);
+        Ok(())
+    });
+    let mut push_options = PushOptions::new();
+    push_options.remote_callbacks(callbacks);
+    remote.push(
+        &[&format!("{tag_refname}:{tag_refname}")],
+        Some(&mut push_options),
+    )?;
+    if let Some(reason) = status.take() {
+        return Err(SquishError::PushRejected {
+            branch: tag_refname.to_string(),
+            remote: remote_name,
+            reason,
+        });
+    }
+    Ok(format!("✅ Pushed {tag_refname} to {remote_name}."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, init_test_repo, topic_repo};
+    use crate::{SquishOptions, continue_squash, squash_branch_with_options};
+
+    #[test]
+    fn test_remote_branch() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.remote("origin/mirror", "https://example.com/mirror.git")
+            .unwrap();
+
+        let split = |spec| remote_branch(&repo, spec);
+        assert_eq!(
+            split("origin/main"),
+            Some(("origin".to_string(), "main".to_string()))
+        );
+        assert_eq!(
+            split("refs/remotes/origin/feature/x"),
+            Some(("origin".to_string(), "feature/x".to_string()))
+        );
+        assert_eq!(
+            split("origin/mirror/main"),
+            Some(("origin/mirror".to_string(), "main".to_string()))
+        );
+        assert_eq!(split("main"), None);
+        assert_eq!(split("upstream/main"), None);
+    }
+
+    #[test]
+    fn test_fetch_upstream() {
+        let (origin_path, _origin_dir) = init_test_repo().unwrap();
+        commit_file(&origin_path, "a.txt", "a\n", "First").unwrap();
+
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.remote("origin", origin_path.to_str().unwrap())
+            .unwrap();
+        fetch_upstream(&repo, "origin/main").unwrap();
+
+        let second = commit_file(&origin_path, "a.txt", "b\n", "Second").unwrap();
+        fetch_upstream(&repo, "origin/main").unwrap();
+        let tracking = repo.revparse_single("origin/main").unwrap();
+        assert_eq!(tracking.id(), second);
+
+        assert!(fetch_upstream(&repo, "main").is_err());
+    }
+
+    /// A repo with a main and topic branch, both pushed to a bare "origin" with topic
+    /// tracking origin/topic.
+    fn pushed_repo() -> (
+        std::path::PathBuf,
+        tempfile::TempDir,
+        std::path::PathBuf,
+        tempfile::TempDir,
+    ) {
+        let origin_dir = tempfile::tempdir().unwrap();
+        let origin_path = origin_dir.path().to_path_buf();
+        Repository::init_bare(&origin_path).unwrap();
+
+        let (repo_path, temp_dir) = topic_repo().unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut remote = repo
+            .remote("origin", origin_path.to_str().unwrap())
+            .unwrap();
+        remote
+            .push(
+                &[
+                    "refs/heads/main:refs/heads/main",
+                    "refs/heads/topic:refs/heads/topic",
+                ],
+                None,
+            )
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("branch.topic.remote", "origin").unwrap();
+        config
+            .set_str("branch.topic.merge", "refs/heads/topic")
+            .unwrap();
+        (repo_path, temp_dir, origin_path, origin_dir)
+    }
+
+    #[test]
+    fn test_push_target() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.remote("fork", "https://example.com/fork.git").unwrap();
+
+        let target = |upstream| push_target(&repo, "refs/heads/topic", upstream).unwrap();
+        assert_eq!(
+            target("origin/main"),
+            ("origin".to_string(), "topic".to_string())
+        );
+        assert!(push_target(&repo, "refs/heads/topic", "main").is_err());
+
+        let mut config = repo.config().unwrap();
+        config.set_str("branch.topic.remote", "origin").unwrap();
+        config
+            .set_str("branch.topic.merge", "refs/heads/feature")
+            .unwrap();
+        assert_eq!(
+            target("main"),
+            ("origin".to_string(), "feature".to_string())
+        );
+
+        config.set_str("branch.topic.pushRemote", "fork").unwrap();
+        assert_eq!(target("main"), ("fork".to_string(), "topic".to_string()));
+    }
+
+    #[test]
+    fn test_squash_and_push() {
+        let (repo_path, _temp_dir, origin_path, _origin_dir) = pushed_repo();
+        let options = SquishOptions::new().push(true);
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap();
+        assert!(
+            result
+                .message
+                .ends_with("✅ Pushed refs/heads/topic to origin/topic.")
+        );
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let squashed = repo.refname_to_id("refs/heads/topic").unwrap();
+        let origin = Repository::open(&origin_path).unwrap();
+        assert_eq!(origin.refname_to_id("refs/heads/topic").unwrap(), squashed);
+        assert_eq!(
+            repo.refname_to_id("refs/remotes/origin/topic").unwrap(),
+            squashed
+        );
+    }
+
+    #[test]
+    fn test_continue_pushes() {
+        let (repo_path, _temp_dir, origin_path, _origin_dir) = pushed_repo();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "a.txt", "main\n", "Add a on main").unwrap();
+        change_to_branch(&repo_path, "topic").unwrap();
+        let options = SquishOptions::new().push(true).pause_on_conflict(true);
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        );
+        assert!(result.is_err(), "Expected the squash to pause");
+
+        std::fs::write(repo_path.join("a.txt"), "a\n").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let result = continue_squash(repo_path.to_str().unwrap()).unwrap();
+
+        assert!(
+            result
+                .message
+                .ends_with("✅ Pushed refs/heads/topic to origin/topic.")
+        );
+        let squashed = repo.refname_to_id("refs/heads/topic").unwrap();
+        let origin = Repository::open(&origin_path).unwrap();
+        assert_eq!(origin.refname_to_id("refs/heads/topic").unwrap(), squashed);
+    }
+
+    #[test]
+    fn test_squash_remote_tracking_branch() {
+        let (repo_path, _temp_dir, origin_path, _origin_dir) = pushed_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        repo.find_branch("topic", git2::BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        // A failed squish leaves no local branch behind.
+        let squash = |upstream: &str| {
+            squash_branch_with_options(
+                &repo_path,
+                "origin/topic",
+                upstream,
+                &SquishOptions::new().push(true),
+            )
+        };
+        assert!(matches!(
+            squash("missing"),
+            Err(SquishError::UpstreamNotFound { .. })
+        ));
+        assert!(repo.find_branch("topic", git2::BranchType::Local).is_err());
+
+        let result = squash("main").unwrap();
+        assert!(
+            result
+                .message
+                .starts_with("Created refs/heads/topic tracking origin/topic.\n")
+        );
+        assert_eq!(result.branch, "refs/heads/topic");
+        let origin = Repository::open(&origin_path).unwrap();
+        assert_eq!(
+            origin.refname_to_id("refs/heads/topic").unwrap(),
+            result.new_id
+        );
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic = repo.find_branch("topic", git2::BranchType::Local).unwrap();
+        assert_eq!(
+            topic.upstream().unwrap().get().name(),
+            Some("refs/remotes/origin/topic")
+        );
+
+        // Once the local branch exists, the remote-tracking name no longer creates one.
+        let error = squash("main").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("local branch topic already exists")
+        );
+    }
+
+    #[test]
+    fn test_push_rejected_when_remote_moved() {
+        let (repo_path, _temp_dir, origin_path, _origin_dir) = pushed_repo();
+
+        // Someone else updates the remote branch without us fetching it.
+        let origin = Repository::open(&origin_path).unwrap();
+        let main = origin.refname_to_id("refs/heads/main").unwrap();
+        origin
+            .reference("refs/heads/topic", main, true, "someone else pushed")
+            .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let result = push_branch(&repo, "refs/heads/topic", "main", false);
+        assert!(matches!(result, Err(SquishError::PushRejected { .. })));
+        assert_eq!(origin.refname_to_id("refs/heads/topic").unwrap(), main);
+    }
+
+    #[test]
+    fn test_release_pushes_branch_and_tag() {
+        let (repo_path, _temp_dir, origin_path, _origin_dir) = pushed_repo();
+        let options = crate::ReleaseOptions::new().sign_tag(false).push(true);
+
+        let result =
+            crate::release_branch(&repo_path, "topic", "main", "v1.0.0", &options).unwrap();
+
+        let origin = Repository::open(&origin_path).unwrap();
+        assert_eq!(
+            origin.refname_to_id("refs/heads/topic").unwrap(),
+            result.squash.new_id
+        );
+        assert_eq!(
+            origin.refname_to_id("refs/tags/v1.0.0").unwrap(),
+            result.tag_id
+        );
+        assert!(
+            result
+                .message
+                .ends_with("✅ Pushed refs/tags/v1.0.0 to origin."),
+            "{}",
+            result.message
+        );
+
+        // The remote keeps its tag rather than having it replaced.
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.find_reference("refs/tags/v1.0.0")
+            .unwrap()
+            .delete()
+            .unwrap();
+        commit_file(&repo_path, "c.txt", "c\n", "Add c").unwrap();
+        commit_file(&repo_path, "d.txt", "d\n", "Add d").unwrap();
+        let error = crate::release_branch(&repo_path, "topic", "main", "v1.0.0", &options);
+        assert!(error.is_err());
+        assert_eq!(
+            origin.refname_to_id("refs/tags/v1.0.0").unwrap(),
+            result.tag_id
+        );
+    }
+
+    #[test]
+    fn test_push_sets_upstream() {
+        let (repo_path, _temp_dir, _origin_path, _origin_dir) = pushed_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.remove("branch.topic.remote").unwrap();
+        config.remove("branch.topic.merge").unwrap();
+
+        let message = push_branch(&repo, "refs/heads/topic", "origin/main", false).unwrap();
+        assert_eq!(message, "✅ Pushed refs/heads/topic to origin/topic.");
+        let config = repo.config().unwrap().snapshot().unwrap();
+        assert!(config.get_str("branch.topic.remote").is_err());
+
+        let message = push_branch(&repo, "refs/heads/topic", "origin/main", true).unwrap();
+        assert!(message.ends_with("refs/heads/topic now tracks origin/topic."));
+        let config = repo.config().unwrap().snapshot().unwrap();
+        assert_eq!(config.get_str("branch.topic.remote").unwrap(), "origin");
+        assert_eq!(
+            config.get_str("branch.topic.merge").unwrap(),
+            "refs/heads/topic"
+        );
+
+        // Existing tracking configuration is left alone.
+        let message = push_branch(&repo, "refs/heads/topic", "origin/main", true).unwrap();
+        assert_eq!(message, "✅ Pushed refs/heads/topic to origin/topic.");
+    }
+}
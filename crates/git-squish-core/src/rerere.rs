@@ -0,0 +1,368 @@
+use git2::{Index, IndexEntry, Repository, build::CheckoutBuilder};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::SquishError;
+use crate::worktree;
+
+/// Length of the conflict markers git writes (`<<<<<<<`, `=======`, ...).
+const MARKER_SIZE: usize = 7;
+/// Bits of `IndexEntry::flags` holding the conflict stage.
+const STAGE_MASK: u16 = 0x3000;
+
+/// A conflict whose preimage was recorded in `rr-cache`, waiting for its resolution.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RerereRecord {
+    /// The conflict id (the `rr-cache` directory name).
+    pub id: String,
+    /// The variant within the conflict id directory (`preimage`, `preimage.1`, ...).
+    pub variant: u32,
+    /// Path of the conflicted file relative to the repository root.
+    pub path: String,
+}
+
+/// Whether recorded resolutions should be used. Like git, this follows `rerere.enabled`,
+/// and when that is unset, whether an `rr-cache` directory already exists.
+pub(crate) fn is_enabled(repo: &Repository) -> bool {
+    match repo
+        .config()
+        .and_then(|config| config.get_bool("rerere.enabled"))
+    {
+        Ok(enabled) => enabled,
+        Err(_) => rr_cache_dir(repo).is_ok_and(|dir| dir.is_dir()),
+    }
+}
+
+/// Resolve the conflicts in `index` which have a recorded resolution, replacing each with
+/// a stage 0 entry holding the recorded postimage.
+///
+/// # Returns
+/// The paths which were resolved.
+pub(crate) fn resolve_conflicts(
+    repo: &Repository,
+    index: &mut Index,
+) -> Result<Vec<String>, SquishError> {
+    let mut conflicts: Vec<(String, IndexEntry)> = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        // Only content conflicts produce conflict markers rerere can match.
+        if let (Some(our), Some(_)) = (conflict.our, conflict.their) {
+            conflicts.push((String::from_utf8_lossy(&our.path).to_string(), our));
+        }
+    }
+    if conflicts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Write the conflicted files (with markers) somewhere we can read them back.
+    let temp_dir = tempfile::tempdir().map_err(|source| SquishError::Io {
+        message: "Failed to create temporary directory".to_string(),
+        source,
+    })?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout
+        .target_dir(temp_dir.path())
+        .force()
+        .allow_conflicts(true)
+        .conflict_style_merge(true)
+        .update_index(false);
+    for (path, _) in &conflicts {
+        checkout.path(path);
+    }
+    repo.checkout_index(Some(index), Some(&mut checkout))?;
+
+    let mut resolved = Vec::new();
+    for (path, our) in conflicts {
+        let Ok(contents) = fs::read(temp_dir.path().join(&path)) else {
+            continue;
+        };
+        let Some((id, preimage)) = normalize_conflicts(&contents) else {
+            continue;
+        };
+        let Some(postimage) = find_resolution(repo, &id, &preimage)? else {
+            continue;
+        };
+
+        let mut entry = our;
+        entry.id = repo.blob(&postimage)?;
+        entry.file_size = postimage.len() as u32;
+        entry.flags &= !STAGE_MASK;
+        for stage in 1..=3 {
+            // Not every stage exists for every conflict (e.g. no common ancestor).
+            let _ = index.remove(Path::new(&path), stage);
+        }
+        index.add(&entry)?;
+        resolved.push(path);
+    }
+    Ok(resolved)
+}
+
+/// Record the preimage of a conflicted file so its resolution can be recorded later.
+///
+/// # Returns
+/// The record to pass to `record_postimage`, or None if the file has no conflict markers.
+pub(crate) fn record_preimage(
+    repo: &Repository,
+    path: &str,
+    contents: &[u8],
+) -> Result<Option<RerereRecord>, SquishError> {
+    let Some((id, preimage)) = normalize_conflicts(contents) else {
+        return Ok(None);
+    };
+    let dir = rr_cache_dir(repo)?.join(&id);
+    create_dir(&dir)?;
+
+    // Reuse the variant holding the same preimage, otherwise take the first free one.
+    let mut variant = 0;
+    loop {
+        let preimage_path = dir.join(variant_name("preimage", variant));
+        match fs::read(&preimage_path) {
+            Ok(existing) if existing == preimage => break,
+            Ok(_) => variant += 1,
+            Err(_) => {
+                write_file(&preimage_path, &preimage)?;
+                break;
+            }
+        }
+    }
+
+    Ok(Some(RerereRecord {
+        id,
+        variant,
+        path: path.to_string(),
+    }))
+}
+
+/// Record the resolution of a conflict previously recorded with `record_preimage`.
+pub(crate) fn record_postimage(
+    repo: &Repository,
+    record: &RerereRecord,
+    contents: &[u8],
+) -> Result<(), SquishError> {
+    let path = rr_cache_dir(repo)?
+        .join(&record.id)
+        .join(variant_name("postimage", record.variant));
+    write_file(&path, contents)
+}
+
+/// Normalize the conflicts in a file the way `git rerere` does and compute the conflict id.
+///
+/// Marker labels and the common ancestor section are dropped and the two sides of each
+/// conflict are sorted, so the same conflict produces the same id regardless of which side
+/// is "ours". The id is the SHA-1 of each hunk's sides, each followed by a NUL byte.
+///
+/// # Returns
+/// The conflict id and the normalized file, or None if the file has no (well formed)
+/// conflicts.
+pub(crate) fn normalize_conflicts(contents: &[u8]) -> Option<(String, Vec<u8>)> {
+    #[derive(PartialEq)]
+    enum Hunk {
+        One,
+        Base,
+        Two,
+    }
+
+    let mut hasher = Sha1::new();
+    let mut normalized = Vec::new();
+    let mut has_conflicts = false;
+    let mut lines = contents.split_inclusive(|b| *b == b'\n');
+
+    while let Some(line) = lines.next() {
+        if !is_marker(line, b'<') {
+            normalized.extend_from_slice(line);
+            continue;
+        }
+
+        let (mut one, mut two) = (Vec::new(), Vec::new());
+        let mut hunk = Hunk::One;
+        loop {
+            // An unterminated or nested conflict is not something we can match.
+            let line = lines.next()?;
+            if is_marker(line, b'<') {
+                return None;
+            } else if is_marker(line, b'|') {
+                if hunk != Hunk::One {
+                    return None;
+                }
+                hunk = Hunk::Base;
+            } else if is_marker(line, b'=') {
+                if hunk == Hunk::Two {
+                    return None;
+                }
+                hunk = Hunk::Two;
+            } else if is_marker(line, b'>') {
+                if hunk != Hunk::Two {
+                    return None;
+                }
+                break;
+            } else {
+                match hunk {
+                    Hunk::One => one.extend_from_slice(line),
+                    Hunk::Base => {}
+                    Hunk::Two => two.extend_from_slice(line),
+                }
+            }
+        }
+
+        if one > two {
+            std::mem::swap(&mut one, &mut two);
+        }
+        for (marker, side) in [(b'<', Some(&one)), (b'=', Some(&two)), (b'>', None)] {
+            normalized.extend_from_slice(&[marker; MARKER_SIZE]);
+            normalized.push(b'\n');
+            if let Some(side) = side {
+                normalized.extend_from_slice(side);
+            }
+        }
+        hasher.update(&one);
+        hasher.update([0]);
+        hasher.update(&two);
+        hasher.update([0]);
+        has_conflicts = true;
+    }
+
+    if !has_conflicts {
+        return None;
+    }
+    let id = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Some((id, normalized))
+}
+
+/// Look up the recorded postimage for a conflict whose normalized preimage matches exactly.
+fn find_resolution(
+    repo: &Repository,
+    id: &str,
+    preimage: &[u8],
+) -> Result<Option<Vec<u8>>, SquishError> {
+    let dir = rr_cache_dir(repo)?.join(id);
+    let mut variant = 0;
+    while let Ok(existing) = fs::read(dir.join(variant_name("preimage", variant))) {
+        if existing == preimage
+            && let Ok(postimage) = fs::read(dir.join(variant_name("postimage", variant)))
+        {
+            return Ok(Some(postimage));
+        }
+        variant += 1;
+    }
+    Ok(None)
+}
+
+/// Mirror git's `is_cmarker`: the marker characters followed by whitespace, where `<` and
+/// `>` markers must be followed by a space (and a label).
+fn is_marker(line: &[u8], marker: u8) -> bool {
+    if line.len() <= MARKER_SIZE || line[..MARKER_SIZE].iter().any(|b| *b != marker) {
+        return false;
+    }
+    let next = line[MARKER_SIZE];
+    if marker == b'<' || marker == b'>' {
+        next == b' '
+    } else {
+        next.is_ascii_whitespace()
+    }
+}
+
+fn variant_name(base: &str, variant: u32) -> String {
+    if variant == 0 {
+        base.to_string()
+    } else {
+        format!("{base}.{variant}")
+    }
+}
+
+fn rr_cache_dir(repo: &Repository) -> Result<PathBuf, SquishError> {
+    Ok(worktree::common_dir(repo)?.join("rr-cache"))
+}
+
+fn create_dir(dir: &Path) -> Result<(), SquishError> {
+    fs::create_dir_all(dir).map_err(|source| SquishError::Io {
+        message: format!("Failed to create {}", dir.display()),
+        source,
+    })
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), SquishError> {
+    fs::write(path, contents).map_err(|source| SquishError::Io {
+        message: format!("Failed to write {}", path.display()),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_test_repo;
+
+    const CONFLICTED: &[u8] = b"a\n<<<<<<< HEAD\nmain\n=======\ntopic\n>>>>>>> topic\nz\n";
+
+    #[test]
+    fn test_normalize_matches_git_conflict_id() {
+        // Id and preimage produced by `git merge` with rerere.enabled for the same conflict.
+        let (id, preimage) = normalize_conflicts(CONFLICTED).unwrap();
+        assert_eq!(id, "17118f8b6ce4c71fc2356be39405651e89f0077c");
+        assert_eq!(
+            preimage,
+            b"a\n<<<<<<<\nmain\n=======\ntopic\n>>>>>>>\nz\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_normalize_ignores_labels_side_order_and_base() {
+        let swapped =
+            b"a\n<<<<<<< ours\ntopic\n||||||| base\nbase\n=======\nmain\n>>>>>>> theirs\nz\n";
+        assert_eq!(
+            normalize_conflicts(swapped),
+            normalize_conflicts(CONFLICTED)
+        );
+    }
+
+    #[test]
+    fn test_normalize_without_conflicts() {
+        assert_eq!(normalize_conflicts(b"a\nb\n"), None);
+        assert_eq!(normalize_conflicts(b"<<<<<<< ours\nunterminated\n"), None);
+        // Marker-like lines without a label are not conflict markers.
+        assert_eq!(
+            normalize_conflicts(b"<<<<<<<\nx\n=======\ny\n>>>>>>>\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_record_and_find_resolution() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(!is_enabled(&repo));
+
+        let record = record_preimage(&repo, "f.txt", CONFLICTED)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.variant, 0);
+        // rr-cache now exists, which enables rerere when rerere.enabled is unset.
+        assert!(is_enabled(&repo));
+
+        let (id, preimage) = normalize_conflicts(CONFLICTED).unwrap();
+        assert_eq!(find_resolution(&repo, &id, &preimage).unwrap(), None);
+
+        record_postimage(&repo, &record, b"a\nresolved\nz\n").unwrap();
+        assert_eq!(
+            find_resolution(&repo, &id, &preimage).unwrap(),
+            Some(b"a\nresolved\nz\n".to_vec())
+        );
+
+        // Same conflict in a different context gets its own variant.
+        let other = b"b\n<<<<<<< HEAD\nmain\n=======\ntopic\n>>>>>>> topic\nz\n";
+        let record = record_preimage(&repo, "g.txt", other).unwrap().unwrap();
+        assert_eq!(record.id, id);
+        assert_eq!(record.variant, 1);
+
+        repo.config()
+            .unwrap()
+            .set_bool("rerere.enabled", false)
+            .unwrap();
+        assert!(!is_enabled(&repo));
+    }
+}
@@ -0,0 +1,200 @@
+use git2::{Commit, Oid, Repository, Signature};
+
+use crate::ignore_revs::IgnoredRevs;
+use crate::message::{add_sources, range_commits, squash_message};
+use crate::{SquishError, SquishOptions, find_branch};
+
+/// Notes ref recording which commits a squish wrote, so a later squish of the same branch
+/// can tell its earlier result apart from commits added since, and what each was made from
+/// (see `squash_provenance`).
+pub const SQUASH_NOTES_REF: &str = "refs/notes/squish";
+
+/// The squash commit the commits between `upstream_id` and `branch_tip_id` would be folded
+/// into with `SquishOptions::amend`: the oldest of them, when one of them is the tip of an
+/// earlier squish recorded in `SQUASH_NOTES_REF`.
+///
+/// The note belongs to the commit id, so a branch rebased with other tools since (without
+/// `notes.rewriteRef` covering the ref) is squashed like any other.
+pub(crate) fn prior_squash(
+    repo: &Repository,
+    branch_tip_id: Oid,
+    upstream_id: Oid,
+) -> Result<Option<Commit<'_>>, SquishError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(branch_tip_id)?;
+    revwalk.hide(upstream_id)?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    let ids = revwalk.collect::<Result<Vec<_>, _>>()?;
+    // The newest commit of the earlier result carries the note, and anything after it was
+    // added since, so the note can't be on the branch tip itself.
+    let squished = ids[..ids.len().saturating_sub(1)]
+        .iter()
+        .any(|&id| repo.find_note(Some(SQUASH_NOTES_REF), id).is_ok());
+    match ids.first() {
+        Some(&first) if squished => Ok(Some(repo.find_commit(first)?)),
+        _ => Ok(None),
+    }
+}
+
+/// The message and author of the squash commit for the commits between `upstream_parent`
+/// and `rebased_tip`. With `SquishOptions::amend` and an earlier squash on the branch, the
+/// new commits are folded into it like `git commit --amend` would: it keeps its message
+/// (unless one is given with `SquishOptions::message`) and its author. Otherwise the
+/// message is the one `squash_message` builds and the author is the committer.
+pub(crate) fn squash_message_and_author(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_parent: &Commit,
+    rebased_tip: &Commit,
+    options: &SquishOptions,
+) -> Result<(String, Option<Signature<'static>>), SquishError> {
+    let branch_tip = find_branch(repo, branch_refname)?.peel_to_commit()?;
+    let sources = range_commits(repo, upstream_parent, &branch_tip)?;
+    if options.amend
+        && let Some(prior) = prior_squash(repo, branch_tip.id(), upstream_parent.id())?
+    {
+        let message = match options.custom_message() {
+            Some(message) => message,
+            None => prior.message().unwrap_or_default().to_string(),
+        };
+        // The earlier squash already lists its own sources.
+        let message = add_sources(message, &sources[1..], options)?;
+        return Ok((message, Some(prior.author().to_owned())));
+    }
+    let ignored = IgnoredRevs::load(repo)?;
+    let message = squash_message(repo, upstream_parent, rebased_tip, &ignored, options)?;
+    Ok((add_sources(message, &sources, options)?, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        change_to_branch, commit_file, create_branch, init_test_repo, topic_repo,
+    };
+    use crate::{continue_squash, squash_branch_with_options};
+    use std::path::Path;
+
+    #[test]
+    fn test_amend_earlier_squash() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Add a\n\nThe details.").unwrap();
+        commit_file(&repo_path, "a.txt", "b\n", "Fix a").unwrap();
+        let squash = |options: &SquishOptions| {
+            squash_branch_with_options(&repo_path, "topic", "main", options).unwrap()
+        };
+        let first = squash(&SquishOptions::new());
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let note = repo
+            .find_note(Some(SQUASH_NOTES_REF), first.new_id)
+            .unwrap();
+        assert_eq!(
+            note.message(),
+            Some(
+                format!(
+                    "squished refs/heads/topic onto {}\n",
+                    repo.refname_to_id("refs/heads/main").unwrap()
+                )
+                .as_str()
+            )
+        );
+        let main = repo.refname_to_id("refs/heads/main").unwrap();
+        assert!(prior_squash(&repo, first.new_id, main).unwrap().is_none());
+
+        // Rewrite the squash commit under another author, as if someone else squished it.
+        let squash_commit = repo.find_commit(first.new_id).unwrap();
+        let alice = Signature::now("Alice", "alice@example.com").unwrap();
+        let amended = squash_commit
+            .amend(
+                Some("refs/heads/topic"),
+                Some(&alice),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        repo.note(
+            &alice,
+            &alice,
+            Some(SQUASH_NOTES_REF),
+            amended,
+            "squished\n",
+            false,
+        )
+        .unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        commit_file(&repo_path, "b.txt", "c\n", "Fix b").unwrap();
+
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let prior = prior_squash(&repo, tip, main).unwrap().unwrap();
+        assert_eq!(prior.id(), amended);
+
+        let result = squash(&SquishOptions::new().amend(true));
+        let commit = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(commit.parent_id(0).unwrap(), main);
+        assert_eq!(commit.message(), Some("Add a\n\nThe details.\n"));
+        assert_eq!(commit.author().name(), Some("Alice"));
+        assert_eq!(commit.committer().name(), Some("Test User"));
+        assert!(commit.tree().unwrap().get_name("b.txt").is_some());
+        assert!(
+            repo.find_note(Some(SQUASH_NOTES_REF), result.new_id)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_amend_after_continue() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let squash = |options: &SquishOptions| {
+            squash_branch_with_options(&repo_path, "topic", "main", options)
+        };
+        let first = squash(&SquishOptions::new()).unwrap();
+
+        // Give the squash commit another author, to tell amending it from a new squash.
+        let repo = Repository::open(&repo_path).unwrap();
+        let alice = Signature::now("Alice", "alice@example.com").unwrap();
+        let amended = repo
+            .find_commit(first.new_id)
+            .unwrap()
+            .amend(
+                Some("refs/heads/topic"),
+                Some(&alice),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        repo.note(
+            &alice,
+            &alice,
+            Some(SQUASH_NOTES_REF),
+            amended,
+            "squished\n",
+            false,
+        )
+        .unwrap();
+        commit_file(&repo_path, "base.txt", "topic\n", "Change base").unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let main = commit_file(&repo_path, "base.txt", "main\n", "Change base on main").unwrap();
+        change_to_branch(&repo_path, "topic").unwrap();
+
+        let options = SquishOptions::new().amend(true).pause_on_conflict(true);
+        assert!(squash(&options).is_err());
+        std::fs::write(repo_path.join("base.txt"), "resolved\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("base.txt")).unwrap();
+        index.write().unwrap();
+        let result = continue_squash(&repo_path).unwrap();
+
+        let commit = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(commit.parent_id(0).unwrap(), main);
+        assert_eq!(commit.message(), Some("Add a\n"));
+        assert_eq!(commit.author().name(), Some("Alice"));
+    }
+}
@@ -0,0 +1,206 @@
+use git2::{Config, Diff, DiffFindOptions, DiffFormat, DiffStatsFormat, Oid, Repository, Time};
+
+use crate::SquishError;
+
+/// Width used for the diffstat, matching git's default for non-terminal output.
+const STAT_WIDTH: usize = 80;
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Render a commit the way `git show --stat` (or `git show --stat --patch`) does: the
+/// header, the indented message, the diffstat against its first parent and, optionally,
+/// the full patch.
+///
+/// # Arguments
+/// * `repo` - The repository holding the commit
+/// * `commit_id` - The commit to render
+/// * `patch` - Whether to include the full patch after the diffstat
+pub fn render_commit(
+    repo: &Repository,
+    commit_id: Oid,
+    patch: bool,
+) -> Result<String, SquishError> {
+    let commit = repo.find_commit(commit_id)?;
+    let author = commit.author();
+
+    let mut out = format!("commit {commit_id}\n");
+    out.push_str(&format!(
+        "Author: {} <{}>\n",
+        author.name().unwrap_or_default(),
+        author.email().unwrap_or_default()
+    ));
+    out.push_str(&format!("Date:   {}\n\n", format_git_date(author.when())));
+    for line in commit.message().unwrap_or_default().lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            out.push_str(&format!("    {line}\n"));
+        }
+    }
+
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    detect_renames(&repo.config()?, &mut diff)?;
+    let stats = diff.stats()?.to_buf(
+        DiffStatsFormat::FULL | DiffStatsFormat::INCLUDE_SUMMARY,
+        STAT_WIDTH,
+    )?;
+    out.push('\n');
+    out.push_str(&String::from_utf8_lossy(&stats));
+
+    if patch {
+        out.push('\n');
+        let mut patch_bytes = Vec::new();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            // Content lines carry their +/-/space marker in `origin` rather than the text.
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                patch_bytes.push(line.origin() as u8);
+            }
+            patch_bytes.extend_from_slice(line.content());
+            true
+        })?;
+        out.push_str(&String::from_utf8_lossy(&patch_bytes));
+    }
+    Ok(out)
+}
+
+/// Pair up deleted and added files as renames (and copies) the way `git diff` does,
+/// following `diff.renames`: on unless set to false, with copies detected for `copies`.
+pub(crate) fn detect_renames(config: &Config, diff: &mut Diff) -> Result<(), SquishError> {
+    let (renames, copies) = match config.get_bool("diff.renames") {
+        Ok(renames) => (renames, false),
+        Err(_) => match config.get_string("diff.renames") {
+            Ok(value) if matches!(value.to_lowercase().as_str(), "copy" | "copies") => (true, true),
+            // Unset (or unrecognized) falls back to git's default of detecting renames.
+            _ => (true, false),
+        },
+    };
+    if renames {
+        diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(copies)))?;
+    }
+    Ok(())
+}
+
+/// Format a time like git's default date format, e.g. "Tue Oct 17 12:00:00 2026 +0200".
+fn format_git_date(time: Time) -> String {
+    let offset_minutes = i64::from(time.offset_minutes());
+    let local = time.seconds() + offset_minutes * 60;
+    let days = local.div_euclid(86_400);
+    let seconds_of_day = local.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {} {sign}{:02}{:02}",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        year,
+        offset_minutes.abs() / 60,
+        offset_minutes.abs() % 60
+    )
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Howard Hinnant's algorithm, shifted so years start on March 1st.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, init_test_repo};
+
+    #[test]
+    fn test_format_git_date() {
+        assert_eq!(
+            format_git_date(Time::new(0, 0)),
+            "Thu Jan 1 00:00:00 1970 +0000"
+        );
+        assert_eq!(
+            format_git_date(Time::new(1_792_238_400, 120)),
+            "Sat Oct 17 14:00:00 2026 +0200"
+        );
+        assert_eq!(
+            format_git_date(Time::new(951_782_400, -330)),
+            "Mon Feb 28 18:30:00 2000 -0530"
+        );
+    }
+
+    #[test]
+    fn test_render_commit_detects_renames() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "old.txt", "same\ncontents\n", "Initial commit").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(std::path::Path::new("old.txt")).unwrap();
+        index.write().unwrap();
+        let commit_id =
+            commit_file(&repo_path, "new.txt", "same\ncontents\n", "Rename old.txt").unwrap();
+
+        let rendered = render_commit(&repo, commit_id, false).unwrap();
+        assert!(rendered.ends_with(
+            " old.txt => new.txt | 0\n 1 file changed, 0 insertions(+), 0 deletions(-)\n"
+        ));
+
+        repo.config()
+            .unwrap()
+            .set_bool("diff.renames", false)
+            .unwrap();
+        let rendered = render_commit(&repo, commit_id, false).unwrap();
+        assert!(rendered.contains(" 2 files changed, 2 insertions(+), 2 deletions(-)\n"));
+    }
+
+    #[test]
+    fn test_render_commit() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "one\ntwo\n", "Initial commit").unwrap();
+        let commit_id = commit_file(
+            &repo_path,
+            "a.txt",
+            "one\nthree\n",
+            "Change a\n\nWith a body",
+        )
+        .unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let commit = repo.find_commit(commit_id).unwrap();
+
+        let rendered = render_commit(&repo, commit_id, false).unwrap();
+        let header = format!(
+            "commit {commit_id}\nAuthor: Test User <test@example.com>\nDate:   {}\n\n    Change a\n\n    With a body\n\n",
+            format_git_date(commit.author().when())
+        );
+        assert_eq!(
+            rendered,
+            format!("{header} a.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n")
+        );
+
+        let rendered = render_commit(&repo, commit_id, true).unwrap();
+        assert!(rendered.contains("diff --git a/a.txt b/a.txt\n"));
+        assert!(rendered.ends_with("@@ -1,2 +1,2 @@\n one\n-two\n+three\n"));
+    }
+}
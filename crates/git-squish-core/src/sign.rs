@@ -0,0 +1,472 @@
+use git2::Repository;
+use git2_ext::ops::{Sign, SshSign, UserSign};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::SquishError;
+
+/// Config key selecting how squash commits are signed: `git` (the default) follows
+/// `commit.gpgsign` and `gpg.format`, `gitsign` always signs with Sigstore's gitsign and
+/// `x509` always signs with the `gpg.x509.program` (smimesign unless set).
+pub(crate) const SIGNING_BACKEND_CONFIG: &str = "squish.signingBackend";
+/// Config key overriding the gitsign program, which is looked up on the PATH otherwise.
+pub(crate) const GITSIGN_PROGRAM_CONFIG: &str = "squish.gitsignProgram";
+/// The first line of the PEM-encoded CMS signatures gitsign and x509 programs produce.
+const CMS_SIGNATURE_HEADER: &str = "-----BEGIN SIGNED MESSAGE-----";
+
+/// Signs squash commits and release tags. Added with `SquishOptions::signer` to sign with
+/// something git's signing config cannot express, taking the place of that config.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{CommitSigner, ProgramSigner, SquishError, SquishOptions};
+///
+/// /// Signs through the company's signing service.
+/// struct SigningService;
+///
+/// impl CommitSigner for SigningService {
+///     fn sign(&self, buffer: &str) -> Result<String, SquishError> {
+///         // ... send `buffer` off and return the armored signature ...
+///         # let _ = buffer;
+///         Ok("-----BEGIN PGP SIGNATURE-----\n...\n-----END PGP SIGNATURE-----\n".to_string())
+///     }
+/// }
+///
+/// let options = SquishOptions::new().signer(SigningService);
+/// let options = SquishOptions::new().signer(ProgramSigner::new("smimesign", "0x1234abcd"));
+/// ```
+pub trait CommitSigner: Send + Sync {
+    /// Sign `buffer`, the raw commit (or tag) object about to be written.
+    ///
+    /// # Returns
+    /// The armored detached signature, which becomes the commit's `gpgsig` header. An error
+    /// fails the squish before any ref is moved.
+    fn sign(&self, buffer: &str) -> Result<String, SquishError>;
+}
+
+/// A `CommitSigner` held by `SquishOptions`, which stay cloneable and printable.
+#[derive(Clone)]
+pub(crate) struct SharedSigner(pub(crate) Arc<dyn CommitSigner>);
+
+impl fmt::Debug for SharedSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CommitSigner")
+    }
+}
+
+impl CommitSigner for SharedSigner {
+    fn sign(&self, buffer: &str) -> Result<String, SquishError> {
+        self.0.sign(buffer)
+    }
+}
+
+/// Signs by running a signing program the way git runs `gpg.x509.program`: the object on
+/// stdin, `--status-fd=2 -bsau <key>` as arguments and the signature on stdout. This is
+/// how gitsign (keyless Sigstore signing), smimesign and gpgsm are driven.
+#[derive(Debug, Clone)]
+pub struct ProgramSigner {
+    program: String,
+    key: String,
+    signature_header: Option<String>,
+}
+
+impl ProgramSigner {
+    /// Sign with `program` (a name looked up on the PATH, or a path) as `key`, a key id or
+    /// an identity such as "Name <email>".
+    pub fn new(program: impl Into<String>, key: impl Into<String>) -> Self {
+        ProgramSigner {
+            program: program.into(),
+            key: key.into(),
+            signature_header: None,
+        }
+    }
+
+    /// Fail unless the signature starts with `header` (e.g. "-----BEGIN SIGNED
+    /// MESSAGE-----"), so a misconfigured program cannot produce an unverifiable commit.
+    pub fn signature_header(mut self, header: impl Into<String>) -> Self {
+        self.signature_header = Some(header.into());
+        self
+    }
+}
+
+impl CommitSigner for ProgramSigner {
+    fn sign(&self, buffer: &str) -> Result<String, SquishError> {
+        let error = |reason: String| SquishError::Other {
+            message: format!("{} failed to sign the commit: {reason}", self.program),
+        };
+        let mut child = Command::new(&self.program)
+            .args(["--status-fd=2", "-bsau", &self.key])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| error(e.to_string()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(buffer.as_bytes())
+                .map_err(|e| error(e.to_string()))?;
+        }
+        let output = child.wait_with_output().map_err(|e| error(e.to_string()))?;
+        if !output.status.success() {
+            return Err(error(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        let signature = String::from_utf8(output.stdout)
+            .map_err(|_| error("the signature is not valid UTF-8".to_string()))?;
+        if let Some(header) = &self.signature_header
+            && !signature.starts_with(header.as_str())
+        {
+            return Err(error(format!("its signature does not start with {header}")));
+        }
+        Ok(signature.replace("\r\n", "\n"))
+    }
+}
+
+/// The signer selected by `squish.signingBackend`: gitsign, which gets a short-lived
+/// certificate for the user's OIDC identity (a browser login, or the ambient credentials
+/// of CI systems like GitHub Actions) from Fulcio so no long-lived key is involved, or an
+/// x509 program. None for the default `git` backend.
+pub(crate) fn backend_signer(repo: &Repository) -> Result<Option<ProgramSigner>, SquishError> {
+    let config = repo.config()?;
+    let program = match config.get_string(SIGNING_BACKEND_CONFIG).ok().as_deref() {
+        None | Some("git") => return Ok(None),
+        Some("gitsign") => config
+            .get_string(GITSIGN_PROGRAM_CONFIG)
+            .unwrap_or_else(|_| "gitsign".to_string()),
+        Some("x509") => config
+            .get_string("gpg.x509.program")
+            .unwrap_or_else(|_| "smimesign".to_string()),
+        Some(backend) => {
+            return Err(SquishError::Other {
+                message: format!(
+                    "Unknown {SIGNING_BACKEND_CONFIG} '{backend}', expected git, gitsign or x509"
+                ),
+            });
+        }
+    };
+    // Like git, sign as user.signingKey or else the committer identity.
+    let key = match config.get_string("user.signingkey") {
+        Ok(key) => key,
+        Err(_) => repo.signature()?.to_string(),
+    };
+    Ok(Some(
+        ProgramSigner::new(program, key).signature_header(CMS_SIGNATURE_HEADER),
+    ))
+}
+
+/// A signer from git's own signing support.
+struct GitSigner(Box<dyn Sign + Send + Sync>);
+
+impl CommitSigner for GitSigner {
+    fn sign(&self, buffer: &str) -> Result<String, SquishError> {
+        Ok(self.0.sign(buffer)?)
+    }
+}
+
+/// The signer for the key configured in git (`gpg.format`, `user.signingKey` and the
+/// program settings), read from the repository's config so per-repository signing setups
+/// apply too.
+///
+/// With `gpg.format = ssh`, a `user.signingKey` path starting with `~/` is taken from the
+/// home directory, like git does, and signed with `ssh-keygen -Y sign` so the commit
+/// verifies with `git verify-commit` against `gpg.ssh.allowedSignersFile`.
+pub(crate) fn user_signer(repo: &Repository) -> Result<Box<dyn CommitSigner>, git2::Error> {
+    let config = repo.config()?;
+    if config.get_string("gpg.format").as_deref() == Ok("ssh")
+        && let Ok(key) = config.get_string("user.signingkey")
+    {
+        let program = config
+            .get_string("gpg.ssh.program")
+            .unwrap_or_else(|_| "ssh-keygen".to_string());
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        let key = expand_home(&key, home.as_deref().map(Path::new));
+        return Ok(Box::new(GitSigner(Box::new(SshSign::new(program, key)))));
+    }
+    let signer = UserSign::from_config(repo, &config)?;
+    Ok(Box::new(GitSigner(Box::new(signer))))
+}
+
+/// `path` with a leading `~/` replaced by `home`. Literal keys (`key::...`, `ssh-...`) and
+/// other paths are left alone.
+fn expand_home(path: &str, home: Option<&Path>) -> String {
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::init_test_repo;
+
+    #[test]
+    fn test_backend_signer() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(backend_signer(&repo).unwrap().is_none());
+
+        let mut config = repo.config().unwrap();
+        config.set_str(SIGNING_BACKEND_CONFIG, "gitsign").unwrap();
+        let gitsign = backend_signer(&repo).unwrap().unwrap();
+        assert_eq!(gitsign.program, "gitsign");
+        assert_eq!(gitsign.key, "Test User <test@example.com>");
+
+        config.set_str(SIGNING_BACKEND_CONFIG, "x509").unwrap();
+        config.set_str("user.signingkey", "0x1234abcd").unwrap();
+        let x509 = backend_signer(&repo).unwrap().unwrap();
+        assert_eq!(x509.program, "smimesign");
+        assert_eq!(x509.key, "0x1234abcd");
+        config.set_str("gpg.x509.program", "gpgsm").unwrap();
+        assert_eq!(backend_signer(&repo).unwrap().unwrap().program, "gpgsm");
+
+        config.set_str(SIGNING_BACKEND_CONFIG, "cosign").unwrap();
+        assert!(backend_signer(&repo).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_squash_signed_with_gitsign() {
+        use crate::squash_branch_with_options;
+        use crate::test_utils::{commit_file, topic_repo};
+        use std::os::unix::fs::PermissionsExt;
+
+        let (repo_path, temp_dir) = topic_repo().unwrap();
+
+        // Stands in for gitsign, which needs an OIDC login and network access.
+        let program = temp_dir.path().join("fake-gitsign");
+        std::fs::write(
+            &program,
+            "#!/bin/sh\ncat > /dev/null\necho '[GNUPG:] SIG_CREATED ' >&2\nprintf '%s\\n' '-----BEGIN SIGNED MESSAGE-----' \"$3\" '-----END SIGNED MESSAGE-----'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str(SIGNING_BACKEND_CONFIG, "gitsign").unwrap();
+        config
+            .set_str(GITSIGN_PROGRAM_CONFIG, program.to_str().unwrap())
+            .unwrap();
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &crate::SquishOptions::new())
+                .unwrap();
+        let (signature, _) = repo.extract_signature(&result.new_id, None).unwrap();
+        assert_eq!(
+            signature.as_str(),
+            Some(
+                "-----BEGIN SIGNED MESSAGE-----\nTest User <test@example.com>\n-----END SIGNED MESSAGE-----\n"
+            )
+        );
+
+        // A program which does not produce a Sigstore signature fails the squish.
+        let unsigned = temp_dir.path().join("unsigned-gitsign");
+        std::fs::write(&unsigned, "#!/bin/sh\ncat > /dev/null\n").unwrap();
+        std::fs::set_permissions(&unsigned, std::fs::Permissions::from_mode(0o755)).unwrap();
+        config
+            .set_str(GITSIGN_PROGRAM_CONFIG, unsigned.to_str().unwrap())
+            .unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let error =
+            squash_branch_with_options(&repo_path, "topic", "main", &crate::SquishOptions::new())
+                .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("its signature does not start with -----BEGIN SIGNED MESSAGE-----")
+        );
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = Path::new("/home/alice");
+        assert_eq!(
+            expand_home("~/.ssh/id_ed25519.pub", Some(home)),
+            "/home/alice/.ssh/id_ed25519.pub"
+        );
+        assert_eq!(expand_home("~/key", None), "~/key");
+        assert_eq!(expand_home("/etc/key", Some(home)), "/etc/key");
+        assert_eq!(
+            expand_home("key::ssh-ed25519 AAAA", Some(home)),
+            "key::ssh-ed25519 AAAA"
+        );
+    }
+
+    /// A repo with a topic branch of two commits, set up to sign with a new SSH key for
+    /// this repository only (as many people do for work repos), or None without ssh-keygen.
+    #[cfg(unix)]
+    fn ssh_signing_repo() -> Option<(std::path::PathBuf, tempfile::TempDir)> {
+        use crate::test_utils::topic_repo;
+
+        let keygen = |args: &[&str]| Command::new("ssh-keygen").args(args).output();
+        if keygen(&["-?"]).is_err() {
+            eprintln!("ssh-keygen not found, skipping");
+            return None;
+        }
+        let (repo_path, temp_dir) = topic_repo().unwrap();
+
+        let key = temp_dir.path().join("id_ed25519");
+        let output = keygen(&["-q", "-t", "ed25519", "-N", "", "-f", key.to_str().unwrap()]);
+        assert!(output.unwrap().status.success());
+        let public_key = std::fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed_signers = temp_dir.path().join("allowed_signers");
+        std::fs::write(&allowed_signers, format!("test@example.com {public_key}")).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str("user.signingkey", key.to_str().unwrap())
+            .unwrap();
+        config
+            .set_str(
+                "gpg.ssh.allowedSignersFile",
+                allowed_signers.to_str().unwrap(),
+            )
+            .unwrap();
+        Some((repo_path, temp_dir))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_squash_signed_with_ssh_key() {
+        use crate::squash_branch_with_options;
+
+        let Some((repo_path, _temp_dir)) = ssh_signing_repo() else {
+            return;
+        };
+        let repo = Repository::open(&repo_path).unwrap();
+        repo.config()
+            .unwrap()
+            .set_bool("commit.gpgsign", true)
+            .unwrap();
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &crate::SquishOptions::new())
+                .unwrap();
+
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+        let (signature, _) = repo.extract_signature(&result.new_id, None).unwrap();
+        assert!(
+            signature
+                .as_str()
+                .unwrap()
+                .starts_with("-----BEGIN SSH SIGNATURE-----")
+        );
+        let verify = Command::new("git")
+            .args(["verify-commit", &result.new_id.to_string()])
+            .current_dir(&repo_path)
+            .env_remove("GIT_DIR")
+            .output();
+        if let Ok(verify) = verify {
+            assert!(
+                verify.status.success(),
+                "{}",
+                String::from_utf8_lossy(&verify.stderr)
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sign_option_overrides_commit_gpgsign() {
+        use crate::squash_branch_with_options;
+        use crate::test_utils::commit_file;
+
+        let Some((repo_path, _temp_dir)) = ssh_signing_repo() else {
+            return;
+        };
+        let repo = Repository::open(&repo_path).unwrap();
+        let squash = |options: crate::SquishOptions| {
+            squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap()
+        };
+
+        // Signing when asked to, though commit.gpgsign is not set.
+        let result = squash(crate::SquishOptions::new().sign(true));
+        assert!(repo.extract_signature(&result.new_id, None).is_ok());
+
+        // Not signing when asked not to, though it is.
+        repo.config()
+            .unwrap()
+            .set_bool("commit.gpgsign", true)
+            .unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let result = squash(crate::SquishOptions::new().sign(false));
+        assert!(repo.extract_signature(&result.new_id, None).is_err());
+    }
+
+    #[test]
+    fn test_unloadable_key_fails_only_when_signing_was_asked_for() {
+        use crate::test_utils::topic_repo;
+        use crate::{SquishOptions, SquishWarning, squash_branch_with_options};
+
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_bool("commit.gpgsign", true).unwrap();
+        config.set_str("gpg.format", "carrier-pigeon").unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        let error = squash_branch_with_options(
+            &repo_path,
+            "topic",
+            "main",
+            &SquishOptions::new().sign(true),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Cannot sign the squash commit: ")
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &SquishOptions::new()).unwrap();
+        assert!(matches!(
+            result.warnings[..],
+            [SquishWarning::SigningSkipped { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_squash_signed_with_custom_signer() {
+        use crate::test_utils::{commit_file, topic_repo};
+        use crate::{SquishOptions, squash_branch_with_options};
+
+        /// Signs with the length of what it was given.
+        struct LengthSigner;
+
+        impl CommitSigner for LengthSigner {
+            fn sign(&self, buffer: &str) -> Result<String, SquishError> {
+                Ok(format!("signed {} bytes\n", buffer.len()))
+            }
+        }
+
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        // The signer takes the place of whatever git is configured with.
+        repo.config()
+            .unwrap()
+            .set_str(SIGNING_BACKEND_CONFIG, "x509")
+            .unwrap();
+
+        let options = SquishOptions::new().signer(LengthSigner);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let (signature, content) = repo.extract_signature(&result.new_id, None).unwrap();
+        assert_eq!(
+            signature.as_str().unwrap(),
+            format!("signed {} bytes\n", content.len())
+        );
+
+        // Turning signing off still wins.
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let options = options.sign(false);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+        assert!(repo.extract_signature(&result.new_id, None).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use git2::{Oid, Repository};
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::warning::Warnings;
+use crate::{SquishError, SquishOptions, SquishWarning};
+
+/// What to do about commits whose signature does not verify (see
+/// `SquishOptions::verify_signatures`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureCheck {
+    /// Fail the squish with `SquishError::UnverifiedSignature` before anything is changed
+    Fail,
+    /// Squish anyway, reporting each commit as `SquishWarning::UnverifiedSignature`
+    Warn,
+}
+
+impl SignatureCheck {
+    /// The name used for this mode on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SignatureCheck::Fail => "fail",
+            SignatureCheck::Warn => "warn",
+        }
+    }
+}
+
+impl FromStr for SignatureCheck {
+    type Err = SquishError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(SignatureCheck::Fail),
+            "warn" => Ok(SignatureCheck::Warn),
+            _ => Err(SquishError::Other {
+                message: format!("Unknown signature check '{s}', expected fail or warn"),
+            }),
+        }
+    }
+}
+
+/// Check the signatures of the upstream tip and the branch's commits on top of it, as
+/// `options` ask, failing on the first commit which does not verify or warning about each.
+///
+/// libgit2 cannot check signatures, so this asks `git` itself, which verifies GPG, SSH and
+/// x509 signatures with the user's own keyring, `gpg.ssh.allowedSignersFile` and
+/// `gpg.minTrustLevel`. Like `git merge --verify-signatures`, only a good signature from a
+/// trusted key passes.
+pub(crate) fn check_signatures(
+    repo: &Repository,
+    branch_id: Oid,
+    upstream_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<(), SquishError> {
+    let Some(check) = options.verify_signatures else {
+        return Ok(());
+    };
+    let mut commits = vec![upstream_id];
+    commits.extend(crate::squashed_commits(repo, branch_id, upstream_id)?);
+
+    for (commit, status, signer) in signature_status(repo, &commits)? {
+        let reason = match status.as_str() {
+            "G" => continue,
+            "N" => "it is not signed".to_string(),
+            "B" => "its signature is bad".to_string(),
+            "U" => format!("it is signed by an untrusted key ({signer})"),
+            "X" => "its signature has expired".to_string(),
+            "Y" => format!("it is signed by an expired key ({signer})"),
+            "R" => format!("it is signed by a revoked key ({signer})"),
+            _ => "its signature cannot be checked, the key may be missing".to_string(),
+        };
+        let summary = repo
+            .find_commit(commit)?
+            .summary()
+            .unwrap_or_default()
+            .to_string();
+        match check {
+            SignatureCheck::Fail => {
+                return Err(SquishError::UnverifiedSignature {
+                    commit,
+                    summary,
+                    reason,
+                });
+            }
+            SignatureCheck::Warn => warnings.push(SquishWarning::UnverifiedSignature {
+                commit,
+                summary,
+                reason,
+            }),
+        }
+    }
+    Ok(())
+}
+
+/// The signature status git reports for each of `commits` (`%G?`: G for good, N for
+/// unsigned, and so on) and the signer (`%GS`), in the order given.
+fn signature_status(
+    repo: &Repository,
+    commits: &[Oid],
+) -> Result<Vec<(Oid, String, String)>, SquishError> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["log", "--no-walk=unsorted", "--format=%H %G? %GS"])
+        .args(commits.iter().map(Oid::to_string))
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE")
+        .output()
+        .map_err(|source| SquishError::Io {
+            message: "Failed to run git to verify signatures".to_string(),
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(SquishError::Other {
+            message: format!(
+                "git failed to verify signatures: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let commit = Oid::from_str(fields.next().unwrap_or_default())?;
+            let status = fields.next().unwrap_or_default().to_string();
+            let signer = fields.next().unwrap_or_default().to_string();
+            Ok((commit, status, signer))
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{create_branch, init_test_repo};
+    use std::path::{Path, PathBuf};
+
+    fn git(repo_path: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .env_remove("GIT_DIR")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    /// A repo set up to sign and verify with a new SSH key, whose upstream tip and topic
+    /// branch of two commits are signed except for the topic commit "Unsigned", or None
+    /// without git and ssh-keygen.
+    fn signed_repo(unsigned: bool) -> Option<(PathBuf, tempfile::TempDir)> {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        let key = temp_dir.path().join("id_ed25519");
+        let keygen = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(&key)
+            .output();
+        if !keygen.is_ok_and(|output| output.status.success()) || !git(&repo_path, &["--version"]) {
+            eprintln!("git or ssh-keygen not found, skipping");
+            return None;
+        }
+        let public_key = std::fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed_signers = temp_dir.path().join("allowed_signers");
+        std::fs::write(&allowed_signers, format!("test@example.com {public_key}")).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str("user.signingkey", key.to_str().unwrap())
+            .unwrap();
+        config
+            .set_str(
+                "gpg.ssh.allowedSignersFile",
+                allowed_signers.to_str().unwrap(),
+            )
+            .unwrap();
+
+        let commit = |file: &str, message: &str, sign: bool| {
+            std::fs::write(repo_path.join(file), message).unwrap();
+            assert!(git(&repo_path, &["add", file]));
+            let sign = if sign { "-S" } else { "--no-gpg-sign" };
+            assert!(git(&repo_path, &["commit", "-q", sign, "-m", message]));
+        };
+        commit("base.txt", "Initial commit", true);
+        create_branch(&repo_path, "topic").unwrap();
+        commit("a.txt", "Add a", true);
+        if unsigned {
+            commit("b.txt", "Unsigned", false);
+        } else {
+            commit("b.txt", "Add b", true);
+        }
+        Some((repo_path, temp_dir))
+    }
+
+    #[test]
+    fn test_signed_branch_verifies() {
+        let Some((repo_path, _temp_dir)) = signed_repo(false) else {
+            return;
+        };
+        let options = SquishOptions::new().verify_signatures(SignatureCheck::Fail);
+
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn test_unsigned_commit_fails_or_warns() {
+        let Some((repo_path, _temp_dir)) = signed_repo(true) else {
+            return;
+        };
+        let repo = Repository::open(&repo_path).unwrap();
+        let tip = repo.refname_to_id("refs/heads/topic").unwrap();
+        let squash = |check| {
+            let options = SquishOptions::new().verify_signatures(check);
+            squash_branch_with_options(&repo_path, "topic", "main", &options)
+        };
+
+        match squash(SignatureCheck::Fail).unwrap_err() {
+            SquishError::UnverifiedSignature {
+                commit,
+                summary,
+                reason,
+            } => {
+                assert_eq!(commit, tip);
+                assert_eq!(summary, "Unsigned");
+                assert_eq!(reason, "it is not signed");
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+
+        let result = squash(SignatureCheck::Warn).unwrap();
+        assert_eq!(
+            result.warnings,
+            [SquishWarning::UnverifiedSignature {
+                commit: tip,
+                summary: "Unsigned".to_string(),
+                reason: "it is not signed".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_untrusted_upstream_fails() {
+        let Some((repo_path, temp_dir)) = signed_repo(false) else {
+            return;
+        };
+        // Nobody is trusted any more.
+        std::fs::write(temp_dir.path().join("allowed_signers"), "").unwrap();
+        let options = SquishOptions::new().verify_signatures(SignatureCheck::Fail);
+
+        let error = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap_err();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        match error {
+            SquishError::UnverifiedSignature { commit, .. } => {
+                assert_eq!(commit, repo.refname_to_id("refs/heads/main").unwrap());
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn test_signature_check_from_str() {
+        for check in [SignatureCheck::Fail, SignatureCheck::Warn] {
+            assert_eq!(check.as_str().parse::<SignatureCheck>().unwrap(), check);
+        }
+        assert!("maybe".parse::<SignatureCheck>().is_err());
+    }
+}
@@ -0,0 +1,595 @@
+use git2::{Index, Oid, Pathspec, PathspecFlags, Repository, Tree};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::classify::changed_paths;
+use crate::generated::index_entry;
+use crate::ignore_revs::IgnoredRevs;
+use crate::message::{
+    add_commit_references, add_references, add_sources, build_squash_message, message_from_commits,
+    range_commits,
+};
+use crate::rebase::{RebaseOutcome, rebase_commits};
+use crate::warning::Warnings;
+use crate::{
+    ClassifiedCommit, CommitKind, SquishError, SquishOptions, classify_commits, find_branch,
+    write_commit, write_squash_commit,
+};
+
+/// Subject prefix of the commits starting a new group with `SquishOptions::split_markers`,
+/// e.g. `!group: Add the parser`.
+pub const GROUP_MARKER: &str = "!group:";
+
+/// A run of consecutive commits which touch the same area of the tree and could be
+/// squashed into a commit of their own.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SplitGroup {
+    /// The directory every commit in the group touches, or None when they touch several.
+    pub area: Option<String>,
+    /// The commits in the group, oldest first.
+    pub commits: Vec<ClassifiedCommit>,
+}
+
+/// Recommend how to split the commits which squishing `branch_refname` onto
+/// `upstream_spec` would fold together.
+///
+/// Consecutive commits are grouped by the directory they touch below the directory shared
+/// by the whole branch (e.g. "src/api" and "src/ui"). Test, docs and chore commits stay with
+/// the group they follow. A single group means the branch is best squashed as a whole.
+pub fn recommend_splits(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+) -> Result<Vec<SplitGroup>, SquishError> {
+    let commits = classify_commits(repo, branch_refname, upstream_spec)?;
+    let mut dirs = Vec::new();
+    for commit in &commits {
+        let paths = changed_paths(repo, &repo.find_commit(commit.id)?)?;
+        let commit_dirs: BTreeSet<PathBuf> = paths
+            .iter()
+            .map(|path| path.parent().unwrap_or(Path::new("")).to_path_buf())
+            .collect();
+        dirs.push(commit_dirs);
+    }
+
+    // Supporting commits (tests, docs, chores) would otherwise pull the base up to the root.
+    let is_supporting = |kind: CommitKind| {
+        matches!(
+            kind,
+            CommitKind::Test | CommitKind::Docs | CommitKind::Chore
+        )
+    };
+    let base = commits
+        .iter()
+        .zip(&dirs)
+        .filter(|(commit, _)| !is_supporting(commit.kind))
+        .flat_map(|(_, commit_dirs)| commit_dirs.iter())
+        .fold(None, |base: Option<PathBuf>, dir| {
+            Some(match base {
+                Some(base) => common_prefix(&base, dir),
+                None => dir.clone(),
+            })
+        })
+        .unwrap_or_default();
+
+    let mut groups: Vec<SplitGroup> = Vec::new();
+    for (commit, commit_dirs) in commits.into_iter().zip(dirs) {
+        let area = area_below(&base, &commit_dirs);
+        match groups.last_mut() {
+            Some(group) if is_supporting(commit.kind) => group.commits.push(commit),
+            Some(group) if group.area == area => group.commits.push(commit),
+            // A group of only supporting commits takes the area of the next commit.
+            Some(group) if group.commits.iter().all(|c| is_supporting(c.kind)) => {
+                group.area = area;
+                group.commits.push(commit);
+            }
+            _ => groups.push(SplitGroup {
+                area,
+                commits: vec![commit],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Group the commits which squishing `branch_refname` onto `upstream_spec` would fold
+/// together at `GROUP_MARKER` commits: each marker commit starts a new group, and any
+/// commits before the first marker form a group of their own.
+pub(crate) fn marker_groups(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_spec: &str,
+) -> Result<Vec<SplitGroup>, SquishError> {
+    let mut groups: Vec<SplitGroup> = Vec::new();
+    for commit in classify_commits(repo, branch_refname, upstream_spec)? {
+        match groups.last_mut() {
+            Some(group) if !commit.summary.starts_with(GROUP_MARKER) => group.commits.push(commit),
+            _ => groups.push(SplitGroup {
+                area: None,
+                commits: vec![commit],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Describe a split recommendation, or return an empty string when the branch is best
+/// squashed as a single commit.
+pub fn format_split_recommendation(groups: &[SplitGroup]) -> String {
+    if groups.len() < 2 {
+        return String::new();
+    }
+
+    let mut out = format!("Consider {} squashes instead of one:\n", groups.len());
+    let mut first = 1;
+    for group in groups {
+        let last = first + group.commits.len() - 1;
+        let commits = if first == last {
+            format!("commit {first} touches")
+        } else {
+            format!("commits {first}–{last} touch")
+        };
+        match &group.area {
+            Some(area) => out.push_str(&format!("  {commits} only {area}/\n")),
+            None => out.push_str(&format!("  {commits} several areas\n")),
+        }
+        first = last + 1;
+    }
+    out.push_str("Run with --split-auto to squash each group separately.\n");
+    out
+}
+
+/// Write one squashed commit per recommended group (or per marker group with
+/// `split_markers`) on top of `upstream_id`, without updating any ref, and return the last
+/// one. Falls back to a single squash commit when no split is recommended.
+pub(crate) fn write_split_commits(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_id: Oid,
+    rebased_tip_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    let groups = if options.split_markers {
+        marker_groups(repo, branch_refname, &upstream_id.to_string())?
+    } else {
+        recommend_splits(repo, branch_refname, &upstream_id.to_string())?
+    };
+    // A single marker group still goes through the loop below, which drops the marker.
+    if rebased_tip_id == upstream_id || (groups.len() < 2 && !options.split_markers) {
+        return write_squash_commit(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        );
+    }
+
+    let upstream_annot = repo.find_annotated_commit(upstream_id)?;
+    // The whole branch already rebased cleanly, so rebasing a prefix of it cannot conflict.
+    let prefix_options = options.clone().pause_on_conflict(false);
+    let ignored = IgnoredRevs::load(repo)?;
+    let branch_tip_id = find_branch(repo, branch_refname)?.peel_to_commit()?.id();
+
+    let mut parent = repo.find_commit(upstream_id)?;
+    let mut previous_last = parent.clone();
+    for group in &groups {
+        let Some(last) = group.commits.last() else {
+            continue;
+        };
+        let tip_id = if last.id == branch_tip_id {
+            rebased_tip_id
+        } else {
+            let last_annot = repo.find_annotated_commit(last.id)?;
+            // Any dropped commits were already reported by the rebase of the whole branch.
+            match rebase_commits(
+                repo,
+                &last_annot,
+                &upstream_annot,
+                None,
+                &prefix_options,
+                &mut Warnings::new(),
+            )? {
+                RebaseOutcome::Complete(tip_id) => tip_id,
+                RebaseOutcome::Conflict { stopped_id, .. } => {
+                    return Err(SquishError::Conflict {
+                        commit: stopped_id,
+                        files: Vec::new(),
+                    });
+                }
+            }
+        };
+
+        let last_commit = repo.find_commit(last.id)?;
+        let tree = repo.find_commit(tip_id)?.tree()?;
+        // Every commit in the group became empty on top of the upstream.
+        if tree.id() != parent.tree_id() {
+            let mut message = build_squash_message(repo, &previous_last, &last_commit, &ignored)?;
+            if options.split_markers
+                && let Some(title) = message.strip_prefix(GROUP_MARKER)
+            {
+                message = title.trim_start().to_string();
+            }
+            let message = add_references(repo, &previous_last, &last_commit, message, options)?;
+            let sources = range_commits(repo, &previous_last, &last_commit)?;
+            let message = add_sources(message, &sources, options)?;
+            let commit_id = write_commit(repo, &message, &tree, &parent, options, warnings)?;
+            parent = repo.find_commit(commit_id)?;
+        }
+        previous_last = last_commit;
+    }
+    Ok(parent.id())
+}
+
+/// Write one squashed commit per pathspec in `SquishOptions::split_path` on top of
+/// `upstream_id`, without updating any ref, and return the last one.
+///
+/// Each commit holds the branch's changes to the paths its pathspec matches (and no earlier
+/// one does), in the order the pathspecs were given, followed by one for the paths none of
+/// them match. Its message is built from the commits which touched those paths. Falls back
+/// to a single squash commit when the changes do not span two of these parts.
+pub(crate) fn write_pathspec_commits(
+    repo: &Repository,
+    branch_refname: &str,
+    upstream_id: Oid,
+    rebased_tip_id: Oid,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Oid, SquishError> {
+    let upstream = repo.find_commit(upstream_id)?;
+    let tree = repo.find_commit(rebased_tip_id)?.tree()?;
+    let parts = partition_changes(repo, &upstream.tree()?, &tree, &options.split_paths)?;
+    if parts.len() < 2 {
+        return write_squash_commit(
+            repo,
+            branch_refname,
+            upstream_id,
+            rebased_tip_id,
+            options,
+            warnings,
+        );
+    }
+
+    // The branch's own commits rather than the rebased ones, so their ids can be annotated.
+    let ignored = IgnoredRevs::load(repo)?;
+    let branch_tip = find_branch(repo, branch_refname)?.peel_to_commit()?;
+    let mut commits = Vec::new();
+    for commit in range_commits(repo, &upstream, &branch_tip)? {
+        let paths = changed_paths(repo, &commit)?;
+        commits.push((commit, paths));
+    }
+
+    // Each commit's tree is its parent's with the changes to its own paths applied, and the
+    // last one has the whole result (including any paths which are not UTF-8).
+    let mut index = Index::new()?;
+    index.read_tree(&upstream.tree()?)?;
+    let mut parent = upstream;
+    for (i, paths) in parts.iter().enumerate() {
+        let part_tree = if i + 1 == parts.len() {
+            tree.clone()
+        } else {
+            for path in paths {
+                match tree.get_path(Path::new(path)) {
+                    Ok(entry) => index.add(&index_entry(path, &entry))?,
+                    Err(_) => index.remove_path(Path::new(path))?,
+                }
+            }
+            repo.find_tree(index.write_tree_to(repo)?)?
+        };
+
+        let touching: Vec<_> = commits
+            .iter()
+            .filter(|(_, changed)| {
+                changed
+                    .iter()
+                    .any(|path| path.to_str().is_some_and(|path| paths.contains(path)))
+            })
+            .map(|(commit, _)| commit.clone())
+            .collect();
+        let message = message_from_commits(&touching, &ignored)?;
+        let message = add_commit_references(&touching, message, options);
+        let message = add_sources(message, &touching, options)?;
+        let commit_id = write_commit(repo, &message, &part_tree, &parent, options, warnings)?;
+        parent = repo.find_commit(commit_id)?;
+    }
+    Ok(parent.id())
+}
+
+/// Partition the paths changed between `base` and `tree` by the first of `pathspecs` they
+/// match, leaving out pathspecs which match no change and ending with the paths no pathspec
+/// matches, if any.
+fn partition_changes(
+    repo: &Repository,
+    base: &Tree,
+    tree: &Tree,
+    pathspecs: &[String],
+) -> Result<Vec<BTreeSet<String>>, SquishError> {
+    let pathspecs = pathspecs
+        .iter()
+        .map(|pathspec| Pathspec::new([pathspec]))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut parts = vec![BTreeSet::new(); pathspecs.len() + 1];
+    let diff = repo.diff_tree_to_tree(Some(base), Some(tree), None)?;
+    for delta in diff.deltas() {
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let part = pathspecs
+            .iter()
+            .position(|pathspec| pathspec.matches_path(path, PathspecFlags::DEFAULT))
+            .unwrap_or(pathspecs.len());
+        if let Some(path) = path.to_str() {
+            parts[part].insert(path.to_string());
+        }
+    }
+    parts.retain(|paths| !paths.is_empty());
+    Ok(parts)
+}
+
+/// The longest directory prefix shared by two paths.
+fn common_prefix(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(component, _)| component)
+        .collect()
+}
+
+/// The single directory directly below `base` which all of `dirs` lie in, if any.
+fn area_below(base: &Path, dirs: &BTreeSet<PathBuf>) -> Option<String> {
+    let areas: BTreeSet<PathBuf> = dirs
+        .iter()
+        .map(|dir| {
+            let below = dir.strip_prefix(base).unwrap_or(dir);
+            Some(base.join(below.components().next()?))
+        })
+        .collect::<Option<_>>()?;
+    match areas.len() {
+        1 => areas.into_iter().next()?.to_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+
+    fn split_repo() -> (std::path::PathBuf, tempfile::TempDir) {
+        let (repo_path, temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "README.md", "readme\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(
+            &repo_path,
+            "src/api/users.rs",
+            "users\n",
+            "Add users endpoint",
+        )
+        .unwrap();
+        commit_file(&repo_path, "src/api/auth.rs", "auth\n", "Add auth endpoint").unwrap();
+        commit_file(&repo_path, "tests/api.rs", "test\n", "Cover the endpoints").unwrap();
+        commit_file(&repo_path, "src/ui/login.rs", "login\n", "Add login page").unwrap();
+        commit_file(
+            &repo_path,
+            "src/ui/form/field.rs",
+            "field\n",
+            "Add form field",
+        )
+        .unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        (repo_path, temp_dir)
+    }
+
+    #[test]
+    fn test_recommend_splits() {
+        let (repo_path, _temp_dir) = split_repo();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let groups = recommend_splits(&repo, "refs/heads/topic", "main").unwrap();
+        let summary: Vec<(Option<&str>, usize)> = groups
+            .iter()
+            .map(|group| (group.area.as_deref(), group.commits.len()))
+            .collect();
+        assert_eq!(summary, vec![(Some("src/api"), 3), (Some("src/ui"), 2)]);
+
+        assert_eq!(
+            format_split_recommendation(&groups),
+            "Consider 2 squashes instead of one:\n  commits 1–3 touch only src/api/\n  commits 4–5 touch only src/ui/\nRun with --split-auto to squash each group separately.\n"
+        );
+        assert_eq!(format_split_recommendation(&groups[..1]), "");
+    }
+
+    #[test]
+    fn test_area_below() {
+        let dirs = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+        let base = Path::new("src");
+        assert_eq!(
+            area_below(base, &dirs(&["src/api", "src/api/v2"])),
+            Some("src/api".to_string())
+        );
+        assert_eq!(area_below(base, &dirs(&["src/api", "src/ui"])), None);
+        assert_eq!(area_below(base, &dirs(&["src"])), None);
+        assert_eq!(
+            area_below(Path::new(""), &dirs(&["api"])),
+            Some("api".to_string())
+        );
+    }
+
+    #[test]
+    fn test_squash_split_auto() {
+        let (repo_path, _temp_dir) = split_repo();
+        let options = SquishOptions::new().split_auto(true);
+        squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let ui = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(ui.summary(), Some("Add login page"));
+        assert!(
+            ui.tree()
+                .unwrap()
+                .get_path(Path::new("src/ui/form/field.rs"))
+                .is_ok()
+        );
+
+        let api = ui.parent(0).unwrap();
+        assert_eq!(api.summary(), Some("Add users endpoint"));
+        let api_tree = api.tree().unwrap();
+        assert!(api_tree.get_path(Path::new("tests/api.rs")).is_ok());
+        assert!(api_tree.get_path(Path::new("src/ui")).is_err());
+
+        let main = repo.refname_to_id("refs/heads/main").unwrap();
+        assert_eq!(api.parent_id(0).unwrap(), main);
+    }
+
+    #[test]
+    fn test_squash_split_by_pathspec() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "README.md", "readme\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "src/parser.rs", "parse\n", "Add parser").unwrap();
+        commit_file(&repo_path, "docs/parser.md", "docs\n", "Document parser").unwrap();
+        commit_file(&repo_path, "README.md", "parser\n", "Mention parser").unwrap();
+        commit_file(&repo_path, "src/parser.rs", "parse!\n", "Fix parser").unwrap();
+
+        let options = SquishOptions::new()
+            .split_path("docs/**")
+            .split_path("src/**")
+            .split_path("tests/**");
+        squash_branch_with_options(repo_path.to_str().unwrap(), "topic", "main", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let rest = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(rest.summary(), Some("Mention parser"));
+        let src = rest.parent(0).unwrap();
+        assert_eq!(src.summary(), Some("Add parser"));
+        let src_tree = src.tree().unwrap();
+        let readme = src_tree.get_path(Path::new("README.md")).unwrap();
+        assert_eq!(repo.find_blob(readme.id()).unwrap().content(), b"readme\n");
+        let parser = src_tree.get_path(Path::new("src/parser.rs")).unwrap();
+        assert_eq!(repo.find_blob(parser.id()).unwrap().content(), b"parse!\n");
+
+        // The pathspec matching no change gets no commit.
+        let docs = src.parent(0).unwrap();
+        assert_eq!(docs.summary(), Some("Document parser"));
+        let docs_tree = docs.tree().unwrap();
+        assert!(docs_tree.get_path(Path::new("docs/parser.md")).is_ok());
+        assert!(docs_tree.get_path(Path::new("src")).is_err());
+        let main = repo.refname_to_id("refs/heads/main").unwrap();
+        assert_eq!(docs.parent_id(0).unwrap(), main);
+    }
+
+    #[test]
+    fn test_squash_split_markers() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "README.md", "readme\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "setup.txt", "setup\n", "Set up").unwrap();
+        commit_file(
+            &repo_path,
+            "parser.rs",
+            "parse\n",
+            "!group: Add the parser\n\nParses things.",
+        )
+        .unwrap();
+        commit_file(&repo_path, "parser.rs", "parse!\n", "Fix the parser").unwrap();
+        // Markers are often empty commits.
+        let repo = Repository::open(&repo_path).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let sig = repo.signature().unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            "!group:Add the CLI",
+            &head.tree().unwrap(),
+            &[&head],
+        )
+        .unwrap();
+        commit_file(&repo_path, "cli.rs", "cli!\n", "Add cli.rs").unwrap();
+
+        let groups = marker_groups(&repo, "refs/heads/topic", "main").unwrap();
+        let sizes: Vec<usize> = groups.iter().map(|group| group.commits.len()).collect();
+        assert_eq!(sizes, vec![1, 2, 2]);
+
+        // Dropping the empty marker is not worth a warning.
+        let options = SquishOptions::new().split_markers(true).strict(true);
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        )
+        .unwrap();
+        assert!(result.warnings.is_empty());
+
+        let cli = repo
+            .find_reference("refs/heads/topic")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(cli.message(), Some("Add the CLI\n"));
+        let parser = cli.parent(0).unwrap();
+        assert_eq!(parser.message(), Some("Add the parser\n\nParses things.\n"));
+        let blob = parser
+            .tree()
+            .unwrap()
+            .get_path(Path::new("parser.rs"))
+            .unwrap();
+        assert_eq!(repo.find_blob(blob.id()).unwrap().content(), b"parse!\n");
+        let setup = parser.parent(0).unwrap();
+        assert_eq!(setup.summary(), Some("Set up"));
+        let main = repo.refname_to_id("refs/heads/main").unwrap();
+        assert_eq!(setup.parent_id(0).unwrap(), main);
+    }
+
+    #[test]
+    fn test_split_markers_never_pause() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "README.md", "readme\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(
+            &repo_path,
+            "README.md",
+            "topic\n",
+            "!group: Rewrite the readme",
+        )
+        .unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        commit_file(&repo_path, "README.md", "main\n", "Update the readme").unwrap();
+
+        // Groups are rebased again from the original commits, which `continue` cannot do.
+        let options = SquishOptions::new()
+            .split_markers(true)
+            .pause_on_conflict(true);
+        let result = squash_branch_with_options(
+            repo_path.to_str().unwrap(),
+            "refs/heads/topic",
+            "main",
+            &options,
+        );
+        assert!(
+            matches!(result, Err(SquishError::Conflict { .. })),
+            "Expected a conflict, got {result:?}"
+        );
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(!crate::state::SquishState::exists(&repo));
+    }
+}
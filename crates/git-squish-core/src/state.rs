@@ -0,0 +1,642 @@
+use git2::{Index, Oid, Repository, ResetType, build::CheckoutBuilder};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::rerere::RerereRecord;
+use crate::{ConflictedFile, MessageNormalization, SquishError, SquishOptions};
+
+/// Name of the directory (inside the git dir) holding the state of a paused squish.
+const STATE_DIR: &str = "squish";
+/// Name of the file within the state directory holding the serialized state.
+const STATE_FILE: &str = "state";
+/// Namespace of the refs keeping the commits of a paused squish reachable. Like the state
+/// directory they belong to the current worktree, and nothing outside it is touched, so
+/// other tools never see a half-finished `git rebase`.
+const STATE_REF_PREFIX: &str = "refs/worktree/squish/";
+
+/// State of a squish which stopped on a conflict, persisted under `.git/squish/` (and
+/// `refs/worktree/squish/`) so that `git squish continue` and `git squish abort` can pick
+/// it back up.
+#[derive(Debug, Clone)]
+pub(crate) struct SquishState {
+    /// The branch being squished (e.g., "refs/heads/feature").
+    pub branch_refname: String,
+    /// The upstream commit the branch is being rebased onto.
+    pub upstream_id: Oid,
+    /// The upstream as it was given (e.g., "origin/main"), which picks the remote to push to.
+    pub upstream_spec: String,
+    /// The branch tip before the squish started.
+    pub orig_tip_id: Oid,
+    /// What HEAD pointed at before pausing: a refname, or a commit id if detached.
+    pub orig_head: String,
+    /// The tip of the commits rebased so far.
+    pub rebased_tip_id: Oid,
+    /// The original commit which failed to apply.
+    pub stopped_id: Oid,
+    /// The options the squish was started with, so `continue_squash` finishes it the same
+    /// way.
+    pub options: SquishOptions,
+    /// Conflicts whose preimage was recorded for rerere, awaiting their resolution.
+    pub rerere: Vec<RerereRecord>,
+    /// The stash holding local changes to re-apply once the squish finishes.
+    pub autostash: Option<Oid>,
+}
+
+impl SquishState {
+    /// Path of the state directory for the given repository.
+    pub fn dir(repo: &Repository) -> PathBuf {
+        repo.path().join(STATE_DIR)
+    }
+
+    /// Whether a paused squish exists in the given repository.
+    pub fn exists(repo: &Repository) -> bool {
+        Self::dir(repo).join(STATE_FILE).exists()
+    }
+
+    /// Load the paused squish state, failing if no squish is in progress.
+    pub fn load(repo: &Repository) -> Result<Self, SquishError> {
+        let path = Self::dir(repo).join(STATE_FILE);
+        let contents = fs::read_to_string(&path).map_err(|_| SquishError::Other {
+            message: "No squish in progress".to_string(),
+        })?;
+        Self::parse(&contents)
+    }
+
+    /// Persist the state, creating the state directory if needed. The commits it refers
+    /// to are also pinned by refs so `git gc` cannot prune them while the squish is paused.
+    pub fn save(&self, repo: &Repository) -> Result<(), SquishError> {
+        for (name, id) in [
+            ("orig-tip", self.orig_tip_id),
+            ("rebased-tip", self.rebased_tip_id),
+            ("stopped", self.stopped_id),
+        ] {
+            repo.reference(
+                &format!("{STATE_REF_PREFIX}{name}"),
+                id,
+                true,
+                "squish: pause",
+            )?;
+        }
+
+        let dir = Self::dir(repo);
+        fs::create_dir_all(&dir).map_err(|source| SquishError::Io {
+            message: format!("Failed to create {}", dir.display()),
+            source,
+        })?;
+        fs::write(dir.join(STATE_FILE), self.serialize()).map_err(|source| SquishError::Io {
+            message: "Failed to write squish state".to_string(),
+            source,
+        })
+    }
+
+    /// Remove the state directory and refs, including any an interrupted squish left
+    /// behind.
+    pub fn remove(repo: &Repository) -> Result<(), SquishError> {
+        for reference in repo.references_glob(&format!("{STATE_REF_PREFIX}*"))? {
+            reference?.delete()?;
+        }
+
+        let dir = Self::dir(repo);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|source| SquishError::Io {
+                message: format!("Failed to remove {}", dir.display()),
+                source,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("branch {}\n", self.branch_refname));
+        out.push_str(&format!("upstream {}\n", self.upstream_id));
+        out.push_str(&format!("upstream-spec {}\n", self.upstream_spec));
+        out.push_str(&format!("orig-tip {}\n", self.orig_tip_id));
+        out.push_str(&format!("orig-head {}\n", self.orig_head));
+        out.push_str(&format!("rebased-tip {}\n", self.rebased_tip_id));
+        out.push_str(&format!("stopped {}\n", self.stopped_id));
+        out.push_str(&serialize_options(&self.options));
+        if let Some(autostash) = self.autostash {
+            out.push_str(&format!("autostash {autostash}\n"));
+        }
+        for record in &self.rerere {
+            out.push_str(&format!(
+                "rerere {} {} {}\n",
+                record.id, record.variant, record.path
+            ));
+        }
+        out
+    }
+
+    fn parse(contents: &str) -> Result<Self, SquishError> {
+        let value = |key: &str| -> Result<&str, SquishError> {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+                .ok_or_else(|| SquishError::Other {
+                    message: format!("Corrupt squish state: missing '{key}'"),
+                })
+        };
+        let oid = |key: &str| -> Result<Oid, SquishError> { Ok(Oid::from_str(value(key)?)?) };
+
+        let autostash = match value("autostash") {
+            Ok(_) => Some(oid("autostash")?),
+            Err(_) => None,
+        };
+
+        let mut rerere = Vec::new();
+        for line in contents.lines() {
+            let Some(record) = line.strip_prefix("rerere ") else {
+                continue;
+            };
+            let mut parts = record.splitn(3, ' ');
+            match (parts.next(), parts.next().map(str::parse), parts.next()) {
+                (Some(id), Some(Ok(variant)), Some(path)) => rerere.push(RerereRecord {
+                    id: id.to_string(),
+                    variant,
+                    path: path.to_string(),
+                }),
+                _ => {
+                    return Err(SquishError::Other {
+                        message: format!("Corrupt squish state: invalid '{line}'"),
+                    });
+                }
+            }
+        }
+
+        Ok(SquishState {
+            branch_refname: value("branch")?.to_string(),
+            upstream_id: oid("upstream")?,
+            upstream_spec: value("upstream-spec")?.to_string(),
+            orig_tip_id: oid("orig-tip")?,
+            orig_head: value("orig-head")?.to_string(),
+            rebased_tip_id: oid("rebased-tip")?,
+            stopped_id: oid("stopped")?,
+            options: parse_options(contents)?,
+            rerere,
+            autostash,
+        })
+    }
+}
+
+/// Fail up front when `SquishOptions::pause_on_conflict` is combined with options the state
+/// file cannot hold, since `continue_squash` would finish the squish without them.
+pub(crate) fn check_resumable(options: &SquishOptions) -> Result<(), SquishError> {
+    if options.pause_on_conflict
+        && (!options.tree_transformers.is_empty()
+            || !options.message_transformers.is_empty()
+            || options.signer.is_some())
+    {
+        return Err(SquishError::Other {
+            message: "Cannot pause on conflicts with a tree transformer, message transformer or \
+                      signer, since continuing could not run it"
+                .to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Serialize the options a squish was started with, one `key value` line each, with values
+/// escaped to fit on a line.
+fn serialize_options(options: &SquishOptions) -> String {
+    let mut lines: Vec<(&str, String)> = Vec::new();
+    let mut flag = |key, set: bool| {
+        if set {
+            lines.push((key, "true".to_string()));
+        }
+    };
+    flag("pause-on-conflict", options.pause_on_conflict);
+    flag("force", options.force);
+    flag("allow-empty", options.allow_empty);
+    flag("allow-protected", options.allow_protected);
+    flag("fetch", options.fetch);
+    flag("push", options.push);
+    flag("split-auto", options.split_auto);
+    flag("set-upstream", options.set_upstream);
+    flag("strict", options.strict);
+    flag("keep-first", options.keep_first);
+    let normalization = &options.normalization;
+    flag("trailing-newline", normalization.trailing_newline);
+    flag("collapse-blank-lines", normalization.collapse_blank_lines);
+    flag("crlf-to-lf", normalization.crlf_to_lf);
+    flag("allow-empty-message", options.allow_empty_message);
+    flag("reference-footer", options.reference_footer);
+    flag("split-markers", options.split_markers);
+    flag("amend", options.amend);
+    flag("annotate-sources", options.annotate_sources);
+    flag("record-provenance", options.record_provenance);
+    flag("verify", options.verify);
+
+    if let Some(strategy_option) = options.strategy_option {
+        lines.push(("strategy-option", strategy_option.as_str().to_string()));
+    }
+    if let Some(cleanup) = options.cleanup {
+        lines.push(("cleanup", cleanup.as_str().to_string()));
+    }
+    if let Some(autostash) = options.autostash {
+        lines.push(("use-autostash", autostash.to_string()));
+    }
+    for pattern in &options.only_authors {
+        lines.push(("only-author", pattern.clone()));
+    }
+    for pattern in &options.exclude_authors {
+        lines.push(("exclude-author", pattern.clone()));
+    }
+    if let Some(since_date) = options.since_date {
+        lines.push(("since-date", since_date.to_string()));
+    }
+    if let Some(until_date) = options.until_date {
+        lines.push(("until-date", until_date.to_string()));
+    }
+    for pattern in &options.generated_paths {
+        lines.push(("generated", pattern.clone()));
+    }
+    if let Some(wrap_body) = normalization.wrap_body {
+        lines.push(("wrap-body", wrap_body.to_string()));
+    }
+    if let Some(message) = &options.message {
+        lines.push(("message", message.clone()));
+    }
+    for (name, value) in &options.headers {
+        lines.push(("header", format!("{name} {value}")));
+    }
+    for id in &options.exclude_commits {
+        lines.push(("exclude-commit", id.to_string()));
+    }
+    for rev in &options.skip_commits {
+        lines.push(("skip", rev.clone()));
+    }
+    for pattern in &options.skip_subject_patterns {
+        lines.push(("skip-subject-pattern", pattern.clone()));
+    }
+    for pathspec in &options.split_paths {
+        lines.push(("split-path", pathspec.clone()));
+    }
+    // Variable names could have spaces, but not line breaks.
+    for (name, value) in &options.variables {
+        lines.push(("variable", format!("{name}\n{value}")));
+    }
+    for trailer in &options.trailers {
+        lines.push(("trailer", trailer.clone()));
+    }
+    if let Some(verify_hooks) = options.verify_hooks {
+        lines.push(("verify-hooks", verify_hooks.to_string()));
+    }
+    for command in &options.exec {
+        lines.push(("exec", command.clone()));
+    }
+    if let Some(sign) = options.sign {
+        lines.push(("sign", sign.to_string()));
+    }
+    if let Some(check) = options.verify_signatures {
+        lines.push(("verify-signatures", check.as_str().to_string()));
+    }
+    if let Some(command) = &options.message_command {
+        lines.push(("message-command", command.clone()));
+    }
+    if let Some(upstream_id) = options.expect_upstream {
+        lines.push(("expect-upstream", upstream_id.to_string()));
+    }
+
+    lines
+        .into_iter()
+        .map(|(key, value)| format!("{key} {}\n", escape(&value)))
+        .collect()
+}
+
+/// Rebuild the options written by `serialize_options`.
+fn parse_options(contents: &str) -> Result<SquishOptions, SquishError> {
+    let corrupt = |key: &str, value: &str| SquishError::Other {
+        message: format!("Corrupt squish state: invalid {key} '{value}'"),
+    };
+    let mut options = SquishOptions::new();
+    let mut normalization = MessageNormalization::default();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let value = unescape(value);
+        let flag = || match value.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(corrupt(key, &value)),
+        };
+        match key {
+            "pause-on-conflict" => options = options.pause_on_conflict(flag()?),
+            "strategy-option" => options = options.strategy_option(value.parse()?),
+            "cleanup" => options = options.cleanup(value.parse()?),
+            "force" => options = options.force(flag()?),
+            "use-autostash" => options = options.autostash(flag()?),
+            "allow-empty" => options = options.allow_empty(flag()?),
+            "allow-protected" => options = options.allow_protected(flag()?),
+            "fetch" => options = options.fetch(flag()?),
+            "push" => options = options.push(flag()?),
+            "split-auto" => options = options.split_auto(flag()?),
+            "set-upstream" => options = options.set_upstream(flag()?),
+            "strict" => options = options.strict(flag()?),
+            "only-author" => options = options.only_author(value),
+            "exclude-author" => options = options.exclude_author(value),
+            "since-date" => {
+                options = options.since_date(value.parse().map_err(|_| corrupt(key, &value))?)
+            }
+            "until-date" => {
+                options = options.until_date(value.parse().map_err(|_| corrupt(key, &value))?)
+            }
+            "keep-first" => options = options.keep_first(flag()?),
+            "generated" => options = options.generated_path(value),
+            "trailing-newline" => normalization.trailing_newline = flag()?,
+            "collapse-blank-lines" => normalization.collapse_blank_lines = flag()?,
+            "crlf-to-lf" => normalization.crlf_to_lf = flag()?,
+            "wrap-body" => {
+                normalization.wrap_body = Some(value.parse().map_err(|_| corrupt(key, &value))?)
+            }
+            "allow-empty-message" => options = options.allow_empty_message(flag()?),
+            "message" => options = options.message(value),
+            "header" => match value.split_once(' ') {
+                Some((name, header)) => options = options.header(name, header),
+                None => return Err(corrupt(key, &value)),
+            },
+            "exclude-commit" => options = options.exclude_commit(Oid::from_str(&value)?),
+            "skip" => options = options.skip_commit(value),
+            "skip-subject-pattern" => options = options.skip_subject_pattern(value),
+            "reference-footer" => options = options.reference_footer(flag()?),
+            "split-path" => options = options.split_path(value),
+            "split-markers" => options = options.split_markers(flag()?),
+            "amend" => options = options.amend(flag()?),
+            "annotate-sources" => options = options.annotate_sources(flag()?),
+            "record-provenance" => options = options.record_provenance(flag()?),
+            "variable" => match value.split_once('\n') {
+                Some((name, variable)) => options = options.template_variable(name, variable),
+                None => return Err(corrupt(key, &value)),
+            },
+            "trailer" => options = options.trailer(value),
+            "verify" => options = options.verify(flag()?),
+            "verify-hooks" => options = options.verify_hooks(flag()?),
+            "exec" => options = options.exec(value),
+            "sign" => options = options.sign(flag()?),
+            "verify-signatures" => options = options.verify_signatures(value.parse()?),
+            "message-command" => options = options.message_command(value),
+            "expect-upstream" => options = options.expect_upstream(Oid::from_str(&value)?),
+            _ => {}
+        }
+    }
+    Ok(options.normalize_message(normalization))
+}
+
+/// Escape backslashes and line breaks so `value` fits on one line of the state file.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo `escape`.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Describe where HEAD currently points so it can be restored later.
+pub(crate) fn current_head(repo: &Repository) -> Result<String, SquishError> {
+    let head = repo.find_reference("HEAD")?;
+    if let Some(target) = head.symbolic_target() {
+        return Ok(target.to_string());
+    }
+    let id = head.target().ok_or_else(|| SquishError::Other {
+        message: "HEAD does not point to a valid commit".to_string(),
+    })?;
+    Ok(id.to_string())
+}
+
+/// Point HEAD back at `orig_head` and reset the index and worktree to match it.
+pub(crate) fn restore_head(repo: &Repository, orig_head: &str) -> Result<(), SquishError> {
+    if orig_head.starts_with("refs/") {
+        repo.set_head(orig_head)?;
+    } else {
+        repo.set_head_detached(Oid::from_str(orig_head)?)?;
+    }
+    let head_commit = repo.head()?.peel_to_commit()?;
+    repo.reset(head_commit.as_object(), ResetType::Hard, None)?;
+    Ok(())
+}
+
+/// Write a conflicted merge into the worktree so the user can resolve it.
+///
+/// HEAD is detached at `rebased_tip_id` and the repository index receives the conflicted
+/// entries, so `git status`, `git diff` and `git add` behave as they do during `git rebase`.
+pub(crate) fn materialize_conflict(
+    repo: &Repository,
+    rebased_tip_id: Oid,
+    conflicted: &Index,
+) -> Result<Vec<String>, SquishError> {
+    let rebased_tip = repo.find_commit(rebased_tip_id)?;
+    repo.checkout_tree(rebased_tip.as_object(), Some(CheckoutBuilder::new().safe()))?;
+    repo.set_head_detached(rebased_tip_id)?;
+
+    let mut index = repo.index()?;
+    index.clear()?;
+    for entry in conflicted.iter() {
+        index.add(&entry)?;
+    }
+    index.write()?;
+
+    repo.checkout_index(
+        Some(&mut index),
+        Some(
+            CheckoutBuilder::new()
+                .force()
+                .allow_conflicts(true)
+                .conflict_style_merge(true),
+        ),
+    )?;
+
+    Ok(ConflictedFile::from_index(&index)?
+        .into_iter()
+        .map(|file| file.path)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, init_test_repo};
+    use crate::{CleanupMode, SignatureCheck, StrategyOption};
+    use std::collections::BTreeMap;
+
+    /// Options with every field set. They are listed without `..Default::default()`, so a
+    /// new option does not compile until it is added here, and then has to be saved for
+    /// `test_options_round_trip` to pass.
+    fn every_option() -> SquishOptions {
+        SquishOptions {
+            strategy_option: Some(StrategyOption::Theirs),
+            pause_on_conflict: true,
+            cleanup: Some(CleanupMode::Scissors),
+            force: true,
+            autostash: Some(false),
+            allow_empty: true,
+            allow_protected: true,
+            fetch: true,
+            push: true,
+            split_auto: true,
+            set_upstream: true,
+            strict: true,
+            only_authors: vec!["dependabot".to_string()],
+            exclude_authors: vec!["alice".to_string(), "bob".to_string()],
+            since_date: Some(1_714_521_600),
+            until_date: Some(1_715_126_400),
+            keep_first: true,
+            generated_paths: vec!["*.lock".to_string(), "dist/".to_string()],
+            normalization: MessageNormalization {
+                trailing_newline: true,
+                collapse_blank_lines: true,
+                crlf_to_lf: true,
+                wrap_body: Some(72),
+            },
+            allow_empty_message: true,
+            message: Some("Add the parser\n\nWith a body.\\n\n".to_string()),
+            headers: vec![
+                ("X-Ticket".to_string(), "ABC 123".to_string()),
+                ("X-Note".to_string(), "two\nlines".to_string()),
+            ],
+            exclude_commits: vec![
+                Oid::from_str("6666666666666666666666666666666666666666").unwrap(),
+            ],
+            skip_commits: vec!["HEAD~2".to_string()],
+            skip_subject_patterns: vec!["^wip".to_string()],
+            reference_footer: true,
+            split_paths: vec!["docs/**".to_string()],
+            split_markers: true,
+            amend: true,
+            annotate_sources: true,
+            record_provenance: true,
+            variables: BTreeMap::from([("ticket".to_string(), "ABC-1".to_string())]),
+            trailers: vec!["Ticket: {ticket}".to_string()],
+            verify: true,
+            // `check_resumable` refuses to pause with these, so they are never saved.
+            tree_transformers: Vec::new(),
+            message_transformers: Vec::new(),
+            signer: None,
+            verify_hooks: Some(true),
+            exec: vec!["cargo test".to_string()],
+            sign: Some(false),
+            verify_signatures: Some(SignatureCheck::Warn),
+            message_command: Some("sed s/Ajouter/Add/".to_string()),
+            expect_upstream: Some(
+                Oid::from_str("6666666666666666666666666666666666666666").unwrap(),
+            ),
+        }
+    }
+
+    fn sample_state(options: SquishOptions) -> SquishState {
+        SquishState {
+            branch_refname: "refs/heads/topic".to_string(),
+            upstream_id: Oid::from_str("1111111111111111111111111111111111111111").unwrap(),
+            upstream_spec: "origin/main".to_string(),
+            orig_tip_id: Oid::from_str("2222222222222222222222222222222222222222").unwrap(),
+            orig_head: "refs/heads/topic".to_string(),
+            rebased_tip_id: Oid::from_str("3333333333333333333333333333333333333333").unwrap(),
+            stopped_id: Oid::from_str("4444444444444444444444444444444444444444").unwrap(),
+            options,
+            rerere: Vec::new(),
+            autostash: None,
+        }
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let commit_id = commit_file(&repo_path, "a.txt", "a\n", "First").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(!SquishState::exists(&repo));
+
+        // The pinning refs need commits which exist.
+        let sample_state = |options| SquishState {
+            orig_tip_id: commit_id,
+            rebased_tip_id: commit_id,
+            stopped_id: commit_id,
+            ..sample_state(options)
+        };
+
+        for state in [
+            sample_state(SquishOptions::new()),
+            sample_state(every_option()),
+            SquishState {
+                rerere: vec![RerereRecord {
+                    id: "17118f8b6ce4c71fc2356be39405651e89f0077c".to_string(),
+                    variant: 1,
+                    path: "dir/file with spaces.txt".to_string(),
+                }],
+                ..sample_state(SquishOptions::new())
+            },
+            SquishState {
+                autostash: Some(Oid::from_str("5555555555555555555555555555555555555555").unwrap()),
+                ..sample_state(SquishOptions::new())
+            },
+        ] {
+            state.save(&repo).unwrap();
+            assert!(SquishState::exists(&repo));
+            let loaded = SquishState::load(&repo).unwrap();
+            assert_eq!(format!("{loaded:?}"), format!("{state:?}"));
+        }
+        assert_eq!(
+            repo.refname_to_id("refs/worktree/squish/stopped").unwrap(),
+            commit_id
+        );
+
+        SquishState::remove(&repo).unwrap();
+        assert!(!SquishState::exists(&repo));
+        assert!(SquishState::load(&repo).is_err());
+        assert_eq!(
+            repo.references_glob("refs/worktree/squish/*")
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_options_round_trip() {
+        let options = every_option();
+
+        let parsed = parse_options(&serialize_options(&options)).unwrap();
+
+        assert_eq!(format!("{parsed:?}"), format!("{options:?}"));
+    }
+
+    #[test]
+    fn test_parse_corrupt_state() {
+        let result = SquishState::parse("branch refs/heads/topic\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_current_head_and_restore() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        let first = commit_file(&repo_path, "a.txt", "a\n", "First").unwrap();
+        commit_file(&repo_path, "a.txt", "b\n", "Second").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        assert_eq!(current_head(&repo).unwrap(), "refs/heads/main");
+
+        repo.set_head_detached(first).unwrap();
+        assert_eq!(current_head(&repo).unwrap(), first.to_string());
+
+        restore_head(&repo, "refs/heads/main").unwrap();
+        assert_eq!(current_head(&repo).unwrap(), "refs/heads/main");
+        assert_eq!(fs::read_to_string(repo_path.join("a.txt")).unwrap(), "b\n");
+    }
+}
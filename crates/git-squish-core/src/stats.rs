@@ -0,0 +1,347 @@
+use git2::{BranchType, ErrorCode, Oid, Repository};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::date::{format_datetime, format_month};
+use crate::list::format_table;
+use crate::{
+    RESOLVED_SQUASH_REFLOG_MESSAGE, SQUASH_NOTES_REF, SquishError, is_squash_reflog_entry,
+    squash_provenance,
+};
+
+/// How many of the most squished branches `squash_stats` reports.
+const TOP_BRANCHES: usize = 10;
+
+/// A squish recorded in a branch's reflog or its squash note, as returned by
+/// `squash_history`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SquashRecord {
+    /// Short name of the squished branch (e.g., "feature").
+    pub branch: String,
+    /// Where the branch pointed before the squish.
+    pub old_id: Oid,
+    /// Where the squish left the branch.
+    pub new_id: Oid,
+    /// When the squish happened, in seconds since the Unix epoch.
+    pub time: i64,
+    /// Number of commits folded into the squash.
+    pub commits: usize,
+    /// Whether the squish paused on a conflict which had to be resolved. Not recorded in
+    /// squash notes, so always false for squishes only found there.
+    pub conflicts: bool,
+}
+
+/// Squash activity summarized by `squash_stats`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SquashStats {
+    /// Number of squishes.
+    pub squashes: usize,
+    /// Average number of commits folded into a squash.
+    pub average_commits: f64,
+    /// Share of squishes which paused on a conflict, between 0 and 1.
+    pub conflict_rate: f64,
+    /// Number of squishes per UTC month (e.g. "2024-05"), oldest first.
+    pub by_month: Vec<(String, usize)>,
+    /// The most squished branches with their number of squishes, most squished first.
+    pub top_branches: Vec<(String, usize)>,
+}
+
+/// Collect the squishes recorded in the reflogs of every local branch, oldest first,
+/// optionally only those at or after `since` (seconds since the Unix epoch).
+///
+/// Reflogs only reach so far back: git expires their entries (after 90 days by default)
+/// and deletes a branch's reflog along with it. Squishes made with
+/// `SquishOptions::record_provenance` are also found through their `SQUASH_NOTES_REF`
+/// note once the reflog has forgotten them, as long as the squash commit still exists.
+pub fn squash_history(
+    repo: &Repository,
+    since: Option<i64>,
+) -> Result<Vec<SquashRecord>, SquishError> {
+    let mut records = Vec::new();
+    for branch_result in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch_result?;
+        let (Some(refname), Some(name)) = (branch.get().name(), branch.name()?) else {
+            // Skip branches whose names are not valid UTF-8.
+            continue;
+        };
+        for entry in repo.reflog(refname)?.iter() {
+            let time = entry.committer().when().seconds();
+            if !is_squash_reflog_entry(&entry) || since.is_some_and(|since| time < since) {
+                continue;
+            }
+            // The squash commits sit on the upstream, so hiding them leaves the commits
+            // which were folded.
+            let mut revwalk = repo.revwalk()?;
+            revwalk.push(entry.id_old())?;
+            revwalk.hide(entry.id_new())?;
+            records.push(SquashRecord {
+                branch: name.to_string(),
+                old_id: entry.id_old(),
+                new_id: entry.id_new(),
+                time,
+                commits: revwalk.count(),
+                conflicts: entry.message() == Some(RESOLVED_SQUASH_REFLOG_MESSAGE),
+            });
+        }
+    }
+    records.extend(noted_squashes(repo, since, &records)?);
+    records.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.branch.cmp(&b.branch)));
+    Ok(records)
+}
+
+/// The squishes recorded with their provenance in `SQUASH_NOTES_REF` which are not among
+/// `known` (found in reflogs already). Their time is when the squash commit was committed.
+fn noted_squashes(
+    repo: &Repository,
+    since: Option<i64>,
+    known: &[SquashRecord],
+) -> Result<Vec<SquashRecord>, SquishError> {
+    let notes = match repo.notes(Some(SQUASH_NOTES_REF)) {
+        Ok(notes) => notes,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let known: HashSet<Oid> = known.iter().map(|record| record.new_id).collect();
+    let mut records = Vec::new();
+    for note in notes {
+        let (_, commit_id) = note?;
+        if known.contains(&commit_id) {
+            continue;
+        }
+        // Notes without provenance (or not written by git-squish at all) have no original
+        // tip to report, and the squash commit itself may have been garbage collected.
+        let Ok(Some(provenance)) = squash_provenance(repo, commit_id) else {
+            continue;
+        };
+        let (Some(old_id), Ok(commit)) = (provenance.original_tip, repo.find_commit(commit_id))
+        else {
+            continue;
+        };
+        let time = commit.committer().when().seconds();
+        if since.is_some_and(|since| time < since) {
+            continue;
+        }
+        records.push(SquashRecord {
+            branch: provenance
+                .branch
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&provenance.branch)
+                .to_string(),
+            old_id,
+            new_id: commit_id,
+            time,
+            commits: provenance.commits.len(),
+            conflicts: false,
+        });
+    }
+    Ok(records)
+}
+
+/// Render squishes as a plain text table, newest first: when (UTC), which branch, how it
+/// moved and how many commits were folded.
+pub fn format_squash_log(records: &[SquashRecord]) -> String {
+    let rows: Vec<[String; 4]> = records
+        .iter()
+        .rev()
+        .map(|record| {
+            [
+                format_datetime(record.time),
+                record.branch.clone(),
+                format!("{:.7} -> {:.7}", record.old_id, record.new_id),
+                record.commits.to_string(),
+            ]
+        })
+        .collect();
+    format_table(["DATE (UTC)", "BRANCH", "CHANGE", "COMMITS"], &rows)
+}
+
+/// Summarize squishes: how many there were, per month and per branch, how many commits
+/// they folded on average and how often they conflicted.
+pub fn squash_stats(records: &[SquashRecord]) -> SquashStats {
+    let squashes = records.len();
+    let ratio = |count: usize| {
+        if squashes == 0 {
+            0.0
+        } else {
+            count as f64 / squashes as f64
+        }
+    };
+
+    let mut by_month: BTreeMap<String, usize> = BTreeMap::new();
+    let mut by_branch: BTreeMap<&str, usize> = BTreeMap::new();
+    for record in records {
+        *by_month.entry(format_month(record.time)).or_default() += 1;
+        *by_branch.entry(&record.branch).or_default() += 1;
+    }
+    let mut top_branches: Vec<(String, usize)> = by_branch
+        .into_iter()
+        .map(|(branch, count)| (branch.to_string(), count))
+        .collect();
+    // Stable, so branches squished equally often stay sorted by name.
+    top_branches.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    top_branches.truncate(TOP_BRANCHES);
+
+    SquashStats {
+        squashes,
+        average_commits: ratio(records.iter().map(|record| record.commits).sum()),
+        conflict_rate: ratio(records.iter().filter(|record| record.conflicts).count()),
+        by_month: by_month.into_iter().collect(),
+        top_branches,
+    }
+}
+
+/// Render squash statistics as plain text: the totals followed by tables of squishes per
+/// month and per branch.
+pub fn format_stats(stats: &SquashStats) -> String {
+    let mut out = format!(
+        "Squashes:           {}\nCommits per squash: {:.1}\nConflict rate:      {:.0}%\n",
+        stats.squashes,
+        stats.average_commits,
+        stats.conflict_rate * 100.0
+    );
+    let rows = |counts: &[(String, usize)]| {
+        counts
+            .iter()
+            .map(|(name, count)| [name.clone(), count.to_string()])
+            .collect::<Vec<_>>()
+    };
+    if !stats.by_month.is_empty() {
+        out.push('\n');
+        out.push_str(&format_table(["MONTH", "SQUASHES"], &rows(&stats.by_month)));
+    }
+    if !stats.top_branches.is_empty() {
+        out.push('\n');
+        out.push_str(&format_table(
+            ["BRANCH", "SQUASHES"],
+            &rows(&stats.top_branches),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        change_to_branch, commit_file, create_branch, init_test_repo, topic_repo,
+    };
+    use crate::{SquishOptions, squash_branch_with_options};
+
+    #[test]
+    fn test_squash_history() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Add a").unwrap();
+        commit_file(&repo_path, "b.txt", "b\n", "Add b").unwrap();
+        let old_id = commit_file(&repo_path, "c.txt", "c\n", "Add c").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let result =
+            squash_branch_with_options(&repo_path, "topic", "main", &SquishOptions::new()).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let records = squash_history(&repo, None).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.branch, "topic");
+        assert_eq!((record.old_id, record.new_id), (old_id, result.new_id));
+        assert_eq!(record.commits, 3);
+        assert!(!record.conflicts);
+        assert!(record.time > 0);
+
+        assert!(squash_history(&repo, Some(i64::MAX)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_squash_history_from_notes() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let old_id = repo.refname_to_id("refs/heads/topic").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+        let options = SquishOptions::new().record_provenance(true);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        // Deleting the branch takes its reflog along, leaving only the note.
+        repo.find_branch("topic", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        let records = squash_history(&repo, None).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.branch, "topic");
+        assert_eq!((record.old_id, record.new_id), (old_id, result.new_id));
+        assert_eq!(record.commits, 2);
+        assert!(squash_history(&repo, Some(i64::MAX)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_squash_log() {
+        let record = |branch: &str, time: i64, commits: usize| SquashRecord {
+            branch: branch.to_string(),
+            old_id: Oid::from_str("1a2b3c4d5e6f").unwrap(),
+            new_id: Oid::from_str("abcdef012345").unwrap(),
+            time,
+            commits,
+            conflicts: false,
+        };
+        let records = [
+            record("feature", 1_714_435_200, 2),
+            record("bugfix", 1_714_570_200, 12),
+        ];
+        assert_eq!(
+            format_squash_log(&records),
+            "DATE (UTC)        BRANCH   CHANGE              COMMITS\n\
+             2024-05-01 13:30  bugfix   1a2b3c4 -> abcdef0  12\n\
+             2024-04-30 00:00  feature  1a2b3c4 -> abcdef0  2\n"
+        );
+    }
+
+    #[test]
+    fn test_squash_stats() {
+        let record = |branch: &str, time: i64, commits: usize, conflicts: bool| SquashRecord {
+            branch: branch.to_string(),
+            old_id: Oid::zero(),
+            new_id: Oid::zero(),
+            time,
+            commits,
+            conflicts,
+        };
+        // 2024-04-30 and 2024-05-01.
+        let records = vec![
+            record("feature", 1_714_435_200, 2, false),
+            record("bugfix", 1_714_521_600, 5, true),
+            record("feature", 1_714_521_600, 4, false),
+            record("docs", 1_714_521_600, 1, false),
+        ];
+        let stats = squash_stats(&records);
+        assert_eq!(stats.squashes, 4);
+        assert_eq!(stats.average_commits, 3.0);
+        assert_eq!(stats.conflict_rate, 0.25);
+        assert_eq!(
+            stats.by_month,
+            vec![("2024-04".to_string(), 1), ("2024-05".to_string(), 3)]
+        );
+        assert_eq!(
+            stats.top_branches,
+            vec![
+                ("feature".to_string(), 2),
+                ("bugfix".to_string(), 1),
+                ("docs".to_string(), 1)
+            ]
+        );
+
+        assert_eq!(
+            format_stats(&stats),
+            "Squashes:           4\nCommits per squash: 3.0\nConflict rate:      25%\n\nMONTH    SQUASHES\n2024-04  1\n2024-05  3\n\nBRANCH   SQUASHES\nfeature  2\nbugfix   1\ndocs     1\n"
+        );
+
+        let empty = squash_stats(&[]);
+        assert_eq!((empty.average_commits, empty.conflict_rate), (0.0, 0.0));
+        assert_eq!(
+            format_stats(&empty),
+            "Squashes:           0\nCommits per squash: 0.0\nConflict rate:      0%\n"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use git2::{BranchType, Repository};
+use git2::{BranchType, Oid, Repository};
 use std::path::PathBuf;
 use tempfile::TempDir;
 
@@ -11,8 +11,8 @@ use crate::SquishError;
 /// The TempDir must be kept alive to prevent the directory from being deleted.
 ///
 /// # Example
-/// ```
-/// use git_squish::test_utils::clone_test_repo;
+/// ```no_run
+/// use git_squish_core::test_utils::clone_test_repo;
 ///
 /// let (repo_path, _temp_dir) = clone_test_repo().unwrap();
 /// // Use repo_path for testing...
@@ -63,8 +63,8 @@ pub fn clone_test_repo() -> Result<(PathBuf, TempDir), SquishError> {
 /// Success message on completion, or a SquishError if the operation fails.
 ///
 /// # Example
-/// ```
-/// use git_squish::test_utils::{clone_test_repo, change_to_branch};
+/// ```no_run
+/// use git_squish_core::test_utils::{clone_test_repo, change_to_branch};
 ///
 /// let (repo_path, _temp_dir) = clone_test_repo().unwrap();
 /// change_to_branch(&repo_path, "main").unwrap();
@@ -124,8 +124,8 @@ pub fn change_to_branch(repo_path: &PathBuf, branch_name: &str) -> Result<String
 /// The commit message of the current HEAD commit, or a SquishError if the operation fails.
 ///
 /// # Example
-/// ```
-/// use git_squish::test_utils::{clone_test_repo, get_current_commit_message};
+/// ```no_run
+/// use git_squish_core::test_utils::{clone_test_repo, get_current_commit_message};
 ///
 /// let (repo_path, _temp_dir) = clone_test_repo().unwrap();
 /// let message = get_current_commit_message(&repo_path).unwrap();
@@ -146,6 +146,112 @@ pub fn get_current_commit_message(repo_path: &PathBuf) -> Result<String, SquishE
     Ok(message.to_string())
 }
 
+/// Initialize an empty repository in a temporary directory with a configured test user.
+///
+/// Unlike `clone_test_repo`, this does not require network access, which makes it suitable
+/// for tests that build their own commit history with `commit_file`.
+///
+/// # Returns
+/// A tuple containing the path to the new repository and the TempDir handle.
+pub fn init_test_repo() -> Result<(PathBuf, TempDir), SquishError> {
+    let temp_dir = tempfile::tempdir().map_err(|e| SquishError::Other {
+        message: format!("Failed to create temporary directory: {}", e),
+    })?;
+
+    let repo_path = temp_dir.path().to_path_buf();
+    let mut init_opts = git2::RepositoryInitOptions::new();
+    init_opts.initial_head("main");
+    let repo = Repository::init_opts(&repo_path, &init_opts)?;
+
+    let mut config = repo.config()?;
+    config.set_str("user.name", "Test User")?;
+    config.set_str("user.email", "test@example.com")?;
+
+    Ok((repo_path, temp_dir))
+}
+
+/// Initialize a repository like `init_test_repo`, with a first commit on main and a topic
+/// branch of two commits on top of it ("Add a" and "Fix a", both writing a.txt), which is
+/// checked out.
+///
+/// # Returns
+/// A tuple containing the path to the new repository and the TempDir handle.
+pub fn topic_repo() -> Result<(PathBuf, TempDir), SquishError> {
+    let (repo_path, temp_dir) = init_test_repo()?;
+    commit_file(&repo_path, "base.txt", "base\n", "Initial commit")?;
+    create_branch(&repo_path, "topic")?;
+    commit_file(&repo_path, "a.txt", "a\n", "Add a")?;
+    commit_file(&repo_path, "a.txt", "b\n", "Fix a")?;
+    Ok((repo_path, temp_dir))
+}
+
+/// Write `contents` to `filename` and commit it on top of the current HEAD.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `filename` - Path of the file relative to the repository root
+/// * `contents` - New contents of the file
+/// * `message` - Commit message
+///
+/// # Returns
+/// The id of the new commit, or a SquishError if the operation fails.
+pub fn commit_file(
+    repo_path: &PathBuf,
+    filename: &str,
+    contents: &str,
+    message: &str,
+) -> Result<Oid, SquishError> {
+    let repo = Repository::open(repo_path)?;
+
+    let file_path = repo_path.join(filename);
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| SquishError::Other {
+            message: format!("Failed to create directory for {}: {}", filename, e),
+        })?;
+    }
+    std::fs::write(&file_path, contents).map_err(|e| SquishError::Other {
+        message: format!("Failed to write file {}: {}", filename, e),
+    })?;
+
+    let mut index = repo.index()?;
+    index.add_path(std::path::Path::new(filename))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let sig = repo.signature()?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+    let oid = repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)?;
+    Ok(oid)
+}
+
+/// Create a new local branch pointing at the current HEAD commit and switch to it.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_name` - Name of the branch to create (e.g., "topic")
+pub fn create_branch(repo_path: &PathBuf, branch_name: &str) -> Result<String, SquishError> {
+    {
+        let repo = Repository::open(repo_path)?;
+        let head_commit = repo.head()?.peel_to_commit()?;
+        repo.branch(branch_name, &head_commit, false)?;
+    }
+    change_to_branch(repo_path, branch_name)
+}
+
+/// Read the contents of a file as recorded in the tree of the current HEAD commit.
+///
+/// The squash runs in memory and does not touch the working directory, so tests should
+/// inspect the committed tree rather than the files on disk.
+pub fn read_head_file(repo_path: &PathBuf, filename: &str) -> Result<String, SquishError> {
+    let repo = Repository::open(repo_path)?;
+    let tree = repo.head()?.peel_to_tree()?;
+    let entry = tree.get_path(std::path::Path::new(filename))?;
+    let blob = repo.find_blob(entry.id())?;
+    Ok(String::from_utf8_lossy(blob.content()).to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,4 +311,23 @@ mod tests {
 
         assert!(!message.is_empty(), "Should have a commit message");
     }
+
+    #[test]
+    fn test_init_test_repo_and_commit_file() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+
+        commit_file(&repo_path, "text.txt", "hello\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "text.txt", "hello topic\n", "Topic commit").unwrap();
+
+        let message = get_current_commit_message(&repo_path).unwrap();
+        assert_eq!(message, "Topic commit");
+        assert_eq!(
+            read_head_file(&repo_path, "text.txt").unwrap(),
+            "hello topic\n"
+        );
+
+        change_to_branch(&repo_path, "main").unwrap();
+        assert_eq!(read_head_file(&repo_path, "text.txt").unwrap(), "hello\n");
+    }
 }
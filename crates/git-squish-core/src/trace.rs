@@ -0,0 +1,90 @@
+use git2::TraceLevel;
+use log::{Level, LevelFilter};
+
+/// The `log` target libgit2's trace output is emitted under.
+pub const LIBGIT2_LOG_TARGET: &str = "libgit2";
+
+/// Route libgit2's own trace output, up to `max_level`, into the `log` crate under the
+/// `libgit2` target, so failures deep inside merges and rebases show up in the same log
+/// stream as everything else. `LevelFilter::Off` turns it off again.
+///
+/// libgit2 has a single trace callback per process, so this affects every repository.
+/// Nothing is printed unless a logger is installed (see the `log` crate).
+pub fn enable_libgit2_trace(max_level: LevelFilter) {
+    let level = match max_level {
+        LevelFilter::Off => TraceLevel::None,
+        LevelFilter::Error => TraceLevel::Error,
+        LevelFilter::Warn => TraceLevel::Warn,
+        LevelFilter::Info => TraceLevel::Info,
+        LevelFilter::Debug => TraceLevel::Debug,
+        LevelFilter::Trace => TraceLevel::Trace,
+    };
+    git2::trace_set(level, forward);
+}
+
+/// Forward one libgit2 trace message to the `log` crate.
+fn forward(level: TraceLevel, message: &str) {
+    let level = match level {
+        TraceLevel::None => return,
+        TraceLevel::Fatal | TraceLevel::Error => Level::Error,
+        TraceLevel::Warn => Level::Warn,
+        TraceLevel::Info => Level::Info,
+        TraceLevel::Debug => Level::Debug,
+        TraceLevel::Trace => Level::Trace,
+    };
+    log::log!(target: LIBGIT2_LOG_TARGET, level, "{message}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records every log message, for checking what was forwarded.
+    struct Recorder(Mutex<Vec<(String, Level, String)>>);
+
+    impl log::Log for Recorder {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((
+                record.target().to_string(),
+                record.level(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+
+    #[test]
+    fn test_forward() {
+        log::set_logger(&RECORDER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+
+        forward(TraceLevel::Fatal, "fatal");
+        forward(TraceLevel::Debug, "debug");
+        forward(TraceLevel::None, "ignored");
+        assert_eq!(
+            *RECORDER.0.lock().unwrap(),
+            vec![
+                (
+                    LIBGIT2_LOG_TARGET.to_string(),
+                    Level::Error,
+                    "fatal".to_string()
+                ),
+                (
+                    LIBGIT2_LOG_TARGET.to_string(),
+                    Level::Debug,
+                    "debug".to_string()
+                ),
+            ]
+        );
+
+        enable_libgit2_trace(LevelFilter::Off);
+    }
+}
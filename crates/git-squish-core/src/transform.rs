@@ -0,0 +1,190 @@
+use git2::{Oid, Repository, Tree};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::warning::Warnings;
+use crate::{SquishError, SquishOptions, SquishWarning};
+
+/// Changes the squashed tree before the squash commit is written, so the squash commit can
+/// carry changes of its own, such as a version bump or a regenerated manifest. Added with
+/// `SquishOptions::tree_transformer`; every change is reported as
+/// `SquishWarning::TreeTransformed`.
+///
+/// # Example
+/// ```
+/// use git2::{Oid, Repository, Tree};
+/// use git_squish_core::{SquishError, SquishOptions, TreeTransformer};
+///
+/// /// Writes the release version to a VERSION file at the root.
+/// struct BumpVersion(String);
+///
+/// impl TreeTransformer for BumpVersion {
+///     fn name(&self) -> String {
+///         format!("bump version to {}", self.0)
+///     }
+///
+///     fn transform(&self, repo: &Repository, tree: &Tree) -> Result<Option<Oid>, SquishError> {
+///         let blob = repo.blob(format!("{}\n", self.0).as_bytes())?;
+///         let mut builder = repo.treebuilder(Some(tree))?;
+///         builder.insert("VERSION", blob, 0o100644)?;
+///         Ok(Some(builder.write()?))
+///     }
+/// }
+///
+/// let options = SquishOptions::new().tree_transformer(BumpVersion("1.2.0".to_string()));
+/// ```
+pub trait TreeTransformer: Send + Sync {
+    /// What the transformer does (e.g. "bump version to 1.2.0"), for
+    /// `SquishWarning::TreeTransformed`.
+    fn name(&self) -> String;
+
+    /// Transform `tree`, the squashed tree (all the branch's changes on top of the
+    /// upstream), into the tree to commit. New blobs and trees must be written to `repo`.
+    ///
+    /// # Returns
+    /// The id of the tree to commit instead, or None to leave the tree as it is. An error
+    /// fails the squish before any ref is moved.
+    fn transform(&self, repo: &Repository, tree: &Tree) -> Result<Option<Oid>, SquishError>;
+}
+
+/// A `TreeTransformer` held by `SquishOptions`, which stay cloneable and printable.
+#[derive(Clone)]
+pub(crate) struct SharedTransformer(pub(crate) Arc<dyn TreeTransformer>);
+
+impl fmt::Debug for SharedTransformer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TreeTransformer")
+            .field(&self.0.name())
+            .finish()
+    }
+}
+
+/// Run `tree` through the transformers in `options`, in the order they were added,
+/// recording each change in `warnings`.
+pub(crate) fn transform_tree<'r>(
+    repo: &'r Repository,
+    tree: Tree<'r>,
+    options: &SquishOptions,
+    warnings: &mut Warnings,
+) -> Result<Tree<'r>, SquishError> {
+    let mut tree = tree;
+    for SharedTransformer(transformer) in &options.tree_transformers {
+        let Some(after) = transformer.transform(repo, &tree)? else {
+            continue;
+        };
+        if after != tree.id() {
+            warnings.push(SquishWarning::TreeTransformed {
+                transformer: transformer.name(),
+                before: tree.id(),
+                after,
+            });
+            tree = repo.find_tree(after)?;
+        }
+    }
+    Ok(tree)
+}
+
+/// The tree a squash of a branch rebased to `rebased_tree` must end with: that tree,
+/// followed through the transformations recorded in `warnings`.
+pub(crate) fn expected_tree(rebased_tree: Oid, warnings: &Warnings) -> Oid {
+    warnings
+        .iter()
+        .fold(rebased_tree, |expected, warning| match warning {
+            SquishWarning::TreeTransformed { before, after, .. } if *before == expected => *after,
+            _ => expected,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::topic_repo;
+
+    /// Writes `content` to `path` at the root of the tree.
+    struct WriteFile(&'static str, &'static str);
+
+    impl TreeTransformer for WriteFile {
+        fn name(&self) -> String {
+            format!("write {}", self.0)
+        }
+
+        fn transform(&self, repo: &Repository, tree: &Tree) -> Result<Option<Oid>, SquishError> {
+            let blob = repo.blob(self.1.as_bytes())?;
+            let mut builder = repo.treebuilder(Some(tree))?;
+            builder.insert(self.0, blob, 0o100644)?;
+            Ok(Some(builder.write()?))
+        }
+    }
+
+    /// Leaves every tree alone.
+    struct Unchanged;
+
+    impl TreeTransformer for Unchanged {
+        fn name(&self) -> String {
+            "nothing".to_string()
+        }
+
+        fn transform(&self, _repo: &Repository, _tree: &Tree) -> Result<Option<Oid>, SquishError> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_squash_with_tree_transformers() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+        let options = SquishOptions::new()
+            .tree_transformer(WriteFile("VERSION", "1.1.0\n"))
+            .tree_transformer(Unchanged)
+            .tree_transformer(WriteFile("Cargo.lock", "lock\n"))
+            .generated_path("Cargo.lock")
+            .verify(true)
+            .strict(true);
+
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let regenerate = repo.find_commit(result.new_id).unwrap();
+        let squash = regenerate.parent(0).unwrap();
+        let read = |commit: &git2::Commit, path: &str| {
+            let entry = commit.tree().unwrap().get_path(path.as_ref()).ok()?;
+            let blob = repo.find_blob(entry.id()).unwrap();
+            Some(String::from_utf8(blob.content().to_vec()).unwrap())
+        };
+        // The version bump goes into the squash commit, the generated lock file after it.
+        assert_eq!(read(&squash, "VERSION").as_deref(), Some("1.1.0\n"));
+        assert_eq!(read(&squash, "a.txt").as_deref(), Some("b\n"));
+        assert_eq!(read(&squash, "Cargo.lock"), None);
+        assert_eq!(read(&regenerate, "Cargo.lock").as_deref(), Some("lock\n"));
+
+        let transformers: Vec<&str> = result
+            .warnings
+            .iter()
+            .map(|warning| match warning {
+                SquishWarning::TreeTransformed { transformer, .. } => transformer.as_str(),
+                other => panic!("unexpected warning: {other}"),
+            })
+            .collect();
+        assert_eq!(transformers, ["write VERSION", "write Cargo.lock"]);
+    }
+
+    #[test]
+    fn test_expected_tree() {
+        let id = |n: u8| Oid::from_bytes(&[n; 20]).unwrap();
+        let mut warnings = Warnings::new();
+        assert_eq!(expected_tree(id(1), &warnings), id(1));
+        warnings.push(SquishWarning::TreeTransformed {
+            transformer: "first".to_string(),
+            before: id(1),
+            after: id(2),
+        });
+        warnings.push(SquishWarning::AutostashConflict);
+        warnings.push(SquishWarning::TreeTransformed {
+            transformer: "second".to_string(),
+            before: id(2),
+            after: id(3),
+        });
+        assert_eq!(expected_tree(id(1), &warnings), id(3));
+        assert_eq!(expected_tree(id(4), &warnings), id(4));
+    }
+}
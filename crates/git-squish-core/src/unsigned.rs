@@ -0,0 +1,220 @@
+use git2::{Oid, Repository};
+use std::path::PathBuf;
+
+use crate::lock::{BranchUpdate, SquishLock};
+use crate::provenance::record_squash;
+use crate::queue::rebase_onto_upstream;
+use crate::resquash::squash_message_and_author;
+use crate::state::SquishState;
+use crate::warning::Warnings;
+use crate::{
+    SQUASH_REFLOG_MESSAGE, SquashResult, SquishError, SquishOptions, SquishWarning, commit_buffer,
+    ensure_related_histories, filter, find_branch, find_upstream, generated, hooks,
+    normalize_branch_refname, protect, squashed_commits, transform, worktree,
+};
+
+/// A squash commit prepared for a signer outside of git, such as an HSM, a cloud KMS or
+/// Sigstore's gitsign, so org-managed keys never have to be on the machine doing the
+/// squish. Send `buffer` to the signer and pass the signature it returns to `finish`.
+///
+/// Nothing is written until `finish` is called. Only a single squash commit can be signed
+/// this way, so commit filters, `split_auto`, `split_markers`, `split_path` and generated
+/// paths are not supported.
+#[derive(Debug, Clone)]
+pub struct UnsignedSquash {
+    /// The branch to squash (e.g., "refs/heads/feature").
+    pub branch: String,
+    /// The upstream to squash onto, as given.
+    pub upstream: String,
+    /// Where the branch pointed when the commit was prepared.
+    pub branch_id: Oid,
+    /// The upstream tip the squash commit sits on.
+    pub upstream_id: Oid,
+    /// The raw commit object, byte for byte the content the signature must cover.
+    pub buffer: String,
+    warnings: Vec<SquishWarning>,
+    repo_path: PathBuf,
+    options: SquishOptions,
+}
+
+impl UnsignedSquash {
+    /// Squash `branch_refname` onto `upstream_spec` in memory and build the raw squash
+    /// commit, without writing it or moving any ref.
+    pub fn prepare(
+        repo: &Repository,
+        branch_refname: &str,
+        upstream_spec: &str,
+        options: &SquishOptions,
+    ) -> Result<Self, SquishError> {
+        if filter::is_filtering(options)
+            || options.split_auto
+            || options.split_markers
+            || !options.split_paths.is_empty()
+            || !generated::generated_patterns(repo, options)?.is_empty()
+        {
+            return Err(SquishError::Other {
+                message: "Only a single squash commit can be signed externally, commit filters, \
+                          --split-auto, --split-markers, --split and generated paths are not supported"
+                    .to_string(),
+            });
+        }
+        let branch_refname = normalize_branch_refname(repo, branch_refname)?;
+        if !options.allow_protected {
+            protect::ensure_not_protected(repo, &branch_refname)?;
+        }
+        ensure_related_histories(repo, &branch_refname, upstream_spec)?;
+        repo.signature()
+            .map_err(|source| SquishError::MissingIdentity { source })?;
+
+        let branch_id = find_branch(repo, &branch_refname)?.peel_to_commit()?.id();
+        let mut warnings = Warnings::new();
+        let (upstream_id, rebased_tip_id) =
+            rebase_onto_upstream(repo, &branch_refname, upstream_spec, options, &mut warnings)?;
+        warnings.check_strict(options)?;
+
+        let upstream = repo.find_commit(upstream_id)?;
+        let rebased_tip = repo.find_commit(rebased_tip_id)?;
+        let (message, author) = if rebased_tip_id != upstream_id {
+            squash_message_and_author(repo, &branch_refname, &upstream, &rebased_tip, options)?
+        } else if options.allow_empty {
+            let message = options
+                .custom_message()
+                .unwrap_or_else(|| format!("Empty squash of {branch_refname}\n"));
+            (message, None)
+        } else {
+            return Err(SquishError::NothingToSquash {
+                branch: branch_refname,
+            });
+        };
+        let tree = transform::transform_tree(repo, rebased_tip.tree()?, options, &mut warnings)?;
+        hooks::check_squashed_tree(repo, upstream_id, &tree, options)?;
+        let buffer = commit_buffer(repo, &message, &tree, &upstream, author.as_ref(), options)?;
+
+        Ok(UnsignedSquash {
+            branch: branch_refname,
+            upstream: upstream_spec.to_string(),
+            branch_id,
+            upstream_id,
+            buffer,
+            warnings: warnings.into_vec(),
+            repo_path: repo.workdir().unwrap_or(repo.path()).to_path_buf(),
+            options: options.clone(),
+        })
+    }
+
+    /// Write the commit with `signature` (an armored signature over `buffer`, stored in
+    /// the `gpgsig` header like git does for GPG, SSH and X.509 signatures) and point the
+    /// branch at it, bringing the worktree along if the branch is checked out.
+    ///
+    /// # Returns
+    /// The outcome of the squish, `UpstreamMoved` if the upstream moved since the commit
+    /// was prepared, or `PlanOutdated` if the branch did.
+    pub fn finish(&self, signature: &str) -> Result<SquashResult, SquishError> {
+        let repo = crate::open_repository(&self.repo_path)?;
+        let _lock = SquishLock::acquire(&repo)?;
+        if SquishState::exists(&repo) {
+            return Err(SquishError::Other {
+                message:
+                    "A squish is already in progress, run `git squish continue` or `git squish abort`"
+                        .to_string(),
+            });
+        }
+        let upstream_id = find_upstream(&repo, &self.upstream)?.peel_to_commit()?.id();
+        if upstream_id != self.upstream_id {
+            return Err(SquishError::UpstreamMoved {
+                upstream: self.upstream.clone(),
+                expected: self.upstream_id,
+                actual: upstream_id,
+            });
+        }
+        let branch_id = find_branch(&repo, &self.branch)?.peel_to_commit()?.id();
+        if branch_id != self.branch_id {
+            return Err(SquishError::PlanOutdated {
+                branch: self.branch.clone(),
+                upstream: self.upstream.clone(),
+            });
+        }
+        worktree::ensure_not_checked_out_elsewhere(&repo, &self.branch)?;
+
+        let new_id = repo.commit_signed(&self.buffer, signature, None)?;
+        let update = BranchUpdate::lock(&repo, &self.branch, self.branch_id)?;
+        worktree::checkout_if_current(&repo, &self.branch, new_id, self.options.force)?;
+        update.commit(new_id, SQUASH_REFLOG_MESSAGE)?;
+        record_squash(
+            &repo,
+            new_id,
+            &self.branch,
+            self.upstream_id,
+            self.branch_id,
+            &self.options,
+        )?;
+
+        Ok(SquashResult {
+            message: format!("✅ Successfully rebased and updated {}.", self.branch),
+            branch: self.branch.clone(),
+            old_id: self.branch_id,
+            new_id,
+            commits: squashed_commits(&repo, self.branch_id, self.upstream_id)?,
+            warnings: self.warnings.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo, topic_repo};
+
+    const SIGNATURE: &str =
+        "-----BEGIN SIGNED MESSAGE-----\nc2lnbmF0dXJl\n-----END SIGNED MESSAGE-----";
+
+    #[test]
+    fn test_prepare_then_finish_with_external_signature() {
+        let (repo_path, _temp_dir) = topic_repo().unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let options = SquishOptions::new().header("squish-version", "1");
+        let unsigned = UnsignedSquash::prepare(&repo, "topic", "main", &options).unwrap();
+        assert_eq!(unsigned.branch, "refs/heads/topic");
+        assert!(unsigned.buffer.starts_with("tree "));
+        assert!(unsigned.buffer.contains("\nsquish-version 1\n\nAdd a\n"));
+        // Nothing is written before the signature comes back.
+        assert_eq!(
+            repo.refname_to_id("refs/heads/topic").unwrap(),
+            unsigned.branch_id
+        );
+
+        let result = unsigned.finish(SIGNATURE).unwrap();
+        assert_eq!(result.commits.len(), 2);
+        assert_eq!(
+            repo.refname_to_id("refs/heads/topic").unwrap(),
+            result.new_id
+        );
+        let (signature, signed_data) = repo.extract_signature(&result.new_id, None).unwrap();
+        assert_eq!(signature.as_str(), Some(SIGNATURE));
+        assert_eq!(signed_data.as_str(), Some(unsigned.buffer.as_str()));
+        let commit = repo.find_commit(result.new_id).unwrap();
+        assert_eq!(commit.parent_id(0).unwrap(), unsigned.upstream_id);
+
+        // A branch which moved in the meantime is not overwritten.
+        commit_file(&repo_path, "c.txt", "c\n", "Add c").unwrap();
+        let unsigned = UnsignedSquash::prepare(&repo, "topic", "main", &options).unwrap();
+        commit_file(&repo_path, "d.txt", "d\n", "Add d").unwrap();
+        assert!(matches!(
+            unsigned.finish(SIGNATURE),
+            Err(SquishError::PlanOutdated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_prepare_rejects_multiple_commits() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Add a").unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let options = SquishOptions::new().split_auto(true);
+        assert!(UnsignedSquash::prepare(&repo, "topic", "main", &options).is_err());
+    }
+}
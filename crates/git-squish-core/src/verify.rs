@@ -0,0 +1,122 @@
+use git2::{Oid, Repository};
+
+use crate::transform::expected_tree;
+use crate::warning::Warnings;
+use crate::{SquishError, SquishOptions};
+
+/// Check that squashing kept the branch's content: the tree of `squash_id`, the last commit
+/// the squash wrote, must be the tree of `rebased_tip_id`, the branch's commits replayed
+/// onto the upstream. However the commits were grouped, split or reordered, the result
+/// has to add up to the same tree.
+///
+/// # Arguments
+/// * `repo` - The repository holding both commits
+/// * `squash_id` - The squash commit (the last one, when the squash wrote several)
+/// * `rebased_tip_id` - The tip of the branch rebased onto the upstream
+///
+/// # Returns
+/// `SquishError::TreeMismatch` if the trees differ.
+pub fn verify_squash(
+    repo: &Repository,
+    squash_id: Oid,
+    rebased_tip_id: Oid,
+) -> Result<(), SquishError> {
+    let expected = repo.find_commit(rebased_tip_id)?.tree_id();
+    check_tree(repo, squash_id, expected)
+}
+
+/// Check a squash like `verify_squash`, expecting the changes of the tree transformers
+/// recorded in `warnings` on top of the rebased branch.
+pub(crate) fn verify_transformed(
+    repo: &Repository,
+    squash_id: Oid,
+    rebased_tip_id: Oid,
+    warnings: &Warnings,
+) -> Result<(), SquishError> {
+    let rebased_tree = repo.find_commit(rebased_tip_id)?.tree_id();
+    check_tree(repo, squash_id, expected_tree(rebased_tree, warnings))
+}
+
+/// Fail with `TreeMismatch` unless `squash_id` has the tree `expected`.
+fn check_tree(repo: &Repository, squash_id: Oid, expected: Oid) -> Result<(), SquishError> {
+    let actual = repo.find_commit(squash_id)?.tree_id();
+    if expected != actual {
+        return Err(SquishError::TreeMismatch {
+            commit: squash_id,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Fail up front when `SquishOptions::verify` is combined with options which drop changes
+/// on purpose, so the squash cannot match the rebased branch.
+pub(crate) fn check_verifiable(options: &SquishOptions) -> Result<(), SquishError> {
+    if options.verify
+        && (!options.skip_commits.is_empty() || !options.skip_subject_patterns.is_empty())
+    {
+        return Err(SquishError::Other {
+            message: "Cannot verify a squash which skips commits, since their changes are dropped"
+                .to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squash_branch_with_options;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+
+    #[test]
+    fn test_verify_squash() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "1\n", "Add a").unwrap();
+        let tip = commit_file(&repo_path, "b.txt", "2\n", "Add b").unwrap();
+
+        let options = SquishOptions::new().verify(true).generated_path("b.txt");
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        verify_squash(&repo, result.new_id, tip).unwrap();
+        let squash_id = repo
+            .find_commit(result.new_id)
+            .unwrap()
+            .parent_id(0)
+            .unwrap();
+        match verify_squash(&repo, squash_id, tip).unwrap_err() {
+            SquishError::TreeMismatch {
+                commit,
+                expected,
+                actual,
+            } => {
+                assert_eq!(commit, squash_id);
+                assert_eq!(expected, repo.find_commit(tip).unwrap().tree_id());
+                assert_eq!(actual, repo.find_commit(squash_id).unwrap().tree_id());
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_skipped_commits() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "1\n", "Add a").unwrap();
+        let tip = commit_file(&repo_path, "b.txt", "2\n", "wip b").unwrap();
+
+        let options = SquishOptions::new()
+            .verify(true)
+            .skip_subject_pattern("^wip");
+        let error = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap_err();
+
+        assert!(error.to_string().starts_with("Cannot verify"), "{error}");
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), tip);
+    }
+}
@@ -0,0 +1,366 @@
+use git2::{ObjectType, Oid, Repository, Tree};
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{SquishError, TreeTransformer};
+
+/// Files whose version is bumped unless others are configured.
+const DEFAULT_VERSION_FILES: [&str; 3] = ["Cargo.toml", "package.json", "VERSION"];
+/// Multi-valued config key holding the files to bump the version in (e.g.
+/// `crates/cli/Cargo.toml`), replacing the defaults.
+const VERSION_FILES_CONFIG: &str = "squish.versionFile";
+
+/// Which part of a `MAJOR.MINOR.PATCH` version `VersionBump` increments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BumpLevel {
+    /// 1.2.3 -> 1.2.4
+    Patch,
+    /// 1.2.3 -> 1.3.0
+    Minor,
+    /// 1.2.3 -> 2.0.0
+    Major,
+}
+
+impl BumpLevel {
+    /// The name used for this level on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BumpLevel::Patch => "patch",
+            BumpLevel::Minor => "minor",
+            BumpLevel::Major => "major",
+        }
+    }
+
+    /// Bump `version`, keeping a leading "v". Pre-release and build suffixes are dropped.
+    ///
+    /// # Returns
+    /// The bumped version, or None if `version` is not `MAJOR.MINOR.PATCH`.
+    pub fn bump(self, version: &str) -> Option<String> {
+        let (prefix, rest) = match version.strip_prefix('v') {
+            Some(rest) => ("v", rest),
+            None => ("", version),
+        };
+        let core = rest.split(['-', '+']).next()?;
+        let parts = core
+            .split('.')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [major, minor, patch] = parts[..] else {
+            return None;
+        };
+        let (major, minor, patch) = match self {
+            BumpLevel::Patch => (major, minor, patch + 1),
+            BumpLevel::Minor => (major, minor + 1, 0),
+            BumpLevel::Major => (major + 1, 0, 0),
+        };
+        Some(format!("{prefix}{major}.{minor}.{patch}"))
+    }
+}
+
+impl FromStr for BumpLevel {
+    type Err = SquishError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "patch" => Ok(BumpLevel::Patch),
+            "minor" => Ok(BumpLevel::Minor),
+            "major" => Ok(BumpLevel::Major),
+            _ => Err(SquishError::Other {
+                message: format!("Unknown bump level '{s}', expected patch, minor or major"),
+            }),
+        }
+    }
+}
+
+/// A `TreeTransformer` bumping the version in the squashed tree, so the squash commit
+/// carries its own version bump.
+///
+/// The version is updated in each of the files which exist: `Cargo.toml` (the
+/// `[package]` or `[workspace.package]` version), `package.json` (the top-level
+/// `"version"`) and `VERSION` (the whole file) at the root, unless other files are given
+/// with `file` or the multi-valued `squish.versionFile` config. A file is recognized by
+/// its name, so `crates/cli/Cargo.toml` works too. Lock files are left alone. Fails if
+/// none of the files has a version.
+///
+/// # Example
+/// ```
+/// use git_squish_core::{BumpLevel, SquishOptions, VersionBump};
+///
+/// let options = SquishOptions::new().tree_transformer(VersionBump::new(BumpLevel::Minor));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VersionBump {
+    level: BumpLevel,
+    files: Vec<String>,
+}
+
+impl VersionBump {
+    /// Bump the version by `level` in the configured (or default) files.
+    pub fn new(level: BumpLevel) -> Self {
+        Self {
+            level,
+            files: Vec::new(),
+        }
+    }
+
+    /// Bump the version in `path` (relative to the repository root) instead of the
+    /// configured or default files. May be called repeatedly.
+    pub fn file(mut self, path: impl Into<String>) -> Self {
+        self.files.push(path.into());
+        self
+    }
+
+    /// The files to bump: the ones given, else `squish.versionFile`, else the defaults.
+    fn version_files(&self, repo: &Repository) -> Result<Vec<String>, SquishError> {
+        if !self.files.is_empty() {
+            return Ok(self.files.clone());
+        }
+        let mut files = Vec::new();
+        let config = repo.config()?;
+        if let Ok(entries) = config.multivar(VERSION_FILES_CONFIG, None) {
+            entries.for_each(|entry| {
+                if let Some(value) = entry.value() {
+                    files.push(value.to_string());
+                }
+            })?;
+        }
+        if files.is_empty() {
+            files = DEFAULT_VERSION_FILES.map(str::to_string).to_vec();
+        }
+        Ok(files)
+    }
+}
+
+impl TreeTransformer for VersionBump {
+    fn name(&self) -> String {
+        format!("{} version bump", self.level.as_str())
+    }
+
+    fn transform(&self, repo: &Repository, tree: &Tree) -> Result<Option<Oid>, SquishError> {
+        let files = self.version_files(repo)?;
+        let mut new_tree = tree.id();
+        let mut bumped = false;
+        for file in &files {
+            let Ok(entry) = tree.get_path(Path::new(file)) else {
+                continue;
+            };
+            if entry.kind() != Some(ObjectType::Blob) {
+                continue;
+            }
+            let blob = repo.find_blob(entry.id())?;
+            let Ok(content) = std::str::from_utf8(blob.content()) else {
+                continue;
+            };
+            let Some(content) = bump_file(file, content, self.level)? else {
+                continue;
+            };
+            let blob_id = repo.blob(content.as_bytes())?;
+            let components: Vec<&str> = file.split('/').filter(|c| !c.is_empty()).collect();
+            new_tree = replace_blob(
+                repo,
+                &repo.find_tree(new_tree)?,
+                &components,
+                blob_id,
+                entry.filemode(),
+            )?;
+            bumped = true;
+        }
+        if !bumped {
+            return Err(SquishError::Other {
+                message: format!("No version found to bump in {}", files.join(", ")),
+            });
+        }
+        Ok(Some(new_tree))
+    }
+}
+
+/// Bump the version in the content of `file`, by the kind of file its name says it is.
+///
+/// # Returns
+/// The new content, or None if the file has no version.
+fn bump_file(file: &str, content: &str, level: BumpLevel) -> Result<Option<String>, SquishError> {
+    let name = file.rsplit('/').next().unwrap_or(file);
+    let bump = |version: &str| {
+        level.bump(version).ok_or_else(|| SquishError::Other {
+            message: format!("Cannot bump '{version}' in {file}, expected MAJOR.MINOR.PATCH"),
+        })
+    };
+    match name {
+        "Cargo.toml" => {
+            let pattern = Regex::new(r#"^(\s*version\s*=\s*")([^"]*)(".*)$"#).unwrap();
+            let mut section = String::new();
+            let mut lines: Vec<String> = Vec::new();
+            let mut found = false;
+            for line in content.split_inclusive('\n') {
+                let trimmed = line.trim();
+                if trimmed.starts_with('[') {
+                    section = trimmed.trim_matches(['[', ']']).trim().to_string();
+                }
+                let in_package = section == "package" || section == "workspace.package";
+                match pattern.captures(line.trim_end_matches(['\r', '\n'])) {
+                    Some(captures) if in_package && !found => {
+                        found = true;
+                        let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+                        lines.push(format!(
+                            "{}{}{}{ending}",
+                            &captures[1],
+                            bump(&captures[2])?,
+                            &captures[3]
+                        ));
+                    }
+                    _ => lines.push(line.to_string()),
+                }
+            }
+            Ok(found.then(|| lines.concat()))
+        }
+        "package.json" => {
+            let pattern = Regex::new(r#""version"\s*:\s*"([^"]*)""#).unwrap();
+            let Some(version) = pattern.captures(content).and_then(|c| c.get(1)) else {
+                return Ok(None);
+            };
+            Ok(Some(format!(
+                "{}{}{}",
+                &content[..version.start()],
+                bump(version.as_str())?,
+                &content[version.end()..]
+            )))
+        }
+        _ => {
+            let version = content.trim();
+            if version.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(content.replacen(version, &bump(version)?, 1)))
+        }
+    }
+}
+
+/// Write a copy of `tree` with the blob at `path` (split into its components) replaced
+/// by `blob_id`, returning the new tree's id.
+fn replace_blob(
+    repo: &Repository,
+    tree: &Tree,
+    path: &[&str],
+    blob_id: Oid,
+    filemode: i32,
+) -> Result<Oid, SquishError> {
+    let mut builder = repo.treebuilder(Some(tree))?;
+    match path {
+        [name] => {
+            builder.insert(name, blob_id, filemode)?;
+        }
+        [dir, rest @ ..] => {
+            let subtree = tree
+                .get_name(dir)
+                .and_then(|entry| repo.find_tree(entry.id()).ok())
+                .ok_or_else(|| SquishError::Other {
+                    message: format!("{dir} is not a directory"),
+                })?;
+            let subtree_id = replace_blob(repo, &subtree, rest, blob_id, filemode)?;
+            builder.insert(dir, subtree_id, 0o040000)?;
+        }
+        [] => unreachable!("the path of a file found in the tree has a name"),
+    }
+    Ok(builder.write()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+    use crate::{SquishOptions, squash_branch_with_options};
+
+    #[test]
+    fn test_bump_level() {
+        assert_eq!(BumpLevel::Patch.bump("1.2.3").as_deref(), Some("1.2.4"));
+        assert_eq!(BumpLevel::Minor.bump("1.2.3").as_deref(), Some("1.3.0"));
+        assert_eq!(BumpLevel::Major.bump("v1.2.3").as_deref(), Some("v2.0.0"));
+        assert_eq!(
+            BumpLevel::Patch.bump("1.2.3-rc.1+b5").as_deref(),
+            Some("1.2.4")
+        );
+        assert_eq!(BumpLevel::Patch.bump("1.2"), None);
+        assert_eq!(BumpLevel::Patch.bump("one.two.three"), None);
+        assert_eq!("minor".parse::<BumpLevel>().unwrap(), BumpLevel::Minor);
+        assert!("huge".parse::<BumpLevel>().is_err());
+    }
+
+    #[test]
+    fn test_bump_file() {
+        let cargo = "[package]\nname = \"demo\"\nversion = \"0.1.4\" # keep\n\n[dependencies]\nfoo = { version = \"1.0.0\" }\n";
+        assert_eq!(
+            bump_file("Cargo.toml", cargo, BumpLevel::Minor)
+                .unwrap()
+                .unwrap(),
+            "[package]\nname = \"demo\"\nversion = \"0.2.0\" # keep\n\n[dependencies]\nfoo = { version = \"1.0.0\" }\n"
+        );
+        let workspace_member = "[package]\nname = \"demo\"\nversion.workspace = true\n";
+        assert_eq!(
+            bump_file("crates/demo/Cargo.toml", workspace_member, BumpLevel::Patch).unwrap(),
+            None
+        );
+        let package =
+            "{\n  \"name\": \"demo\",\n  \"version\": \"2.3.4\",\n  \"dependencies\": {}\n}\n";
+        assert_eq!(
+            bump_file("web/package.json", package, BumpLevel::Major)
+                .unwrap()
+                .unwrap(),
+            "{\n  \"name\": \"demo\",\n  \"version\": \"3.0.0\",\n  \"dependencies\": {}\n}\n"
+        );
+        assert_eq!(
+            bump_file("VERSION", "v1.0.9\n", BumpLevel::Patch)
+                .unwrap()
+                .unwrap(),
+            "v1.0.10\n"
+        );
+        assert!(bump_file("VERSION", "latest\n", BumpLevel::Patch).is_err());
+    }
+
+    #[test]
+    fn test_squash_with_version_bump() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "VERSION", "1.4.2\n", "Initial commit").unwrap();
+        commit_file(
+            &repo_path,
+            "web/package.json",
+            "{\"version\": \"1.4.2\"}\n",
+            "Add web",
+        )
+        .unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "a.txt", "1\n", "Add a").unwrap();
+        commit_file(&repo_path, "a.txt", "2\n", "Fix a").unwrap();
+
+        let options = SquishOptions::new()
+            .tree_transformer(
+                VersionBump::new(BumpLevel::Minor)
+                    .file("VERSION")
+                    .file("web/package.json")
+                    .file("Cargo.toml"),
+            )
+            .verify(true);
+        let result = squash_branch_with_options(&repo_path, "topic", "main", &options).unwrap();
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let tree = repo.find_commit(result.new_id).unwrap().tree().unwrap();
+        let read = |path: &str| {
+            let entry = tree.get_path(Path::new(path)).unwrap();
+            let blob = repo.find_blob(entry.id()).unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        };
+        assert_eq!(read("VERSION"), "1.5.0\n");
+        assert_eq!(read("web/package.json"), "{\"version\": \"1.5.0\"}\n");
+        assert_eq!(read("a.txt"), "2\n");
+
+        // Without any version to bump the squash fails, leaving the branch alone.
+        create_branch(&repo_path, "other").unwrap();
+        commit_file(&repo_path, "b.txt", "1\n", "Add b").unwrap();
+        let tip = commit_file(&repo_path, "b.txt", "2\n", "Fix b").unwrap();
+        let options = SquishOptions::new()
+            .tree_transformer(VersionBump::new(BumpLevel::Patch).file("Cargo.toml"));
+        let error = squash_branch_with_options(&repo_path, "other", "main", &options).unwrap_err();
+        assert_eq!(error.to_string(), "No version found to bump in Cargo.toml");
+        assert_eq!(repo.refname_to_id("refs/heads/other").unwrap(), tip);
+    }
+}
@@ -0,0 +1,248 @@
+use git2::Oid;
+use std::fmt;
+
+use crate::{SquishError, SquishOptions};
+
+/// A non-fatal issue encountered while squishing. With `SquishOptions::strict` these
+/// become errors instead.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SquishWarning {
+    /// The squish went ahead (with `force`) although the worktree had uncommitted changes
+    UncommittedChanges {
+        /// Paths with uncommitted changes
+        files: Vec<String>,
+    },
+    /// A commit became empty on top of the upstream and was dropped, like `git rebase` does
+    EmptyCommitDropped {
+        /// The original commit which was dropped
+        commit: Oid,
+        /// Its subject
+        summary: String,
+    },
+    /// A commit was skipped because the upstream already has a commit making the same
+    /// changes (e.g. it was cherry-picked), like `git rebase` does
+    AlreadyApplied {
+        /// The original commit which was skipped
+        commit: Oid,
+        /// Its subject
+        summary: String,
+        /// The upstream commit with the same changes
+        upstream_commit: Oid,
+    },
+    /// The autostashed changes conflicted with the squashed branch and were left in the stash
+    AutostashConflict,
+    /// The autostash disappeared from the stash list before it could be re-applied
+    AutostashMissing {
+        /// The stash commit which was not found
+        stash: Oid,
+    },
+    /// `commit.gpgsign` is set but the signing key could not be loaded, so the commit was
+    /// written unsigned
+    SigningSkipped {
+        /// Why signing was not possible
+        reason: String,
+    },
+    /// A `TreeTransformer` changed the squashed tree. This only records what was done, so
+    /// it does not fail a `strict` squish
+    TreeTransformed {
+        /// The transformer's name
+        transformer: String,
+        /// The tree it was given
+        before: Oid,
+        /// The tree it returned
+        after: Oid,
+    },
+    /// A commit's signature did not verify, and `verify_signatures` only asked to warn
+    UnverifiedSignature {
+        /// The commit, the upstream tip or one of the branch's
+        commit: Oid,
+        /// Its subject
+        summary: String,
+        /// What is wrong with its signature (e.g. "it is not signed")
+        reason: String,
+    },
+    /// A git hook run after the squish (e.g. "post-squish") failed. The squish itself is
+    /// already done
+    HookFailed {
+        /// The hook
+        hook: String,
+        /// What the hook printed
+        output: String,
+    },
+}
+
+impl fmt::Display for SquishWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SquishWarning::UncommittedChanges { files } => {
+                write!(
+                    f,
+                    "Squished with uncommitted changes in: {}",
+                    files.join(", ")
+                )
+            }
+            SquishWarning::EmptyCommitDropped { commit, summary } => write!(
+                f,
+                "Dropped {commit:.7} ({summary}), its changes are already on the upstream"
+            ),
+            SquishWarning::AlreadyApplied {
+                commit,
+                summary,
+                upstream_commit,
+            } => write!(
+                f,
+                "Skipped {commit:.7} ({summary}), it was already applied upstream as {upstream_commit:.7}"
+            ),
+            SquishWarning::AutostashConflict => write!(
+                f,
+                "Applying autostash resulted in conflicts.\nYour changes are safe in the stash.\nYou can run \"git stash pop\" or \"git stash drop\" at any time."
+            ),
+            SquishWarning::AutostashMissing { stash } => write!(
+                f,
+                "The autostash {stash:.7} is no longer in the stash list and was not applied."
+            ),
+            SquishWarning::SigningSkipped { reason } => {
+                write!(f, "The squashed commit was not signed: {reason}")
+            }
+            SquishWarning::TreeTransformed {
+                transformer,
+                before,
+                after,
+            } => write!(
+                f,
+                "Transformed the squashed tree: {transformer} ({before:.7} -> {after:.7})"
+            ),
+            SquishWarning::UnverifiedSignature {
+                commit,
+                summary,
+                reason,
+            } => write!(
+                f,
+                "The signature of {commit:.7} ({summary}) did not verify: {reason}"
+            ),
+            SquishWarning::HookFailed { hook, output } if output.is_empty() => {
+                write!(f, "The {hook} hook failed")
+            }
+            SquishWarning::HookFailed { hook, output } => {
+                write!(f, "The {hook} hook failed:\n{output}")
+            }
+        }
+    }
+}
+
+/// Outcome of a successful squish.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SquashResult {
+    /// What was done, e.g. "✅ Successfully rebased and updated refs/heads/feature."
+    pub message: String,
+    /// The branch which was squished (e.g., "refs/heads/feature").
+    pub branch: String,
+    /// Where the branch pointed before the squish.
+    pub old_id: Oid,
+    /// The squashed commit the branch points at now.
+    pub new_id: Oid,
+    /// The original commits which were squashed, oldest first.
+    pub commits: Vec<Oid>,
+    /// Non-fatal issues encountered along the way.
+    pub warnings: Vec<SquishWarning>,
+}
+
+impl fmt::Display for SquashResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for warning in &self.warnings {
+            write!(f, "\n⚠️ {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the warnings raised while squishing. Each step of the pipeline is handed the
+/// same accumulator, so issues it works around end up in the `SquashResult` (or fail the
+/// squish with `strict`) instead of going unreported.
+#[derive(Debug, Default)]
+pub(crate) struct Warnings(Vec<SquishWarning>);
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `warning`, unless the same issue was already reported.
+    pub fn push(&mut self, warning: SquishWarning) {
+        if !self.0.contains(&warning) {
+            self.0.push(warning);
+        }
+    }
+
+    /// Fail with `SquishError::Strict` if strict mode is on and anything was warned about.
+    pub fn check_strict(&self, options: &SquishOptions) -> Result<(), SquishError> {
+        let warnings: Vec<SquishWarning> = self
+            .0
+            .iter()
+            .filter(|warning| !matches!(warning, SquishWarning::TreeTransformed { .. }))
+            .cloned()
+            .collect();
+        if options.strict && !warnings.is_empty() {
+            return Err(SquishError::Strict { warnings });
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SquishWarning> {
+        self.0.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<SquishWarning> {
+        self.0
+    }
+}
+
+impl Extend<SquishWarning> for Warnings {
+    fn extend<I: IntoIterator<Item = SquishWarning>>(&mut self, iter: I) {
+        for warning in iter {
+            self.push(warning);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_squash_result_display() {
+        let result = SquashResult {
+            message: "✅ Done.".to_string(),
+            branch: "refs/heads/topic".to_string(),
+            old_id: Oid::zero(),
+            new_id: Oid::zero(),
+            commits: Vec::new(),
+            warnings: vec![SquishWarning::UncommittedChanges {
+                files: vec!["a.txt".to_string(), "b.txt".to_string()],
+            }],
+        };
+        assert_eq!(
+            result.to_string(),
+            "✅ Done.\n⚠️ Squished with uncommitted changes in: a.txt, b.txt"
+        );
+    }
+
+    #[test]
+    fn test_check_strict() {
+        let mut warnings = Warnings::new();
+        let strict = SquishOptions::new().strict(true);
+        assert!(warnings.check_strict(&strict).is_ok());
+
+        warnings.push(SquishWarning::AutostashConflict);
+        warnings.push(SquishWarning::AutostashConflict);
+        assert!(warnings.check_strict(&SquishOptions::new()).is_ok());
+        assert!(matches!(
+            warnings.check_strict(&strict),
+            Err(SquishError::Strict { .. })
+        ));
+        assert_eq!(warnings.into_vec(), vec![SquishWarning::AutostashConflict]);
+    }
+}
@@ -0,0 +1,136 @@
+use git2::Oid;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    SquashResult, SquishError, SquishOptions, find_upstream, normalize_branch_refname,
+    open_repository, squash_branch_with_options,
+};
+
+/// Watches an upstream and re-squashes a branch onto it whenever the upstream moves.
+///
+/// The watcher does not fetch; point it at a local branch or at a remote-tracking branch
+/// which something else keeps up to date.
+///
+/// # Example
+/// ```no_run
+/// use git_squish_core::{SquishOptions, UpstreamWatcher};
+///
+/// let mut watcher = UpstreamWatcher::new(
+///     ".",
+///     "refs/heads/integration".to_string(),
+///     "origin/main".to_string(),
+///     SquishOptions::new(),
+/// )?;
+/// loop {
+///     if let Some(message) = watcher.poll()? {
+///         println!("{message}");
+///     }
+///     std::thread::sleep(std::time::Duration::from_secs(5));
+/// }
+/// # Ok::<(), git_squish_core::SquishError>(())
+/// ```
+#[derive(Debug)]
+pub struct UpstreamWatcher {
+    repo_path: PathBuf,
+    branch_refname: String,
+    upstream_spec: String,
+    options: SquishOptions,
+    upstream_id: Oid,
+}
+
+impl UpstreamWatcher {
+    /// Start watching `upstream_spec`, remembering where it currently points.
+    ///
+    /// # Arguments
+    /// * `repo_path` - Path to the git repository
+    /// * `branch_refname` - The branch to keep squashed (e.g., "feature" or "refs/heads/feature")
+    /// * `upstream_spec` - The upstream to watch (e.g., "main" or "origin/main")
+    /// * `options` - Options used for every squash
+    pub fn new(
+        repo_path: impl AsRef<Path>,
+        branch_refname: impl AsRef<str>,
+        upstream_spec: impl AsRef<str>,
+        options: SquishOptions,
+    ) -> Result<Self, SquishError> {
+        let repo_path = repo_path.as_ref().to_path_buf();
+        let repo = open_repository(&repo_path)?;
+        let branch_refname = normalize_branch_refname(&repo, branch_refname.as_ref())?;
+        let upstream_spec = upstream_spec.as_ref().to_string();
+        let upstream_id = find_upstream(&repo, &upstream_spec)?.id();
+        Ok(Self {
+            repo_path,
+            branch_refname,
+            upstream_spec,
+            options,
+            upstream_id,
+        })
+    }
+
+    /// The upstream commit the branch was last squashed onto (or the upstream when
+    /// watching started).
+    pub fn upstream_id(&self) -> Oid {
+        self.upstream_id
+    }
+
+    /// Check the upstream once and re-squash the branch if it moved.
+    ///
+    /// A failed squish is not retried until the upstream moves again, so a conflict is
+    /// reported once rather than on every poll.
+    ///
+    /// # Returns
+    /// The squash outcome if the branch was re-squashed, None if the upstream has not moved,
+    /// or the SquishError from the squash.
+    pub fn poll(&mut self) -> Result<Option<SquashResult>, SquishError> {
+        let repo = open_repository(&self.repo_path)?;
+        let upstream_id = find_upstream(&repo, &self.upstream_spec)?.id();
+        if upstream_id == self.upstream_id {
+            return Ok(None);
+        }
+        self.upstream_id = upstream_id;
+
+        squash_branch_with_options(
+            &self.repo_path,
+            self.branch_refname.clone(),
+            self.upstream_spec.clone(),
+            &self.options,
+        )
+        .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{change_to_branch, commit_file, create_branch, init_test_repo};
+    use git2::Repository;
+
+    #[test]
+    fn test_watcher_resquashes_when_upstream_moves() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "base.txt", "base\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        commit_file(&repo_path, "topic.txt", "1\n", "Topic 1").unwrap();
+        commit_file(&repo_path, "topic.txt", "2\n", "Topic 2").unwrap();
+        change_to_branch(&repo_path, "main").unwrap();
+
+        let mut watcher =
+            UpstreamWatcher::new(&repo_path, "refs/heads/topic", "main", SquishOptions::new())
+                .unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let topic_tip = repo.refname_to_id("refs/heads/topic").unwrap();
+
+        // Nothing happens until the upstream moves.
+        assert_eq!(watcher.poll().unwrap(), None);
+        assert_eq!(repo.refname_to_id("refs/heads/topic").unwrap(), topic_tip);
+
+        let main_tip = commit_file(&repo_path, "main.txt", "main\n", "Main change").unwrap();
+        assert!(watcher.poll().unwrap().is_some());
+        assert_eq!(watcher.upstream_id(), main_tip);
+
+        let squashed = repo
+            .find_commit(repo.refname_to_id("refs/heads/topic").unwrap())
+            .unwrap();
+        assert_eq!(squashed.parent_ids().collect::<Vec<_>>(), vec![main_tip]);
+        assert_eq!(watcher.poll().unwrap(), None);
+    }
+}
@@ -0,0 +1,192 @@
+use git2::{ErrorCode, Repository, Status, StatusOptions, build::CheckoutBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::SquishError;
+
+/// A worktree whose HEAD has a given branch checked out.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BranchCheckout {
+    /// The worktree's git directory (`.git` or `.git/worktrees/<name>`).
+    pub git_dir: PathBuf,
+    /// The worktree's working directory.
+    pub workdir: PathBuf,
+    /// Whether this is the worktree the squish was started from.
+    pub is_current: bool,
+}
+
+/// Find every worktree (the main one and all linked ones) which has `branch_refname`
+/// checked out.
+///
+/// `Repository::head` only reflects the worktree the repository was opened from, so a
+/// branch checked out in a linked worktree is invisible to it. This consults the HEAD of
+/// each worktree instead.
+pub(crate) fn find_branch_checkouts(
+    repo: &Repository,
+    branch_refname: &str,
+) -> Result<Vec<BranchCheckout>, SquishError> {
+    let mut worktree_repos = vec![Repository::open(common_dir(repo)?)?];
+    for name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(name)?;
+        // Skip worktrees whose directory has been removed without being pruned.
+        if worktree.validate().is_err() {
+            continue;
+        }
+        worktree_repos.push(Repository::open_from_worktree(&worktree)?);
+    }
+
+    let current_git_dir = canonical(repo.path());
+    let mut checkouts = Vec::new();
+    for worktree_repo in worktree_repos {
+        let Some(workdir) = worktree_repo.workdir() else {
+            // A bare repository's HEAD is not a checkout.
+            continue;
+        };
+        let head = worktree_repo.find_reference("HEAD")?;
+        if head.symbolic_target() == Some(branch_refname) {
+            checkouts.push(BranchCheckout {
+                git_dir: worktree_repo.path().to_path_buf(),
+                workdir: workdir.to_path_buf(),
+                is_current: canonical(worktree_repo.path()) == current_git_dir,
+            });
+        }
+    }
+    Ok(checkouts)
+}
+
+/// Fail if `branch_refname` is checked out in a worktree other than the current one,
+/// matching `git rebase`'s refusal to rewrite a branch out from under another worktree.
+pub(crate) fn ensure_not_checked_out_elsewhere(
+    repo: &Repository,
+    branch_refname: &str,
+) -> Result<(), SquishError> {
+    let checkouts = find_branch_checkouts(repo, branch_refname)?;
+    if let Some(other) = checkouts.iter().find(|checkout| !checkout.is_current) {
+        return Err(SquishError::Other {
+            message: format!(
+                "{branch_refname} is checked out in another worktree at {}",
+                other.workdir.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Update the current worktree's index and files to `commit_id` if it has `branch_refname`
+/// checked out. This must run before the branch ref moves so the checkout can tell local
+/// modifications apart from the rewrite.
+///
+/// Files with local modifications are never overwritten: the checkout fails with
+/// `DirtyWorktree`, or with `keep_local_changes` those files are left as they are.
+pub(crate) fn checkout_if_current(
+    repo: &Repository,
+    branch_refname: &str,
+    commit_id: git2::Oid,
+    keep_local_changes: bool,
+) -> Result<bool, SquishError> {
+    let checkouts = find_branch_checkouts(repo, branch_refname)?;
+    if !checkouts.iter().any(|checkout| checkout.is_current) {
+        return Ok(false);
+    }
+    let commit = repo.find_commit(commit_id)?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe().allow_conflicts(keep_local_changes);
+    match repo.checkout_tree(commit.as_object(), Some(&mut checkout)) {
+        Ok(()) => Ok(true),
+        // A safe checkout refuses to overwrite local modifications.
+        Err(e) if e.code() == ErrorCode::Conflict => Err(SquishError::DirtyWorktree {
+            files: dirty_files(repo)?,
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Paths with uncommitted changes in the index or worktree, ignoring untracked files.
+pub(crate) fn dirty_files(repo: &Repository) -> Result<Vec<String>, SquishError> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut options))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect())
+}
+
+/// The git directory shared by all worktrees of the repository.
+pub(crate) fn common_dir(repo: &Repository) -> Result<PathBuf, SquishError> {
+    if !repo.is_worktree() {
+        return Ok(repo.path().to_path_buf());
+    }
+    let commondir_file = repo.path().join("commondir");
+    let commondir = fs::read_to_string(&commondir_file).map_err(|source| SquishError::Io {
+        message: format!("Failed to read {}", commondir_file.display()),
+        source,
+    })?;
+    Ok(repo.path().join(commondir.trim()))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{commit_file, create_branch, init_test_repo};
+    use git2::WorktreeAddOptions;
+
+    /// Create a linked worktree named `name` with `branch` checked out.
+    fn add_worktree(repo: &Repository, name: &str, branch: &str, path: &Path) -> Repository {
+        let branch_ref = repo
+            .find_reference(&format!("refs/heads/{branch}"))
+            .unwrap();
+        let mut opts = WorktreeAddOptions::new();
+        opts.reference(Some(&branch_ref));
+        let worktree = repo.worktree(name, path, Some(&opts)).unwrap();
+        Repository::open_from_worktree(&worktree).unwrap()
+    }
+
+    #[test]
+    fn test_find_branch_checkouts_main_worktree() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let checkouts = find_branch_checkouts(&repo, "refs/heads/main").unwrap();
+        assert_eq!(checkouts.len(), 1);
+        assert!(checkouts[0].is_current);
+
+        assert!(
+            find_branch_checkouts(&repo, "refs/heads/other")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_find_branch_checkouts_linked_worktree() {
+        let (repo_path, _temp_dir) = init_test_repo().unwrap();
+        commit_file(&repo_path, "a.txt", "a\n", "Initial commit").unwrap();
+        create_branch(&repo_path, "topic").unwrap();
+        crate::test_utils::change_to_branch(&repo_path, "main").unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+
+        let worktree_dir = tempfile::tempdir().unwrap();
+        let worktree_path = worktree_dir.path().join("topic-wt");
+        let worktree_repo = add_worktree(&repo, "topic-wt", "topic", &worktree_path);
+
+        // Seen from the main worktree, topic is checked out elsewhere.
+        let checkouts = find_branch_checkouts(&repo, "refs/heads/topic").unwrap();
+        assert_eq!(checkouts.len(), 1);
+        assert!(!checkouts[0].is_current);
+        assert!(ensure_not_checked_out_elsewhere(&repo, "refs/heads/topic").is_err());
+
+        // Seen from the linked worktree, topic is the current checkout and main is elsewhere.
+        let checkouts = find_branch_checkouts(&worktree_repo, "refs/heads/topic").unwrap();
+        assert_eq!(checkouts.len(), 1);
+        assert!(checkouts[0].is_current);
+        assert!(ensure_not_checked_out_elsewhere(&worktree_repo, "refs/heads/topic").is_ok());
+        assert!(ensure_not_checked_out_elsewhere(&worktree_repo, "refs/heads/main").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use git_squish_core::{MAX_MESSAGE_BYTES, SquishError};
+use std::time::Duration;
+
+/// How long `fetch_message` waits for the whole response.
+pub const MESSAGE_URL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Download a squash message (e.g. release notes published by another job) with a GET
+/// request, failing on an error status, a response larger than `MAX_MESSAGE_BYTES` or one
+/// which takes longer than `MESSAGE_URL_TIMEOUT`.
+pub fn fetch_message(url: &str) -> Result<String, SquishError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(MESSAGE_URL_TIMEOUT))
+        .build()
+        .into();
+    let fetch_error = |e: ureq::Error| SquishError::Other {
+        message: match e {
+            ureq::Error::BodyExceedsLimit(_) => {
+                format!("The squash message at {url} is larger than {MAX_MESSAGE_BYTES} bytes")
+            }
+            e => format!("Failed to fetch the squash message from {url}: {e}"),
+        },
+    };
+    let mut response = agent.get(url).call().map_err(fetch_error)?;
+    response
+        .body_mut()
+        .with_config()
+        .limit(MAX_MESSAGE_BYTES)
+        .read_to_string()
+        .map_err(fetch_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serve one canned HTTP response on a local port, returning its URL.
+    fn serve_once(response: String) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/notes.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_fetch_message() {
+        let body = "Release notes\n\n- Fixed it\n";
+        let url = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ));
+        assert_eq!(fetch_message(&url).unwrap(), body);
+
+        let url = serve_once(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        );
+        let error = fetch_message(&url).unwrap_err().to_string();
+        assert!(error.contains("404"), "{error}");
+
+        let body = "x".repeat(MAX_MESSAGE_BYTES as usize + 1);
+        let url = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ));
+        let error = fetch_message(&url).unwrap_err().to_string();
+        assert!(error.contains("larger than"), "{error}");
+    }
+}